@@ -28,6 +28,10 @@ pub(crate) struct ForkserverCommand {
 
     #[clap(long)]
     state_dir: PathBuf,
+
+    /// Run each command in its own cgroup (Linux only).
+    #[clap(long)]
+    enable_cgroups: bool,
 }
 
 impl ForkserverCommand {
@@ -55,6 +59,7 @@ impl ForkserverCommand {
                 self.fd,
                 log_reload_handle,
                 state_dir,
+                self.enable_cgroups,
             ))
         }
 
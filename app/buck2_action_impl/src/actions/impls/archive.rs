@@ -0,0 +1,614 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Actions producing tar and zip archives in-process. Archives are deterministic: entries are
+//! sorted by path, and all metadata other than the executable bit is normalized.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::Write;
+use std::slice;
+use std::time::Instant;
+
+use allocative::Allocative;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use buck2_build_api::actions::artifact::build_artifact::BuildArtifact;
+use buck2_build_api::actions::box_slice_set::BoxSliceSet;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionKind;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionMetadata;
+use buck2_build_api::actions::execute::action_executor::ActionOutputs;
+use buck2_build_api::actions::Action;
+use buck2_build_api::actions::ActionExecutable;
+use buck2_build_api::actions::ActionExecutionCtx;
+use buck2_build_api::actions::IncrementalActionExecutable;
+use buck2_build_api::actions::UnregisteredAction;
+use buck2_build_api::artifact_groups::ArtifactGroup;
+use buck2_build_api::interpreter::rule_defs::artifact::starlark_artifact_like::ValueAsArtifactLike;
+use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileMetadata;
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_core::category::Category;
+use buck2_core::directory::ordered_entry_walk;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::execute::command_executor::ActionExecutionTimingData;
+use dupe::Dupe;
+use flate2::Compression;
+use flate2::GzBuilder;
+use gazebo::prelude::*;
+use indexmap::indexmap;
+use indexmap::IndexSet;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use starlark::values::dict::DictRef;
+use starlark::values::OwnedFrozenValue;
+use starlark::values::Value;
+use starlark::values::ValueError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum ArchiveError {
+    #[error("Unknown tar compression `{0}`, expected one of `gz` or `zstd`")]
+    UnknownCompression(String),
+    #[error("Paths in an archive must be non-empty")]
+    EmptyPath,
+    #[error("Paths in an archive must be non-overlapping, but got `{0}` and `{1}`")]
+    OverlappingPaths(ForwardRelativePathBuf, ForwardRelativePathBuf),
+    #[error("Only artifact inputs are supported in archive actions, got {0}")]
+    UnsupportedInput(ArtifactGroup),
+    #[error("Zip archives cannot contain symlinks, but `{0}` is a symlink")]
+    SymlinkInZip(ForwardRelativePathBuf),
+    #[error("Archives cannot contain symlinks to outside of the project, but `{0}` is one")]
+    ExternalSymlink(ForwardRelativePathBuf),
+}
+
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Allocative)]
+pub(crate) enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarZstd,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The tar format for the `compression` parameter of `ctx.actions.tar`.
+    pub(crate) fn tar(compression: Option<&str>) -> anyhow::Result<Self> {
+        match compression {
+            None => Ok(ArchiveFormat::Tar),
+            Some("gz") => Ok(ArchiveFormat::TarGz),
+            Some("zstd") => Ok(ArchiveFormat::TarZstd),
+            Some(other) => Err(ArchiveError::UnknownCompression(other.to_owned()).into()),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Tar => write!(f, "tar"),
+            ArchiveFormat::TarGz => write!(f, "tar.gz"),
+            ArchiveFormat::TarZstd => write!(f, "tar.zst"),
+            ArchiveFormat::Zip => write!(f, "zip"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ArchiveEntryKind {
+    /// A file whose contents are read from `src` as the archive is written.
+    File {
+        src: AbsNormPathBuf,
+        is_executable: bool,
+    },
+    Directory,
+    Symlink(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ArchiveEntry {
+    pub(crate) path: ForwardRelativePathBuf,
+    pub(crate) kind: ArchiveEntryKind,
+}
+
+const FILE_MODE: u32 = 0o644;
+const EXECUTABLE_MODE: u32 = 0o755;
+
+fn open_src(src: &AbsNormPath) -> anyhow::Result<File> {
+    File::open(src).with_context(|| format!("open({})", src))
+}
+
+/// Write an archive of `entries` to `out`, reading the contents of files as they are written.
+pub(crate) fn write_archive<W: Write + Seek>(
+    format: ArchiveFormat,
+    entries: &[ArchiveEntry],
+    out: W,
+) -> anyhow::Result<W> {
+    match format {
+        ArchiveFormat::Tar => write_tar(out, entries),
+        ArchiveFormat::TarGz => {
+            let encoder = GzBuilder::new().mtime(0).write(out, Compression::default());
+            Ok(write_tar(encoder, entries)?.finish()?)
+        }
+        ArchiveFormat::TarZstd => {
+            let encoder = zstd::stream::write::Encoder::new(out, 0)?;
+            Ok(write_tar(encoder, entries)?.finish()?)
+        }
+        ArchiveFormat::Zip => write_zip(out, entries),
+    }
+}
+
+fn write_tar<W: Write>(out: W, entries: &[ArchiveEntry]) -> anyhow::Result<W> {
+    let mut builder = tar::Builder::new(out);
+    for entry in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        match &entry.kind {
+            ArchiveEntryKind::File { src, is_executable } => {
+                let file = open_src(src)?;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(if *is_executable {
+                    EXECUTABLE_MODE
+                } else {
+                    FILE_MODE
+                });
+                header.set_size(file.metadata()?.len());
+                builder.append_data(&mut header, entry.path.as_str(), file)?;
+            }
+            ArchiveEntryKind::Directory => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(EXECUTABLE_MODE);
+                header.set_size(0);
+                builder.append_data(&mut header, format!("{}/", entry.path), std::io::empty())?;
+            }
+            ArchiveEntryKind::Symlink(target) => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_size(0);
+                builder.append_link(&mut header, entry.path.as_str(), target)?;
+            }
+        }
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn write_zip<W: Write + Seek>(out: W, entries: &[ArchiveEntry]) -> anyhow::Result<W> {
+    let mut writer = zip::ZipWriter::new(out);
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+    for entry in entries {
+        match &entry.kind {
+            ArchiveEntryKind::File { src, is_executable } => {
+                let mut file = open_src(src)?;
+                writer.start_file(
+                    entry.path.as_str(),
+                    options.unix_permissions(if *is_executable {
+                        EXECUTABLE_MODE
+                    } else {
+                        FILE_MODE
+                    }),
+                )?;
+                std::io::copy(&mut file, &mut writer)?;
+            }
+            ArchiveEntryKind::Directory => {
+                writer.add_directory(
+                    format!("{}/", entry.path),
+                    options.unix_permissions(EXECUTABLE_MODE),
+                )?;
+            }
+            ArchiveEntryKind::Symlink(..) => {
+                return Err(ArchiveError::SymlinkInZip(entry.path.clone()).into());
+            }
+        }
+    }
+    Ok(writer.finish()?)
+}
+
+#[derive(Allocative)]
+pub(crate) struct UnregisteredArchiveAction {
+    format: ArchiveFormat,
+    srcs: Vec<(ArtifactGroup, ForwardRelativePathBuf)>,
+}
+
+impl UnregisteredArchiveAction {
+    /// Validate that no path in the archive is empty, duplicated or overlapping.
+    fn validate_srcs(srcs: &mut [(ArtifactGroup, ForwardRelativePathBuf)]) -> anyhow::Result<()> {
+        // Sort by components rather than as strings, so that a path is always immediately followed
+        // by its descendants (`a-b` sorts between `a` and `a/c` as a string).
+        srcs.sort_by(|x, y| x.1.iter().cmp(y.1.iter()));
+
+        for ((_, x), (_, y)) in srcs.iter().tuple_windows() {
+            if y.starts_with(x) {
+                return Err(ArchiveError::OverlappingPaths(x.clone(), y.clone()).into());
+            }
+        }
+
+        for (g, path) in srcs.iter() {
+            if path.is_empty() {
+                return Err(ArchiveError::EmptyPath.into());
+            }
+            match g {
+                ArtifactGroup::Artifact(..) => {}
+                other => return Err(ArchiveError::UnsupportedInput(other.dupe()).into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unpack_srcs(srcs: Value) -> anyhow::Result<Vec<(ArtifactGroup, ForwardRelativePathBuf)>> {
+        let srcs = DictRef::from_value(srcs)
+            .ok_or_else(|| ValueError::IncorrectParameterTypeNamed("srcs".to_owned()))?;
+        srcs.iter()
+            .map(|(k, v)| {
+                let path = k
+                    .unpack_str()
+                    .ok_or_else(|| ValueError::IncorrectParameterTypeNamed("srcs".to_owned()))?;
+                let artifact = v
+                    .as_artifact()
+                    .ok_or_else(|| ValueError::IncorrectParameterTypeNamed("srcs".to_owned()))?
+                    .get_bound_artifact()?;
+                Ok((
+                    ArtifactGroup::Artifact(artifact),
+                    ForwardRelativePathBuf::try_from(path.to_owned())?,
+                ))
+            })
+            .collect()
+    }
+
+    pub(crate) fn new(format: ArchiveFormat, srcs: Value) -> anyhow::Result<Self> {
+        let mut srcs = Self::unpack_srcs(srcs)?;
+        Self::validate_srcs(&mut srcs)?;
+        Ok(Self { format, srcs })
+    }
+
+    pub(crate) fn inputs(&self) -> IndexSet<ArtifactGroup> {
+        self.srcs.iter().map(|x| x.0.dupe()).collect()
+    }
+}
+
+impl UnregisteredAction for UnregisteredArchiveAction {
+    fn register(
+        self: Box<Self>,
+        inputs: IndexSet<ArtifactGroup>,
+        outputs: IndexSet<BuildArtifact>,
+        _starlark_data: Option<OwnedFrozenValue>,
+    ) -> anyhow::Result<Box<dyn Action>> {
+        let output = outputs
+            .into_iter()
+            .exactly_one()
+            .map_err(|_| anyhow::anyhow!("Archive action must have exactly one output"))?;
+        Ok(Box::new(ArchiveAction {
+            format: self.format,
+            srcs: self.srcs,
+            inputs: BoxSliceSet::from(inputs),
+            output,
+        }))
+    }
+}
+
+#[derive(Debug, Allocative)]
+struct ArchiveAction {
+    format: ArchiveFormat,
+    srcs: Vec<(ArtifactGroup, ForwardRelativePathBuf)>,
+    inputs: BoxSliceSet<ArtifactGroup>,
+    output: BuildArtifact,
+}
+
+#[async_trait]
+impl Action for ArchiveAction {
+    fn kind(&self) -> buck2_data::ActionKind {
+        buck2_data::ActionKind::Archive
+    }
+
+    fn inputs(&self) -> anyhow::Result<Cow<'_, [ArtifactGroup]>> {
+        Ok(Cow::Borrowed(self.inputs.as_slice()))
+    }
+
+    fn outputs(&self) -> anyhow::Result<Cow<'_, [BuildArtifact]>> {
+        Ok(Cow::Borrowed(slice::from_ref(&self.output)))
+    }
+
+    fn as_executable(&self) -> ActionExecutable<'_> {
+        ActionExecutable::Incremental(self)
+    }
+
+    fn category(&self) -> &Category {
+        static ARCHIVE_CATEGORY: Lazy<Category> =
+            Lazy::new(|| Category::try_from("archive").unwrap());
+
+        &ARCHIVE_CATEGORY
+    }
+
+    fn identifier(&self) -> Option<&str> {
+        Some(self.output.get_path().path().as_str())
+    }
+}
+
+#[async_trait]
+impl IncrementalActionExecutable for ArchiveAction {
+    async fn execute(
+        &self,
+        ctx: &mut dyn ActionExecutionCtx,
+    ) -> anyhow::Result<(ActionOutputs, ActionExecutionMetadata)> {
+        let fs = ctx.fs();
+
+        let mut entries = Vec::new();
+        let mut to_materialize = Vec::new();
+        for (group, dest) in &self.srcs {
+            let (src_artifact, value) = ctx
+                .artifact_values(group)
+                .iter()
+                .into_singleton()
+                .context("Input did not dereference to exactly one artifact")?;
+
+            let src = src_artifact.resolve_path(fs)?;
+            if !src_artifact.is_source() {
+                to_materialize.push(src.clone());
+            }
+            let src = fs.fs().resolve(&src);
+
+            let mut walk = ordered_entry_walk(value.entry().as_ref());
+            while let Some((entry_path, entry)) = walk.next() {
+                let entry_path = entry_path.get();
+                let path = dest.join(&entry_path);
+                let kind = match entry {
+                    DirectoryEntry::Dir(..) => ArchiveEntryKind::Directory,
+                    DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) => {
+                        ArchiveEntryKind::File {
+                            src: src.join(&entry_path),
+                            is_executable: file.is_executable,
+                        }
+                    }
+                    DirectoryEntry::Leaf(ActionDirectoryMember::Symlink(symlink)) => {
+                        ArchiveEntryKind::Symlink(symlink.target().to_string())
+                    }
+                    DirectoryEntry::Leaf(ActionDirectoryMember::ExternalSymlink(..)) => {
+                        return Err(ArchiveError::ExternalSymlink(path).into());
+                    }
+                };
+                entries.push(ArchiveEntry { path, kind });
+            }
+        }
+        entries.sort_by(|x, y| x.path.cmp(&y.path));
+
+        ctx.materializer()
+            .ensure_materialized(to_materialize)
+            .await?;
+        ctx.cleanup_outputs().await?;
+
+        let execution_start = Instant::now();
+        let fs = ctx.fs();
+        let project_fs = fs.fs();
+        let output_path = fs.resolve_build(self.output.get_path());
+
+        // The archive is written to scratch and only moved to the output once complete, so that a
+        // failure never leaves a truncated archive behind.
+        let scratch_path = fs
+            .buck_out_path_resolver()
+            .resolve_scratch(&ctx.target().custom_tmpdir());
+        let scratch_abs_path = project_fs.resolve(&scratch_path);
+        fs_util::remove_all(&scratch_abs_path)?;
+        fs_util::create_dir_all(&scratch_abs_path)?;
+        let archive_path = scratch_abs_path.join(FileName::new("archive")?);
+
+        let digest = File::create(&archive_path)
+            .with_context(|| format!("create({})", archive_path))
+            .and_then(|file| {
+                let mut out = write_archive(self.format, &entries, BufWriter::new(file))?;
+                out.flush()?;
+                let cas_digest_config = ctx.digest_config().cas_digest_config();
+                Ok(TrackedFileDigest::new(
+                    FileDigest::from_file(&archive_path, cas_digest_config)?,
+                    cas_digest_config,
+                ))
+            })
+            .with_context(|| format!("creating {} archive", self.format));
+        let digest = match digest {
+            Ok(digest) => digest,
+            Err(e) => {
+                fs_util::remove_all(&scratch_abs_path)?;
+                return Err(e);
+            }
+        };
+
+        let output_abs_path = project_fs.resolve(&output_path);
+        if let Some(parent) = output_abs_path.parent() {
+            fs_util::create_dir_all(parent)?;
+        }
+        fs_util::rename(&archive_path, &output_abs_path)?;
+
+        let value = ArtifactValue::file(FileMetadata {
+            digest,
+            is_executable: false,
+        });
+        ctx.materializer()
+            .declare_existing(vec![(output_path, value.dupe())])
+            .await?;
+
+        let wall_time = execution_start.elapsed();
+
+        Ok((
+            ActionOutputs::new(indexmap![self.output.get_path().dupe() => value]),
+            ActionExecutionMetadata {
+                execution_kind: ActionExecutionKind::Simple,
+                timing: ActionExecutionTimingData { wall_time },
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    /// Write an archive of `entries` to memory.
+    pub(crate) fn build_archive(
+        format: ArchiveFormat,
+        entries: &[ArchiveEntry],
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(write_archive(format, entries, Cursor::new(Vec::new()))?.into_inner())
+    }
+
+    /// A file entry, whose contents are written to a new file in `dir`.
+    pub(crate) fn file(
+        dir: &AbsNormPath,
+        contents: &str,
+        is_executable: bool,
+    ) -> anyhow::Result<ArchiveEntryKind> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let src = dir.join(ForwardRelativePathBuf::unchecked_new(format!(
+            "src-{}",
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        )));
+        fs_util::write(&src, contents)?;
+        Ok(ArchiveEntryKind::File { src, is_executable })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::io::Read;
+
+    use buck2_build_api::actions::artifact::artifact_type::Artifact;
+    use buck2_build_api::actions::artifact::source_artifact::SourceArtifact;
+    use buck2_core::buck_path::path::BuckPath;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::package::package_relative_path::PackageRelativePathBuf;
+    use buck2_core::package::PackageLabel;
+
+    use super::testing::build_archive;
+    use super::testing::file;
+    use super::*;
+
+    fn mk_artifact() -> Artifact {
+        let pkg = PackageLabel::testing_parse("cell//pkg");
+        let path = PackageRelativePathBuf::unchecked_new("".to_owned());
+        let buck_path = BuckPath::testing_new(pkg, path);
+        Artifact::from(SourceArtifact::new(buck_path))
+    }
+
+    fn entries(dir: &AbsNormPath) -> anyhow::Result<Vec<ArchiveEntry>> {
+        Ok(vec![
+            ArchiveEntry {
+                path: ForwardRelativePathBuf::unchecked_new("bin".to_owned()),
+                kind: ArchiveEntryKind::Directory,
+            },
+            ArchiveEntry {
+                path: ForwardRelativePathBuf::unchecked_new("bin/tool".to_owned()),
+                kind: file(dir, "#!/bin/sh", true)?,
+            },
+            ArchiveEntry {
+                path: ForwardRelativePathBuf::unchecked_new("data.txt".to_owned()),
+                kind: file(dir, "data", false)?,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_archive_validation() {
+        fn validate(paths: &[&str]) -> anyhow::Result<()> {
+            let a = ArtifactGroup::Artifact(mk_artifact());
+            let mut xs = paths.map(|x| {
+                (
+                    a.dupe(),
+                    ForwardRelativePathBuf::unchecked_new((*x).to_owned()),
+                )
+            });
+            UnregisteredArchiveAction::validate_srcs(&mut xs)
+        }
+
+        assert!(validate(&["test", "other"]).is_ok());
+        assert!(validate(&["test", "test"]).is_err());
+        assert!(validate(&["test", "other", "test"]).is_err());
+        assert!(validate(&["test", "test/child"]).is_err());
+        assert!(validate(&["a", "a-b", "a/c"]).is_err());
+        assert!(validate(&["a/c", "a-b", "a"]).is_err());
+        assert!(validate(&["a-b", "a/c"]).is_ok());
+        assert!(validate(&[""]).is_err());
+    }
+
+    #[test]
+    fn test_tar_is_deterministic() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let entries = entries(fs.path().root())?;
+        for format in [
+            ArchiveFormat::Tar,
+            ArchiveFormat::TarGz,
+            ArchiveFormat::TarZstd,
+        ] {
+            assert_eq!(
+                build_archive(format, &entries)?,
+                build_archive(format, &entries)?
+            );
+        }
+
+        let archive = build_archive(ArchiveFormat::Tar, &entries)?;
+        let mut archive = tar::Archive::new(archive.as_slice());
+        let mut seen = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            assert_eq!(0, header.mtime()?);
+            assert_eq!(0, header.uid()?);
+            seen.push((entry.path()?.to_string_lossy().into_owned(), header.mode()?));
+        }
+        assert_eq!(
+            vec![
+                ("bin/".to_owned(), 0o755),
+                ("bin/tool".to_owned(), 0o755),
+                ("data.txt".to_owned(), 0o644),
+            ],
+            seen
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let entries = entries(fs.path().root())?;
+        let archive = build_archive(ArchiveFormat::Zip, &entries)?;
+        assert_eq!(archive, build_archive(ArchiveFormat::Zip, &entries)?);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive))?;
+        let mut tool = archive.by_name("bin/tool")?;
+        assert_eq!(Some(0o755), tool.unix_mode().map(|m| m & 0o777));
+        let mut contents = String::new();
+        tool.read_to_string(&mut contents)?;
+        assert_eq!("#!/bin/sh", contents);
+        drop(tool);
+
+        let symlink = [ArchiveEntry {
+            path: ForwardRelativePathBuf::unchecked_new("link".to_owned()),
+            kind: ArchiveEntryKind::Symlink("data.txt".to_owned()),
+        }];
+        assert!(build_archive(ArchiveFormat::Zip, &symlink).is_err());
+        assert!(build_archive(ArchiveFormat::Tar, &symlink).is_ok());
+        Ok(())
+    }
+}
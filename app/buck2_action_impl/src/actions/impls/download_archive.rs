@@ -0,0 +1,1036 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An action to download an archive and extract it into a directory output.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::io::Seek;
+use std::slice;
+use std::sync::Arc;
+use std::time::Instant;
+
+use allocative::Allocative;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use buck2_build_api::actions::artifact::build_artifact::BuildArtifact;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionKind;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionMetadata;
+use buck2_build_api::actions::execute::action_executor::ActionOutputs;
+use buck2_build_api::actions::Action;
+use buck2_build_api::actions::ActionExecutable;
+use buck2_build_api::actions::ActionExecutionCtx;
+use buck2_build_api::actions::IncrementalActionExecutable;
+use buck2_build_api::actions::UnregisteredAction;
+use buck2_build_api::artifact_groups::ArtifactGroup;
+use buck2_common::executor_config::Executor;
+use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileMetadata;
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_core::category::Category;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::DirectoryIterator;
+use buck2_core::directory::FingerprintedDirectory;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_execute::artifact::fs::ExecutorFs;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::digest::CasDigestToReExt;
+use buck2_execute::digest_config::DigestConfig;
+use buck2_execute::directory::ActionDirectoryBuilder;
+use buck2_execute::directory::ActionDirectoryEntry;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::directory::ActionSharedDirectory;
+use buck2_execute::directory::Symlink;
+use buck2_execute::directory::INTERNER;
+use buck2_execute::execute::blobs::ActionBlobs;
+use buck2_execute::execute::command_executor::ActionExecutionTimingData;
+use buck2_execute::materialize::http::http_client;
+use buck2_execute::materialize::http::http_download;
+use buck2_execute::materialize::http::Checksum;
+use buck2_execute::materialize::materializer::CasDownloadInfo;
+use buck2_execute::re::manager::ManagedRemoteExecutionClient;
+use chrono::Utc;
+use dashmap::DashMap;
+use dupe::Dupe;
+use indexmap::indexmap;
+use indexmap::IndexMap;
+use indexmap::IndexSet;
+use once_cell::sync::Lazy;
+use relative_path::RelativePathBuf;
+use remote_execution::RemoteExecutorUseCase;
+use starlark::values::OwnedFrozenValue;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum DownloadArchiveError {
+    #[error("download_archive action should not have inputs, got {0}")]
+    WrongNumberOfInputs(usize),
+    #[error(
+        "Exactly one output directory must be specified for a download_archive action, got {0}"
+    )]
+    WrongNumberOfOutputs(usize),
+    #[error(
+        "Unknown archive type `{0}`, expected one of `tar`, `tar.gz`, `tar.xz`, `tar.zst` or `zip`"
+    )]
+    UnknownArchiveType(String),
+    #[error("Cannot infer the archive type from URL `{0}`, pass `archive_type`")]
+    CannotInferArchiveType(String),
+    #[error("Archive entry `{0}` has an invalid path")]
+    InvalidPath(String),
+    #[error("Archive entry `{0}` has an unsupported type")]
+    UnsupportedEntry(String),
+    #[error("Archive entry `{0}` is a hard link to `{1}`, which is not an earlier file")]
+    InvalidHardLink(String, String),
+    #[error("Archive entry `{0}` is a symlink to `{1}`, which is outside of the archive")]
+    SymlinkEscapes(ForwardRelativePathBuf, String),
+    #[error("Archive entry `{0}` is under `{1}`, which is a symlink")]
+    ThroughSymlink(String, ForwardRelativePathBuf),
+    #[error("No archive entries are under `strip_prefix` `{0}`")]
+    StripPrefixNotFound(ForwardRelativePathBuf),
+}
+
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Hash, Allocative)]
+pub(crate) enum ArchiveType {
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveType {
+    const EXTENSIONS: &'static [(&'static str, ArchiveType)] = &[
+        ("tar", ArchiveType::Tar),
+        ("tar.gz", ArchiveType::TarGz),
+        ("tgz", ArchiveType::TarGz),
+        ("tar.xz", ArchiveType::TarXz),
+        ("txz", ArchiveType::TarXz),
+        ("tar.zst", ArchiveType::TarZst),
+        ("tzst", ArchiveType::TarZst),
+        ("zip", ArchiveType::Zip),
+    ];
+
+    /// The type for the `archive_type` parameter of `ctx.actions.download_archive`, or, if that
+    /// isn't set, from the extension of the URL's path.
+    pub(crate) fn new(archive_type: Option<&str>, url: &str) -> anyhow::Result<Self> {
+        match archive_type {
+            Some(archive_type) => Self::EXTENSIONS
+                .iter()
+                .find(|(ext, _)| *ext == archive_type)
+                .map(|(_, t)| *t)
+                .ok_or_else(|| DownloadArchiveError::UnknownArchiveType(archive_type.to_owned())),
+            None => {
+                let path = url.split(&['?', '#'][..]).next().unwrap_or_default();
+                Self::EXTENSIONS
+                    .iter()
+                    .find(|(ext, _)| {
+                        path.strip_suffix(ext)
+                            .map_or(false, |rest| rest.ends_with('.'))
+                    })
+                    .map(|(_, t)| *t)
+                    .ok_or_else(|| DownloadArchiveError::CannotInferArchiveType(url.to_owned()))
+            }
+        }
+        .map_err(anyhow::Error::from)
+    }
+}
+
+impl fmt::Display for ArchiveType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveType::Tar => write!(f, "tar"),
+            ArchiveType::TarGz => write!(f, "tar.gz"),
+            ArchiveType::TarXz => write!(f, "tar.xz"),
+            ArchiveType::TarZst => write!(f, "tar.zst"),
+            ArchiveType::Zip => write!(f, "zip"),
+        }
+    }
+}
+
+/// Normalize the path of an archive entry, dropping `.` components. Returns `None` for the root.
+fn normalize_path(path: &str) -> anyhow::Result<Option<ForwardRelativePathBuf>> {
+    let parts: Vec<&str> = path
+        .split('/')
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+    if path.starts_with('/') || parts.contains(&"..") {
+        return Err(DownloadArchiveError::InvalidPath(path.to_owned()).into());
+    }
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    ForwardRelativePathBuf::try_from(parts.join("/"))
+        .map(Some)
+        .with_context(|| DownloadArchiveError::InvalidPath(path.to_owned()))
+}
+
+/// An extracted archive entry. File contents are only on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExtractedEntry {
+    File {
+        digest: TrackedFileDigest,
+        is_executable: bool,
+    },
+    Directory,
+    Symlink(String),
+}
+
+/// An archive entry, as read from the archive.
+enum EntryContents<'r> {
+    File {
+        reader: &'r mut dyn Read,
+        is_executable: bool,
+    },
+    Directory,
+    Symlink(String),
+    /// A hard link to an earlier file, by its path in the archive.
+    HardLink(String),
+}
+
+/// The part of `path` under `strip_prefix`, which is empty for `strip_prefix` itself.
+fn strip<'p>(
+    strip_prefix: Option<&ForwardRelativePath>,
+    path: &'p ForwardRelativePath,
+) -> Option<&'p ForwardRelativePath> {
+    match strip_prefix {
+        Some(prefix) => path.strip_prefix(prefix).ok(),
+        None => Some(path),
+    }
+}
+
+/// Writes archive entries to a directory as they are read, so that archives are never held in
+/// memory, and records what was written.
+struct Extractor<'a> {
+    root: &'a AbsNormPath,
+    strip_prefix: Option<&'a ForwardRelativePath>,
+    found_prefix: bool,
+    digest_config: DigestConfig,
+    entries: BTreeMap<ForwardRelativePathBuf, ExtractedEntry>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(
+        root: &'a AbsNormPath,
+        strip_prefix: Option<&'a ForwardRelativePath>,
+        digest_config: DigestConfig,
+    ) -> Self {
+        Self {
+            root,
+            strip_prefix,
+            found_prefix: strip_prefix.is_none(),
+            digest_config,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Extract an entry. Entries that are not under `strip_prefix` are dropped. Later entries
+    /// for a path replace earlier ones, like they would when extracting with `tar`.
+    fn add(&mut self, raw_path: &str, contents: EntryContents<'_>) -> anyhow::Result<()> {
+        let path = match normalize_path(raw_path)? {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let path = match strip(self.strip_prefix, &path) {
+            Some(path) => {
+                self.found_prefix = true;
+                if path.is_empty() {
+                    return Ok(());
+                }
+                path.to_buf()
+            }
+            None => return Ok(()),
+        };
+
+        // Never write through a symlink extracted earlier, which could point anywhere once
+        // combined with later entries.
+        let mut ancestor = path.parent();
+        while let Some(dir) = ancestor.filter(|dir| !dir.is_empty()) {
+            if let Some(ExtractedEntry::Symlink(_)) = self.entries.get(dir) {
+                return Err(DownloadArchiveError::ThroughSymlink(
+                    raw_path.to_owned(),
+                    dir.to_buf(),
+                )
+                .into());
+            }
+            ancestor = dir.parent();
+        }
+
+        let dest = self.root.join(&path);
+        let keeps_existing = matches!(
+            (self.entries.get(&path), &contents),
+            (Some(ExtractedEntry::Directory), EntryContents::Directory)
+        );
+        if self.entries.contains_key(&path) && !keeps_existing {
+            fs_util::remove_all(&dest)?;
+            self.entries.retain(|p, _| !p.starts_with(&path));
+        }
+        if let Some(parent) = dest.parent() {
+            fs_util::create_dir_all(parent)?;
+        }
+
+        let entry = match contents {
+            EntryContents::File {
+                reader,
+                is_executable,
+            } => {
+                let mut file = fs_util::create_file(&dest)?;
+                std::io::copy(reader, &mut file)
+                    .with_context(|| format!("Error extracting `{}`", raw_path))?;
+                drop(file);
+                if is_executable {
+                    fs_util::set_executable(&dest)?;
+                }
+                self.file_entry(&dest, is_executable)?
+            }
+            EntryContents::Directory => {
+                fs_util::create_dir_all(&dest)?;
+                ExtractedEntry::Directory
+            }
+            EntryContents::Symlink(target) => {
+                check_symlink(&path, &target)?;
+                fs_util::symlink(&target, &dest)?;
+                ExtractedEntry::Symlink(target)
+            }
+            EntryContents::HardLink(target) => {
+                let linked = normalize_path(&target)?.and_then(|target| {
+                    let target = strip(self.strip_prefix, &target)?.to_buf();
+                    match self.entries.get(&target)? {
+                        ExtractedEntry::File { .. } => Some(target),
+                        _ => None,
+                    }
+                });
+                match linked {
+                    Some(linked) => {
+                        fs_util::copy(self.root.join(&linked), &dest)?;
+                        self.entries[&linked].clone()
+                    }
+                    None => {
+                        return Err(DownloadArchiveError::InvalidHardLink(
+                            raw_path.to_owned(),
+                            target,
+                        )
+                        .into());
+                    }
+                }
+            }
+        };
+
+        self.entries.insert(path, entry);
+        Ok(())
+    }
+
+    fn file_entry(
+        &self,
+        path: &AbsNormPath,
+        is_executable: bool,
+    ) -> anyhow::Result<ExtractedEntry> {
+        let cas_digest_config = self.digest_config.cas_digest_config();
+        Ok(ExtractedEntry::File {
+            digest: TrackedFileDigest::new(
+                FileDigest::from_file(path, cas_digest_config)?,
+                cas_digest_config,
+            ),
+            is_executable,
+        })
+    }
+
+    fn finish(self) -> anyhow::Result<BTreeMap<ForwardRelativePathBuf, ExtractedEntry>> {
+        match self.strip_prefix {
+            Some(prefix) if !self.found_prefix => {
+                Err(DownloadArchiveError::StripPrefixNotFound(prefix.to_buf()).into())
+            }
+            _ => Ok(self.entries),
+        }
+    }
+}
+
+fn link_name(entry: &tar::Entry<impl Read>, raw_path: &str) -> anyhow::Result<String> {
+    let target = entry
+        .link_name_bytes()
+        .with_context(|| DownloadArchiveError::InvalidPath(raw_path.to_owned()))?;
+    Ok(String::from_utf8_lossy(&target).into_owned())
+}
+
+fn extract_tar(reader: impl Read, extractor: &mut Extractor<'_>) -> anyhow::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let raw_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_pax_global_extensions() || entry_type.is_pax_local_extensions() {
+            continue;
+        }
+
+        let contents = if entry_type.is_file() || entry_type.is_contiguous() {
+            let is_executable = entry.header().mode()? & 0o111 != 0;
+            EntryContents::File {
+                reader: &mut entry,
+                is_executable,
+            }
+        } else if entry_type.is_dir() {
+            EntryContents::Directory
+        } else if entry_type.is_symlink() {
+            EntryContents::Symlink(link_name(&entry, &raw_path)?)
+        } else if entry_type.is_hard_link() {
+            EntryContents::HardLink(link_name(&entry, &raw_path)?)
+        } else {
+            return Err(DownloadArchiveError::UnsupportedEntry(raw_path).into());
+        };
+
+        extractor.add(&raw_path, contents)?;
+    }
+
+    Ok(())
+}
+
+fn extract_zip(reader: impl Read + Seek, extractor: &mut Extractor<'_>) -> anyhow::Result<()> {
+    const S_IFMT: u32 = 0o170000;
+    const S_IFLNK: u32 = 0o120000;
+
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let raw_path = file.name().to_owned();
+        let mode = file.unix_mode();
+
+        if file.is_dir() {
+            extractor.add(&raw_path, EntryContents::Directory)?;
+        } else if matches!(mode, Some(mode) if mode & S_IFMT == S_IFLNK) {
+            let mut target = String::new();
+            file.read_to_string(&mut target)
+                .with_context(|| DownloadArchiveError::InvalidPath(raw_path.clone()))?;
+            extractor.add(&raw_path, EntryContents::Symlink(target))?;
+        } else {
+            let is_executable = mode.map_or(false, |mode| mode & 0o111 != 0);
+            extractor.add(
+                &raw_path,
+                EntryContents::File {
+                    reader: &mut file,
+                    is_executable,
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract an archive into the directory `root`, with `strip_prefix` removed from the paths of
+/// its entries, and return what was extracted.
+fn extract<R: Read + Seek>(
+    archive_type: ArchiveType,
+    reader: R,
+    strip_prefix: Option<&ForwardRelativePath>,
+    root: &AbsNormPath,
+    digest_config: DigestConfig,
+) -> anyhow::Result<BTreeMap<ForwardRelativePathBuf, ExtractedEntry>> {
+    fs_util::create_dir_all(root)?;
+    let mut extractor = Extractor::new(root, strip_prefix, digest_config);
+    match archive_type {
+        ArchiveType::Tar => extract_tar(reader, &mut extractor)?,
+        ArchiveType::TarGz => extract_tar(flate2::read::GzDecoder::new(reader), &mut extractor)?,
+        ArchiveType::TarXz => extract_tar(xz2::read::XzDecoder::new(reader), &mut extractor)?,
+        ArchiveType::TarZst => {
+            extract_tar(zstd::stream::read::Decoder::new(reader)?, &mut extractor)?
+        }
+        ArchiveType::Zip => extract_zip(reader, &mut extractor)?,
+    }
+    extractor.finish()
+}
+
+/// Symlinks must be relative and stay within the extracted directory.
+fn check_symlink(path: &ForwardRelativePath, target: &str) -> anyhow::Result<()> {
+    let mut depth = path.iter().count() - 1;
+    let escapes = target.starts_with('/')
+        || target.split('/').any(|part| match part {
+            "" | "." => false,
+            ".." => match depth.checked_sub(1) {
+                Some(d) => {
+                    depth = d;
+                    false
+                }
+                None => true,
+            },
+            _ => {
+                depth += 1;
+                false
+            }
+        });
+    if escapes {
+        return Err(DownloadArchiveError::SymlinkEscapes(path.to_buf(), target.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// The directory for extracted `entries`.
+fn build_directory(
+    entries: &BTreeMap<ForwardRelativePathBuf, ExtractedEntry>,
+) -> anyhow::Result<ActionDirectoryBuilder> {
+    let mut builder = ActionDirectoryBuilder::empty();
+
+    for (path, entry) in entries {
+        match entry {
+            ExtractedEntry::Directory => {
+                builder.mkdir(path)?;
+            }
+            ExtractedEntry::File {
+                digest,
+                is_executable,
+            } => {
+                builder.insert(
+                    path,
+                    DirectoryEntry::Leaf(ActionDirectoryMember::File(FileMetadata {
+                        digest: digest.dupe(),
+                        is_executable: *is_executable,
+                    })),
+                )?;
+            }
+            ExtractedEntry::Symlink(target) => {
+                builder.insert(
+                    path,
+                    DirectoryEntry::Leaf(ActionDirectoryMember::Symlink(Arc::new(Symlink::new(
+                        RelativePathBuf::from(target.as_str()),
+                    )))),
+                )?;
+            }
+        }
+    }
+
+    Ok(builder)
+}
+
+#[derive(Debug, Allocative)]
+pub(crate) struct UnregisteredDownloadArchiveAction {
+    checksum: Checksum,
+    url: Arc<str>,
+    mirrors: Arc<[Arc<str>]>,
+    archive_type: ArchiveType,
+    strip_prefix: Option<ForwardRelativePathBuf>,
+    is_deferrable: bool,
+}
+
+impl UnregisteredDownloadArchiveAction {
+    pub(crate) fn new(
+        checksum: Checksum,
+        url: Arc<str>,
+        mirrors: Arc<[Arc<str>]>,
+        archive_type: ArchiveType,
+        strip_prefix: Option<ForwardRelativePathBuf>,
+        is_deferrable: bool,
+    ) -> Self {
+        Self {
+            checksum,
+            url,
+            mirrors,
+            archive_type,
+            strip_prefix,
+            is_deferrable,
+        }
+    }
+}
+
+impl UnregisteredAction for UnregisteredDownloadArchiveAction {
+    fn register(
+        self: Box<Self>,
+        inputs: IndexSet<ArtifactGroup>,
+        outputs: IndexSet<BuildArtifact>,
+        _starlark_data: Option<OwnedFrozenValue>,
+    ) -> anyhow::Result<Box<dyn Action>> {
+        if !inputs.is_empty() {
+            return Err(DownloadArchiveError::WrongNumberOfInputs(inputs.len()).into());
+        }
+        let output = match outputs.into_iter().collect::<Vec<_>>().as_slice() {
+            [output] => output.dupe(),
+            outputs => {
+                return Err(DownloadArchiveError::WrongNumberOfOutputs(outputs.len()).into());
+            }
+        };
+        Ok(Box::new(DownloadArchiveAction {
+            output,
+            inner: *self,
+        }))
+    }
+}
+
+#[derive(Debug, Allocative)]
+struct DownloadArchiveAction {
+    output: BuildArtifact,
+    inner: UnregisteredDownloadArchiveAction,
+}
+
+#[async_trait]
+impl Action for DownloadArchiveAction {
+    fn kind(&self) -> buck2_data::ActionKind {
+        buck2_data::ActionKind::DownloadArchive
+    }
+
+    fn inputs(&self) -> anyhow::Result<Cow<'_, [ArtifactGroup]>> {
+        Ok(Cow::Borrowed(&[]))
+    }
+
+    fn outputs(&self) -> anyhow::Result<Cow<'_, [BuildArtifact]>> {
+        Ok(Cow::Borrowed(slice::from_ref(&self.output)))
+    }
+
+    fn as_executable(&self) -> ActionExecutable<'_> {
+        ActionExecutable::Incremental(self)
+    }
+
+    fn category(&self) -> &Category {
+        static DOWNLOAD_ARCHIVE_CATEGORY: Lazy<Category> =
+            Lazy::new(|| Category::try_from("download_archive").unwrap());
+
+        &DOWNLOAD_ARCHIVE_CATEGORY
+    }
+
+    fn identifier(&self) -> Option<&str> {
+        Some(self.output.get_path().path().as_str())
+    }
+
+    fn aquery_attributes(&self, _fs: &ExecutorFs) -> IndexMap<String, String> {
+        indexmap! {
+            "url".to_owned() => self.inner.url.to_string(),
+            "archive_type".to_owned() => self.inner.archive_type.to_string(),
+            "strip_prefix".to_owned() => self.inner.strip_prefix.as_ref().map_or_else(String::new, |p| p.to_string()),
+        }
+    }
+}
+
+/// Identifies the output of extracting an archive.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ExtractedKey {
+    sha256: Arc<str>,
+    archive_type: ArchiveType,
+    strip_prefix: Option<ForwardRelativePathBuf>,
+}
+
+/// The directories of the deferrable archives this daemon extracted and uploaded to the CAS, so
+/// that they need not be downloaded and extracted again while the CAS still has them.
+static EXTRACTED: Lazy<DashMap<ExtractedKey, ActionSharedDirectory>> = Lazy::new(DashMap::new);
+
+/// Whether the CAS has every blob of `directory`, for long enough for it to be used.
+async fn is_in_cas(
+    re_client: &ManagedRemoteExecutionClient,
+    directory: &ActionSharedDirectory,
+    use_case: RemoteExecutorUseCase,
+) -> anyhow::Result<bool> {
+    // Like the uploader, leave the 10 minutes of leeway RE usually takes.
+    const TTL_WANTED: i64 = 600;
+    // Don't send an unbounded amount of digests at once.
+    const CHUNK_SIZE: usize = 500;
+
+    let mut digests = HashSet::new();
+    digests.insert(directory.fingerprint().to_re());
+    for entry in directory.fingerprinted_unordered_walk().without_paths() {
+        match entry {
+            DirectoryEntry::Dir(d) => digests.insert(d.fingerprint().to_re()),
+            DirectoryEntry::Leaf(ActionDirectoryMember::File(f)) => {
+                digests.insert(f.digest.to_re())
+            }
+            DirectoryEntry::Leaf(..) => continue,
+        };
+    }
+
+    let deadline = Utc::now() + chrono::Duration::seconds(TTL_WANTED);
+    let digests = digests.into_iter().collect::<Vec<_>>();
+    for chunk in digests.chunks(CHUNK_SIZE) {
+        let expirations = re_client
+            .get_digest_expirations(chunk.to_vec(), use_case.dupe())
+            .await?;
+        if expirations.len() != chunk.len()
+            || expirations.iter().any(|(_, expires)| *expires <= deadline)
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+impl DownloadArchiveAction {
+    /// Download and extract the archive, to the output, or to scratch and the CAS with RE.
+    async fn download_and_extract(
+        &self,
+        ctx: &mut dyn ActionExecutionCtx,
+        project_fs: &ProjectRoot,
+        scratch_path: &ProjectRelativePath,
+        output_path: &ProjectRelativePath,
+        re_use_case: Option<&RemoteExecutorUseCase>,
+    ) -> anyhow::Result<ActionSharedDirectory> {
+        let digest_config = ctx.digest_config();
+        let archive_path = scratch_path.join(FileName::new("archive")?);
+
+        http_download(
+            &http_client()?,
+            project_fs,
+            digest_config,
+            &archive_path,
+            &self.inner.url,
+            &self.inner.mirrors,
+            &self.inner.checksum,
+            false,
+        )
+        .await?;
+
+        let extract_path = match re_use_case {
+            Some(_) => {
+                let extract_path = scratch_path.join(FileName::new("extracted")?);
+                fs_util::remove_all(project_fs.resolve(&extract_path))?;
+                extract_path
+            }
+            None => {
+                ctx.cleanup_outputs().await?;
+                output_path.to_buf()
+            }
+        };
+
+        let archive_abs_path = project_fs.resolve(&archive_path);
+        let entries = std::fs::File::open(&archive_abs_path)
+            .with_context(|| format!("open({})", archive_abs_path))
+            .and_then(|file| {
+                extract(
+                    self.inner.archive_type,
+                    std::io::BufReader::new(file),
+                    self.inner.strip_prefix.as_deref(),
+                    &project_fs.resolve(&extract_path),
+                    digest_config,
+                )
+            })
+            .with_context(|| {
+                format!(
+                    "Error extracting {} archive downloaded from `{}`",
+                    self.inner.archive_type, self.inner.url
+                )
+            });
+        fs_util::remove_file(&archive_abs_path)?;
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                if re_use_case.is_some() {
+                    fs_util::remove_all(project_fs.resolve(&extract_path))?;
+                }
+                return Err(e);
+            }
+        };
+
+        let directory =
+            build_directory(&entries)?.fingerprint(digest_config.as_directory_serializer());
+
+        if let Some(re_use_case) = re_use_case {
+            // Only blobs that are missing from the CAS are read from scratch and uploaded.
+            ctx.re_client()
+                .upload(
+                    ctx.materializer(),
+                    &ActionBlobs::new(digest_config),
+                    &extract_path,
+                    &directory,
+                    re_use_case.dupe(),
+                    digest_config,
+                )
+                .await
+                .context("Error uploading extracted archive")?;
+            fs_util::remove_all(project_fs.resolve(&extract_path))?;
+        }
+
+        Ok(directory.shared(&*INTERNER))
+    }
+}
+
+#[async_trait]
+impl IncrementalActionExecutable for DownloadArchiveAction {
+    async fn execute(
+        &self,
+        ctx: &mut dyn ActionExecutionCtx,
+    ) -> anyhow::Result<(ActionOutputs, ActionExecutionMetadata)> {
+        let start = Instant::now();
+        let project_fs = ctx.fs().fs().dupe();
+        let output_path = ctx.fs().resolve_build(self.output.get_path());
+        let scratch_path = ctx
+            .fs()
+            .buck_out_path_resolver()
+            .resolve_scratch(&ctx.target().custom_tmpdir());
+
+        // With RE, a deferrable archive is extracted to scratch and uploaded to the CAS, and the
+        // output is only declared. The download and the extraction still happen here, unless the
+        // archive was extracted before and its directory is still in the CAS.
+        let re_use_case = match &ctx.target().execution_config().executor {
+            Executor::RemoteEnabled { re_use_case, .. } if self.inner.is_deferrable => {
+                Some(re_use_case.dupe())
+            }
+            _ => None,
+        };
+
+        let extracted_key = ExtractedKey {
+            sha256: self
+                .inner
+                .checksum
+                .sha256()
+                .context("download_archive requires a sha256")?
+                .into(),
+            archive_type: self.inner.archive_type,
+            strip_prefix: self.inner.strip_prefix.clone(),
+        };
+        let previously_extracted = match &re_use_case {
+            Some(re_use_case) => match EXTRACTED.get(&extracted_key).map(|d| d.dupe()) {
+                Some(directory)
+                    if is_in_cas(&ctx.re_client(), &directory, re_use_case.dupe()).await? =>
+                {
+                    Some(directory)
+                }
+                _ => None,
+            },
+            None => None,
+        };
+
+        let directory = match previously_extracted {
+            Some(directory) => directory,
+            None => {
+                let directory = self
+                    .download_and_extract(
+                        ctx,
+                        &project_fs,
+                        &scratch_path,
+                        &output_path,
+                        re_use_case.as_ref(),
+                    )
+                    .await?;
+                if re_use_case.is_some() {
+                    EXTRACTED.insert(extracted_key, directory.dupe());
+                }
+                directory
+            }
+        };
+
+        let value = ArtifactValue::new(ActionDirectoryEntry::Dir(directory), None);
+        let execution_kind = match re_use_case {
+            Some(re_use_case) => {
+                ctx.materializer()
+                    .declare_cas_many(
+                        Arc::new(CasDownloadInfo::new_declared(re_use_case)),
+                        vec![(output_path, value.dupe())],
+                    )
+                    .await?;
+                ActionExecutionKind::Deferred
+            }
+            None => {
+                ctx.materializer()
+                    .declare_existing(vec![(output_path, value.dupe())])
+                    .await?;
+                ActionExecutionKind::Simple
+            }
+        };
+
+        Ok((
+            ActionOutputs::new(indexmap![self.output.get_path().dupe() => value]),
+            ActionExecutionMetadata {
+                execution_kind,
+                timing: ActionExecutionTimingData {
+                    wall_time: start.elapsed(),
+                },
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use buck2_core::fs::project::ProjectRootTemp;
+
+    use super::*;
+    use crate::actions::impls::archive::testing::build_archive;
+    use crate::actions::impls::archive::testing::file;
+    use crate::actions::impls::archive::ArchiveEntry;
+    use crate::actions::impls::archive::ArchiveEntryKind;
+    use crate::actions::impls::archive::ArchiveFormat;
+
+    fn entry(path: &str, kind: ArchiveEntryKind) -> ArchiveEntry {
+        ArchiveEntry {
+            path: ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_archive_type() -> anyhow::Result<()> {
+        assert_eq!(
+            ArchiveType::TarGz,
+            ArchiveType::new(None, "https://example.com/foo-1.0.tar.gz?raw=1")?
+        );
+        assert_eq!(
+            ArchiveType::Tar,
+            ArchiveType::new(None, "https://example.com/foo.tar")?
+        );
+        assert_eq!(
+            ArchiveType::Zip,
+            ArchiveType::new(Some("zip"), "https://example.com/download")?
+        );
+        assert!(ArchiveType::new(None, "https://example.com/foozip").is_err());
+        assert!(ArchiveType::new(Some("rar"), "https://example.com/foo.zip").is_err());
+        Ok(())
+    }
+
+    fn extracted_file(contents: &str, is_executable: bool) -> ExtractedEntry {
+        let digest_config = DigestConfig::testing_default();
+        ExtractedEntry::File {
+            digest: TrackedFileDigest::from_content(
+                contents.as_bytes(),
+                digest_config.cas_digest_config(),
+            ),
+            is_executable,
+        }
+    }
+
+    #[test]
+    fn test_extract_strip_prefix() -> anyhow::Result<()> {
+        for format in [ArchiveFormat::TarGz, ArchiveFormat::Zip] {
+            let fs = ProjectRootTemp::new()?;
+            let root = fs.path().root().join(ForwardRelativePath::new("out")?);
+            let srcs = fs.path().root();
+            let archive = build_archive(
+                format,
+                &[
+                    entry("foo-1.0", ArchiveEntryKind::Directory),
+                    entry("foo-1.0/bin/run", file(srcs, "#!/bin/sh", true)?),
+                    entry("foo-1.0/empty", ArchiveEntryKind::Directory),
+                    entry("foo-1.0/README", file(srcs, "hello", false)?),
+                    entry("other", file(srcs, "dropped", false)?),
+                ],
+            )?;
+            let archive_type = match format {
+                ArchiveFormat::TarGz => ArchiveType::TarGz,
+                _ => ArchiveType::Zip,
+            };
+
+            let entries = extract(
+                archive_type,
+                Cursor::new(archive),
+                Some(ForwardRelativePath::new("foo-1.0")?),
+                &root,
+                DigestConfig::testing_default(),
+            )?;
+            assert_eq!(
+                vec![
+                    ("README", &extracted_file("hello", false)),
+                    ("bin/run", &extracted_file("#!/bin/sh", true)),
+                    ("empty", &ExtractedEntry::Directory),
+                ],
+                entries
+                    .iter()
+                    .map(|(path, entry)| (path.as_str(), entry))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                "hello",
+                fs_util::read_to_string(root.join(ForwardRelativePath::new("README")?))?
+            );
+            assert!(fs_util::try_exists(
+                root.join(ForwardRelativePath::new("empty")?)
+            )?);
+            assert!(!fs_util::try_exists(
+                root.join(ForwardRelativePath::new("other")?)
+            )?);
+
+            assert!(
+                extract(
+                    archive_type,
+                    Cursor::new(build_archive(
+                        format,
+                        &[entry("a", file(srcs, "a", false)?)]
+                    )?),
+                    Some(ForwardRelativePath::new("foo-1.0")?),
+                    &fs.path().root().join(ForwardRelativePath::new("missing")?),
+                    DigestConfig::testing_default(),
+                )
+                .is_err()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_links() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let root = fs.path().root();
+        let mut extractor = Extractor::new(root, None, DigestConfig::testing_default());
+
+        extractor.add(
+            "a/file",
+            EntryContents::File {
+                reader: &mut "hello".as_bytes(),
+                is_executable: false,
+            },
+        )?;
+        extractor.add("a/copy", EntryContents::HardLink("./a/file".to_owned()))?;
+        extractor.add("link", EntryContents::Symlink("a".to_owned()))?;
+        assert!(
+            extractor
+                .add("missing", EntryContents::HardLink("b".to_owned()))
+                .is_err()
+        );
+        assert!(
+            extractor
+                .add(
+                    "link/file",
+                    EntryContents::File {
+                        reader: &mut "overwritten".as_bytes(),
+                        is_executable: false,
+                    },
+                )
+                .is_err()
+        );
+
+        let entries = extractor.finish()?;
+        assert_eq!(
+            Some(&extracted_file("hello", false)),
+            entries.get(ForwardRelativePath::new("a/copy")?)
+        );
+        assert_eq!(
+            "hello",
+            fs_util::read_to_string(root.join(ForwardRelativePath::new("a/file")?))?
+        );
+        assert_eq!(
+            "hello",
+            fs_util::read_to_string(root.join(ForwardRelativePath::new("a/copy")?))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_path() -> anyhow::Result<()> {
+        assert_eq!(None, normalize_path("./")?);
+        assert_eq!(
+            Some(ForwardRelativePathBuf::unchecked_new("a/b".to_owned())),
+            normalize_path("./a//b/")?
+        );
+        assert!(normalize_path("/etc/passwd").is_err());
+        assert!(normalize_path("a/../../b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_symlink() {
+        let path = ForwardRelativePath::new("a/b/link").unwrap();
+        assert!(check_symlink(path, "target").is_ok());
+        assert!(check_symlink(path, "../../c").is_ok());
+        assert!(check_symlink(path, "../../../c").is_err());
+        assert!(check_symlink(path, "/etc/passwd").is_err());
+    }
+}
@@ -28,6 +28,7 @@ use buck2_common::file_ops::FileDigest;
 use buck2_common::file_ops::FileMetadata;
 use buck2_common::file_ops::TrackedFileDigest;
 use buck2_core::category::Category;
+use buck2_core::fs::fs_util;
 use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::command_executor::ActionExecutionTimingData;
@@ -35,6 +36,7 @@ use buck2_execute::materialize::http::http_client;
 use buck2_execute::materialize::http::http_download;
 use buck2_execute::materialize::http::http_head;
 use buck2_execute::materialize::http::Checksum;
+use buck2_execute::materialize::http::HttpClient;
 use buck2_execute::materialize::http::HttpError;
 use buck2_execute::materialize::materializer::HttpDownloadInfo;
 use dupe::Dupe;
@@ -57,6 +59,7 @@ enum DownloadFileActionError {
 pub(crate) struct UnregisteredDownloadFileAction {
     checksum: Checksum,
     url: Arc<str>,
+    mirrors: Arc<[Arc<str>]>,
     is_executable: bool,
     is_deferrable: bool,
 }
@@ -65,12 +68,14 @@ impl UnregisteredDownloadFileAction {
     pub(crate) fn new(
         checksum: Checksum,
         url: Arc<str>,
+        mirrors: Arc<[Arc<str>]>,
         is_executable: bool,
         is_deferrable: bool,
     ) -> Self {
         Self {
             checksum,
             url,
+            mirrors,
             is_executable,
             is_deferrable,
         }
@@ -128,7 +133,7 @@ impl DownloadFileAction {
     /// Try to produce a FileMetadata without downloading the file.
     async fn declared_metadata(
         &self,
-        client: &reqwest::Client,
+        client: &HttpClient,
         digest_config: DigestConfig,
     ) -> anyhow::Result<Option<FileMetadata>> {
         if !self.inner.is_deferrable {
@@ -149,7 +154,25 @@ impl DownloadFileAction {
             Err(_) => return Ok(None),
         };
 
-        let head = http_head(client, &self.inner.url).await?;
+        // A file in the download cache has a known size, so there is no need to ask the server.
+        if let Some(cached) = client
+            .download_cache()
+            .zip(self.inner.checksum.sha256())
+            .and_then(|(cache, sha256)| cache.get(sha256))
+        {
+            if let Ok(meta) = fs_util::metadata(&cached) {
+                let digest = TrackedFileDigest::new(
+                    FileDigest::new(sha1, meta.len()),
+                    digest_config.cas_digest_config(),
+                );
+                return Ok(Some(FileMetadata {
+                    digest,
+                    is_executable: self.inner.is_executable,
+                }));
+            }
+        }
+
+        let head = http_head(client, &self.inner.url, &self.inner.mirrors).await?;
 
         // NOTE: Don't use reqwest's content_length() method here, that always returns zero!
         // https://github.com/seanmonstar/reqwest/issues/843
@@ -243,6 +266,7 @@ impl IncrementalActionExecutable for DownloadFileAction {
                             rel_path,
                             HttpDownloadInfo {
                                 url: self.inner.url.dupe(),
+                                mirrors: self.inner.mirrors.dupe(),
                                 checksum: self.inner.checksum.dupe(),
                                 metadata: metadata.dupe(),
                                 owner: ctx.target().owner().dupe().into_dyn(),
@@ -266,6 +290,7 @@ impl IncrementalActionExecutable for DownloadFileAction {
                         ctx.digest_config(),
                         &rel_path,
                         &self.inner.url,
+                        &self.inner.mirrors,
                         &self.inner.checksum,
                         self.inner.is_executable,
                     )
@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::borrow::Cow;
+use std::slice;
+use std::time::Instant;
+
+use allocative::Allocative;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use buck2_build_api::actions::artifact::build_artifact::BuildArtifact;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionKind;
+use buck2_build_api::actions::execute::action_executor::ActionExecutionMetadata;
+use buck2_build_api::actions::execute::action_executor::ActionOutputs;
+use buck2_build_api::actions::Action;
+use buck2_build_api::actions::ActionExecutable;
+use buck2_build_api::actions::ActionExecutionCtx;
+use buck2_build_api::actions::IncrementalActionExecutable;
+use buck2_build_api::actions::UnregisteredAction;
+use buck2_build_api::artifact_groups::ArtifactGroup;
+use buck2_build_api::interpreter::rule_defs::cmd_args::DefaultCommandLineContext;
+use buck2_build_api::interpreter::rule_defs::cmd_args::ValueAsCommandLineLike;
+use buck2_core::category::Category;
+use buck2_core::fs::fs_util;
+use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
+use buck2_execute::artifact::fs::ExecutorFs;
+use buck2_execute::execute::command_executor::ActionExecutionTimingData;
+use buck2_execute::materialize::materializer::WriteRequest;
+use dupe::Dupe;
+use gazebo::prelude::*;
+use indexmap::indexmap;
+use indexmap::IndexMap;
+use indexmap::IndexSet;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use starlark::values::list::ListRef;
+use starlark::values::OwnedFrozenValue;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum ExpandTemplateActionValidationError {
+    #[error("ExpandTemplateAction must have exactly one input, the template")]
+    WrongNumberOfInputs,
+    #[error("ExpandTemplateAction must have exactly one output")]
+    WrongNumberOfOutputs,
+    #[error("Expected a list of command line values, got {0}")]
+    SubstitutionsNotCommandLineValues(String),
+    #[error("Substitution keys may not be empty")]
+    EmptyKey,
+}
+
+#[derive(Allocative)]
+pub(crate) struct UnregisteredExpandTemplateAction {
+    /// Keys of the substitutions, the values are in the Starlark data, in the same order.
+    keys: Vec<String>,
+    is_executable: bool,
+}
+
+impl UnregisteredExpandTemplateAction {
+    pub(crate) fn new(keys: Vec<String>, is_executable: bool) -> anyhow::Result<Self> {
+        if keys.iter().any(|k| k.is_empty()) {
+            return Err(ExpandTemplateActionValidationError::EmptyKey.into());
+        }
+        Ok(Self {
+            keys,
+            is_executable,
+        })
+    }
+}
+
+impl UnregisteredAction for UnregisteredExpandTemplateAction {
+    fn register(
+        self: Box<Self>,
+        inputs: IndexSet<ArtifactGroup>,
+        outputs: IndexSet<BuildArtifact>,
+        starlark_data: Option<OwnedFrozenValue>,
+    ) -> anyhow::Result<Box<dyn Action>> {
+        let values = starlark_data.expect("module data to be present");
+
+        let template = inputs
+            .into_iter()
+            .exactly_one()
+            .map_err(|_| ExpandTemplateActionValidationError::WrongNumberOfInputs)?;
+        let output = outputs
+            .into_iter()
+            .exactly_one()
+            .map_err(|_| ExpandTemplateActionValidationError::WrongNumberOfOutputs)?;
+
+        match ListRef::from_value(values.value()) {
+            Some(list)
+                if list.len() == self.keys.len()
+                    && list.iter().all(|v| v.as_command_line().is_some()) => {}
+            _ => {
+                return Err(
+                    ExpandTemplateActionValidationError::SubstitutionsNotCommandLineValues(
+                        values.value().to_repr(),
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        Ok(Box::new(ExpandTemplateAction {
+            keys: self.keys,
+            values,
+            is_executable: self.is_executable,
+            template,
+            output,
+        }))
+    }
+}
+
+#[derive(Debug, Allocative)]
+struct ExpandTemplateAction {
+    keys: Vec<String>,
+    values: OwnedFrozenValue, // List of StarlarkCommandLine
+    is_executable: bool,
+    template: ArtifactGroup,
+    output: BuildArtifact,
+}
+
+impl ExpandTemplateAction {
+    /// The substitutions, with each value rendered like a command line, arguments separated by
+    /// spaces.
+    fn get_substitutions(&self, fs: &ExecutorFs) -> anyhow::Result<Vec<(&str, String)>> {
+        let values = ListRef::from_value(self.values.value()).unwrap();
+        self.keys
+            .iter()
+            .zip(values.iter())
+            .map(|(key, value)| {
+                let mut cli = Vec::<String>::new();
+                let mut ctx = DefaultCommandLineContext::new(fs);
+                value
+                    .as_command_line()
+                    .unwrap()
+                    .add_to_command_line(&mut cli, &mut ctx)?;
+                Ok((key.as_str(), cli.join(" ")))
+            })
+            .collect()
+    }
+}
+
+/// Replace every occurrence of a key of `substitutions` in `template` with its value, in a single
+/// pass, so substituted values are never expanded again. Where several keys match at the same
+/// position the longest one wins.
+fn expand(template: &[u8], substitutions: &[(&str, String)]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(template.len());
+    let mut rest = template;
+    while !rest.is_empty() {
+        let matching = substitutions
+            .iter()
+            .filter(|(key, _)| rest.starts_with(key.as_bytes()))
+            .max_by_key(|(key, _)| key.len());
+        match matching {
+            Some((key, value)) => {
+                res.extend_from_slice(value.as_bytes());
+                rest = &rest[key.len()..];
+            }
+            None => {
+                res.push(rest[0]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    res
+}
+
+#[async_trait]
+impl Action for ExpandTemplateAction {
+    fn kind(&self) -> buck2_data::ActionKind {
+        buck2_data::ActionKind::ExpandTemplate
+    }
+
+    fn inputs(&self) -> anyhow::Result<Cow<'_, [ArtifactGroup]>> {
+        Ok(Cow::Borrowed(slice::from_ref(&self.template)))
+    }
+
+    fn outputs(&self) -> anyhow::Result<Cow<'_, [BuildArtifact]>> {
+        Ok(Cow::Borrowed(slice::from_ref(&self.output)))
+    }
+
+    fn as_executable(&self) -> ActionExecutable<'_> {
+        ActionExecutable::Incremental(self)
+    }
+
+    fn category(&self) -> &Category {
+        static EXPAND_TEMPLATE_CATEGORY: Lazy<Category> =
+            Lazy::new(|| Category::try_from("expand_template").unwrap());
+
+        &EXPAND_TEMPLATE_CATEGORY
+    }
+
+    fn identifier(&self) -> Option<&str> {
+        Some(self.output.get_path().path().as_str())
+    }
+
+    fn aquery_attributes(&self, fs: &ExecutorFs) -> IndexMap<String, String> {
+        indexmap! {
+            "template".to_owned() => self.template.to_string(),
+            "substitutions".to_owned() => match self.get_substitutions(fs) {
+                Ok(v) => v.iter().map(|(k, v)| format!("{}={}", k, v)).join("\n"),
+                Err(e) => format!("ERROR: constructing substitutions ({})", e)
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl IncrementalActionExecutable for ExpandTemplateAction {
+    async fn execute(
+        &self,
+        ctx: &mut dyn ActionExecutionCtx,
+    ) -> anyhow::Result<(ActionOutputs, ActionExecutionMetadata)> {
+        let fs = ctx.fs();
+
+        let (template, _) = ctx
+            .artifact_values(&self.template)
+            .iter()
+            .into_singleton()
+            .context("Template did not dereference to exactly one artifact")?;
+        let template_path = template.resolve_path(fs)?;
+        if !template.is_source() {
+            ctx.materializer()
+                .ensure_materialized(vec![template_path.clone()])
+                .await?;
+        }
+
+        let mut execution_start = None;
+
+        let value = ctx
+            .materializer()
+            .declare_write(Box::new(|| {
+                execution_start = Some(Instant::now());
+                let template = fs_util::read(fs.fs().resolve(&template_path))
+                    .context("Error reading template")?;
+                let substitutions = self.get_substitutions(&ctx.executor_fs())?;
+                Ok(vec![WriteRequest {
+                    path: fs.resolve_build(self.output.get_path()),
+                    content: expand(&template, &substitutions),
+                    is_executable: self.is_executable,
+                }])
+            }))
+            .await?
+            .into_iter()
+            .next()
+            .context("Expand template did not execute")?;
+
+        let wall_time = execution_start
+            .context("Action did not set execution_start")?
+            .elapsed();
+
+        Ok((
+            ActionOutputs::new(indexmap![self.output.get_path().dupe() => value]),
+            ActionExecutionMetadata {
+                execution_kind: ActionExecutionKind::Simple,
+                timing: ActionExecutionTimingData { wall_time },
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        fn expand_str(template: &str, substitutions: &[(&str, &str)]) -> String {
+            let substitutions: Vec<_> = substitutions
+                .iter()
+                .map(|(k, v)| (*k, (*v).to_owned()))
+                .collect();
+            String::from_utf8(expand(template.as_bytes(), &substitutions)).unwrap()
+        }
+
+        assert_eq!("", expand_str("", &[("{A}", "a")]));
+        assert_eq!("no keys", expand_str("no keys", &[("{A}", "a")]));
+        assert_eq!(
+            "a b a",
+            expand_str("{A} {B} {A}", &[("{A}", "a"), ("{B}", "b")])
+        );
+        // Substituted values are not expanded again.
+        assert_eq!(
+            "{B} b",
+            expand_str("{A} {B}", &[("{A}", "{B}"), ("{B}", "b")])
+        );
+        // The longest key wins.
+        assert_eq!(
+            "long",
+            expand_str("%NAME%", &[("%NAME", "short"), ("%NAME%", "long")])
+        );
+    }
+}
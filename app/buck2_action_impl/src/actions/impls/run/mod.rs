@@ -9,6 +9,7 @@
 
 use std::borrow::Cow;
 use std::fmt::Display;
+use std::time::Duration;
 
 use allocative::Allocative;
 use async_trait::async_trait;
@@ -43,6 +44,8 @@ use buck2_execute::execute::request::CommandExecutionOutput;
 use buck2_execute::execute::request::CommandExecutionPaths;
 use buck2_execute::execute::request::CommandExecutionRequest;
 use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::request::ResourceLimits;
+use buck2_execute::execute::request::RetryPolicy;
 use dupe::Dupe;
 use gazebo::prelude::*;
 use host_sharing::HostSharingRequirements;
@@ -144,6 +147,10 @@ pub(crate) struct UnregisteredRunAction {
     pub(crate) no_outputs_cleanup: bool,
     pub(crate) allow_cache_upload: bool,
     pub(crate) force_full_hybrid_if_capable: bool,
+    pub(crate) resource_limits: ResourceLimits,
+    pub(crate) memory_estimate: Option<u64>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry_policy: RetryPolicy,
 }
 
 impl UnregisteredAction for UnregisteredRunAction {
@@ -394,7 +401,7 @@ impl Action for RunAction {
         cli.add_to_command_line(&mut cli_rendered, &mut ctx)
             .unwrap();
         let cmd = format!("[{}]", cli_rendered.iter().join(", "));
-        indexmap! {
+        let mut attrs = indexmap! {
             "cmd".to_owned() => cmd,
             "executor_preference".to_owned() => self.inner.executor_preference.to_string(),
             "always_print_stderr".to_owned() => self.inner.always_print_stderr.to_string(),
@@ -405,7 +412,19 @@ impl Action for RunAction {
                 Some(x) => x.to_string(),
             },
             "no_outputs_cleanup".to_owned() => self.inner.no_outputs_cleanup.to_string(),
+            "timeout".to_owned() => match self.inner.timeout {
+                None => "None".to_owned(),
+                Some(x) => format!("{:?}", x),
+            },
+            "retry_policy".to_owned() => self.inner.retry_policy.to_string(),
+        };
+        if !self.inner.resource_limits.is_empty() {
+            attrs.insert(
+                "resource_limits".to_owned(),
+                self.inner.resource_limits.to_string(),
+            );
         }
+        attrs
     }
 }
 
@@ -489,7 +508,14 @@ impl IncrementalActionExecutable for RunAction {
             .with_allow_cache_upload(self.inner.allow_cache_upload)
             .with_local_environment_inheritance(EnvironmentInheritance::local_command_exclusions())
             .with_force_full_hybrid_if_capable(self.inner.force_full_hybrid_if_capable)
+            .with_resource_limits(self.inner.resource_limits)
+            .with_memory_estimate(self.inner.memory_estimate)
+            .with_retry_policy(self.inner.retry_policy.clone())
             .with_custom_tmpdir(ctx.target().custom_tmpdir());
+        let req = match self.inner.timeout {
+            Some(timeout) => req.with_timeout(timeout),
+            None => req,
+        };
 
         let (outputs, meta) = ctx.exec_cmd(&req).await?;
 
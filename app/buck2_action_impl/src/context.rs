@@ -10,6 +10,7 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use buck2_build_api::actions::artifact::artifact_type::OutputArtifact;
@@ -36,6 +37,8 @@ use buck2_core::category::Category;
 use buck2_core::collections::ordered_set::OrderedSet;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
 use buck2_execute::execute::request::OutputType;
+use buck2_execute::execute::request::ResourceLimits;
+use buck2_execute::execute::request::RetryPolicy;
 use buck2_execute::materialize::http::Checksum;
 use buck2_interpreter::starlark_promise::StarlarkPromise;
 use buck2_interpreter_for_build::rule::FrozenRuleCallable;
@@ -66,12 +69,17 @@ use starlark::values::ValueTyped;
 use starlark_map::small_map::SmallMap;
 use starlark_map::small_set::SmallSet;
 
+use crate::actions::impls::archive::ArchiveFormat;
+use crate::actions::impls::archive::UnregisteredArchiveAction;
 use crate::actions::impls::cas_artifact::ArtifactKind;
 use crate::actions::impls::cas_artifact::DirectoryKind;
 use crate::actions::impls::cas_artifact::UnregisteredCasArtifactAction;
 use crate::actions::impls::copy::CopyMode;
 use crate::actions::impls::copy::UnregisteredCopyAction;
+use crate::actions::impls::download_archive::ArchiveType;
+use crate::actions::impls::download_archive::UnregisteredDownloadArchiveAction;
 use crate::actions::impls::download_file::UnregisteredDownloadFileAction;
+use crate::actions::impls::expand_template::UnregisteredExpandTemplateAction;
 use crate::actions::impls::run::dep_files::RunActionDepFiles;
 use crate::actions::impls::run::new_executor_preference;
 use crate::actions::impls::run::MetadataParameter;
@@ -131,6 +139,12 @@ enum RunActionError {
         "Recursion limit exceeded when visiting artifacts: do you have a cycle in your inputs or ouputs?"
     )]
     ArtifactVisitRecursionLimitExceeded,
+    #[error("`{0}` must be a positive integer")]
+    InvalidResourceLimit(&'static str),
+    #[error("`timeout_seconds` must be a positive integer")]
+    InvalidTimeout,
+    #[error("`retries` has no effect without `retry_on_exit_codes` or `retry_on_infra_errors`")]
+    RetriesWithoutConditions,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -162,6 +176,24 @@ fn create_dir_tree<'v>(
     Ok(value)
 }
 
+fn create_archive<'v>(
+    eval: &mut Evaluator<'v, '_>,
+    this: &AnalysisActions<'v>,
+    output: Value<'v>,
+    srcs: Value<'v>,
+    format: ArchiveFormat,
+) -> anyhow::Result<Value<'v>> {
+    let action = UnregisteredArchiveAction::new(format, srcs)?;
+    let inputs = action.inputs();
+
+    let mut this = this.state();
+    let (declaration, output_artifact) =
+        this.get_or_declare_output(eval, output, "output", OutputType::File)?;
+    this.register_action(inputs, indexset![output_artifact], action, None)?;
+
+    Ok(declaration.into_declared_artifact(Default::default()))
+}
+
 fn copy_file<'v>(
     eval: &mut Evaluator<'v, '_>,
     this: &AnalysisActions<'v>,
@@ -524,6 +556,80 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
         create_dir_tree(eval, this, output, srcs, true)
     }
 
+    /// Returns an `artifact` whose contents are those of the `template` artifact, with each occurrence of a key of `substitutions` replaced by its value.
+    /// Values may be strings, artifacts (rendered as paths relative to the project root) or anything convertible to `cmd_args`, rendered as for a command line with arguments separated by spaces.
+    /// Substitutions are applied in a single pass, so substituted values are never expanded again; where several keys match at the same position, the longest one wins.
+    fn expand_template<'v>(
+        this: &AnalysisActions<'v>,
+        #[starlark(require = pos)] output: Value<'v>,
+        #[starlark(require = pos)] template: Value<'v>,
+        #[starlark(require = named, default = SmallMap::new())] substitutions: SmallMap<
+            &'v str,
+            Value<'v>,
+        >,
+        #[starlark(require = named, default = false)] is_executable: bool,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let template = template
+            .as_artifact()
+            .ok_or_else(|| ValueError::IncorrectParameterTypeNamed("template".to_owned()))?
+            .get_bound_artifact()?;
+
+        let mut keys = Vec::with_capacity(substitutions.len());
+        let mut values = Vec::with_capacity(substitutions.len());
+        for (key, value) in substitutions {
+            keys.push(key.to_owned());
+            values.push(if value.as_command_line().is_some() {
+                value
+            } else {
+                eval.heap()
+                    .alloc(StarlarkCommandLine::try_from_value(value)?)
+            });
+        }
+        let action = UnregisteredExpandTemplateAction::new(keys, is_executable)?;
+
+        let mut this = this.state();
+        let (declaration, output_artifact) =
+            this.get_or_declare_output(eval, output, "output", OutputType::File)?;
+        this.register_action(
+            indexset![ArtifactGroup::Artifact(template)],
+            indexset![output_artifact],
+            action,
+            Some(eval.heap().alloc(values)),
+        )?;
+
+        Ok(declaration.into_declared_artifact(Default::default()))
+    }
+
+    /// Returns an `artifact` which is a tar archive, created without running a command.
+    /// The srcs must be a dictionary of path (as string, relative to the root of the archive) to bound `artifact`, which may be a file or a directory.
+    /// The archive is deterministic: entries are sorted, owners and timestamps are zeroed, and files have mode 0644 or, if executable, 0755.
+    /// Symlinks within the srcs are kept as symlinks.
+    ///
+    /// * `compression`: `"gz"` or `"zstd"` to compress the archive, uncompressed if not set
+    fn tar<'v>(
+        this: &AnalysisActions<'v>,
+        #[starlark(require = pos)] output: Value<'v>,
+        #[starlark(require = pos)] srcs: Value<'v>,
+        #[starlark(require = named, default = NoneOr::None)] compression: NoneOr<&str>,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let format = ArchiveFormat::tar(compression.into_option())?;
+        create_archive(eval, this, output, srcs, format)
+    }
+
+    /// Returns an `artifact` which is a zip archive, created without running a command.
+    /// The srcs are as for `tar`, and the archive is normalized the same way, except that timestamps are set to 1980-01-01, the earliest zip supports.
+    /// Symlinks are not supported in zip archives.
+    fn zip<'v>(
+        this: &AnalysisActions<'v>,
+        #[starlark(require = pos)] output: Value<'v>,
+        #[starlark(require = pos)] srcs: Value<'v>,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        create_archive(eval, this, output, srcs, ArchiveFormat::Zip)
+    }
+
     /// Runs a command
     ///
     /// * `arguments`: must be of type `cmd_args`, or a type convertible to such (such as a list of strings and artifacts) and must contain at least one `.as_output()` artifact
@@ -534,6 +640,10 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
     ///     * `metadata_path`: defines a path relative to the result directory for a file with action metadata, which will be created right before the command will be run.
     ///     * Metadata contains the path relative to the Buck2 project root and hash digest for every action input (this excludes symlinks as they could be resolved by a user script if needed). The resolved path relative to the Buck2 project for the metadata file will be passed to command from arguments, via the environment variable, with its name set by `metadata_env_var`
     ///     * Both `metadata_env_var` and `metadata_path` are useful when making actions behave in an incremental manner (for details, see [Incremental Actions](https://buck2.build/docs/rule_authors/incremental_actions/))
+    /// * `memory_limit`, `cpu_limit_millicores` and `pids_limit`: limits on the memory (in bytes), CPU (1000 millicores is one CPU) and number of processes the command may use when it runs locally. They are only enforced on Linux when `buck2.forkserver_cgroups` is set, in which case a command that exceeds its memory limit is OOM-killed
+    /// * `timeout_seconds`: how long the command may run for, locally or on RE, before it is killed and the action fails
+    /// * `retries`: how many times to run the command again if it fails with one of `retry_on_exit_codes`, or, if `retry_on_infra_errors` is set, if it could not be run at all (e.g. because of an RE error). Every attempt is recorded in the action's events, so `buck2 log what-ran` shows them
+    /// * `memory_estimate`: how much memory (in bytes) the command is expected to use. When `build.local_memory_fraction` is set, local commands are only started if their estimated footprint fits in that fraction of system memory. Defaults to `memory_limit`, or to the peak memory observed for previous runs of this action
     fn run<'v>(
         this: &AnalysisActions<'v>,
         #[starlark(require = pos)] arguments: Value<'v>,
@@ -553,6 +663,14 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
         #[starlark(require = named, default = false)] no_outputs_cleanup: bool,
         #[starlark(require = named, default = false)] allow_cache_upload: bool,
         #[starlark(require = named, default = false)] force_full_hybrid_if_capable: bool,
+        #[starlark(require = named)] memory_limit: Option<u64>,
+        #[starlark(require = named)] cpu_limit_millicores: Option<u64>,
+        #[starlark(require = named)] pids_limit: Option<u64>,
+        #[starlark(require = named)] memory_estimate: Option<u64>,
+        #[starlark(require = named)] timeout_seconds: Option<u64>,
+        #[starlark(require = named, default = 0)] retries: u32,
+        #[starlark(require = named, default = Vec::new())] retry_on_exit_codes: Vec<i32>,
+        #[starlark(require = named, default = false)] retry_on_infra_errors: bool,
         eval: &mut Evaluator<'v, '_>,
     ) -> anyhow::Result<NoneType> {
         struct RunCommandArtifactVisitor {
@@ -627,6 +745,34 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
             }
         };
 
+        for (name, limit) in [
+            ("memory_limit", memory_limit),
+            ("cpu_limit_millicores", cpu_limit_millicores),
+            ("pids_limit", pids_limit),
+            ("memory_estimate", memory_estimate),
+        ] {
+            if limit == Some(0) {
+                return Err(RunActionError::InvalidResourceLimit(name).into());
+            }
+        }
+        let resource_limits = ResourceLimits {
+            memory_max_bytes: memory_limit,
+            cpu_max_millicores: cpu_limit_millicores,
+            pids_max: pids_limit,
+        };
+
+        if timeout_seconds == Some(0) {
+            return Err(RunActionError::InvalidTimeout.into());
+        }
+        if retries > 0 && retry_on_exit_codes.is_empty() && !retry_on_infra_errors {
+            return Err(RunActionError::RetriesWithoutConditions.into());
+        }
+        let retry_policy = RetryPolicy {
+            max_retries: retries,
+            exit_codes: retry_on_exit_codes,
+            infra_errors: retry_on_infra_errors,
+        };
+
         let starlark_env = match env {
             None => Value::new_none(),
             Some(env) => {
@@ -711,6 +857,10 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
             no_outputs_cleanup,
             allow_cache_upload,
             force_full_hybrid_if_capable,
+            resource_limits,
+            memory_estimate,
+            timeout: timeout_seconds.map(Duration::from_secs),
+            retry_policy,
         };
         this.state().register_action(
             artifacts.inputs,
@@ -724,10 +874,12 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
     /// Downloads a URL to an output (filename as string or output artifact).
     /// The file at the URL must have the given sha1 or the command will fail.
     /// The optional parameter is_executable indicates whether the resulting file should be marked with executable permissions.
+    /// The optional parameter mirrors lists URLs to try, in order, if downloading from url fails.
     fn download_file<'v>(
         this: &AnalysisActions<'v>,
         #[starlark(require = pos)] output: Value<'v>,
         #[starlark(require = pos)] url: &str,
+        #[starlark(require = named, default = Vec::new())] mirrors: Vec<&str>,
         #[starlark(require = named, default = NoneOr::None)] sha1: NoneOr<&str>,
         #[starlark(require = named, default = NoneOr::None)] sha256: NoneOr<&str>,
         #[starlark(require = named, default = false)] is_executable: bool,
@@ -754,6 +906,7 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
             UnregisteredDownloadFileAction::new(
                 checksum,
                 Arc::from(url),
+                mirrors.into_iter().map(Arc::from).collect(),
                 is_executable,
                 is_deferrable,
             ),
@@ -764,6 +917,51 @@ fn register_context_actions(builder: &mut MethodsBuilder) {
         Ok(value)
     }
 
+    /// Downloads an archive from a URL and extracts it into an output directory.
+    /// The archive must have the given sha256 or the command will fail.
+    /// The optional parameter mirrors lists URLs to try, in order, if downloading from url fails.
+    /// The optional parameter strip_prefix is a directory in the archive whose contents are extracted, entries outside of it are dropped.
+    /// The optional parameter archive_type is one of `tar`, `tar.gz`, `tar.xz`, `tar.zst` or `zip`, and is inferred from the URL when not set.
+    /// The optional parameter is_deferrable indicates that, when the action runs with RE, the extracted directory should only be uploaded to the CAS, and only materialized locally when needed.
+    fn download_archive<'v>(
+        this: &AnalysisActions<'v>,
+        #[starlark(require = pos)] output: Value<'v>,
+        #[starlark(require = pos)] url: &str,
+        #[starlark(require = named)] sha256: &str,
+        #[starlark(require = named, default = Vec::new())] mirrors: Vec<&str>,
+        #[starlark(require = named, default = NoneOr::None)] strip_prefix: NoneOr<&str>,
+        #[starlark(require = named, default = NoneOr::None)] archive_type: NoneOr<&str>,
+        #[starlark(require = named, default = false)] is_deferrable: bool,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let mut this = this.state();
+        let (declaration, output_artifact) =
+            this.get_or_declare_output(eval, output, "output", OutputType::Directory)?;
+
+        let archive_type = ArchiveType::new(archive_type.into_option(), url)?;
+        let strip_prefix = strip_prefix
+            .into_option()
+            .map(|p| ForwardRelativePathBuf::try_from(p.trim_end_matches('/').to_owned()))
+            .transpose()?;
+
+        this.register_action(
+            IndexSet::new(),
+            indexset![output_artifact],
+            UnregisteredDownloadArchiveAction::new(
+                Checksum::Sha256(Arc::from(sha256)),
+                Arc::from(url),
+                mirrors.into_iter().map(Arc::from).collect(),
+                archive_type,
+                strip_prefix,
+                is_deferrable,
+            ),
+            None,
+        )?;
+
+        let value = declaration.into_declared_artifact(Default::default());
+        Ok(value)
+    }
+
     /// Downloads a CAS artifact to an output
     ///
     /// * `digest`: must look like `SHA1:SIZE`
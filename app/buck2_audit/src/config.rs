@@ -16,9 +16,12 @@ use buck2_cli_proto::ClientContext;
 use buck2_client_ctx::common::CommonCommandOptions;
 use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::schema::ConfigSchema;
+use buck2_common::legacy_configs::schema::ConfigSchemaOrigin;
 use buck2_common::legacy_configs::LegacyBuckConfigLocation;
 use buck2_common::legacy_configs::LegacyBuckConfigValue;
 use buck2_core::cells::name::CellName;
+use buck2_interpreter_for_build::interpreter::config_schema::HasConfigSchema;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::ctx::ServerCommandDiceContext;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
@@ -102,6 +105,12 @@ pub struct AuditConfigCommand {
     #[clap(long = "value", default_value = "resolved", possible_values=&["resolved", "raw", "both"])]
     value_style: ValueStyle,
 
+    #[clap(
+        long,
+        help = "Print the type, default and documentation of known buckconfig keys instead of their values. That includes keys declared from Starlark while loading the prelude, the root import of the cell, and the files they load, which are evaluated for this. When buckconfigs are loaded, values of keys declared by buck2 itself are checked against their type, and unknown keys are only warned about when they are near-misses of those."
+    )]
+    schema: bool,
+
     #[clap(
         name = "SPECS",
         help = "config section/key specs of the form `section` or `section.key`. If any specs are provided, only values matching a spec will be printed (section headers will be printed only for sections with a key matching the spec)."
@@ -180,6 +189,57 @@ fn print_value(
     Ok(())
 }
 
+fn print_schema(
+    writer: &mut impl Write,
+    schema: &ConfigSchema,
+    format: OutputFormat,
+    filter: impl Fn(&str, &str) -> bool,
+) -> anyhow::Result<()> {
+    let keys = schema.iter().filter(|k| filter(&k.section, &k.key));
+    match format {
+        OutputFormat::Json => writeln!(
+            writer,
+            "{}",
+            json!(
+                keys.map(|k| json!({
+                    "section": k.section,
+                    "key": k.key,
+                    "type": k.ty,
+                    "default": k.default,
+                    "doc": k.doc,
+                    "origin": match k.origin {
+                        ConfigSchemaOrigin::Builtin => "builtin",
+                        ConfigSchemaOrigin::Starlark => "starlark",
+                    },
+                    "daemon_startup": k.daemon_startup,
+                }))
+                .collect::<Vec<_>>()
+            )
+        )?,
+        OutputFormat::Simple => {
+            let mut section = None;
+            for k in keys {
+                if section != Some(&k.section) {
+                    writeln!(writer, "[{}]", k.section)?;
+                    section = Some(&k.section);
+                }
+                match &k.default {
+                    Some(default) => writeln!(writer, "    {}: {} = {}", k.key, k.ty, default)?,
+                    None => writeln!(writer, "    {}: {}", k.key, k.ty)?,
+                }
+                if !k.doc.is_empty() {
+                    writeln!(writer, "        {}", k.doc)?;
+                }
+                if k.daemon_startup {
+                    writeln!(writer, "        Only read when the daemon starts.")?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl AuditConfigCommand {
     fn output_format(&self) -> OutputFormat {
         if let Some(format) = &self.output_format {
@@ -255,6 +315,16 @@ impl AuditSubcommand for AuditConfigCommand {
 
                 let mut stdout = stdout.as_writer();
 
+                if self.schema {
+                    let schema = ctx.get_config_schema(resolved_relevant_cell).await?;
+                    return print_schema(
+                        &mut stdout,
+                        &schema,
+                        self.output_format(),
+                        |section, key| filter(resolved_relevant_cell, section, key).is_some(),
+                    );
+                }
+
                 match self.output_format() {
                     OutputFormat::Json => writeln!(
                         &mut stdout,
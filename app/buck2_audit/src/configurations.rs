@@ -13,6 +13,7 @@ use async_trait::async_trait;
 use buck2_cli_proto::ClientContext;
 use buck2_client_ctx::common::CommonCommandOptions;
 use buck2_core::configuration::bound_id::BoundConfigurationId;
+use buck2_core::configuration::cfg_diff::cfg_diff;
 use buck2_core::configuration::data::ConfigurationData;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
@@ -35,6 +36,23 @@ pub struct AuditConfigurationsCommand {
         help = "configurations to audit (example: `cell//package:target-105fe3389fc7e436`). If none provided, will print information about all known configurations."
     )]
     configs: Vec<String>,
+
+    /// Print the constraints that differ between the two given configurations, instead of their
+    /// constraints.
+    ///
+    /// A configuration is only its constraints: buckconfig values are the same in every
+    /// configuration, so are not part of the diff. To see which `select()` keys (including
+    /// buckconfig-based `config_setting`s) match differently for a target, and which transition
+    /// or dependency edge introduced each of its configurations, use
+    /// `buck2 cquery --explain-configurations`.
+    #[clap(long)]
+    diff: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum AuditConfigurationsError {
+    #[error("`--diff` requires exactly two configurations, got {0}")]
+    DiffArgs(usize),
 }
 
 #[async_trait]
@@ -47,7 +65,18 @@ impl AuditSubcommand for AuditConfigurationsCommand {
     ) -> anyhow::Result<()> {
         let mut stdout = stdout.as_writer();
 
-        if self.configs.is_empty() {
+        if self.diff {
+            let (a, b) = match self.configs.as_slice() {
+                [a, b] => (a, b),
+                configs => return Err(AuditConfigurationsError::DiffArgs(configs.len()).into()),
+            };
+            let a = ConfigurationData::lookup_bound(BoundConfigurationId::parse(a)?)?;
+            let b = ConfigurationData::lookup_bound(BoundConfigurationId::parse(b)?)?;
+            match cfg_diff(&a, &b) {
+                Ok(()) => writeln!(stdout, "Configurations are equal")?,
+                Err(diff) => write!(stdout, "{}", diff)?,
+            }
+        } else if self.configs.is_empty() {
             for cfg in ConfigurationData::iter_existing()
                 .filter(|c| c.is_bound())
                 .sorted_by_cached_key(|c| c.full_name().to_owned())
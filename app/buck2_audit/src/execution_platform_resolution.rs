@@ -13,15 +13,22 @@ use async_trait::async_trait;
 use buck2_build_api::calculation::load_patterns;
 use buck2_build_api::calculation::Calculation;
 use buck2_build_api::calculation::MissingTargetBehavior;
+use buck2_build_api::nodes::calculation::get_execution_platform_resolution;
 use buck2_build_api::nodes::calculation::NodeCalculation;
 use buck2_cli_proto::ClientContext;
 use buck2_client_ctx::common::CommonCommandOptions;
+use buck2_common::result::recursive_shared_downcast_ref;
 use buck2_core::configuration::bound_id::BoundConfigurationId;
 use buck2_core::configuration::data::ConfigurationData;
 use buck2_core::pattern::pattern_type::ConfigurationPredicate;
 use buck2_core::pattern::pattern_type::ConfiguredTargetPatternExtra;
 use buck2_core::pattern::ParsedPattern;
+use buck2_core::target::label::ConfiguredTargetLabel;
 use buck2_core::target::label::TargetLabel;
+use buck2_node::configuration::execution::ExecutionPlatformError;
+use buck2_node::configuration::execution::ExecutionPlatformIncompatibleReason;
+use buck2_node::configuration::execution::ExecutionPlatformRejection;
+use buck2_node::configuration::execution::ExecutionPlatformResolution;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::ctx::ServerCommandDiceContext;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
@@ -52,6 +59,114 @@ pub struct AuditExecutionPlatformResolutionCommand {
 
     #[clap(name = "TARGET_PATTERNS", help = "Patterns to analyze")]
     patterns: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Print the resolution as JSON, with an entry for each skipped platform naming the attribute and the constraint that ruled it out"
+    )]
+    json: bool,
+}
+
+/// The execution platform resolution of a target, and why the platforms considered before the
+/// chosen one (or all of them, if none was chosen) were skipped.
+#[derive(serde::Serialize)]
+struct ResolutionReport {
+    target: String,
+    platform: Option<String>,
+    platform_configuration: Option<String>,
+    /// Set when no platform could be chosen.
+    error: Option<String>,
+    exec_deps: Vec<String>,
+    toolchain_deps: Vec<String>,
+    skipped: Vec<ExecutionPlatformRejection>,
+}
+
+impl ResolutionReport {
+    fn new(target: &ConfiguredTargetLabel, resolution: &ExecutionPlatformResolution) -> Self {
+        let (platform, platform_configuration, error) = match resolution.platform() {
+            Ok(platform) => (Some(platform.id()), Some(platform.cfg().to_string()), None),
+            Err(e) => (None, None, Some(format!("{:#}", e))),
+        };
+        ResolutionReport {
+            target: target.to_string(),
+            platform,
+            platform_configuration,
+            error,
+            exec_deps: Vec::new(),
+            toolchain_deps: Vec::new(),
+            skipped: rejections(resolution.skipped()),
+        }
+    }
+
+    fn failed(target: &ConfiguredTargetLabel, error: &ExecutionPlatformError) -> Self {
+        let skipped = match error {
+            ExecutionPlatformError::NoCompatiblePlatform(skipped) => rejections(skipped),
+        };
+        ResolutionReport {
+            target: target.to_string(),
+            platform: None,
+            platform_configuration: None,
+            error: Some("No compatible execution platform".to_owned()),
+            exec_deps: Vec::new(),
+            toolchain_deps: Vec::new(),
+            skipped,
+        }
+    }
+
+    fn print(&self, mut stdout: impl Write) -> anyhow::Result<()> {
+        writeln!(stdout, "{}:", self.target)?;
+        match (&self.platform, &self.error) {
+            (Some(platform), _) => {
+                writeln!(stdout, "  Execution platform: {}", platform)?;
+                if let Some(cfg) = &self.platform_configuration {
+                    writeln!(stdout, "    Execution platform configuration: {}", cfg)?;
+                }
+                writeln!(stdout, "    Execution deps:")?;
+                for execution_dep in &self.exec_deps {
+                    writeln!(stdout, "      {}", execution_dep)?;
+                }
+                writeln!(stdout, "    Toolchain deps:")?;
+                for toolchain_dep in &self.toolchain_deps {
+                    writeln!(stdout, "      {}", toolchain_dep)?;
+                }
+            }
+            (None, error) => writeln!(
+                stdout,
+                "  {}",
+                error.as_deref().unwrap_or("No execution platform")
+            )?,
+        }
+        for rejection in &self.skipped {
+            writeln!(stdout, "    Skipped {}", rejection.platform)?;
+            writeln!(stdout, "      Attribute: {}", rejection.attribute)?;
+            if let Some(dependency) = &rejection.dependency {
+                writeln!(stdout, "      Dependency: {}", dependency)?;
+            }
+            match &rejection.constraint_required_by {
+                Some(required_by) => writeln!(
+                    stdout,
+                    "      Constraint: {} (required by {})",
+                    rejection.constraint, required_by
+                )?,
+                None => writeln!(stdout, "      Constraint: {}", rejection.constraint)?,
+            }
+            writeln!(
+                IndentWriter::new("      ", &mut stdout),
+                "{}",
+                rejection.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn rejections(
+    skipped: &[(String, ExecutionPlatformIncompatibleReason)],
+) -> Vec<ExecutionPlatformRejection> {
+    skipped
+        .iter()
+        .map(|(platform, reason)| reason.rejection(platform))
+        .collect()
 }
 
 #[async_trait]
@@ -109,29 +224,51 @@ impl AuditSubcommand for AuditExecutionPlatformResolutionCommand {
 
                 let mut stdout = stdout.as_writer();
 
+                let mut reports = Vec::new();
                 for configured_target in configured_patterns {
-                    let configured_node = ctx.get_configured_target_node(&configured_target).await?;
-                    let configured_node = configured_node.require_compatible()?;
-                    writeln!(stdout, "{}:", configured_target)?;
-                    let resolution = configured_node.execution_platform_resolution();
-                    match resolution.platform() {
-                        Ok(platform) => {
-                            writeln!(stdout, "  Execution platform: {}", platform.id())?;
-                            writeln!(stdout, "    Execution platform configuration: {}", platform.cfg())?;
-                            writeln!(stdout, "    Execution deps:")?;
-                            for execution_dep in configured_node.exec_deps() {
-                                writeln!(stdout, "      {}", execution_dep.label())?;
-                            }
-                            writeln!(stdout, "    Toolchain deps:")?;
-                            for toolchain_dep in configured_node.toolchain_deps() {
-                                writeln!(stdout, "      {}", toolchain_dep.label())?;
-                            }
-                            for (label, reason) in resolution.skipped() {
-                                writeln!(stdout, "    Skipped {}", label)?;
-                                writeln!(IndentWriter::new("      ", &mut stdout), "{:#}", reason)?;
+                    let report = match ctx.get_configured_target_node(&configured_target).await {
+                        Ok(configured_node) => {
+                            let configured_node = configured_node.require_compatible()?;
+                            let mut report = ResolutionReport::new(
+                                &configured_target,
+                                configured_node.execution_platform_resolution(),
+                            );
+                            report.exec_deps = configured_node
+                                .exec_deps()
+                                .map(|d| d.label().to_string())
+                                .collect();
+                            report.toolchain_deps = configured_node
+                                .toolchain_deps()
+                                .map(|d| d.label().to_string())
+                                .collect();
+                            report
+                        }
+                        Err(e) => {
+                            // Resolve the platform on its own to tell whether it is what failed,
+                            // and why each candidate was skipped.
+                            match get_execution_platform_resolution(&ctx, &configured_target).await {
+                                Ok(resolution) if resolution.platform().is_err() => {
+                                    ResolutionReport::new(&configured_target, &resolution)
+                                }
+                                Err(resolution_error) => {
+                                    match recursive_shared_downcast_ref::<ExecutionPlatformError>(&resolution_error) {
+                                        Some(error) => ResolutionReport::failed(&configured_target, error),
+                                        None => return Err(e),
+                                    }
+                                }
+                                Ok(_) => return Err(e),
                             }
                         }
-                        Err(e) => writeln!(stdout, "{}", e)?,
+                    };
+                    reports.push(report);
+                }
+
+                if self.json {
+                    serde_json::to_writer_pretty(&mut stdout, &reports)?;
+                    writeln!(stdout)?;
+                } else {
+                    for report in &reports {
+                        report.print(&mut stdout)?;
                     }
                 }
 
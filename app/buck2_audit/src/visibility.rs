@@ -43,7 +43,8 @@ enum VisibilityCommandError {
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
 #[clap(
     name = "audit-visibility",
-    about = "Verify the visibility for transitive deps of the specified target(s) on the unconfigured target graph"
+    about = "Verify the visibility for transitive deps of the specified target(s) on the unconfigured target graph",
+    long_about = "Verify the visibility for transitive deps of the specified target(s) on the unconfigured target graph.\n\nA dep must be visible to the target depending on it, and within the view of that target's package. Targets that don't set `visibility` get the default `visibility` of their `PACKAGE` files, and `within_view` of `PACKAGE` files restricts what the targets below them may depend on."
 )]
 pub struct AuditVisibilityCommand {
     #[clap(flatten)]
@@ -99,6 +100,11 @@ impl AuditVisibilityCommand {
                                 dep.dupe(),
                                 target.label().dupe(),
                             ));
+                        } else if !target.is_within_view(dep) {
+                            visibility_errors.push(VisibilityError::NotWithinView(
+                                dep.dupe(),
+                                target.label().dupe(),
+                            ));
                         }
                     }
                     None => {
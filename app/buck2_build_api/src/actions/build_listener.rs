@@ -230,6 +230,74 @@ fn extract_critical_path<TKey: Hash + Eq, TValue>(
     path
 }
 
+/// Describe a node for the event log. Returns `None` for nodes we don't display.
+fn critical_path_entry(
+    key: &NodeKey,
+    data: &NodeData,
+) -> Option<buck2_data::critical_path_entry2::Entry> {
+    let entry = match key {
+        NodeKey::ActionKey(action_key) => {
+            let owner = match action_key.owner() {
+                BaseDeferredKey::TargetLabel(t) => t.as_proto().into(),
+                BaseDeferredKey::AnonTarget(t) => t.as_proto().into(),
+                BaseDeferredKey::BxlLabel(t) => t.as_proto().into(),
+            };
+
+            // If we have a NodeKey that's an ActionKey we'd expect to have an `action`
+            // in our data.
+            let action = data.action.as_ref()?;
+
+            buck2_data::critical_path_entry2::ActionExecution {
+                owner: Some(owner),
+                name: Some(buck2_data::ActionName {
+                    category: action.category().as_str().to_owned(),
+                    identifier: action.identifier().unwrap_or("").to_owned(),
+                }),
+            }
+            .into()
+        }
+        NodeKey::Analysis(key) => buck2_data::critical_path_entry2::Analysis {
+            target: Some(key.as_proto().into()),
+        }
+        .into(),
+        NodeKey::Materialization(key) => {
+            let owner = match key.key().owner() {
+                BaseDeferredKey::TargetLabel(t) => t.as_proto().into(),
+                BaseDeferredKey::AnonTarget(t) => t.as_proto().into(),
+                BaseDeferredKey::BxlLabel(t) => t.as_proto().into(),
+            };
+
+            buck2_data::critical_path_entry2::Materialization {
+                owner: Some(owner),
+                path: key.get_path().path().to_string(),
+            }
+            .into()
+        }
+        NodeKey::Load(package) => buck2_data::critical_path_entry2::Load {
+            package: package.to_string(),
+        }
+        .into(),
+        NodeKey::TransitiveSetProjection(..) => return None,
+    };
+
+    Some(entry)
+}
+
+fn critical_path_entry2(
+    entry: Option<buck2_data::critical_path_entry2::Entry>,
+    data: &NodeData,
+    potential_improvement: Option<Duration>,
+) -> anyhow::Result<buck2_data::CriticalPathEntry2> {
+    Ok(buck2_data::CriticalPathEntry2 {
+        span_id: data.span_id.map(|span_id| span_id.into()),
+        duration: Some(data.duration.critical_path_duration().try_into()?),
+        user_duration: Some(data.duration.user.try_into()?),
+        total_duration: Some(data.duration.total.try_into()?),
+        potential_improvement_duration: potential_improvement.map(|p| p.try_into()).transpose()?,
+        entry,
+    })
+}
+
 impl<T> BuildSignalReceiver<T>
 where
     T: BuildListenerBackend,
@@ -270,6 +338,7 @@ where
             critical_path,
             num_nodes,
             num_edges,
+            graph,
         } = self.backend.finish()?;
 
         let compute_elapsed = now.elapsed();
@@ -292,64 +361,25 @@ where
         let critical_path2 = critical_path
             .iter()
             .filter_map(|(key, data, potential_improvement)| {
-                let entry: buck2_data::critical_path_entry2::Entry = match key {
-                    NodeKey::ActionKey(action_key) => {
-                        let owner = match action_key.owner() {
-                            BaseDeferredKey::TargetLabel(t) => t.as_proto().into(),
-                            BaseDeferredKey::AnonTarget(t) => t.as_proto().into(),
-                            BaseDeferredKey::BxlLabel(t) => t.as_proto().into(),
-                        };
-
-                        // If we have a NodeKey that's an ActionKey we'd expect to have an `action`
-                        // in our data.
-                        let action = data.action.as_ref()?;
-
-                        buck2_data::critical_path_entry2::ActionExecution {
-                            owner: Some(owner),
-                            name: Some(buck2_data::ActionName {
-                                category: action.category().as_str().to_owned(),
-                                identifier: action.identifier().unwrap_or("").to_owned(),
-                            }),
-                        }
-                        .into()
-                    }
-                    NodeKey::Analysis(key) => buck2_data::critical_path_entry2::Analysis {
-                        target: Some(key.as_proto().into()),
-                    }
-                    .into(),
-                    NodeKey::Materialization(key) => {
-                        let owner = match key.key().owner() {
-                            BaseDeferredKey::TargetLabel(t) => t.as_proto().into(),
-                            BaseDeferredKey::AnonTarget(t) => t.as_proto().into(),
-                            BaseDeferredKey::BxlLabel(t) => t.as_proto().into(),
-                        };
-
-                        buck2_data::critical_path_entry2::Materialization {
-                            owner: Some(owner),
-                            path: key.get_path().path().to_string(),
-                        }
-                        .into()
-                    }
-                    NodeKey::Load(package) => buck2_data::critical_path_entry2::Load {
-                        package: package.to_string(),
-                    }
-                    .into(),
-                    NodeKey::TransitiveSetProjection(..) => return None,
-                };
-
+                let entry = critical_path_entry(key, data)?;
                 Some((entry, data, potential_improvement))
             })
             .chain(std::iter::once(meta_entry))
             .map(|(entry, data, potential_improvement)| {
-                anyhow::Ok(buck2_data::CriticalPathEntry2 {
-                    span_id: data.span_id.map(|span_id| span_id.into()),
-                    duration: Some(data.duration.critical_path_duration().try_into()?),
-                    user_duration: Some(data.duration.user.try_into()?),
-                    total_duration: Some(data.duration.total.try_into()?),
-                    potential_improvement_duration: potential_improvement
-                        .map(|p| p.try_into())
-                        .transpose()?,
-                    entry: Some(entry),
+                critical_path_entry2(Some(entry), data, *potential_improvement)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let graph = graph
+            .into_iter()
+            .map(|(key, data, deps)| {
+                anyhow::Ok(buck2_data::BuildGraphVertex {
+                    entry: Some(critical_path_entry2(
+                        critical_path_entry(&key, &data),
+                        &data,
+                        None,
+                    )?),
+                    deps,
                 })
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -362,6 +392,7 @@ where
             num_edges,
             uses_total_duration: false,
             backend_name: Some(T::name().to_string()),
+            graph,
         });
         Ok(())
     }
@@ -547,6 +578,8 @@ pub struct BuildInfo {
     critical_path: Vec<(NodeKey, NodeData, Option<Duration>)>,
     num_nodes: u64,
     num_edges: u64,
+    // Every node, its data, and the positions of its deps in this list. Empty unless requested.
+    graph: Vec<(NodeKey, NodeData, Vec<u64>)>,
 }
 
 struct DefaultBackend {
@@ -621,6 +654,7 @@ impl BuildListenerBackend for DefaultBackend {
             critical_path,
             num_nodes: self.num_nodes,
             num_edges: self.num_edges,
+            graph: Vec::new(),
         })
     }
 
@@ -634,6 +668,8 @@ impl BuildListenerBackend for DefaultBackend {
 struct LongestPathGraphBackend {
     builder: anyhow::Result<GraphBuilder<NodeKey, NodeData>>,
     top_level_analysis: Vec<VisibilityEdge>,
+    /// Whether to report the whole graph, so it can be replayed offline.
+    log_graph: bool,
 }
 
 #[derive(Dupe, Clone)]
@@ -650,10 +686,11 @@ struct VisibilityEdge {
 }
 
 impl LongestPathGraphBackend {
-    fn new() -> Self {
+    fn new(log_graph: bool) -> Self {
         Self {
             builder: Ok(GraphBuilder::new()),
             top_level_analysis: Vec::new(),
+            log_graph,
         }
     }
 }
@@ -755,6 +792,22 @@ impl BuildListenerBackend for LongestPathGraphBackend {
             (graph, keys, data)
         };
 
+        let logged_graph = if self.log_graph {
+            // Report vertices in topological order (dependencies first) so that the graph can be
+            // rebuilt by pushing them in order.
+            let topo_order = graph.topo_sort().context("Error sorting graph")?;
+            let mut position = graph.allocate_vertex_data(0u64);
+            let mut logged_graph = Vec::with_capacity(graph.vertices_count());
+            for (i, vertex) in topo_order.iter().rev().copied().enumerate() {
+                position[vertex] = i as u64;
+                let deps = graph.iter_edges(vertex).map(|dep| position[dep]).collect();
+                logged_graph.push((keys[vertex].dupe(), data[vertex].dupe(), deps));
+            }
+            logged_graph
+        } else {
+            Vec::new()
+        };
+
         let durations = data.try_map_ref(|d| {
             d.duration
                 .critical_path_duration()
@@ -796,6 +849,7 @@ impl BuildListenerBackend for LongestPathGraphBackend {
             critical_path,
             num_nodes: graph.vertices_count() as _,
             num_edges: graph.edges_count() as _,
+            graph: logged_graph,
         })
     }
 
@@ -877,9 +931,13 @@ impl FromStr for CriticalPathBackendName {
 /// This function arranges for a background task to be spawned that drives the receiver, while invoking the called
 /// function with a live BuildSignalSender that can be used to send events to the listening receiver. Upon return of
 /// `scope`, the sender terminates the receiver by sending a `BuildFinished` signal and joins the receiver task.
+///
+/// If `log_graph` is set and the backend supports it, the whole graph is included in the
+/// `BuildGraphExecutionInfo` event so that the critical path can be recomputed offline.
 pub async fn scope<F, R, Fut>(
     events: EventDispatcher,
     backend: CriticalPathBackendName,
+    log_graph: bool,
     func: F,
 ) -> anyhow::Result<R>
 where
@@ -888,7 +946,7 @@ where
 {
     let (sender, handle) = match backend {
         CriticalPathBackendName::LongestPathGraph => {
-            start_listener(events, LongestPathGraphBackend::new())
+            start_listener(events, LongestPathGraphBackend::new(log_graph))
         }
         CriticalPathBackendName::Default => start_listener(events, DefaultBackend::new()),
     };
@@ -324,22 +324,48 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
         ActionExecutionMetadata,
     )> {
         let action = self.target();
-        let manager = CommandExecutionManager::new(
-            Box::new(MutexClaimManager::new()),
-            self.executor.events.dupe(),
-            NoopLivelinessObserver::create(),
-        );
+        // Attempts which are retried are kept in the command reports, so they show up in the
+        // action's events.
+        let mut retries = 0;
         let CommandExecutionResult {
             outputs,
             report,
             rejected_execution,
             did_cache_upload,
             eligible_for_full_hybrid,
-        } = self
-            .executor
-            .command_executor
-            .exec_cmd(&action as _, request, manager, self.digest_config())
-            .await;
+        } = loop {
+            let manager = CommandExecutionManager::new(
+                Box::new(MutexClaimManager::new()),
+                self.executor.events.dupe(),
+                NoopLivelinessObserver::create(),
+            );
+            let result = self
+                .executor
+                .command_executor
+                .exec_cmd(&action as _, request, manager, self.digest_config())
+                .await;
+
+            if !request.retry_policy().should_retry(
+                retries,
+                &result.report.status,
+                result.report.exit_code,
+            ) {
+                break result;
+            }
+
+            retries += 1;
+            tracing::info!(
+                "Retrying `{}` action of `{}` after {} (retry {} of {})",
+                action.category(),
+                action.owner(),
+                result.report.status,
+                retries,
+                request.retry_policy().max_retries
+            );
+            self.command_reports
+                .extend(result.rejected_execution.into_iter());
+            self.command_reports.push(result.report);
+        };
 
         // TODO (@torozco): The execution kind should be made to come via the command reports too.
         let res = match &report.status {
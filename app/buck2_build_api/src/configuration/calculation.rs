@@ -172,7 +172,7 @@ async fn check_execution_platform(
     for allowed in toolchain_allows {
         if let Err(e) = allowed.allows(exec_platform) {
             return Ok(Err(
-                ExecutionPlatformIncompatibleReason::ExecutionDependencyIncompatible(e),
+                ExecutionPlatformIncompatibleReason::ToolchainDependencyIncompatible(e),
             ));
         }
     }
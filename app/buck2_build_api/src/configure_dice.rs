@@ -13,6 +13,8 @@ use buck2_common::dice::cells::SetCellResolver;
 use buck2_common::dice::data::SetIoProvider;
 use buck2_common::io::IoProvider;
 use buck2_common::legacy_configs::dice::SetLegacyConfigs;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::digest_config::SetDigestConfig;
@@ -20,6 +22,31 @@ use dice::DetectCycles;
 use dice::Dice;
 use dice::WhichDice;
 
+static DETECT_CYCLES: ConfigKey<DetectCycles> = ConfigKey::new(
+    "buck2",
+    "detect_cycles",
+    "Whether DICE detects cycles between computations: `enabled` or `disabled`.",
+)
+.with_default("enabled")
+.read_at_daemon_startup();
+
+static WHICH_DICE: ConfigKey<WhichDice> = ConfigKey::new(
+    "buck2",
+    "dice",
+    "Which DICE implementation to use: `legacy` or `modern`.",
+)
+.with_default("legacy")
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| {
+            schema.declare(&DETECT_CYCLES);
+            schema.declare(&WHICH_DICE);
+        },
+    }
+}
+
 /// Utility to configure the dice globals.
 /// One place to not forget to initialize something in all places.
 pub async fn configure_dice_for_buck(
@@ -29,26 +56,15 @@ pub async fn configure_dice_for_buck(
     detect_cycles: Option<DetectCycles>,
     which_dice: Option<WhichDice>,
 ) -> anyhow::Result<Arc<Dice>> {
-    let detect_cycles = detect_cycles.map_or_else(
-        || {
-            root_config
-                .and_then(|c| {
-                    c.parse::<DetectCycles>("buck2", "detect_cycles")
-                        .transpose()
-                })
-                .unwrap_or(Ok(DetectCycles::Enabled))
-        },
-        Ok,
-    )?;
+    let detect_cycles = match detect_cycles {
+        Some(detect_cycles) => detect_cycles,
+        None => DETECT_CYCLES.read_or_default(root_config)?,
+    };
 
-    let which_dice = which_dice.map_or_else(
-        || {
-            root_config
-                .and_then(|c| c.parse::<WhichDice>("buck2", "dice").transpose())
-                .unwrap_or(Ok(WhichDice::Legacy))
-        },
-        Ok,
-    )?;
+    let which_dice = match which_dice {
+        Some(which_dice) => which_dice,
+        None => WHICH_DICE.read_or_default(root_config)?,
+    };
 
     let mut dice = match which_dice {
         WhichDice::Legacy => Dice::builder(),
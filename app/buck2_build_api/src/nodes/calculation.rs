@@ -521,6 +521,78 @@ fn check_compatible(
     )))
 }
 
+/// Resolve the execution platform of a target (ignoring the transition of the target itself),
+/// without computing its configured node and so without failing because of its dependencies.
+///
+/// Used to report why execution platform resolution failed.
+pub async fn get_execution_platform_resolution(
+    ctx: &DiceComputations,
+    target_label: &ConfiguredTargetLabel,
+) -> anyhow::Result<ExecutionPlatformResolution> {
+    let target_node = ctx.get_target_node(target_label.unconfigured()).await?;
+    let resolved_configuration = ctx
+        .get_resolved_configuration(
+            target_label.cfg(),
+            target_node.label().pkg().cell_name(),
+            target_node.get_configuration_deps(),
+        )
+        .await?;
+    let mut resolved_transitions = OrderedMap::new();
+    for (_dep, tr) in target_node.transition_deps() {
+        let resolved_cfg = ctx
+            .apply_transition(&target_node, target_label.cfg(), tr)
+            .await?;
+        resolved_transitions.insert(tr.dupe(), resolved_cfg);
+    }
+    target_execution_platform(
+        ctx,
+        target_label,
+        &target_node,
+        &resolved_configuration,
+        &resolved_transitions,
+    )
+    .await
+}
+
+async fn target_execution_platform(
+    ctx: &DiceComputations,
+    target_label: &ConfiguredTargetLabel,
+    target_node: &TargetNode,
+    resolved_configuration: &ResolvedConfiguration,
+    resolved_transitions: &OrderedMap<Arc<TransitionId>, Arc<TransitionApplied>>,
+) -> anyhow::Result<ExecutionPlatformResolution> {
+    Ok(if target_label.cfg().is_unbound() {
+        // The unbound configuration is used when evaluation configuration nodes.
+        // That evaluation is
+        // (1) part of execution platform resolution and
+        // (2) isn't allowed to do execution
+        // And so we use an "unspecified" execution platform to avoid cycles and cause any attempts at execution to fail.
+        ExecutionPlatformResolution::unspecified()
+    } else if let Some(exec_cfg) = target_label.exec_cfg() {
+        // The label was produced by a toolchain_dep, so we use the execution platform of our parent
+        // We need to convert that to an execution platform, so just find the one with the same configuration.
+        ExecutionPlatformResolution::new(
+            Some(
+                find_execution_platform_by_configuration(
+                    ctx,
+                    exec_cfg,
+                    resolved_configuration.cfg().cfg(),
+                )
+                .await?,
+            ),
+            Vec::new(),
+        )
+    } else {
+        resolve_execution_platform(
+            ctx,
+            target_node,
+            resolved_configuration,
+            resolved_transitions,
+        )
+        .await?
+    })
+}
+
 /// Compute configured target node ignoring transition for this node.
 async fn compute_configured_target_node_no_transition(
     target_label: &ConfiguredTargetLabel,
@@ -567,36 +639,14 @@ async fn compute_configured_target_node_no_transition(
         resolved_transitions.insert(tr.dupe(), resolved_cfg);
     }
 
-    let execution_platform_resolution = if target_cfg.is_unbound() {
-        // The unbound configuration is used when evaluation configuration nodes.
-        // That evaluation is
-        // (1) part of execution platform resolution and
-        // (2) isn't allowed to do execution
-        // And so we use an "unspecified" execution platform to avoid cycles and cause any attempts at execution to fail.
-        ExecutionPlatformResolution::unspecified()
-    } else if let Some(exec_cfg) = target_label.exec_cfg() {
-        // The label was produced by a toolchain_dep, so we use the execution platform of our parent
-        // We need to convert that to an execution platform, so just find the one with the same configuration.
-        ExecutionPlatformResolution::new(
-            Some(
-                find_execution_platform_by_configuration(
-                    ctx,
-                    exec_cfg,
-                    resolved_configuration.cfg().cfg(),
-                )
-                .await?,
-            ),
-            Vec::new(),
-        )
-    } else {
-        resolve_execution_platform(
-            ctx,
-            &target_node,
-            &resolved_configuration,
-            &resolved_transitions,
-        )
-        .await?
-    };
+    let execution_platform_resolution = target_execution_platform(
+        ctx,
+        target_label,
+        &target_node,
+        &resolved_configuration,
+        &resolved_transitions,
+    )
+    .await?;
 
     let mut deps = SmallSet::new();
     let mut exec_deps = SmallSet::new();
@@ -663,6 +713,13 @@ async fn compute_configured_target_node_no_transition(
                             target_label.unconfigured().dupe(),
                         ))),
                     )
+                } else if !target_node.is_within_view(dep.label().unconfigured()) {
+                    ControlFlow::Break(
+                        Err(anyhow::anyhow!(VisibilityError::NotWithinView(
+                            dep.label().unconfigured().dupe(),
+                            target_label.unconfigured().dupe(),
+                        ))),
+                    )
                 } else {
                     ControlFlow::Continue(dep)
                 }
@@ -17,6 +17,7 @@ use buck2_core::target::label::TargetLabel;
 use buck2_events::dispatch::console_message;
 use buck2_node::configured_universe::CqueryUniverse;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::DiceComputations;
@@ -74,6 +75,15 @@ impl CqueryEvaluator<'_> {
         })
         .await
     }
+
+    /// The configured targets a `--target-universe` is built from.
+    pub async fn universe_roots<U: AsRef<str>>(
+        &self,
+        target_universe: &[U],
+    ) -> anyhow::Result<TargetSet<ConfiguredTargetNode>> {
+        let refs: Vec<_> = target_universe.map(|v| v.as_ref());
+        self.dice_query_delegate.eval_literals(&refs).await
+    }
 }
 
 async fn preresolve_literals_and_build_universe(
@@ -89,6 +89,13 @@ fn format_result_stats(stats: buck2_data::CleanStaleStats) -> String {
             bytesize::to_string(stats.cleaned_bytes, true),
         );
     }
+    if stats.cleaned_local_store_blob_count > 0 {
+        output += &format!(
+            "Cleaned {} unreferenced local store blobs ({})\n",
+            stats.cleaned_local_store_blob_count,
+            bytesize::to_string(stats.cleaned_local_store_bytes, true),
+        );
+    }
     output
 }
 
@@ -80,6 +80,17 @@ pub struct CqueryCommand {
     /// See this post https://fburl.com/1mf2d2xj for details.
     #[clap(long)]
     correct_owner: bool,
+
+    /// Instead of printing the query result, explain the configurations of the resulting targets.
+    ///
+    /// For each configuration, prints a dependency path to the target from the
+    /// `--target-universe` (or from the query result if no universe is given), and the edge on
+    /// that path which introduced the configuration: an attribute transition, the incoming
+    /// transition of a rule, an exec dep or a toolchain dep. Targets with several configurations
+    /// also get the constraints that differ between them, and the buckconfig values required by
+    /// the `select()` keys which match in one configuration but not the other.
+    #[clap(long)]
+    explain_configurations: bool,
 }
 
 #[async_trait]
@@ -125,6 +136,7 @@ impl StreamingCommand for CqueryCommand {
                     unstable_output_format,
                     target_call_stacks: self.query_common.target_call_stacks,
                     correct_owner,
+                    explain_configurations: self.explain_configurations,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -29,6 +29,7 @@ use crate::commands::debug::allocative::AllocativeCommand;
 use crate::commands::debug::daemon_dir::DaemonDirCommand;
 use crate::commands::debug::exe::ExeCommand;
 use crate::commands::debug::log_perf::LogPerfCommand;
+use crate::commands::debug::offline_archive::OfflineArchiveCommand;
 use crate::commands::debug::persist_event_logs::PersistEventLogsCommand;
 use crate::commands::debug::segfault::SegfaultCommand;
 use crate::commands::debug::set_log_filter::SetLogFilterCommand;
@@ -50,6 +51,7 @@ mod heap_dump;
 mod internal_version;
 mod log_perf;
 mod materialize;
+mod offline_archive;
 mod persist_event_logs;
 pub mod replay;
 mod segfault;
@@ -104,6 +106,9 @@ pub enum DebugCommand {
     LogPerf(LogPerfCommand),
     /// Interact with I/O tracing of the daemon.
     TraceIo(TraceIoCommand),
+    /// Package a traced build into an archive, and build offline from it.
+    #[clap(subcommand)]
+    OfflineArchive(OfflineArchiveCommand),
     /// Write event logs to disk and upload
     PersistEventLogs(PersistEventLogsCommand),
 }
@@ -140,6 +145,7 @@ impl DebugCommand {
             DebugCommand::FileStatus(cmd) => cmd.exec(matches, ctx),
             DebugCommand::LogPerf(cmd) => cmd.exec(matches, ctx),
             DebugCommand::TraceIo(cmd) => cmd.exec(matches, ctx),
+            DebugCommand::OfflineArchive(cmd) => cmd.exec(matches, ctx),
             DebugCommand::PersistEventLogs(cmd) => cmd.exec(matches, ctx),
         }
     }
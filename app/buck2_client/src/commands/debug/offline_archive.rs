@@ -0,0 +1,212 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_cli_proto::trace_io_request;
+use buck2_cli_proto::TraceIoRequest;
+use buck2_cli_proto::TraceIoResponse;
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::common::CommonBuildConfigurationOptions;
+use buck2_client_ctx::common::CommonConsoleOptions;
+use buck2_client_ctx::common::CommonDaemonCommandOptions;
+use buck2_client_ctx::daemon::client::BuckdClientConnector;
+use buck2_client_ctx::daemon::client::NoPartialResultHandler;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_client_ctx::streaming::BuckSubcommand;
+use buck2_client_ctx::streaming::StreamingCommand;
+use buck2_offline_archive::create_archive;
+use buck2_offline_archive::import_archive;
+use buck2_offline_archive::read_imported_manifest;
+
+use crate::commands::debug::trace_io::manifest_from_trace;
+
+#[derive(Debug, thiserror::Error)]
+enum OfflineArchiveCommandError {
+    #[error(
+        "I/O tracing is not enabled. Run `buck2 debug trace-io enable` and then the build first"
+    )]
+    TracingDisabled,
+
+    #[error("The build read {0} paths that are not in the offline archive")]
+    PathsOutsideArchive(usize),
+}
+
+/// Package what a build needs into an archive, and run it offline from that archive.
+///
+/// The build to package must run with I/O tracing enabled (`buck2 debug trace-io enable`). To
+/// build offline, remote execution must be disabled as well, since only downloads are served from
+/// the archive.
+#[derive(Debug, clap::Subcommand)]
+pub enum OfflineArchiveCommand {
+    /// Write an archive of the files the traced build read, the artifacts it downloaded, and the
+    /// buck2 binaries.
+    Create(CreateCommand),
+    /// Extract an archive into a directory, configured to read downloads from the archive.
+    Import(ImportCommand),
+    /// Check that the traced build only read paths that are in the imported archive.
+    Validate(ValidateCommand),
+}
+
+impl OfflineArchiveCommand {
+    pub fn exec(self, matches: &clap::ArgMatches, ctx: ClientCommandContext) -> ExitResult {
+        let matches = matches.subcommand().expect("subcommand not found").1;
+        match self {
+            Self::Create(cmd) => cmd.exec(matches, ctx),
+            Self::Import(cmd) => cmd.exec(matches, ctx),
+            Self::Validate(cmd) => cmd.exec(matches, ctx),
+        }
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CreateCommand {
+    #[clap(short, long, help = "Output path to write the archive to")]
+    out: PathArg,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct ImportCommand {
+    #[clap(help = "Path of the archive to import")]
+    archive: PathArg,
+
+    #[clap(short, long, help = "Directory to extract the archive into")]
+    dest: PathArg,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct ValidateCommand {}
+
+/// Fetch the I/O trace, which must be enabled.
+async fn request_trace(
+    buckd: &mut BuckdClientConnector,
+    mut ctx: ClientCommandContext,
+    matches: &clap::ArgMatches,
+    sanitized_argv: Vec<String>,
+) -> anyhow::Result<TraceIoResponse> {
+    let context = ctx.client_context(
+        CommonBuildConfigurationOptions::default_ref(),
+        matches,
+        sanitized_argv,
+    )?;
+    let req = TraceIoRequest {
+        context: Some(context),
+        read_state: Some(trace_io_request::ReadIoTracingState { with_trace: true }),
+    };
+    let resp = buckd
+        .with_flushing()
+        .trace_io(
+            req,
+            ctx.stdin()
+                .console_interaction_stream(CommonConsoleOptions::default_ref()),
+            &mut NoPartialResultHandler,
+        )
+        .await??;
+    if !resp.enabled {
+        return Err(OfflineArchiveCommandError::TracingDisabled.into());
+    }
+    Ok(resp)
+}
+
+#[async_trait]
+impl StreamingCommand for CreateCommand {
+    const COMMAND_NAME: &'static str = "offline-archive-create";
+
+    async fn exec_impl(
+        self,
+        buckd: &mut BuckdClientConnector,
+        matches: &clap::ArgMatches,
+        ctx: ClientCommandContext,
+    ) -> ExitResult {
+        let project_root = ctx.paths()?.project_root().root().to_owned();
+        let out = self.out.resolve(&ctx.working_dir);
+        let sanitized_argv = self.sanitized_argv();
+        let resp = request_trace(buckd, ctx, matches, sanitized_argv).await?;
+        let manifest = manifest_from_trace(resp).await?;
+
+        create_archive(project_root.as_path(), &manifest, out.as_path())
+            .context("creating offline archive")?;
+        buck2_client_ctx::eprintln!(
+            "Archived {} paths and {} downloaded files to `{}`",
+            manifest.paths.len(),
+            manifest.blobs.len(),
+            out.display()
+        )?;
+        ExitResult::success()
+    }
+
+    fn console_opts(&self) -> &CommonConsoleOptions {
+        CommonConsoleOptions::default_ref()
+    }
+
+    fn event_log_opts(&self) -> &CommonDaemonCommandOptions {
+        CommonDaemonCommandOptions::default_ref()
+    }
+
+    fn common_opts(&self) -> &CommonBuildConfigurationOptions {
+        CommonBuildConfigurationOptions::default_ref()
+    }
+}
+
+impl ImportCommand {
+    pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext) -> ExitResult {
+        let archive = self.archive.resolve(&ctx.working_dir);
+        let dest = self.dest.resolve(&ctx.working_dir);
+        let manifest = import_archive(archive.as_path(), dest.as_path())
+            .context("importing offline archive")?;
+        buck2_client_ctx::eprintln!(
+            "Imported {} paths and {} downloaded files into `{}`",
+            manifest.paths.len(),
+            manifest.blobs.len(),
+            dest.display()
+        )?;
+        ExitResult::success()
+    }
+}
+
+#[async_trait]
+impl StreamingCommand for ValidateCommand {
+    const COMMAND_NAME: &'static str = "offline-archive-validate";
+
+    async fn exec_impl(
+        self,
+        buckd: &mut BuckdClientConnector,
+        matches: &clap::ArgMatches,
+        ctx: ClientCommandContext,
+    ) -> ExitResult {
+        let project_root = ctx.paths()?.project_root().root().to_owned();
+        let manifest = read_imported_manifest(project_root.as_path())?;
+        let sanitized_argv = self.sanitized_argv();
+        let resp = request_trace(buckd, ctx, matches, sanitized_argv).await?;
+
+        let outside = manifest.paths_outside(resp.trace.iter().map(|p| p.as_str()));
+        for path in &outside {
+            buck2_client_ctx::println!("{}", path)?;
+        }
+        if !outside.is_empty() {
+            return ExitResult::bail(OfflineArchiveCommandError::PathsOutsideArchive(
+                outside.len(),
+            ));
+        }
+        ExitResult::success()
+    }
+
+    fn console_opts(&self) -> &CommonConsoleOptions {
+        CommonConsoleOptions::default_ref()
+    }
+
+    fn event_log_opts(&self) -> &CommonDaemonCommandOptions {
+        CommonDaemonCommandOptions::default_ref()
+    }
+
+    fn common_opts(&self) -> &CommonBuildConfigurationOptions {
+        CommonBuildConfigurationOptions::default_ref()
+    }
+}
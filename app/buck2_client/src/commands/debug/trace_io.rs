@@ -25,6 +25,7 @@ use buck2_client_ctx::daemon::client::NoPartialResultHandler;
 use buck2_client_ctx::exit_result::ExitResult;
 use buck2_client_ctx::streaming::StreamingCommand;
 use buck2_core::fs::fs_util;
+use buck2_offline_archive::OfflineArchiveBlob;
 use buck2_offline_archive::OfflineArchiveManifest;
 use tokio::process::Command;
 
@@ -53,7 +54,7 @@ enum Subcommand {
 }
 
 /// Fetch the current hg revision.
-async fn hg_revision() -> anyhow::Result<Option<String>> {
+pub(crate) async fn hg_revision() -> anyhow::Result<Option<String>> {
     let result = Command::new("hg")
         .arg("whereami")
         .env("HGPLAIN", "1")
@@ -73,6 +74,30 @@ async fn hg_revision() -> anyhow::Result<Option<String>> {
     }
 }
 
+/// Build an offline archive manifest out of the I/O trace and the downloads the daemon recorded.
+pub(crate) async fn manifest_from_trace(
+    resp: TraceIoResponse,
+) -> anyhow::Result<OfflineArchiveManifest> {
+    let mut trace = resp.trace;
+
+    // Incorporate buck2 executable files.
+    trace.push(".buck2".to_owned());
+    trace.push(".buck2-previous".to_owned());
+
+    Ok(OfflineArchiveManifest {
+        paths: trace,
+        repo_revision: hg_revision().await.context("fetching hg revision")?,
+        blobs: resp
+            .downloads
+            .into_iter()
+            .map(|d| OfflineArchiveBlob {
+                path: d.path,
+                name: d.blob,
+            })
+            .collect(),
+    })
+}
+
 impl TraceIoCommand {
     async fn send_request(
         &self,
@@ -117,16 +142,7 @@ impl StreamingCommand for TraceIoCommand {
                     read_state: Some(trace_io_request::ReadIoTracingState { with_trace: true }),
                 };
                 let resp = self.send_request(req, buckd, ctx).await??;
-                let mut trace = resp.trace;
-
-                // Incorporate buck2 executable files.
-                trace.push(".buck2".to_owned());
-                trace.push(".buck2-previous".to_owned());
-
-                let manifest = OfflineArchiveManifest {
-                    paths: trace,
-                    repo_revision: hg_revision().await.context("fetching hg revision")?,
-                };
+                let manifest = manifest_from_trace(resp).await?;
                 let serialized = serde_json::to_string(&manifest)
                     .context("serializing offline archive manifest to json")?;
                 if let Some(output_path) = &out {
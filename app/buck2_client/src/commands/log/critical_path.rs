@@ -10,24 +10,87 @@
 use std::fmt;
 use std::time::Duration;
 
+use anyhow::Context;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::exit_result::ExitResult;
 use buck2_client_ctx::stream_value::StreamValue;
 use buck2_client_ctx::subscribers::event_log::options::EventLogOptions;
+use buck2_critical_path::compute_critical_path_potentials;
+use buck2_critical_path::GraphBuilder;
 use buck2_event_observer::display;
 use buck2_event_observer::display::TargetDisplayOptions;
+use thiserror::Error;
 use tokio_stream::StreamExt;
 
-/// This command outputs stats about uploads to RE from the selected invocation.
+use crate::commands::log::LogCommandOutputFormat;
+
+/// This command outputs the critical path of the selected invocation, along with how much each
+/// entry could shorten it.
+///
+/// With `--what-if`, the critical path is instead recomputed from the build graph recorded in the
+/// event log, after overriding the durations of some of its entries. This requires the build to
+/// have run with `-c buck2.critical_path_backend2=longest-path-graph -c
+/// buck2.critical_path_log_graph=true`.
 #[derive(Debug, clap::Parser)]
 pub struct CriticalPathCommand {
     #[clap(flatten)]
     event_log: EventLogOptions,
+
+    #[clap(
+        long = "--format",
+        help = "Which output format to use for this command",
+        default_value = "tabulated",
+        ignore_case = true,
+        arg_enum
+    )]
+    pub output: LogCommandOutputFormat,
+
+    /// Override the duration of every entry whose name starts with PREFIX, then report the
+    /// resulting critical path. For example, `--what-if root//foo:=0s` assumes every action
+    /// owned by a target in `root//foo` was a cache hit. Can be passed multiple times.
+    #[clap(
+        long,
+        value_name = "PREFIX=DURATION",
+        parse(try_from_str = parse_what_if)
+    )]
+    pub what_if: Vec<WhatIf>,
+}
+
+#[derive(Debug)]
+pub struct WhatIf {
+    prefix: String,
+    duration: Duration,
+}
+
+#[derive(Debug, Error)]
+enum CriticalPathCommandError {
+    #[error("Expected `PREFIX=DURATION`, got `{0}`")]
+    InvalidWhatIf(String),
+    #[error(
+        "The event log does not contain the build graph. Run the build with `-c buck2.critical_path_backend2=longest-path-graph -c buck2.critical_path_log_graph=true` to record it"
+    )]
+    NoBuildGraph,
+}
+
+fn parse_what_if(s: &str) -> anyhow::Result<WhatIf> {
+    let (prefix, duration) = s
+        .rsplit_once('=')
+        .ok_or_else(|| CriticalPathCommandError::InvalidWhatIf(s.to_owned()))?;
+    let duration = humantime::parse_duration(duration)
+        .with_context(|| CriticalPathCommandError::InvalidWhatIf(s.to_owned()))?;
+    Ok(WhatIf {
+        prefix: prefix.to_owned(),
+        duration,
+    })
 }
 
 impl CriticalPathCommand {
     pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext) -> ExitResult {
-        let Self { event_log } = self;
+        let Self {
+            event_log,
+            output,
+            what_if,
+        } = self;
 
         let log_path = event_log.get(&ctx)?;
 
@@ -47,7 +110,11 @@ impl CriticalPathCommand {
                                 Some(buck2_data::instant_event::Data::BuildGraphInfo(
                                     build_graph,
                                 )) => {
-                                    log_critical_path(&build_graph)?;
+                                    if what_if.is_empty() {
+                                        log_critical_path(&build_graph.critical_path2, &output)?;
+                                    } else {
+                                        log_what_if(&build_graph, &what_if, &output)?;
+                                    }
                                 }
                                 _ => {}
                             }
@@ -65,114 +132,370 @@ impl CriticalPathCommand {
     }
 }
 
-fn log_critical_path(critical_path: &buck2_data::BuildGraphExecutionInfo) -> anyhow::Result<()> {
+/// The parts of a critical path entry we display.
+struct EntryInfo<'a> {
+    kind: &'static str,
+    name: String,
+    category: &'a str,
+    identifier: &'a str,
+}
+
+/// Returns `None` for entries we don't know how to display.
+fn entry_info(entry: &buck2_data::CriticalPathEntry2) -> anyhow::Result<Option<EntryInfo<'_>>> {
+    use buck2_data::critical_path_entry2::Entry;
+
     let target_display_options = TargetDisplayOptions::for_log();
 
-    for entry in &critical_path.critical_path2 {
-        use buck2_data::critical_path_entry2::Entry;
+    let kind;
+    let name;
+    let mut category = "";
+    let mut identifier = "";
 
-        let kind;
-        let name;
-        let mut category = "";
-        let mut identifier = "";
+    match &entry.entry {
+        Some(Entry::Analysis(analysis)) => {
+            use buck2_data::critical_path_entry2::analysis::Target;
 
-        match &entry.entry {
-            Some(Entry::Analysis(analysis)) => {
-                use buck2_data::critical_path_entry2::analysis::Target;
+            kind = "analysis";
 
-                kind = "analysis";
+            name = match &analysis.target {
+                Some(Target::StandardTarget(t)) => {
+                    display::display_configured_target_label(t, target_display_options)?
+                }
+                None => return Ok(None),
+            };
+        }
+        Some(Entry::ActionExecution(action_execution)) => {
+            use buck2_data::critical_path_entry2::action_execution::Owner;
 
-                name = match &analysis.target {
-                    Some(Target::StandardTarget(t)) => {
-                        display::display_configured_target_label(t, target_display_options)?
-                    }
-                    None => continue,
-                };
-            }
-            Some(Entry::ActionExecution(action_execution)) => {
-                use buck2_data::critical_path_entry2::action_execution::Owner;
+            kind = "action";
 
-                kind = "action";
+            name = match &action_execution.owner {
+                Some(Owner::TargetLabel(t)) => {
+                    display::display_configured_target_label(t, target_display_options)?
+                }
+                Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
+                Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
+                None => return Ok(None),
+            };
 
-                name = match &action_execution.owner {
-                    Some(Owner::TargetLabel(t)) => {
-                        display::display_configured_target_label(t, target_display_options)?
-                    }
-                    Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
-                    Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
-                    None => continue,
-                };
-
-                match &action_execution.name {
-                    Some(name) => {
-                        category = &name.category;
-                        identifier = &name.identifier;
-                    }
-                    None => {}
+            match &action_execution.name {
+                Some(name) => {
+                    category = &name.category;
+                    identifier = &name.identifier;
                 }
+                None => {}
             }
-            Some(Entry::Materialization(materialization)) => {
-                use buck2_data::critical_path_entry2::materialization::Owner;
+        }
+        Some(Entry::Materialization(materialization)) => {
+            use buck2_data::critical_path_entry2::materialization::Owner;
 
-                kind = "materialization";
+            kind = "materialization";
 
-                name = match &materialization.owner {
-                    Some(Owner::TargetLabel(t)) => {
-                        display::display_configured_target_label(t, target_display_options)?
-                    }
-                    Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
-                    Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
-                    None => continue,
-                };
+            name = match &materialization.owner {
+                Some(Owner::TargetLabel(t)) => {
+                    display::display_configured_target_label(t, target_display_options)?
+                }
+                Some(Owner::BxlKey(t)) => display::display_bxl_key(t)?,
+                Some(Owner::AnonTarget(t)) => display::display_anon_target(t)?,
+                None => return Ok(None),
+            };
 
-                identifier = &materialization.path;
-            }
-            Some(Entry::ComputeCriticalPath(..)) => {
-                kind = "compute-critical-path";
-                name = "".to_owned();
-            }
-            Some(Entry::Load(load)) => {
-                kind = "load";
-                name = load.package.clone();
-            }
-            None => continue,
+            identifier = &materialization.path;
         }
+        Some(Entry::ComputeCriticalPath(..)) => {
+            kind = "compute-critical-path";
+            name = "".to_owned();
+        }
+        Some(Entry::Load(load)) => {
+            kind = "load";
+            name = load.package.clone();
+        }
+        None => return Ok(None),
+    }
 
-        struct OptionalDuration {
-            inner: Option<Duration>,
+    Ok(Some(EntryInfo {
+        kind,
+        name,
+        category,
+        identifier,
+    }))
+}
+
+fn log_critical_path(
+    critical_path: &[buck2_data::CriticalPathEntry2],
+    format: &LogCommandOutputFormat,
+) -> anyhow::Result<()> {
+    struct OptionalDuration {
+        inner: Option<Duration>,
+    }
+
+    impl OptionalDuration {
+        fn new<T, E>(d: Option<T>) -> Result<Self, E>
+        where
+            T: TryInto<Duration, Error = E>,
+        {
+            Ok(Self {
+                inner: d.map(|d| d.try_into()).transpose()?,
+            })
         }
 
-        impl OptionalDuration {
-            fn new<T, E>(d: Option<T>) -> Result<Self, E>
-            where
-                T: TryInto<Duration, Error = E>,
-            {
-                Ok(Self {
-                    inner: d.map(|d| d.try_into()).transpose()?,
-                })
-            }
+        fn as_micros(&self) -> Option<u64> {
+            self.inner.map(|d| d.as_micros() as u64)
         }
+    }
 
-        impl fmt::Display for OptionalDuration {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                if let Some(inner) = self.inner {
-                    write!(f, "{}", inner.as_micros())?;
-                }
-                Ok(())
+    impl fmt::Display for OptionalDuration {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if let Some(inner) = self.inner {
+                write!(f, "{}", inner.as_micros())?;
             }
+            Ok(())
         }
+    }
+
+    #[derive(serde::Serialize)]
+    struct Record<'a> {
+        kind: &'a str,
+        name: &'a str,
+        category: &'a str,
+        identifier: &'a str,
+        total_duration_us: Option<u64>,
+        user_duration_us: Option<u64>,
+        potential_improvement_duration_us: Option<u64>,
+    }
+
+    // The CSV header row is written with the first record, from the same field names as JSON.
+    let mut csv_headers = true;
 
-        buck2_client_ctx::println!(
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+    for entry in critical_path {
+        let EntryInfo {
             kind,
             name,
             category,
             identifier,
-            OptionalDuration::new(entry.total_duration.clone())?,
-            OptionalDuration::new(entry.user_duration.clone())?,
-            OptionalDuration::new(entry.potential_improvement_duration.clone())?,
-        )?;
+        } = match entry_info(entry)? {
+            Some(info) => info,
+            None => continue,
+        };
+
+        let total_duration = OptionalDuration::new(entry.total_duration.clone())?;
+        let user_duration = OptionalDuration::new(entry.user_duration.clone())?;
+        let potential_improvement_duration =
+            OptionalDuration::new(entry.potential_improvement_duration.clone())?;
+
+        let record = Record {
+            kind,
+            name: &name,
+            category,
+            identifier,
+            total_duration_us: total_duration.as_micros(),
+            user_duration_us: user_duration.as_micros(),
+            potential_improvement_duration_us: potential_improvement_duration.as_micros(),
+        };
+
+        match format {
+            LogCommandOutputFormat::Tabulated => {
+                buck2_client_ctx::println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    kind,
+                    name,
+                    category,
+                    identifier,
+                    total_duration,
+                    user_duration,
+                    potential_improvement_duration,
+                )?;
+            }
+            LogCommandOutputFormat::Csv => {
+                buck2_client_ctx::stdio::print_with_writer(|w| {
+                    let mut writer = csv::WriterBuilder::new()
+                        .has_headers(csv_headers)
+                        .from_writer(w);
+                    writer.serialize(record)
+                })?;
+                csv_headers = false;
+            }
+            LogCommandOutputFormat::Json => {
+                buck2_client_ctx::stdio::print_with_writer(|w| serde_json::to_writer(w, &record))?;
+                buck2_client_ctx::println!("")?;
+            }
+        }
     }
 
     Ok(())
 }
+
+fn log_what_if(
+    build_graph: &buck2_data::BuildGraphExecutionInfo,
+    what_if: &[WhatIf],
+    format: &LogCommandOutputFormat,
+) -> anyhow::Result<()> {
+    let (before, after, critical_path) = compute_what_if(&build_graph.graph, what_if)?;
+
+    buck2_client_ctx::eprintln!(
+        "Critical path: {} -> {}",
+        humantime::format_duration(before),
+        humantime::format_duration(after)
+    )?;
+
+    log_critical_path(&critical_path, format)
+}
+
+/// Recompute the critical path over `graph` after applying the `what_if` overrides. Returns the
+/// critical path duration before and after, and the new critical path.
+fn compute_what_if(
+    graph: &[buck2_data::BuildGraphVertex],
+    what_if: &[WhatIf],
+) -> anyhow::Result<(Duration, Duration, Vec<buck2_data::CriticalPathEntry2>)> {
+    if graph.is_empty() {
+        return Err(CriticalPathCommandError::NoBuildGraph.into());
+    }
+
+    let mut builder = GraphBuilder::new();
+    let mut original = Vec::with_capacity(graph.len());
+    let mut overrides = Vec::with_capacity(graph.len());
+
+    for (i, vertex) in graph.iter().enumerate() {
+        builder
+            .push(i as u64, vertex.deps.iter().copied(), i)
+            .context("Invalid build graph")?;
+
+        let entry = vertex.entry.as_ref().context("Missing `entry`")?;
+        let duration: Duration = entry
+            .duration
+            .clone()
+            .context("Missing `duration`")?
+            .try_into()?;
+        original.push(duration);
+
+        let name = entry_info(entry)?.map(|info| info.name);
+        overrides.push(name.and_then(|name| {
+            // The last matching override wins.
+            what_if
+                .iter()
+                .rev()
+                .find(|w| name.starts_with(&w.prefix))
+                .map(|w| w.duration)
+        }));
+    }
+
+    let (graph_without_keys, _keys, data) = builder.finish();
+
+    let micros = |d: Duration| -> anyhow::Result<u64> {
+        d.as_micros()
+            .try_into()
+            .context("Duration `as_micros()` exceeds u64")
+    };
+
+    let original_weights = data.try_map_ref(|i| micros(original[*i]))?;
+    let weights = data.try_map_ref(|i| micros(overrides[*i].unwrap_or(original[*i])))?;
+
+    let (_, before, _) = compute_critical_path_potentials(&graph_without_keys, &original_weights)
+        .context("Error computing critical path")?;
+    let (critical_path, after, replacement_durations) =
+        compute_critical_path_potentials(&graph_without_keys, &weights)
+            .context("Error computing critical path")?;
+
+    let mut entries = Vec::new();
+
+    for (cp_idx, vertex_idx) in critical_path.iter() {
+        let i = data[*vertex_idx];
+        let mut entry = match &graph[i].entry {
+            Some(entry) if entry.entry.is_some() => entry.clone(),
+            _ => continue,
+        };
+
+        if let Some(duration) = overrides[i] {
+            let duration: prost_types::Duration = duration.try_into()?;
+            entry.duration = Some(duration.clone());
+            entry.user_duration = Some(duration.clone());
+            entry.total_duration = Some(duration);
+        }
+
+        let potential = after
+            .runtime
+            .saturating_sub(replacement_durations[cp_idx].runtime);
+        entry.potential_improvement_duration = Some(Duration::from_micros(potential).try_into()?);
+
+        entries.push(entry);
+    }
+
+    Ok((
+        Duration::from_micros(before.runtime),
+        Duration::from_micros(after.runtime),
+        entries,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(package: &str, duration_secs: u64, deps: &[u64]) -> buck2_data::BuildGraphVertex {
+        let duration: prost_types::Duration =
+            Duration::from_secs(duration_secs).try_into().unwrap();
+        buck2_data::BuildGraphVertex {
+            entry: Some(buck2_data::CriticalPathEntry2 {
+                duration: Some(duration.clone()),
+                user_duration: Some(duration.clone()),
+                total_duration: Some(duration),
+                entry: Some(
+                    buck2_data::critical_path_entry2::Load {
+                        package: package.to_owned(),
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            }),
+            deps: deps.to_vec(),
+        }
+    }
+
+    fn packages(entries: &[buck2_data::CriticalPathEntry2]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|e| entry_info(e).unwrap().unwrap().name)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_what_if() {
+        let what_if = parse_what_if("root//foo:=1s").unwrap();
+        assert_eq!("root//foo:", what_if.prefix);
+        assert_eq!(Duration::from_secs(1), what_if.duration);
+
+        assert!(parse_what_if("root//foo:").is_err());
+        assert!(parse_what_if("root//foo:=banana").is_err());
+    }
+
+    #[test]
+    fn test_what_if() {
+        // a -> b -> d
+        //   -> c -> d
+        let graph = vec![
+            vertex("d", 1, &[]),
+            vertex("b", 5, &[0]),
+            vertex("c", 3, &[0]),
+            vertex("a", 1, &[1, 2]),
+        ];
+
+        let (before, after, critical_path) = compute_what_if(&graph, &[]).unwrap();
+        assert_eq!(Duration::from_secs(7), before);
+        assert_eq!(Duration::from_secs(7), after);
+        assert_eq!(vec!["d", "b", "a"], packages(&critical_path));
+
+        let what_if = [WhatIf {
+            prefix: "b".to_owned(),
+            duration: Duration::ZERO,
+        }];
+        let (before, after, critical_path) = compute_what_if(&graph, &what_if).unwrap();
+        assert_eq!(Duration::from_secs(7), before);
+        assert_eq!(Duration::from_secs(5), after);
+        assert_eq!(vec!["d", "c", "a"], packages(&critical_path));
+    }
+
+    #[test]
+    fn test_what_if_requires_graph() {
+        assert!(compute_what_if(&[], &[]).is_err());
+    }
+}
@@ -0,0 +1,610 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_client_ctx::stream_value::StreamValue;
+use buck2_client_ctx::subscribers::event_log::file_names::retrieve_nth_recent_log;
+use buck2_client_ctx::subscribers::event_log::read::EventLogPathBuf;
+use buck2_common::legacy_configs::diff::buckconfig_change_name;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use futures::TryStreamExt;
+use indexmap::IndexMap;
+use tokio::runtime;
+
+use crate::commands::log::LogCommandOutputFormat;
+
+/// This command compares two invocations of Buck2, which by default are the two most recent ones.
+///
+/// The output is presented as a series of tab-delimited records with the following structure:
+///
+/// The kind of difference. That's one of `args`, `config`, `buckconfig`, `only_in_first`,
+/// `only_in_second`, `execution`, `digest` or `duration`. `config` only compares the `-c`
+/// arguments of the two commands. `buckconfig` lists the keys the daemon found changed when the
+/// second command started, which includes edits to buckconfig files, but is relative to whichever
+/// command the daemon ran before it, not necessarily the first one.
+///
+/// What differs: a config key, or the identity of an action. Actions that ran several times with
+/// the same identity are compared run by run, in the order they finished, and runs without a
+/// counterpart are reported as `only_in_first` or `only_in_second`.
+///
+/// The value in the first invocation.
+///
+/// The value in the second invocation.
+///
+/// Details about the difference, if any. For `digest` differences, that's what the event logs
+/// show changed in the command line and environment of the action. Input digests are not in the
+/// event log, so they are never compared, and the detail says so.
+#[derive(Debug, clap::Parser)]
+pub struct DiffCommand {
+    /// A path to the event log of the first invocation.
+    #[clap(long, group = "first", value_name = "PATH")]
+    path1: Option<PathArg>,
+
+    /// Use the event log of the Nth most recent command as the first invocation. Defaults to 1,
+    /// i.e. the command before the most recent one.
+    #[clap(long, group = "first", value_name = "NUMBER")]
+    recent1: Option<usize>,
+
+    /// A path to the event log of the second invocation.
+    #[clap(long, group = "second", value_name = "PATH")]
+    path2: Option<PathArg>,
+
+    /// Use the event log of the Nth most recent command as the second invocation. Defaults to 0,
+    /// i.e. the most recent command.
+    #[clap(long, group = "second", value_name = "NUMBER")]
+    recent2: Option<usize>,
+
+    #[clap(
+        long = "--format",
+        help = "Which output format to use for this command",
+        default_value = "tabulated",
+        ignore_case = true,
+        arg_enum
+    )]
+    output: LogCommandOutputFormat,
+
+    /// Only report actions whose wall time changed by more than this.
+    #[clap(long, value_name = "DURATION", default_value = "1s")]
+    min_duration_change: humantime::Duration,
+}
+
+impl DiffCommand {
+    pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext) -> ExitResult {
+        let first = match &self.path1 {
+            Some(path) => path.resolve(&ctx.working_dir),
+            None => retrieve_nth_recent_log(&ctx, self.recent1.unwrap_or(1))?.into_abs_path_buf(),
+        };
+        let second = match &self.path2 {
+            Some(path) => path.resolve(&ctx.working_dir),
+            None => retrieve_nth_recent_log(&ctx, self.recent2.unwrap_or(0))?.into_abs_path_buf(),
+        };
+        let first = EventLogPathBuf::infer(first)?;
+        let second = EventLogPathBuf::infer(second)?;
+
+        let rt = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        rt.block_on(async move {
+            let first = LogSummary::read(&first).await?;
+            let second = LogSummary::read(&second).await?;
+
+            buck2_client_ctx::eprintln!("First: {}", first.invocation)?;
+            buck2_client_ctx::eprintln!("Second: {}", second.invocation)?;
+
+            for record in diff(&first, &second, self.min_duration_change.into()) {
+                record.print(&self.output)?;
+            }
+
+            anyhow::Ok(())
+        })?;
+
+        ExitResult::success()
+    }
+}
+
+/// What we know about an action from its `ActionExecutionEnd`.
+#[derive(Debug, Default, Clone)]
+struct ActionSummary {
+    execution_kind: String,
+    wall_time: Option<Duration>,
+    failed: bool,
+    digest: Option<String>,
+    /// Only known for actions that ran locally and whose command was logged.
+    argv: Option<Vec<String>>,
+    env: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default)]
+struct LogSummary {
+    invocation: String,
+    args: Vec<String>,
+    /// Buckconfig changes the daemon reported when this command started.
+    config_changes: Vec<buck2_data::BuckconfigChange>,
+    /// Runs of actions, keyed by their identity, in the order they finished. Usually there is one
+    /// run per identity, but nothing guarantees it.
+    actions: IndexMap<String, Vec<ActionSummary>>,
+}
+
+impl LogSummary {
+    async fn read(log_path: &EventLogPathBuf) -> anyhow::Result<Self> {
+        let (invocation, mut events) = log_path.unpack_stream().await?;
+
+        let mut summary = LogSummary {
+            invocation: invocation.to_string(),
+            args: invocation.command_line_args,
+            config_changes: Vec::new(),
+            actions: IndexMap::new(),
+        };
+
+        while let Some(event) = events.try_next().await? {
+            let event = match event {
+                StreamValue::Event(event) => event,
+                _ => continue,
+            };
+
+            let action = match event.data {
+                Some(buck2_data::buck_event::Data::SpanEnd(end)) => match end.data {
+                    Some(buck2_data::span_end_event::Data::ActionExecution(action)) => action,
+                    _ => continue,
+                },
+                Some(buck2_data::buck_event::Data::Instant(instant)) => {
+                    if let Some(buck2_data::instant_event::Data::ConfigurationChanged(changed)) =
+                        instant.data
+                    {
+                        summary.config_changes.extend(changed.config_changes);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let identity = display::display_action_identity(
+                action.key.as_ref(),
+                action.name.as_ref(),
+                TargetDisplayOptions::for_log(),
+            )?;
+
+            summary
+                .actions
+                .entry(identity)
+                .or_default()
+                .push(ActionSummary::from_end(&action));
+        }
+
+        Ok(summary)
+    }
+}
+
+impl ActionSummary {
+    fn from_end(action: &buck2_data::ActionExecutionEnd) -> Self {
+        use buck2_data::command_execution_details::Command;
+
+        let execution_kind = match buck2_data::ActionExecutionKind::from_i32(action.execution_kind)
+        {
+            Some(kind) => format!("{:?}", kind),
+            None => "unknown".to_owned(),
+        };
+
+        let mut summary = ActionSummary {
+            execution_kind,
+            wall_time: action
+                .wall_time
+                .clone()
+                .and_then(|d| Duration::try_from(d).ok()),
+            failed: action.failed,
+            ..Default::default()
+        };
+
+        // The command that should be shown to the user is always last.
+        let command = action
+            .commands
+            .last()
+            .and_then(|c| c.details.as_ref())
+            .and_then(|d| d.command.as_ref());
+
+        match command {
+            Some(Command::LocalCommand(local)) => {
+                summary.digest = Some(local.action_digest.clone());
+                summary.argv = Some(local.argv.clone());
+                summary.env = Some(
+                    local
+                        .env
+                        .iter()
+                        .map(|e| (e.key.clone(), e.value.clone()))
+                        .collect(),
+                );
+            }
+            Some(Command::RemoteCommand(remote)) => {
+                summary.digest = Some(remote.action_digest.clone());
+            }
+            Some(Command::OmittedLocalCommand(omitted)) => {
+                summary.digest = Some(omitted.action_digest.clone());
+            }
+            None => {}
+        }
+
+        summary
+    }
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct DiffRecord {
+    kind: &'static str,
+    subject: String,
+    first: String,
+    second: String,
+    detail: String,
+}
+
+impl DiffRecord {
+    fn print(&self, format: &LogCommandOutputFormat) -> anyhow::Result<()> {
+        match format {
+            LogCommandOutputFormat::Tabulated => buck2_client_ctx::println!(
+                "{}\t{}\t{}\t{}\t{}",
+                self.kind,
+                self.subject,
+                self.first,
+                self.second,
+                self.detail
+            ),
+            LogCommandOutputFormat::Csv => buck2_client_ctx::stdio::print_with_writer(|w| {
+                let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+                writer.serialize(self)
+            }),
+            LogCommandOutputFormat::Json => {
+                buck2_client_ctx::stdio::print_with_writer(|w| serde_json::to_writer(w, self))?;
+                buck2_client_ctx::println!("")
+            }
+        }
+    }
+}
+
+/// Split command line arguments into `-c` config values and everything else.
+fn split_config_args(args: &[String]) -> (BTreeMap<String, String>, Vec<String>) {
+    let mut config = BTreeMap::new();
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = if arg == "-c" || arg == "--config" {
+            args.next().map(|v| v.as_str())
+        } else if let Some(v) = arg.strip_prefix("--config=") {
+            Some(v)
+        } else if let Some(v) = arg.strip_prefix("-c").filter(|v| v.contains('=')) {
+            Some(v)
+        } else {
+            rest.push(arg.clone());
+            continue;
+        };
+
+        if let Some(value) = value {
+            match value.split_once('=') {
+                Some((key, value)) => {
+                    config.insert(key.to_owned(), value.to_owned());
+                }
+                None => {
+                    config.insert(value.to_owned(), String::new());
+                }
+            }
+        }
+    }
+
+    (config, rest)
+}
+
+/// Describe what the event logs show changed about an action whose digest changed. This is not a
+/// complete explanation: input digests are not logged, so they are never compared.
+fn digest_change_reason(first: &ActionSummary, second: &ActionSummary) -> String {
+    if first.argv.is_none() || second.argv.is_none() {
+        return "command not logged, nothing compared".to_owned();
+    }
+
+    let mut reasons = Vec::new();
+
+    if let (Some(a), Some(b)) = (&first.argv, &second.argv) {
+        if a != b {
+            reasons.push("command line changed".to_owned());
+        }
+    }
+
+    if let (Some(a), Some(b)) = (&first.env, &second.env) {
+        let changed: BTreeSet<&String> = a
+            .iter()
+            .filter(|(k, v)| b.get(*k) != Some(*v))
+            .map(|(k, _)| k)
+            .chain(b.keys().filter(|k| !a.contains_key(*k)))
+            .collect();
+        if !changed.is_empty() {
+            reasons.push(format!(
+                "environment changed: {}",
+                changed.into_iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+
+    if reasons.is_empty() {
+        reasons.push("command line and environment unchanged".to_owned());
+    }
+    reasons.push("inputs not compared".to_owned());
+    reasons.join("; ")
+}
+
+fn display_duration(d: Option<Duration>) -> String {
+    match d {
+        Some(d) => format!("{}", d.as_micros()),
+        None => String::new(),
+    }
+}
+
+/// Detail for runs of an action without a counterpart, when the other invocation ran it too.
+fn run_count_detail(first: usize, second: usize) -> String {
+    if first == 0 || second == 0 {
+        String::new()
+    } else {
+        format!("ran {} times in first, {} times in second", first, second)
+    }
+}
+
+fn diff(first: &LogSummary, second: &LogSummary, min_duration_change: Duration) -> Vec<DiffRecord> {
+    let mut records = Vec::new();
+
+    let (first_config, first_rest) = split_config_args(&first.args);
+    let (second_config, second_rest) = split_config_args(&second.args);
+
+    if first_rest != second_rest {
+        records.push(DiffRecord {
+            kind: "args",
+            subject: String::new(),
+            first: shlex::join(first_rest.iter().map(|a| a.as_str())),
+            second: shlex::join(second_rest.iter().map(|a| a.as_str())),
+            detail: String::new(),
+        });
+    }
+
+    let config_keys: BTreeSet<&String> = first_config.keys().chain(second_config.keys()).collect();
+    for key in config_keys {
+        let a = first_config.get(key);
+        let b = second_config.get(key);
+        if a != b {
+            records.push(DiffRecord {
+                kind: "config",
+                subject: key.clone(),
+                first: a.cloned().unwrap_or_default(),
+                second: b.cloned().unwrap_or_default(),
+                detail: String::new(),
+            });
+        }
+    }
+
+    for change in &second.config_changes {
+        records.push(DiffRecord {
+            kind: "buckconfig",
+            subject: buckconfig_change_name(change),
+            first: change.previous.clone().unwrap_or_default(),
+            second: change.current.clone().unwrap_or_default(),
+            detail: String::new(),
+        });
+    }
+
+    let no_runs = Vec::new();
+
+    for (identity, first_runs) in &first.actions {
+        let second_runs = second.actions.get(identity).unwrap_or(&no_runs);
+        for action in first_runs.iter().skip(second_runs.len()) {
+            records.push(DiffRecord {
+                kind: "only_in_first",
+                subject: identity.clone(),
+                first: action.execution_kind.clone(),
+                second: String::new(),
+                detail: run_count_detail(first_runs.len(), second_runs.len()),
+            });
+        }
+    }
+
+    for (identity, second_runs) in &second.actions {
+        let first_runs = first.actions.get(identity).unwrap_or(&no_runs);
+
+        for (a, b) in first_runs.iter().zip(second_runs) {
+            if a.execution_kind != b.execution_kind || a.failed != b.failed {
+                let status = |s: &ActionSummary| {
+                    if s.failed {
+                        format!("{} (failed)", s.execution_kind)
+                    } else {
+                        s.execution_kind.clone()
+                    }
+                };
+                records.push(DiffRecord {
+                    kind: "execution",
+                    subject: identity.clone(),
+                    first: status(a),
+                    second: status(b),
+                    detail: String::new(),
+                });
+            }
+
+            if let (Some(da), Some(db)) = (&a.digest, &b.digest) {
+                if da != db {
+                    records.push(DiffRecord {
+                        kind: "digest",
+                        subject: identity.clone(),
+                        first: da.clone(),
+                        second: db.clone(),
+                        detail: digest_change_reason(a, b),
+                    });
+                }
+            }
+
+            if let (Some(ta), Some(tb)) = (a.wall_time, b.wall_time) {
+                let change = if tb > ta { tb - ta } else { ta - tb };
+                if change > min_duration_change {
+                    records.push(DiffRecord {
+                        kind: "duration",
+                        subject: identity.clone(),
+                        first: display_duration(Some(ta)),
+                        second: display_duration(Some(tb)),
+                        detail: if tb > ta { "slower" } else { "faster" }.to_owned(),
+                    });
+                }
+            }
+        }
+
+        for action in second_runs.iter().skip(first_runs.len()) {
+            records.push(DiffRecord {
+                kind: "only_in_second",
+                subject: identity.clone(),
+                first: String::new(),
+                second: action.execution_kind.clone(),
+                detail: run_count_detail(first_runs.len(), second_runs.len()),
+            });
+        }
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| (*a).to_owned()).collect()
+    }
+
+    fn local(digest: &str, argv: &[&str], env: &[(&str, &str)], secs: u64) -> ActionSummary {
+        ActionSummary {
+            execution_kind: "ActionExecutionKindLocal".to_owned(),
+            wall_time: Some(Duration::from_secs(secs)),
+            failed: false,
+            digest: Some(digest.to_owned()),
+            argv: Some(args(argv)),
+            env: Some(
+                env.iter()
+                    .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_split_config_args() {
+        let (config, rest) = split_config_args(&args(&[
+            "buck2",
+            "build",
+            "-c",
+            "a.b=1",
+            "--config=c.d=2",
+            "-ce.f=3",
+            "//:x",
+        ]));
+        assert_eq!(
+            vec![
+                ("a.b".to_owned(), "1".to_owned()),
+                ("c.d".to_owned(), "2".to_owned()),
+                ("e.f".to_owned(), "3".to_owned()),
+            ],
+            config.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(args(&["buck2", "build", "//:x"]), rest);
+    }
+
+    #[test]
+    fn test_digest_change_reason() {
+        assert_eq!(
+            "command line and environment unchanged; inputs not compared",
+            digest_change_reason(&local("1", &["cc"], &[], 1), &local("2", &["cc"], &[], 1))
+        );
+        assert_eq!(
+            "command line changed; inputs not compared",
+            digest_change_reason(&local("1", &["cc"], &[], 1), &local("2", &["ld"], &[], 1))
+        );
+        let remote = ActionSummary {
+            digest: Some("2".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "command not logged, nothing compared",
+            digest_change_reason(&local("1", &["cc"], &[], 1), &remote)
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut first = LogSummary {
+            args: args(&["buck2", "build", "-c", "a.b=1", "//:x"]),
+            ..Default::default()
+        };
+        let mut second = LogSummary {
+            args: args(&["buck2", "build", "-c", "a.b=2", "//:x"]),
+            config_changes: vec![buck2_data::BuckconfigChange {
+                cell: "root".to_owned(),
+                section: "c".to_owned(),
+                key: "d".to_owned(),
+                previous: Some("1".to_owned()),
+                current: None,
+            }],
+            ..Default::default()
+        };
+
+        first
+            .actions
+            .insert("gone".to_owned(), vec![local("1", &[], &[], 1)]);
+        second
+            .actions
+            .insert("new".to_owned(), vec![local("2", &[], &[], 1)]);
+        first.actions.insert(
+            "changed".to_owned(),
+            vec![local("3", &["cc"], &[("A", "1")], 1)],
+        );
+        second.actions.insert(
+            "changed".to_owned(),
+            vec![local("4", &["cc"], &[("A", "2"), ("B", "1")], 10)],
+        );
+        // The second run has no counterpart, and isn't hidden by the first.
+        first.actions.insert(
+            "twice".to_owned(),
+            vec![local("5", &[], &[], 1), local("6", &[], &[], 1)],
+        );
+        second
+            .actions
+            .insert("twice".to_owned(), vec![local("5", &[], &[], 1)]);
+
+        let records = diff(&first, &second, Duration::from_secs(1));
+        let kinds: Vec<_> = records
+            .iter()
+            .map(|r| (r.kind, r.subject.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("config", "a.b"),
+                ("buckconfig", "root//c.d"),
+                ("only_in_first", "gone"),
+                ("only_in_first", "twice"),
+                ("only_in_second", "new"),
+                ("digest", "changed"),
+                ("duration", "changed"),
+            ],
+            kinds
+        );
+        assert_eq!("", records[2].detail);
+        assert_eq!("ran 2 times in first, 1 times in second", records[3].detail);
+        assert_eq!(
+            "environment changed: A, B; inputs not compared",
+            records[5].detail
+        );
+        assert_eq!("slower", records[6].detail);
+    }
+}
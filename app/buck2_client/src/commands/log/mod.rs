@@ -8,9 +8,11 @@
  */
 
 pub mod critical_path;
+pub mod diff;
 pub mod last_log;
 pub mod show_log;
 pub mod what_failed;
+pub mod what_invalidated;
 pub mod what_materialized;
 pub mod what_ran;
 pub mod what_up;
@@ -56,6 +58,10 @@ pub enum LogCommand {
     #[clap(alias = "whatup")]
     WhatUp(what_up::WhatUpCommand),
 
+    /// Shows why the daemon restarted or invalidated state: changed daemon constraints, cells and
+    /// buckconfig values.
+    WhatInvalidated(what_invalidated::WhatInvalidatedCommand),
+
     /// Shows materializations in a log.
     WhatMaterialized(what_materialized::WhatMaterializedCommand),
 
@@ -64,6 +70,10 @@ pub enum LogCommand {
 
     /// Shows how many bytes/digests were uploaded by a command.
     CriticalPath(critical_path::CriticalPathCommand),
+
+    /// Shows the differences between two commands: flags, and actions that ran, changed or got
+    /// slower.
+    Diff(diff::DiffCommand),
 }
 
 impl LogCommand {
@@ -74,9 +84,11 @@ impl LogCommand {
             Self::Last(cmd) => cmd.exec(matches, ctx),
             Self::Show(cmd) => cmd.exec(matches, ctx),
             Self::WhatUp(cmd) => cmd.exec(matches, ctx),
+            Self::WhatInvalidated(cmd) => cmd.exec(matches, ctx),
             Self::WhatMaterialized(cmd) => cmd.exec(matches, ctx),
             Self::WhatUploaded(cmd) => cmd.exec(matches, ctx),
             Self::CriticalPath(cmd) => cmd.exec(matches, ctx),
+            Self::Diff(cmd) => cmd.exec(matches, ctx),
         }
     }
 }
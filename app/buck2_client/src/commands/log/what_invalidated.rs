@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::stream_value::StreamValue;
+use buck2_client_ctx::subscribers::event_log::options::EventLogOptions;
+use buck2_common::legacy_configs::diff::buckconfig_change_name;
+use tokio::runtime;
+use tokio_stream::StreamExt;
+
+use crate::commands::log::LogCommandOutputFormat;
+
+/// This command shows why the selected invocation restarted the daemon or invalidated state:
+/// daemon constraints, cells and buckconfig values that changed since the previous command.
+#[derive(Debug, clap::Parser)]
+pub struct WhatInvalidatedCommand {
+    #[clap(flatten)]
+    event_log: EventLogOptions,
+    #[clap(
+        long = "--format",
+        help = "Which output format to use for this command",
+        default_value = "tabulated",
+        ignore_case = true,
+        arg_enum
+    )]
+    pub output: LogCommandOutputFormat,
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    /// One of `daemon`, `cell` or `buckconfig`.
+    kind: &'static str,
+    name: String,
+    previous: Option<&'a str>,
+    current: Option<&'a str>,
+}
+
+fn records(instant: &buck2_data::instant_event::Data) -> Vec<Record<'_>> {
+    match instant {
+        buck2_data::instant_event::Data::DaemonRestarted(restarted) => restarted
+            .changes
+            .iter()
+            .map(|change| Record {
+                kind: "daemon",
+                name: change.constraint.clone(),
+                previous: Some(&change.previous),
+                current: Some(&change.current),
+            })
+            .collect(),
+        buck2_data::instant_event::Data::ConfigurationChanged(changed) => changed
+            .cell_changes
+            .iter()
+            .map(|change| Record {
+                kind: "cell",
+                name: change.cell.clone(),
+                previous: change.previous_path.as_deref(),
+                current: change.current_path.as_deref(),
+            })
+            .chain(changed.config_changes.iter().map(|change| Record {
+                kind: "buckconfig",
+                name: buckconfig_change_name(change),
+                previous: change.previous.as_deref(),
+                current: change.current.as_deref(),
+            }))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn print_record(format: &LogCommandOutputFormat, record: &Record) -> anyhow::Result<()> {
+    match format {
+        LogCommandOutputFormat::Tabulated => buck2_client_ctx::println!(
+            "{}\t{}\t{}\t{}",
+            record.kind,
+            record.name,
+            record.previous.unwrap_or("<unset>"),
+            record.current.unwrap_or("<unset>"),
+        ),
+        LogCommandOutputFormat::Csv => buck2_client_ctx::stdio::print_with_writer(|w| {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+            writer.serialize(record)
+        }),
+        LogCommandOutputFormat::Json => {
+            buck2_client_ctx::stdio::print_with_writer(|w| serde_json::to_writer(w, record))?;
+            buck2_client_ctx::println!("")
+        }
+    }
+}
+
+impl WhatInvalidatedCommand {
+    pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext) -> ExitResult {
+        let Self { event_log, output } = self;
+
+        let log_path = event_log.get(&ctx)?;
+
+        let rt = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        rt.block_on(async move {
+            let (invocation, mut events) = log_path.unpack_stream().await?;
+            buck2_client_ctx::eprintln!("Showing invalidations from: {}", invocation)?;
+
+            let mut invalidated = Vec::new();
+            while let Some(event) = events.try_next().await? {
+                match event {
+                    StreamValue::Event(event) => match event.data {
+                        Some(buck2_data::buck_event::Data::Instant(instant)) => {
+                            if let Some(data) = &instant.data {
+                                for record in records(data) {
+                                    print_record(&output, &record)?;
+                                }
+                                match data {
+                                    buck2_data::instant_event::Data::DaemonRestarted(
+                                        restarted,
+                                    ) => {
+                                        invalidated.push(format!(
+                                            "all daemon state: the daemon was restarted because {} changed",
+                                            restarted
+                                                .changes
+                                                .iter()
+                                                .map(|change| change.constraint.as_str())
+                                                .collect::<Vec<_>>()
+                                                .join(", ")
+                                        ));
+                                    }
+                                    buck2_data::instant_event::Data::ConfigurationChanged(
+                                        changed,
+                                    ) => {
+                                        invalidated.extend(changed.invalidated.iter().cloned());
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    },
+                    StreamValue::Result(..) | StreamValue::PartialResult(..) => {}
+                }
+            }
+
+            if invalidated.is_empty() {
+                buck2_client_ctx::eprintln!(
+                    "Nothing was invalidated by a daemon restart or configuration change"
+                )?;
+            }
+            for invalidated in invalidated {
+                buck2_client_ctx::eprintln!("Invalidated {}", invalidated)?;
+            }
+
+            anyhow::Ok(())
+        })?;
+
+        ExitResult::success()
+    }
+}
@@ -74,6 +74,7 @@ impl StatusCommand {
                         "uptime": uptime,
                         "process_info": serde_json::to_value(status.process_info)?,
                         "daemon_constraints": serde_json::to_value(status.daemon_constraints)?,
+                        "dice_explorer_address": status.dice_explorer_address,
                         "snapshot": serde_json::to_value(status.snapshot)?,
                     });
                     buck2_client_ctx::println!("{}", serde_json::to_string_pretty(&json_status)?)?;
@@ -83,6 +83,11 @@ pub struct TargetsCommand {
     #[clap(long)]
     stats: bool,
 
+    /// Print targets as the BUCK file rule calls that define them, with all macros expanded,
+    /// preceded by the `load()` of the rule and the call stack of the target as a comment
+    #[clap(long, conflicts_with_all = &["json", "json-lines", "stats"])]
+    starlark: bool,
+
     /// Print the fully-qualified build target for the specified aliases
     #[clap(long, alias = "resolvealias")]
     resolve_alias: bool,
@@ -184,7 +189,12 @@ pub struct TargetsCommand {
 
 impl TargetsCommand {
     fn output_format(&self) -> anyhow::Result<OutputFormat> {
-        if self.json {
+        if self.starlark {
+            if self.json || self.json_lines || self.stats || !self.attributes.get()?.is_empty() {
+                return Err(TargetsError::IncompatibleArguments.into());
+            }
+            Ok(OutputFormat::Starlark)
+        } else if self.json {
             if self.json_lines || self.stats {
                 return Err(TargetsError::IncompatibleArguments.into());
             }
@@ -283,7 +293,7 @@ impl StreamingCommand for TargetsCommand {
                     },
                     target_hash_modified_paths,
                     target_hash_use_fast_hash,
-                    target_call_stacks: self.target_call_stacks,
+                    target_call_stacks: self.target_call_stacks || self.starlark,
                     target_hash_graph_type,
                     include_default_attributes: self.include_defaults,
                     target_hash_recursive: self.target_hash_recursive,
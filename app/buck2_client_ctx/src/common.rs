@@ -122,6 +122,15 @@ pub struct CommonDaemonCommandOptions {
 
     #[clap(long = "--write-build-id")]
     pub build_id_file: Option<PathArg>,
+
+    /// Export spans for this command to an OpenTelemetry collector, via OTLP/HTTP with the JSON
+    /// encoding. Pass the full traces URL, e.g. `http://localhost:4318/v1/traces`.
+    #[clap(
+        value_name = "URL",
+        long = "otlp-endpoint",
+        env = "BUCK2_OTLP_ENDPOINT"
+    )]
+    pub otlp_endpoint: Option<String>,
 }
 
 impl CommonDaemonCommandOptions {
@@ -130,6 +139,7 @@ impl CommonDaemonCommandOptions {
             event_log: None,
             no_event_log: false,
             build_id_file: None,
+            otlp_endpoint: None,
         };
         &DEFAULT
     }
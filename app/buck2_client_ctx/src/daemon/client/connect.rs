@@ -43,6 +43,7 @@ use crate::daemon::client::BuckdClientConnector;
 use crate::daemon::client::BuckdLifecycleLock;
 use crate::daemon::client::ClientKind;
 use crate::daemon::daemon_windows::spawn_background_process_on_windows;
+use crate::daemon_constraints::constraint_changes;
 use crate::events_ctx::EventsCtx;
 use crate::replayer::Replayer;
 use crate::startup_deadline::StartupDeadline;
@@ -361,6 +362,8 @@ pub struct BootstrapBuckdClient {
     info: DaemonProcessInfo,
     daemon_dir: DaemonDir,
     client: DaemonApiClient<InterceptedService<Channel, BuckAddAuthTokenInterceptor>>,
+    /// Set when we had to restart the daemon to connect to it.
+    restarted: Option<buck2_data::DaemonRestarted>,
 }
 
 impl BootstrapBuckdClient {
@@ -392,6 +395,7 @@ impl BootstrapBuckdClient {
             info,
             daemon_dir,
             client,
+            restarted: None,
         }
     }
 
@@ -406,6 +410,7 @@ impl BootstrapBuckdClient {
                 client: ClientKind::Daemon(self.client),
                 events_ctx: EventsCtx::new(subscribers),
                 tailers: None,
+                daemon_restarted: self.restarted,
             },
         }
     }
@@ -491,6 +496,7 @@ impl BuckdConnectOptions {
             daemon_dir: paths.daemon_dir()?,
             info: fake_info,
             tailers: None,
+            daemon_restarted: None,
         };
 
         Ok(BuckdClientConnector { client })
@@ -538,16 +544,19 @@ async fn establish_connection_inner(
 
     // Even if we didn't connect before, it's possible that we just raced with another invocation
     // starting the server, so we try to connect again while holding the lock.
+    let mut restarted = None;
     if let Ok(mut client) = try_connect_existing(&paths.daemon_dir()?, &deadline).await {
-        if constraints.existing_only()
-            || client
-                .get_constraints()
-                .await?
-                .satisfies(&constraints)
-                .is_match()
-        {
+        if constraints.existing_only() {
             return Ok(client);
         }
+        match client.get_constraints().await?.satisfies(&constraints) {
+            ConstraintCheckResult::Match => return Ok(client),
+            ConstraintCheckResult::Mismatch { expected, actual } => {
+                restarted = Some(buck2_data::DaemonRestarted {
+                    changes: constraint_changes(&actual, &expected),
+                });
+            }
+        }
         deadline
             .run(
                 "sending kill command to the Buck daemon",
@@ -581,7 +590,10 @@ async fn establish_connection_inner(
     }
 
     match client.get_constraints().await?.satisfies(&constraints) {
-        ConstraintCheckResult::Match => Ok(client),
+        ConstraintCheckResult::Match => {
+            client.restarted = restarted;
+            Ok(client)
+        }
         ConstraintCheckResult::Mismatch { expected, actual } => {
             Err(BuckdConnectError::BuckDaemonConstraintWrongAfterStart { expected, actual }.into())
         }
@@ -724,6 +736,25 @@ mod tests {
         assert!(!c1.satisfies(&c2).is_match());
     }
 
+    #[test]
+    fn test_constraint_changes() {
+        let existing = constraints(TraceIoState::Existing).0;
+        let enabled = constraints(TraceIoState::Enabled).0;
+        let disabled = constraints(TraceIoState::Disabled).0;
+        assert!(constraint_changes(&existing, &enabled).is_empty());
+
+        let changes = constraint_changes(&disabled, &enabled);
+        assert_eq!(1, changes.len());
+        assert_eq!("trace_io_state", changes[0].constraint);
+
+        let mut older = disabled.clone();
+        older.version = "older".to_owned();
+        let changes = constraint_changes(&older, &disabled);
+        assert_eq!(1, changes.len());
+        assert_eq!("version", changes[0].constraint);
+        assert_eq!("older", changes[0].previous);
+    }
+
     #[test]
     fn test_trace_io_is_enabled() {
         let c = BuckdConnectConstraints::Constraints(constraints(TraceIoState::Enabled));
@@ -11,6 +11,7 @@ use std::fs::create_dir_all;
 use std::fs::File;
 use std::pin::Pin;
 use std::time::Duration;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use async_trait::async_trait;
@@ -19,6 +20,8 @@ use buck2_cli_proto::*;
 use buck2_common::daemon_dir::DaemonDir;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_events::BuckEvent;
+use buck2_wrapper_common::invocation_id::TraceId;
 use fs2::FileExt;
 use futures::future::BoxFuture;
 use futures::pin_mut;
@@ -83,6 +86,24 @@ impl BuckdClientConnector {
         }
     }
 
+    /// If connecting to the daemon required restarting it, tell the subscribers why, as part of
+    /// the command identified by `trace_id`.
+    pub async fn report_daemon_restart(&mut self, trace_id: TraceId) -> anyhow::Result<()> {
+        if let Some(restarted) = self.client.daemon_restarted.take() {
+            let event = BuckEvent::new(
+                SystemTime::now(),
+                trace_id,
+                None,
+                None,
+                buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                    data: Some(restarted.into()),
+                }),
+            );
+            self.client.events_ctx.handle_client_event(event).await?;
+        }
+        Ok(())
+    }
+
     pub fn collect_error_cause(&self) -> ErrorCause {
         for s in &self.client.events_ctx.subscribers {
             if let Some(observer) = s.as_error_observer() {
@@ -163,6 +184,8 @@ pub struct BuckdClient {
     // TODO(brasselsprouts): events_ctx should own tailers
     tailers: Option<FileTailers>,
     pub(crate) events_ctx: EventsCtx,
+    /// Why we restarted the daemon while connecting, if we did. Reported once to subscribers.
+    daemon_restarted: Option<buck2_data::DaemonRestarted>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -7,6 +7,10 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
+
+use buck2_cli_proto::daemon_constraints::TraceIoState;
+use buck2_common::legacy_configs::diff::diff_maps;
 use buck2_core::env_helper::EnvHelper;
 
 use crate::version::BuckVersion;
@@ -22,3 +26,49 @@ pub fn gen_daemon_constraints(
         trace_io_state: desired_tracing_state.into(),
     })
 }
+
+/// Describe which constraints of a running daemon (`actual`) differ from what the client
+/// `expected`, i.e. why the daemon has to be restarted.
+///
+/// Buckconfigs are not daemon constraints: changing them never restarts the daemon, and the
+/// daemon reports them itself, computed by the same `diff_maps`.
+pub fn constraint_changes(
+    actual: &buck2_cli_proto::DaemonConstraints,
+    expected: &buck2_cli_proto::DaemonConstraints,
+) -> Vec<buck2_data::DaemonConstraintChange> {
+    fn constraints(
+        c: &buck2_cli_proto::DaemonConstraints,
+        compare_trace_io_state: bool,
+    ) -> BTreeMap<&'static str, String> {
+        let mut constraints = BTreeMap::from([
+            ("version", c.version.clone()),
+            ("user_version", c.user_version.clone().unwrap_or_default()),
+        ]);
+        if compare_trace_io_state {
+            constraints.insert("trace_io_state", format!("{:?}", trace_io_state(c)));
+        }
+        constraints
+    }
+
+    fn trace_io_state(c: &buck2_cli_proto::DaemonConstraints) -> TraceIoState {
+        TraceIoState::from_i32(c.trace_io_state).unwrap_or(TraceIoState::Existing)
+    }
+
+    // Only an explicit enabled / disabled disagreement requires a restart.
+    let compare_trace_io_state = trace_io_state(actual) != TraceIoState::Existing
+        && trace_io_state(expected) != TraceIoState::Existing;
+
+    diff_maps(
+        constraints(actual, compare_trace_io_state),
+        constraints(expected, compare_trace_io_state),
+    )
+    .into_iter()
+    .map(
+        |(constraint, previous, current)| buck2_data::DaemonConstraintChange {
+            constraint: constraint.to_owned(),
+            previous: previous.unwrap_or_default(),
+            current: current.unwrap_or_default(),
+        },
+    )
+    .collect()
+}
@@ -397,6 +397,11 @@ fn convert_result<R: TryFrom<command_result::Result, Error = command_result::Res
 }
 
 impl EventsCtx {
+    /// Dispatch an event that originates in the client rather than the daemon.
+    pub(crate) async fn handle_client_event(&mut self, event: BuckEvent) -> anyhow::Result<()> {
+        self.handle_events(vec![event], &mut None).await
+    }
+
     async fn handle_tailer_stdout(&mut self, raw_output: &str) -> anyhow::Result<()> {
         self.handle_subscribers(|subscriber| subscriber.handle_output(raw_output.as_bytes()))
             .await
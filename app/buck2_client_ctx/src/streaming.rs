@@ -29,6 +29,7 @@ use crate::exit_result::FailureExitCode;
 use crate::subscribers::get::get_console_with_root;
 use crate::subscribers::get::try_get_build_id_writer;
 use crate::subscribers::get::try_get_event_log_subscriber;
+use crate::subscribers::get::try_get_otlp_exporter;
 use crate::subscribers::get::try_get_re_log_subscriber;
 use crate::subscribers::recorder::try_get_invocation_recorder;
 use crate::subscribers::subscriber::EventSubscriber;
@@ -68,6 +69,9 @@ fn default_subscribers<T: StreamingCommand>(
     if let Some(build_id_writer) = try_get_build_id_writer(cmd.event_log_opts(), ctx)? {
         subscribers.push(build_id_writer)
     }
+    if let Some(otlp_exporter) = try_get_otlp_exporter(cmd.event_log_opts(), ctx)? {
+        subscribers.push(otlp_exporter)
+    }
     if let Some(recorder) = try_get_invocation_recorder(ctx, T::COMMAND_NAME, cmd.sanitized_argv())?
     {
         subscribers.push(recorder);
@@ -168,6 +172,8 @@ impl<T: StreamingCommand> BuckSubcommand for T {
                     }
                 };
 
+                buckd.report_daemon_restart(ctx.trace_id.dupe()).await?;
+
                 let mut command_result = self.exec_impl(&mut buckd, matches, ctx).await;
 
                 if matches!(command_result, ExitResult::UncategorizedError) {
@@ -19,6 +19,7 @@ use crate::common::CommonDaemonCommandOptions;
 use crate::common::ConsoleType;
 use crate::subscribers::build_id_writer::BuildIdWriter;
 use crate::subscribers::event_log::subscriber::EventLog;
+use crate::subscribers::otlp::OtlpExporter;
 use crate::subscribers::re_log::ReLog;
 use crate::subscribers::simpleconsole::SimpleConsole;
 use crate::subscribers::subscriber::EventSubscriber;
@@ -155,3 +156,19 @@ pub(crate) fn try_get_build_id_writer(
         Ok(None)
     }
 }
+
+/// Given the command arguments, conditionally create a subscriber exporting spans over OTLP.
+pub(crate) fn try_get_otlp_exporter(
+    opts: &CommonDaemonCommandOptions,
+    ctx: &ClientCommandContext,
+) -> anyhow::Result<Option<Box<dyn EventSubscriber>>> {
+    // Unlike the event log, we do export when replaying, since that is a convenient way to send
+    // the spans of an old event log to a collector.
+    match opts.otlp_endpoint.as_ref() {
+        Some(endpoint) => Ok(Some(Box::new(OtlpExporter::new(
+            endpoint.clone(),
+            ctx.command_name.clone(),
+        )))),
+        None => Ok(None),
+    }
+}
@@ -13,6 +13,7 @@ pub(crate) mod build_id_writer;
 pub mod event_log;
 pub(crate) mod get;
 pub(crate) mod observer;
+pub(crate) mod otlp;
 pub mod re_log;
 pub mod recorder;
 pub(crate) mod simpleconsole;
@@ -0,0 +1,576 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Live export of buck2 spans to an OpenTelemetry collector.
+//!
+//! Spans are sent as OTLP/HTTP with the JSON encoding, so that we don't need to pull in the
+//! OpenTelemetry SDK. Every buck2 span becomes an OTLP span: the trace ID is the command's trace
+//! ID and span parents are preserved, so the exported tree matches what `buck2 debug chrome-trace`
+//! reconstructs from the event log.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::subscribers::subscriber::EventSubscriber;
+use crate::subscribers::subscriber::Tick;
+
+/// OTLP `Status.code` values.
+const STATUS_CODE_OK: u32 = 1;
+const STATUS_CODE_ERROR: u32 = 2;
+
+/// OTLP `Span.kind` for spans that are neither client nor server calls.
+const SPAN_KIND_INTERNAL: u32 = 1;
+
+/// A span that has started but not yet finished.
+struct OpenSpan {
+    name: String,
+    parent_id: Option<SpanId>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AttributeValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl AttributeValue {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            AttributeValue::String(s) => json!({ "stringValue": s }),
+            // OTLP/JSON encodes 64-bit integers as strings.
+            AttributeValue::Int(i) => json!({ "intValue": i.to_string() }),
+            AttributeValue::Bool(b) => json!({ "boolValue": b }),
+        }
+    }
+}
+
+/// Converts buck2 span start/end pairs into OTLP spans and ships them to a collector.
+pub(crate) struct OtlpExporter {
+    endpoint: String,
+    client: reqwest::Client,
+    trace_id: Option<String>,
+    command_name: String,
+    open_spans: HashMap<SpanId, OpenSpan>,
+    finished_spans: Vec<serde_json::Value>,
+    last_flush: Instant,
+    pending_requests: Vec<JoinHandle<()>>,
+}
+
+impl OtlpExporter {
+    /// Maximum number of spans sent in a single request.
+    const BATCH_SIZE: usize = 512;
+    /// Spans are flushed at least this often while the command is running.
+    const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+    /// How long we wait for outstanding exports when the command finishes.
+    const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(crate) fn new(endpoint: String, command_name: String) -> Self {
+        Self {
+            endpoint,
+            client: reqwest::Client::new(),
+            trace_id: None,
+            command_name,
+            open_spans: HashMap::new(),
+            finished_spans: Vec::new(),
+            last_flush: Instant::now(),
+            pending_requests: Vec::new(),
+        }
+    }
+
+    fn handle_event(&mut self, event: &BuckEvent) -> anyhow::Result<()> {
+        if self.trace_id.is_none() {
+            self.trace_id = Some(otlp_trace_id(&event.trace_id()?.to_string()));
+        }
+
+        match event.data() {
+            buck2_data::buck_event::Data::SpanStart(start) => {
+                let span_id = match event.span_id() {
+                    Some(span_id) => span_id,
+                    None => return Ok(()),
+                };
+                let (name, attributes) = match start.data.as_ref() {
+                    Some(data) => span_start_info(event, data, &self.command_name),
+                    None => return Ok(()),
+                };
+                self.open_spans.insert(
+                    span_id,
+                    OpenSpan {
+                        name,
+                        parent_id: event.parent_id(),
+                        start: event.timestamp(),
+                        attributes,
+                    },
+                );
+            }
+            buck2_data::buck_event::Data::SpanEnd(end) => {
+                let span_id = match event.span_id() {
+                    Some(span_id) => span_id,
+                    None => return Ok(()),
+                };
+                // Spans that started before we subscribed have nothing to pair with.
+                let mut open = match self.open_spans.remove(&span_id) {
+                    Some(open) => open,
+                    None => return Ok(()),
+                };
+                let error = end
+                    .data
+                    .as_ref()
+                    .and_then(|data| span_end_info(data, &mut open.attributes));
+                let trace_id = self.trace_id.as_deref().unwrap_or_default();
+                self.finished_spans.push(otlp_span(
+                    trace_id,
+                    span_id,
+                    &open,
+                    event.timestamp(),
+                    error,
+                ));
+            }
+            buck2_data::buck_event::Data::Instant(_) | buck2_data::buck_event::Data::Record(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Send every finished span to the collector. Requests run in the background so that a slow
+    /// or unreachable collector never holds up the command.
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        self.pending_requests.retain(|r| !r.is_finished());
+
+        while !self.finished_spans.is_empty() {
+            let batch_len = Self::BATCH_SIZE.min(self.finished_spans.len());
+            let batch: Vec<_> = self.finished_spans.drain(..batch_len).collect();
+            let body = otlp_request(batch).to_string();
+            let request = self
+                .client
+                .post(&self.endpoint)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+            let endpoint = self.endpoint.clone();
+            self.pending_requests.push(tokio::spawn(async move {
+                let res = match request.send().await {
+                    Ok(response) => response.error_for_status().map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = res {
+                    tracing::warn!("Failed to export spans to `{}`: {:#}", endpoint, e);
+                }
+            }));
+        }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for OtlpExporter {
+    async fn handle_events(&mut self, events: &[Arc<BuckEvent>]) -> anyhow::Result<()> {
+        for event in events {
+            self.handle_event(event)?;
+        }
+        if self.finished_spans.len() >= Self::BATCH_SIZE {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    async fn tick(&mut self, _tick: &Tick) -> anyhow::Result<()> {
+        if self.last_flush.elapsed() >= Self::FLUSH_INTERVAL {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    async fn exit(&mut self) -> anyhow::Result<()> {
+        self.flush();
+        let pending = futures::future::join_all(mem::take(&mut self.pending_requests));
+        if tokio::time::timeout(Self::EXIT_TIMEOUT, pending)
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "Timed out exporting spans to `{}`, some spans were dropped",
+                self.endpoint
+            );
+        }
+        Ok(())
+    }
+}
+
+/// OTLP wants trace IDs as 32 hex characters, which is a UUID without the hyphens.
+fn otlp_trace_id(trace_id: &str) -> String {
+    trace_id.replace('-', "")
+}
+
+fn otlp_span_id(span_id: SpanId) -> String {
+    format!("{:016x}", u64::from(span_id))
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+        .to_string()
+}
+
+fn otlp_span(
+    trace_id: &str,
+    span_id: SpanId,
+    open: &OpenSpan,
+    end: SystemTime,
+    error: Option<String>,
+) -> serde_json::Value {
+    let status = match error {
+        Some(message) => json!({ "code": STATUS_CODE_ERROR, "message": message }),
+        None => json!({ "code": STATUS_CODE_OK }),
+    };
+    let attributes: Vec<_> = open
+        .attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": value.to_json() }))
+        .collect();
+    let mut span = json!({
+        "traceId": trace_id,
+        "spanId": otlp_span_id(span_id),
+        "name": open.name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(open.start),
+        "endTimeUnixNano": unix_nanos(end),
+        "attributes": attributes,
+        "status": status,
+    });
+    if let Some(parent_id) = open.parent_id {
+        span["parentSpanId"] = json!(otlp_span_id(parent_id));
+    }
+    span
+}
+
+fn otlp_request(spans: Vec<serde_json::Value>) -> serde_json::Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "buck2" } },
+                    {
+                        "key": "service.version",
+                        "value": { "stringValue": buck2_build_info::revision().unwrap_or("unknown") },
+                    },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "buck2" },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// Name and initial attributes for a span, derived from its start event.
+fn span_start_info(
+    event: &BuckEvent,
+    data: &buck2_data::span_start_event::Data,
+    command_name: &str,
+) -> (String, Vec<(&'static str, AttributeValue)>) {
+    use buck2_data::span_start_event::Data;
+
+    let opts = TargetDisplayOptions::for_log();
+    let mut attributes = Vec::new();
+
+    let name = match data {
+        Data::Command(_) => {
+            attributes.push((
+                "buck2.command",
+                AttributeValue::String(command_name.to_owned()),
+            ));
+            format!("buck2 {}", command_name)
+        }
+        Data::Analysis(analysis) => {
+            if let Some(target) = &analysis.target {
+                if let Ok(target) = display::display_analysis_target(target, opts) {
+                    attributes.push(("buck2.target", AttributeValue::String(target)));
+                }
+            }
+            "analysis".to_owned()
+        }
+        Data::ActionExecution(action) => {
+            if let Some(key) = &action.key {
+                if let Ok(target) = display::display_action_key(key, opts) {
+                    attributes.push(("buck2.target", AttributeValue::String(target)));
+                }
+            }
+            if let Some(name) = &action.name {
+                attributes.push((
+                    "buck2.action.category",
+                    AttributeValue::String(name.category.clone()),
+                ));
+                if !name.identifier.is_empty() {
+                    attributes.push((
+                        "buck2.action.identifier",
+                        AttributeValue::String(name.identifier.clone()),
+                    ));
+                }
+            }
+            if let Some(kind) = buck2_data::ActionKind::from_i32(action.kind) {
+                attributes.push((
+                    "buck2.action.kind",
+                    AttributeValue::String(format!("{:?}", kind)),
+                ));
+            }
+            "action".to_owned()
+        }
+        Data::ExecutorStage(stage) => match stage
+            .stage
+            .as_ref()
+            .and_then(|s| display::display_executor_stage(s).ok())
+        {
+            Some(stage) => stage.to_owned(),
+            None => "executor_stage".to_owned(),
+        },
+        Data::Load(load) => {
+            attributes.push((
+                "buck2.package",
+                AttributeValue::String(load.module_id.clone()),
+            ));
+            "load".to_owned()
+        }
+        Data::Materialization(materialization) => {
+            if let Some(digest) = &materialization.action_digest {
+                attributes.push((
+                    "buck2.action.digest",
+                    AttributeValue::String(digest.clone()),
+                ));
+            }
+            "materialization".to_owned()
+        }
+        Data::FinalMaterialization(materialization) => {
+            if let Some(artifact) = &materialization.artifact {
+                if let Some(key) = &artifact.key {
+                    if let Ok(target) = display::display_action_key(key, opts) {
+                        attributes.push(("buck2.target", AttributeValue::String(target)));
+                    }
+                }
+                attributes.push((
+                    "buck2.artifact.path",
+                    AttributeValue::String(artifact.path.clone()),
+                ));
+            }
+            "final_materialization".to_owned()
+        }
+        Data::ReUpload(_) => "re_upload".to_owned(),
+        // Everything else gets the same description the console would show for it.
+        _ => display::display_event(event, opts).unwrap_or_else(|_| "span".to_owned()),
+    };
+
+    (name, attributes)
+}
+
+/// Record the attributes a span end event carries, and return an error message if the span
+/// failed.
+fn span_end_info(
+    data: &buck2_data::span_end_event::Data,
+    attributes: &mut Vec<(&'static str, AttributeValue)>,
+) -> Option<String> {
+    use buck2_data::span_end_event::Data;
+
+    match data {
+        Data::Command(command) => {
+            attributes.push(("buck2.success", AttributeValue::Bool(command.is_success)));
+            if command.is_success {
+                None
+            } else {
+                Some(command.error_messages.join("\n"))
+            }
+        }
+        Data::ActionExecution(action) => {
+            if let Some(kind) = buck2_data::ActionExecutionKind::from_i32(action.execution_kind) {
+                attributes.push((
+                    "buck2.action.execution_kind",
+                    AttributeValue::String(format!("{:?}", kind)),
+                ));
+            }
+            attributes.push((
+                "buck2.action.output_size",
+                AttributeValue::Int(action.output_size as i64),
+            ));
+            if action.failed {
+                Some("action failed".to_owned())
+            } else {
+                None
+            }
+        }
+        Data::Materialization(materialization) => {
+            attributes.push((
+                "buck2.materialization.path",
+                AttributeValue::String(materialization.path.clone()),
+            ));
+            attributes.push((
+                "buck2.materialization.file_count",
+                AttributeValue::Int(materialization.file_count as i64),
+            ));
+            attributes.push((
+                "buck2.materialization.total_bytes",
+                AttributeValue::Int(materialization.total_bytes as i64),
+            ));
+            if materialization.success {
+                None
+            } else {
+                Some(
+                    materialization
+                        .error
+                        .clone()
+                        .unwrap_or_else(|| "materialization failed".to_owned()),
+                )
+            }
+        }
+        Data::ReUpload(upload) => {
+            if let Some(digests) = upload.digests_uploaded {
+                attributes.push((
+                    "buck2.re.digests_uploaded",
+                    AttributeValue::Int(digests as i64),
+                ));
+            }
+            if let Some(bytes) = upload.bytes_uploaded {
+                attributes.push(("buck2.re.bytes_uploaded", AttributeValue::Int(bytes as i64)));
+            }
+            None
+        }
+        Data::SpanCancelled(_) => Some("cancelled".to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_wrapper_common::invocation_id::TraceId;
+    use dupe::Dupe;
+
+    use super::*;
+
+    fn span_start(
+        trace_id: &TraceId,
+        span_id: SpanId,
+        parent_id: Option<SpanId>,
+        data: buck2_data::span_start_event::Data,
+    ) -> Arc<BuckEvent> {
+        Arc::new(BuckEvent::new(
+            SystemTime::now(),
+            trace_id.dupe(),
+            Some(span_id),
+            parent_id,
+            buck2_data::SpanStartEvent { data: Some(data) }.into(),
+        ))
+    }
+
+    fn span_end(
+        trace_id: &TraceId,
+        span_id: SpanId,
+        parent_id: Option<SpanId>,
+        data: buck2_data::span_end_event::Data,
+    ) -> Arc<BuckEvent> {
+        Arc::new(BuckEvent::new(
+            SystemTime::now(),
+            trace_id.dupe(),
+            Some(span_id),
+            parent_id,
+            buck2_data::SpanEndEvent {
+                data: Some(data),
+                ..Default::default()
+            }
+            .into(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_spans_are_paired_and_parented() -> anyhow::Result<()> {
+        let trace_id = TraceId::new();
+        let command = SpanId::new();
+        let action = SpanId::new();
+
+        let mut exporter = OtlpExporter::new(
+            "http://127.0.0.1:1/v1/traces".to_owned(),
+            "build".to_owned(),
+        );
+        exporter
+            .handle_events(&[
+                span_start(
+                    &trace_id,
+                    command,
+                    None,
+                    buck2_data::CommandStart::default().into(),
+                ),
+                span_start(
+                    &trace_id,
+                    action,
+                    Some(command),
+                    buck2_data::ActionExecutionStart {
+                        name: Some(buck2_data::ActionName {
+                            category: "cxx_compile".to_owned(),
+                            identifier: "foo.cpp".to_owned(),
+                        }),
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                span_end(
+                    &trace_id,
+                    action,
+                    Some(command),
+                    buck2_data::ActionExecutionEnd {
+                        failed: true,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            ])
+            .await?;
+
+        assert_eq!(1, exporter.finished_spans.len());
+        assert_eq!(1, exporter.open_spans.len());
+
+        let span = &exporter.finished_spans[0];
+        assert_eq!(otlp_trace_id(&trace_id.to_string()), span["traceId"]);
+        assert_eq!(otlp_span_id(action), span["spanId"]);
+        assert_eq!(otlp_span_id(command), span["parentSpanId"]);
+        assert_eq!("action", span["name"]);
+        assert_eq!(STATUS_CODE_ERROR, span["status"]["code"]);
+        assert!(span["attributes"].as_array().unwrap().contains(&json!({
+            "key": "buck2.action.category",
+            "value": { "stringValue": "cxx_compile" },
+        })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(
+            "0f3e4d3a8c1b4f5e9a2b3c4d5e6f7a8b",
+            otlp_trace_id("0f3e4d3a-8c1b-4f5e-9a2b-3c4d5e6f7a8b")
+        );
+        let span_id = SpanId::new();
+        assert_eq!(
+            format!("{:016x}", u64::from(span_id)),
+            otlp_span_id(span_id)
+        );
+        assert_eq!(16, otlp_span_id(span_id).len());
+    }
+}
@@ -19,6 +19,8 @@ use async_trait::async_trait;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_data::CommandExecutionDetails;
 use buck2_event_observer::display;
+use buck2_event_observer::display::display_configuration_changed;
+use buck2_event_observer::display::display_daemon_restarted;
 use buck2_event_observer::display::display_file_watcher_end;
 use buck2_event_observer::display::TargetDisplayOptions;
 use buck2_event_observer::event_observer::EventObserver;
@@ -335,6 +337,30 @@ where
         Ok(())
     }
 
+    async fn handle_configuration_changed(
+        &mut self,
+        changed: &buck2_data::ConfigurationChanged,
+        _event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        for x in display_configuration_changed(changed) {
+            echo!("{}", x)?;
+        }
+        self.notify_printed();
+        Ok(())
+    }
+
+    async fn handle_daemon_restarted(
+        &mut self,
+        restarted: &buck2_data::DaemonRestarted,
+        _event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        for x in display_daemon_restarted(restarted) {
+            echo!("{}", x)?;
+        }
+        self.notify_printed();
+        Ok(())
+    }
+
     async fn handle_event(&mut self, event: &Arc<BuckEvent>) -> anyhow::Result<()> {
         self.update_event_observer(Instant::now(), event)?;
         self.handle_inner_event(event)
@@ -127,6 +127,12 @@ pub trait UnpackingEventSubscriber: Send {
             buck2_data::instant_event::Data::ConsolePreferences(preferences) => {
                 self.handle_console_preferences(preferences, event).await
             }
+            buck2_data::instant_event::Data::ConfigurationChanged(changed) => {
+                self.handle_configuration_changed(changed, event).await
+            }
+            buck2_data::instant_event::Data::DaemonRestarted(restarted) => {
+                self.handle_daemon_restarted(restarted, event).await
+            }
             _ => Ok(()),
         }
     }
@@ -192,6 +198,22 @@ pub trait UnpackingEventSubscriber: Send {
         _event: &BuckEvent,
     ) -> anyhow::Result<()>;
 
+    async fn handle_configuration_changed(
+        &mut self,
+        _changed: &buck2_data::ConfigurationChanged,
+        _event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn handle_daemon_restarted(
+        &mut self,
+        _restarted: &buck2_data::DaemonRestarted,
+        _event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     /// Give the subscriber a chance to react to errors as we start trying to clean up.
     /// They may return another error, which will be incorporated into the end result.
     async fn handle_error(&mut self, _error: &anyhow::Error) -> anyhow::Result<()>;
@@ -18,6 +18,8 @@ use async_trait::async_trait;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_data::CommandExecutionDetails;
 use buck2_event_observer::display;
+use buck2_event_observer::display::display_configuration_changed;
+use buck2_event_observer::display::display_daemon_restarted;
 use buck2_event_observer::display::display_file_watcher_end;
 use buck2_event_observer::display::TargetDisplayOptions;
 use buck2_event_observer::event_observer::DebugEventObserverExtra;
@@ -36,6 +38,8 @@ use superconsole::components::Split;
 use superconsole::content::colored_lines_from_multiline_string;
 use superconsole::content::lines_from_multiline_string;
 use superconsole::content::LinesExt;
+use superconsole::input::Key;
+use superconsole::input::KeyDecoder;
 use superconsole::style::Attribute;
 use superconsole::style::Color;
 use superconsole::style::ContentStyle;
@@ -54,6 +58,8 @@ pub(crate) use superconsole::SuperConsole;
 use crate::subscribers::simpleconsole::SimpleConsole;
 use crate::subscribers::subscriber::Tick;
 use crate::subscribers::subscriber_unpack::UnpackingEventSubscriber;
+use crate::subscribers::superconsole::action_browser::ActionBrowser;
+use crate::subscribers::superconsole::action_browser::ActionBrowserComponent;
 use crate::subscribers::superconsole::commands::CommandsComponent;
 use crate::subscribers::superconsole::debug_events::DebugEventsComponent;
 use crate::subscribers::superconsole::dice::DiceComponent;
@@ -62,6 +68,7 @@ use crate::subscribers::superconsole::re::ReHeader;
 use crate::subscribers::superconsole::timed_list::Cutoffs;
 use crate::subscribers::superconsole::timed_list::TimedList;
 
+mod action_browser;
 mod commands;
 mod common;
 pub(crate) mod debug_events;
@@ -113,6 +120,8 @@ pub(crate) struct SuperConsoleState {
     /// This contains the SpanTracker, which is why it's part of the SuperConsoleState.
     simple_console: SimpleConsole<DebugEventObserverExtra>,
     config: SuperConsoleConfig,
+    action_browser: ActionBrowser,
+    key_decoder: KeyDecoder,
 }
 
 pub struct SuperConsoleConfig {
@@ -160,6 +169,7 @@ impl StatefulSuperConsole {
         components.push(Box::new(DiceComponent));
         components.push(Box::new(CommandsComponent));
         components.push(Box::new(TimedList::new(CUTOFFS, header)));
+        components.push(Box::new(ActionBrowserComponent));
         let root = Box::new(Split::new(
             components,
             Direction::Vertical,
@@ -241,6 +251,8 @@ impl StatefulSuperConsole {
                     show_waiting_message,
                 ),
                 config,
+                action_browser: ActionBrowser::new(),
+                key_decoder: KeyDecoder::new(),
             },
             super_console: Some(super_console),
             verbosity,
@@ -288,8 +300,10 @@ impl SuperConsoleState {
             observer.session_info(),
             observer.re_state(),
             observer.io_state(),
+            observer.downward_api_state(),
             observer.extra().dice_state(),
             observer.extra().debug_events(),
+            &self.action_browser,
         ]
     }
 }
@@ -310,6 +324,80 @@ impl StatefulSuperConsole {
         self.handle_stderr(&format!("{what}: {on_off}, press `{key}` to revert"))
             .await
     }
+
+    async fn handle_key(&mut self, key: Key) -> anyhow::Result<()> {
+        // The action browser gets first pick when it's open, so that e.g. typing a filter doesn't
+        // toggle other components.
+        let height = self.state.config.max_lines;
+        if self
+            .state
+            .action_browser
+            .handle_key(key, height)
+            .is_handled()
+        {
+            return Ok(());
+        }
+
+        match key {
+            Key::Char('a') => self.state.action_browser.toggle(),
+            Key::Char(c) => self.handle_toggle_key(c).await?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn handle_toggle_key(&mut self, c: char) -> anyhow::Result<()> {
+        if c == 'd' {
+            self.toggle("DICE component", 'd', |s| &mut s.state.config.enable_dice)
+                .await?;
+        } else if c == 'e' {
+            self.toggle("Debug events component", 'e', |s| {
+                &mut s.state.config.enable_debug_events
+            })
+            .await?;
+        } else if c == '2' {
+            self.toggle("Two lines mode", '2', |s| &mut s.state.config.two_lines)
+                .await?;
+        } else if c == 'r' {
+            self.toggle("Detailed RE", 'r', |s| {
+                &mut s.state.config.enable_detailed_re
+            })
+            .await?;
+        } else if c == 'i' {
+            self.toggle("I/O counters", 'i', |s| &mut s.state.config.enable_io)
+                .await?;
+        } else if c == 'p' {
+            self.toggle("Display target configurations", 'p', |s| {
+                &mut s.state.config.display_platform
+            })
+            .await?;
+        } else if c == 'c' {
+            self.toggle("Commands", 'c', |s| &mut s.state.config.enable_commands)
+                .await?;
+        } else if c == '+' {
+            self.state.config.max_lines = self.state.config.max_lines.saturating_add(1);
+        } else if c == '-' {
+            self.state.config.max_lines = self.state.config.max_lines.saturating_sub(1);
+        } else if c == '?' || c == 'h' {
+            self.handle_stderr(
+                "Help:\n\
+                `d` = toggle DICE\n\
+                `e` = toggle debug events\n\
+                `2` = toggle two lines mode\n\
+                `r` = toggle detailed RE\n\
+                `i` = toggle I/O counters\n\
+                `p` = display target configurations\n\
+                `+` = show more lines\n\
+                `-` = show fewer lines\n\
+                `a` = browse all actions\n\
+                `h` = show this help",
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
 }
 
 // TODO(brasselsprouts): after deprecating filetailers, simplify these code paths
@@ -321,6 +409,10 @@ impl UnpackingEventSubscriber for StatefulSuperConsole {
                 self.handle_inner_event(event)
                     .await
                     .with_context(|| display::InvalidBuckEvent(event.clone()))?;
+                self.state
+                    .action_browser
+                    .handle_event(event)
+                    .with_context(|| display::InvalidBuckEvent(event.clone()))?;
                 self.state
                     .simple_console
                     .update_event_observer(self.state.current_tick.start_time, event)?;
@@ -408,6 +500,46 @@ impl UnpackingEventSubscriber for StatefulSuperConsole {
         }
     }
 
+    async fn handle_configuration_changed(
+        &mut self,
+        changed: &buck2_data::ConfigurationChanged,
+        event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        match &mut self.super_console {
+            Some(super_console) => {
+                super_console
+                    .emit(display_configuration_changed(changed).into_map(|x| Line::sanitized(&x)));
+                Ok(())
+            }
+            None => {
+                self.state
+                    .simple_console
+                    .handle_configuration_changed(changed, event)
+                    .await
+            }
+        }
+    }
+
+    async fn handle_daemon_restarted(
+        &mut self,
+        restarted: &buck2_data::DaemonRestarted,
+        event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        match &mut self.super_console {
+            Some(super_console) => {
+                super_console
+                    .emit(display_daemon_restarted(restarted).into_map(|x| Line::sanitized(&x)));
+                Ok(())
+            }
+            None => {
+                self.state
+                    .simple_console
+                    .handle_daemon_restarted(restarted, event)
+                    .await
+            }
+        }
+    }
+
     async fn handle_output(&mut self, raw_output: &[u8]) -> anyhow::Result<()> {
         if let Some(super_console) = self.super_console.take() {
             super_console.finalize(&self.state.state())?;
@@ -417,53 +549,9 @@ impl UnpackingEventSubscriber for StatefulSuperConsole {
     }
 
     async fn handle_console_interaction(&mut self, c: char) -> anyhow::Result<()> {
-        if c == 'd' {
-            self.toggle("DICE component", 'd', |s| &mut s.state.config.enable_dice)
-                .await?;
-        } else if c == 'e' {
-            self.toggle("Debug events component", 'e', |s| {
-                &mut s.state.config.enable_debug_events
-            })
-            .await?;
-        } else if c == '2' {
-            self.toggle("Two lines mode", '2', |s| &mut s.state.config.two_lines)
-                .await?;
-        } else if c == 'r' {
-            self.toggle("Detailed RE", 'r', |s| {
-                &mut s.state.config.enable_detailed_re
-            })
-            .await?;
-        } else if c == 'i' {
-            self.toggle("I/O counters", 'i', |s| &mut s.state.config.enable_io)
-                .await?;
-        } else if c == 'p' {
-            self.toggle("Display target configurations", 'p', |s| {
-                &mut s.state.config.display_platform
-            })
-            .await?;
-        } else if c == 'c' {
-            self.toggle("Commands", 'c', |s| &mut s.state.config.enable_commands)
-                .await?;
-        } else if c == '+' {
-            self.state.config.max_lines = self.state.config.max_lines.saturating_add(1);
-        } else if c == '-' {
-            self.state.config.max_lines = self.state.config.max_lines.saturating_sub(1);
-        } else if c == '?' || c == 'h' {
-            self.handle_stderr(
-                "Help:\n\
-                `d` = toggle DICE\n\
-                `e` = toggle debug events\n\
-                `2` = toggle two lines mode\n\
-                `r` = toggle detailed RE\n\
-                `i` = toggle I/O counters\n\
-                `p` = display target configurations\n\
-                `+` = show more lines\n\
-                `-` = show fewer lines\n\
-                `h` = show this help",
-            )
-            .await?;
+        for key in self.state.key_decoder.push(c) {
+            self.handle_key(key).await?;
         }
-
         Ok(())
     }
 
@@ -499,6 +587,10 @@ impl UnpackingEventSubscriber for StatefulSuperConsole {
 
     async fn tick(&mut self, tick: &Tick) -> anyhow::Result<()> {
         self.state.simple_console.detect_hangs().await?;
+        // A lone `esc` can only be told apart from an escape sequence once input goes quiet.
+        if let Some(key) = self.state.key_decoder.flush() {
+            self.handle_key(key).await?;
+        }
         match &mut self.super_console {
             Some(super_console) => {
                 self.state.current_tick = tick.dupe();
@@ -0,0 +1,581 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An interactive, scrollable list of every action this command ran, toggled with `a`.
+//! Actions can be expanded to show their command line and stderr, and filtered by target.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_event_observer::what_ran::local_command_to_string;
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+use superconsole::content::colored_lines_from_multiline_string;
+use superconsole::input::Focus;
+use superconsole::input::InputHandler;
+use superconsole::input::InputResult;
+use superconsole::input::Key;
+use superconsole::input::ScrollState;
+use superconsole::input::TextInput;
+use superconsole::style::Attribute;
+use superconsole::style::Color;
+use superconsole::style::Stylize;
+use superconsole::Component;
+use superconsole::Dimensions;
+use superconsole::DrawMode;
+use superconsole::Line;
+use superconsole::Lines;
+use superconsole::Span;
+use superconsole::State;
+
+use crate::subscribers::subscriber::Tick;
+use crate::subscribers::superconsole::SuperConsoleConfig;
+use crate::subscribers::superconsole::TimeSpeed;
+
+/// Older actions are dropped once this many have been recorded, to bound memory on large builds.
+const MAX_ACTIONS: usize = 10000;
+/// How much of an action's stderr is kept, from the end.
+const MAX_STDERR_BYTES: usize = 16 * 1024;
+/// How many lines of stderr are shown when an action is expanded.
+const MAX_STDERR_LINES: usize = 20;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum BrowserFocus {
+    List,
+    Filter,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ActionStatus {
+    Running { start: Instant },
+    Succeeded { duration: Duration },
+    Failed { duration: Duration },
+}
+
+#[derive(Debug)]
+struct ActionRecord {
+    /// Monotonic identifier, used to keep track of the expanded action as the list changes.
+    seq: u64,
+    target: String,
+    identity: String,
+    status: ActionStatus,
+    command: Option<String>,
+    stderr: String,
+}
+
+pub(crate) struct ActionBrowser {
+    open: bool,
+    actions: VecDeque<ActionRecord>,
+    /// `seq` of the first entry in `actions`.
+    first_seq: u64,
+    running: HashMap<SpanId, u64>,
+    focus: Focus<BrowserFocus>,
+    scroll: ScrollState,
+    filter: TextInput,
+    expanded: Option<u64>,
+}
+
+impl ActionBrowser {
+    pub(crate) fn new() -> Self {
+        Self {
+            open: false,
+            actions: VecDeque::new(),
+            first_seq: 0,
+            running: HashMap::new(),
+            focus: Focus::new(vec![BrowserFocus::List, BrowserFocus::Filter]),
+            scroll: ScrollState::new(),
+            filter: TextInput::new(),
+            expanded: None,
+        }
+    }
+
+    #[cfg(test)]
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.open = !self.open;
+        self.focus.focus(BrowserFocus::List);
+    }
+
+    pub(crate) fn handle_event(&mut self, event: &BuckEvent) -> anyhow::Result<()> {
+        let span_id = match event.span_id() {
+            Some(span_id) => span_id,
+            None => return Ok(()),
+        };
+
+        match event.data() {
+            buck2_data::buck_event::Data::SpanStart(buck2_data::SpanStartEvent {
+                data: Some(buck2_data::span_start_event::Data::ActionExecution(action)),
+            }) => {
+                let opts = TargetDisplayOptions::for_console(false);
+                let target = match &action.key {
+                    Some(key) => display::display_action_key(key, opts)?,
+                    None => String::new(),
+                };
+                let identity = display::display_action_identity(
+                    action.key.as_ref(),
+                    action.name.as_ref(),
+                    opts,
+                )?;
+                let seq = self.first_seq + self.actions.len() as u64;
+                self.actions.push_back(ActionRecord {
+                    seq,
+                    target,
+                    identity,
+                    status: ActionStatus::Running {
+                        start: Instant::now(),
+                    },
+                    command: None,
+                    stderr: String::new(),
+                });
+                self.running.insert(span_id, seq);
+                if self.actions.len() > MAX_ACTIONS {
+                    if let Some(evicted) = self.actions.pop_front() {
+                        if let ActionStatus::Running { .. } = evicted.status {
+                            self.running.retain(|_, seq| *seq != evicted.seq);
+                        }
+                    }
+                    self.first_seq += 1;
+                }
+            }
+            buck2_data::buck_event::Data::SpanEnd(buck2_data::SpanEndEvent {
+                data: Some(buck2_data::span_end_event::Data::ActionExecution(action)),
+                ..
+            }) => {
+                let record = match self
+                    .running
+                    .remove(&span_id)
+                    .and_then(|seq| self.get_mut(seq))
+                {
+                    Some(record) => record,
+                    None => return Ok(()),
+                };
+                let wall_time = action
+                    .wall_time
+                    .clone()
+                    .and_then(|d| Duration::try_from(d).ok());
+                let duration = match (&record.status, wall_time) {
+                    (_, Some(wall_time)) => wall_time,
+                    (ActionStatus::Running { start }, None) => start.elapsed(),
+                    (_, None) => Duration::ZERO,
+                };
+                record.status = if action.failed {
+                    ActionStatus::Failed { duration }
+                } else {
+                    ActionStatus::Succeeded { duration }
+                };
+                record.command = command_line(action);
+                record.stderr = action
+                    .commands
+                    .last()
+                    .and_then(|c| c.details.as_ref())
+                    .map(|d| tail(&d.stderr, MAX_STDERR_BYTES).to_owned())
+                    .unwrap_or_default();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn get_mut(&mut self, seq: u64) -> Option<&mut ActionRecord> {
+        let idx = seq.checked_sub(self.first_seq)?;
+        self.actions.get_mut(idx as usize)
+    }
+
+    fn filtered(&self) -> impl Iterator<Item = &ActionRecord> {
+        let filter = self.filter.text();
+        self.actions
+            .iter()
+            .filter(move |a| filter.is_empty() || a.target.contains(filter))
+    }
+
+    /// Offer a key to the browser. Keys it doesn't use are left for the regular console bindings.
+    pub(crate) fn handle_key(&mut self, key: Key, height: usize) -> InputResult {
+        if !self.open {
+            return InputResult::Ignored;
+        }
+
+        if self.focus.handle_key(key).is_handled() {
+            return InputResult::Handled;
+        }
+
+        match self.focus.current() {
+            BrowserFocus::Filter => match key {
+                Key::Enter => self.focus.focus(BrowserFocus::List),
+                Key::Escape => {
+                    self.filter.clear();
+                    self.focus.focus(BrowserFocus::List);
+                }
+                key => {
+                    let handled = self.filter.handle_key(key);
+                    if handled.is_handled() {
+                        // The old selection is meaningless in the new list.
+                        self.scroll.select(0, 0, height);
+                    }
+                    return handled;
+                }
+            },
+            BrowserFocus::List => {
+                let len = self.filtered().count();
+                if self.scroll.handle_key(key, len, height).is_handled() {
+                    return InputResult::Handled;
+                }
+                match key {
+                    Key::Enter | Key::Char(' ') => {
+                        let selected = self
+                            .scroll
+                            .selected(len)
+                            .and_then(|idx| self.filtered().nth(idx))
+                            .map(|a| a.seq);
+                        self.expanded = if self.expanded == selected {
+                            None
+                        } else {
+                            selected
+                        };
+                    }
+                    Key::Char('/') => self.focus.focus(BrowserFocus::Filter),
+                    Key::Escape | Key::Char('q') => {
+                        if self.expanded.is_some() {
+                            self.expanded = None;
+                        } else {
+                            self.open = false;
+                        }
+                    }
+                    _ => return InputResult::Ignored,
+                }
+            }
+        }
+
+        InputResult::Handled
+    }
+}
+
+fn command_line(action: &buck2_data::ActionExecutionEnd) -> Option<String> {
+    use buck2_data::command_execution_details::Command;
+
+    match action.commands.last()?.details.as_ref()?.command.as_ref()? {
+        Command::LocalCommand(command) => Some(local_command_to_string(command)),
+        Command::RemoteCommand(command) => Some(format!(
+            "Remote action, reproduce with: `frecli cas download-action {}`",
+            command.action_digest
+        )),
+        Command::OmittedLocalCommand(command) => Some(format!(
+            "Local command omitted from the event log, action digest: {}",
+            command.action_digest
+        )),
+    }
+}
+
+/// The last `max` bytes of `s`, adjusted to a char boundary.
+fn tail(s: &str, max: usize) -> &str {
+    let mut start = s.len().saturating_sub(max);
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    &s[start..]
+}
+
+/// Draws the [`ActionBrowser`] when it is open.
+#[derive(Debug)]
+pub(crate) struct ActionBrowserComponent;
+
+impl Component for ActionBrowserComponent {
+    fn draw_unchecked(
+        &self,
+        state: &State,
+        _dimensions: Dimensions,
+        mode: DrawMode,
+    ) -> anyhow::Result<Lines> {
+        let browser = state.get::<ActionBrowser>()?;
+        if !browser.open || mode == DrawMode::Final {
+            return Ok(vec![]);
+        }
+
+        let config = state.get::<SuperConsoleConfig>()?;
+        let tick = state.get::<Tick>()?;
+        let time_speed = state.get::<TimeSpeed>()?;
+        let now = tick.start_time + tick.elapsed_time;
+
+        let actions: Vec<&ActionRecord> = browser.filtered().collect();
+        let running = browser.running.len();
+        let finished = browser.actions.len().saturating_sub(running);
+        let range = browser
+            .scroll
+            .visible_range(actions.len(), config.max_lines);
+        let selected = browser.scroll.selected(actions.len());
+
+        let filter_focused = browser.focus.is_focused(BrowserFocus::Filter);
+        let mut header = format!(
+            "Actions: {} running, {} finished. Showing {}-{} of {}. Filter: {}",
+            running,
+            finished,
+            (range.start + 1).min(range.end),
+            range.end,
+            actions.len(),
+            browser.filter.text(),
+        );
+        if filter_focused {
+            header.push('_');
+        }
+        let mut lines = vec![Line::from_iter([Span::new_styled_lossy(header.bold())])];
+
+        for (idx, action) in actions[range.clone()].iter().enumerate() {
+            let is_selected = selected == Some(range.start + idx);
+            let (status, color, duration) = match action.status {
+                ActionStatus::Running { start } => (
+                    "running",
+                    Color::White,
+                    now.saturating_duration_since(start),
+                ),
+                ActionStatus::Succeeded { duration } => ("ok", Color::Green, duration),
+                ActionStatus::Failed { duration } => ("failed", Color::Red, duration),
+            };
+            let marker = if is_selected && !filter_focused {
+                "> "
+            } else {
+                "  "
+            };
+            let mut line = Line::from_iter([
+                Span::new_unstyled_lossy(marker),
+                Span::new_colored_lossy(&format!("{:<8}", status), color),
+                Span::new_unstyled_lossy(format!(
+                    "{:>6} ",
+                    display::duration_as_secs_elapsed(duration, time_speed.speed())
+                )),
+                Span::new_unstyled_lossy(&action.identity),
+            ]);
+            if is_selected {
+                for span in &mut line.0 {
+                    span.stylization.attributes.set(Attribute::Reverse);
+                }
+            }
+            lines.push(line);
+
+            if browser.expanded == Some(action.seq) {
+                lines.extend(expanded_lines(action));
+            }
+        }
+
+        lines.push(Line::sanitized(if filter_focused {
+            "Type to filter by target. `enter` = done, `esc` = clear filter"
+        } else {
+            "`up`/`down` = select, `enter` = expand, `/` = filter, `esc` = close"
+        }));
+
+        Ok(lines)
+    }
+}
+
+fn expanded_lines(action: &ActionRecord) -> Lines {
+    let indent = |mut line: Line| {
+        line.0.insert(0, Span::padding(6));
+        line
+    };
+
+    let mut lines = Vec::new();
+    match &action.command {
+        Some(command) => lines.push(indent(Line::sanitized(&format!("$ {}", command)))),
+        None if matches!(action.status, ActionStatus::Running { .. }) => lines.push(indent(
+            Line::sanitized("(command is shown once the action finishes)"),
+        )),
+        None => lines.push(indent(Line::sanitized("(no command)"))),
+    }
+
+    if action.stderr.is_empty() {
+        lines.push(indent(Line::sanitized("(no stderr)")));
+    } else {
+        let stderr = colored_lines_from_multiline_string(&action.stderr);
+        let skip = stderr.len().saturating_sub(MAX_STDERR_LINES);
+        if skip > 0 {
+            lines.push(indent(Line::sanitized(&format!(
+                "({} earlier lines of stderr omitted)",
+                skip
+            ))));
+        }
+        lines.extend(stderr.into_iter().skip(skip).map(indent));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn action_key(target: &str) -> buck2_data::ActionKey {
+        let (package, name) = target.split_once(':').unwrap();
+        buck2_data::ActionKey {
+            owner: Some(buck2_data::action_key::Owner::TargetLabel(
+                buck2_data::ConfiguredTargetLabel {
+                    label: Some(buck2_data::TargetLabel {
+                        package: package.to_owned(),
+                        name: name.to_owned(),
+                    }),
+                    configuration: Some(buck2_data::Configuration {
+                        full_name: "cfg".to_owned(),
+                    }),
+                    execution_configuration: None,
+                },
+            )),
+            ..Default::default()
+        }
+    }
+
+    fn event(span_id: SpanId, data: buck2_data::buck_event::Data) -> BuckEvent {
+        BuckEvent::new(SystemTime::now(), TraceId::new(), Some(span_id), None, data)
+    }
+
+    fn start(browser: &mut ActionBrowser, span_id: SpanId, target: &str) {
+        let data: buck2_data::span_start_event::Data = buck2_data::ActionExecutionStart {
+            key: Some(action_key(target)),
+            ..Default::default()
+        }
+        .into();
+        browser
+            .handle_event(&event(
+                span_id,
+                buck2_data::SpanStartEvent { data: Some(data) }.into(),
+            ))
+            .unwrap();
+    }
+
+    fn end(browser: &mut ActionBrowser, span_id: SpanId, target: &str, stderr: &str) {
+        let data: buck2_data::span_end_event::Data = buck2_data::ActionExecutionEnd {
+            key: Some(action_key(target)),
+            failed: true,
+            commands: vec![buck2_data::CommandExecution {
+                details: Some(buck2_data::CommandExecutionDetails {
+                    stderr: stderr.to_owned(),
+                    command: Some(
+                        buck2_data::command_execution_details::Command::LocalCommand(
+                            buck2_data::LocalCommand {
+                                argv: vec!["cc".to_owned(), "a.c".to_owned()],
+                                ..Default::default()
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .into();
+        browser
+            .handle_event(&event(
+                span_id,
+                buck2_data::SpanEndEvent {
+                    data: Some(data),
+                    ..Default::default()
+                }
+                .into(),
+            ))
+            .unwrap();
+    }
+
+    fn draw(browser: &ActionBrowser) -> Vec<String> {
+        let config = SuperConsoleConfig::default();
+        let tick = Tick::now();
+        let time_speed = TimeSpeed::new(None).unwrap();
+        ActionBrowserComponent
+            .draw_unchecked(
+                &superconsole::state![browser, &config, &tick, &time_speed],
+                Dimensions::new(100, 100),
+                DrawMode::Normal,
+            )
+            .unwrap()
+            .iter()
+            .map(|l| l.to_unstyled())
+            .collect()
+    }
+
+    #[test]
+    fn test_browse_expand_and_filter() {
+        let mut browser = ActionBrowser::new();
+        let (a, b) = (SpanId::new(), SpanId::new());
+        start(&mut browser, a, "root//foo:a");
+        start(&mut browser, b, "root//bar:b");
+        end(&mut browser, a, "root//foo:a", "warning: oops\n");
+
+        assert!(draw(&browser).is_empty());
+        assert_eq!(
+            browser.handle_key(Key::Down, 10),
+            InputResult::Ignored,
+            "closed browser must not swallow keys"
+        );
+
+        browser.toggle();
+        let lines = draw(&browser);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Actions: 1 running, 1 finished."));
+        assert!(lines[1].starts_with("> failed"));
+        assert!(lines[1].ends_with("root//foo:a"));
+        assert!(lines[2].starts_with("  running"));
+
+        // Expand the failed action.
+        assert!(browser.handle_key(Key::Enter, 10).is_handled());
+        let lines = draw(&browser);
+        assert_eq!(lines[2].trim(), "$ cc a.c");
+        assert_eq!(lines[3].trim(), "warning: oops");
+
+        // Filter down to the running one, which is now selected.
+        assert!(browser.handle_key(Key::Char('/'), 10).is_handled());
+        for c in "bar".chars() {
+            assert!(browser.handle_key(Key::Char(c), 10).is_handled());
+        }
+        assert!(browser.handle_key(Key::Enter, 10).is_handled());
+        let lines = draw(&browser);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("of 1. Filter: bar"));
+        assert!(lines[1].starts_with("> running"));
+
+        // Unrelated keys fall through to the regular console bindings.
+        assert_eq!(browser.handle_key(Key::Char('d'), 10), InputResult::Ignored);
+
+        // The first `esc` collapses the expanded action, the second closes the browser.
+        assert!(browser.handle_key(Key::Escape, 10).is_handled());
+        assert!(browser.is_open());
+        assert!(browser.handle_key(Key::Escape, 10).is_handled());
+        assert!(!browser.is_open());
+    }
+
+    #[test]
+    fn test_oldest_actions_are_dropped() {
+        let mut browser = ActionBrowser::new();
+        let first = SpanId::new();
+        start(&mut browser, first, "root//foo:first");
+        for _ in 0..MAX_ACTIONS {
+            start(&mut browser, SpanId::new(), "root//foo:other");
+        }
+        assert_eq!(browser.actions.len(), MAX_ACTIONS);
+        assert_eq!(browser.running.len(), MAX_ACTIONS);
+        assert!(!browser.running.contains_key(&first));
+        // Ending an action that was dropped is a no-op.
+        end(&mut browser, first, "root//foo:first", "");
+        assert!(browser.filtered().all(|a| a.target != "root//foo:first"));
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("abc", 2), "bc");
+        assert_eq!(tail("abc", 10), "abc");
+        assert_eq!(tail("aé", 1), "");
+    }
+}
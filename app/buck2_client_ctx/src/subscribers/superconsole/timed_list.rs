@@ -13,7 +13,7 @@ use std::time::Instant;
 
 use buck2_event_observer::action_stats::ActionStats;
 use buck2_event_observer::display;
-use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_event_observer::downward_api_state::DownwardApiState;
 use buck2_event_observer::span_tracker::BuckEventSpanHandle;
 use buck2_event_observer::span_tracker::BuckEventSpanTracker;
 use superconsole::components::bordering::BorderedSpec;
@@ -31,6 +31,7 @@ use superconsole::Lines;
 use superconsole::Span;
 use superconsole::State;
 
+use self::table_builder::display_span;
 use self::table_builder::Table;
 use crate::subscribers::subscriber::Tick;
 use crate::subscribers::superconsole::common::HeaderLineComponent;
@@ -96,20 +97,15 @@ impl TimedListBodyInner {
         display_platform: bool,
     ) -> anyhow::Result<Row> {
         let time_speed = state.get::<TimeSpeed>()?;
+        let downward_api = state.get::<DownwardApiState>()?;
         let info = root.info();
         let child_info = single_child.info();
 
         // always display the event and subaction
         let mut event_string = format!(
             "{} [{}",
-            display::display_event(
-                &info.event,
-                TargetDisplayOptions::for_console(display_platform)
-            )?,
-            display::display_event(
-                &child_info.event,
-                TargetDisplayOptions::for_console(display_platform)
-            )?
+            display_span(info, downward_api, display_platform)?,
+            display_span(child_info, downward_api, display_platform)?
         );
 
         let now = Instant::now();
@@ -143,6 +139,7 @@ impl TimedListBodyInner {
 
     fn draw_root(&self, root: &BuckEventSpanHandle, state: &State) -> anyhow::Result<Vec<Row>> {
         let time_speed = state.get::<TimeSpeed>()?;
+        let downward_api = state.get::<DownwardApiState>()?;
         let config = state.get::<SuperConsoleConfig>()?;
         let two_lines = config.two_lines;
         let display_platform = config.display_platform;
@@ -163,6 +160,7 @@ impl TimedListBodyInner {
                 rows.push(Row::span(
                     0,
                     info,
+                    downward_api,
                     time_speed.speed(),
                     &self.cutoffs,
                     display_platform,
@@ -172,6 +170,7 @@ impl TimedListBodyInner {
                     rows.push(Row::span(
                         2,
                         child.info(),
+                        downward_api,
                         time_speed.speed(),
                         &self.cutoffs,
                         display_platform,
@@ -466,7 +465,14 @@ mod tests {
         };
 
         let output = timed_list.draw(
-            &superconsole::state!(&state, &tick, &time_speed, &action_stats, &timed_list_state),
+            &superconsole::state!(
+                &state,
+                &tick,
+                &time_speed,
+                &action_stats,
+                &timed_list_state,
+                &DownwardApiState::default()
+            ),
             Dimensions {
                 width: 40,
                 height: 10,
@@ -556,7 +562,14 @@ mod tests {
         };
 
         let output = timed_list.draw(
-            &superconsole::state!(&state, &tick, &time_speed, &action_stats, &timed_list_state),
+            &superconsole::state!(
+                &state,
+                &tick,
+                &time_speed,
+                &action_stats,
+                &timed_list_state,
+                &DownwardApiState::default()
+            ),
             Dimensions {
                 width: 40,
                 height: 10,
@@ -662,7 +675,14 @@ mod tests {
         };
 
         let output = timed_list.draw(
-            &superconsole::state!(&state, &tick, &time_speed, &action_stats, &timed_list_state),
+            &superconsole::state!(
+                &state,
+                &tick,
+                &time_speed,
+                &action_stats,
+                &timed_list_state,
+                &DownwardApiState::default()
+            ),
             Dimensions {
                 width: 80,
                 height: 10,
@@ -717,7 +737,14 @@ mod tests {
         state.start_at(&re_download, fake_time(&tick, 2)).unwrap();
 
         let output = timed_list.draw(
-            &superconsole::state!(&state, &tick, &time_speed, &action_stats, &timed_list_state),
+            &superconsole::state!(
+                &state,
+                &tick,
+                &time_speed,
+                &action_stats,
+                &timed_list_state,
+                &DownwardApiState::default()
+            ),
             Dimensions {
                 width: 80,
                 height: 10,
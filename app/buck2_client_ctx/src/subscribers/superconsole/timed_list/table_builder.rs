@@ -7,10 +7,12 @@
  * of this source tree.
  */
 
+use std::fmt::Write;
 use std::time::Duration;
 
 use buck2_event_observer::display;
 use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_event_observer::downward_api_state::DownwardApiState;
 use buck2_event_observer::span_tracker::BuckEventSpanInfo;
 use superconsole::style::style;
 use superconsole::style::StyledContent;
@@ -82,6 +84,35 @@ impl Component for Table {
     }
 }
 
+/// Display a span, followed by the latest progress and metrics reported for it through the
+/// downward API, if any.
+pub(crate) fn display_span(
+    span: &BuckEventSpanInfo,
+    downward_api: &DownwardApiState,
+    display_platform: bool,
+) -> anyhow::Result<String> {
+    let mut event = display::display_event(
+        &span.event,
+        TargetDisplayOptions::for_console(display_platform),
+    )?;
+
+    if let Some(span_id) = span.event.span_id() {
+        let progress = downward_api
+            .progress(span_id)
+            .map(display::display_downward_api_progress);
+        let metrics = downward_api
+            .metrics(span_id)
+            .iter()
+            .map(display::display_downward_api_metric);
+        let reported: Vec<String> = progress.into_iter().chain(metrics).collect();
+        if !reported.is_empty() {
+            write!(event, " ({})", reported.join(", ")).expect("Write to String is not fallible");
+        }
+    }
+
+    Ok(event)
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Row {
     event: Line,
@@ -92,14 +123,12 @@ impl Row {
     pub(crate) fn span(
         padding: usize,
         span: &BuckEventSpanInfo,
+        downward_api: &DownwardApiState,
         time_speed: f64,
         cutoffs: &Cutoffs,
         display_platform: bool,
     ) -> anyhow::Result<Row> {
-        let event = display::display_event(
-            &span.event,
-            TargetDisplayOptions::for_console(display_platform),
-        )?;
+        let event = display_span(span, downward_api, display_platform)?;
         let time = display::duration_as_secs_elapsed(span.start.elapsed(), time_speed);
         let age = span.start.elapsed().mul_f64(time_speed);
         Row::text(padding, event, time, age, cutoffs)
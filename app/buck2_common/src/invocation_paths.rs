@@ -195,8 +195,21 @@ impl InvocationPaths {
         FileName::unchecked_new("materializer_state")
     }
 
+    /// Subdirectory of `cache_dir` where the file watcher keeps its journal, if it has one.
+    pub fn file_watcher_state_path(&self) -> AbsNormPathBuf {
+        self.cache_dir_path()
+            .join(self.file_watcher_state_dir_name())
+    }
+
+    pub fn file_watcher_state_dir_name(&self) -> &FileName {
+        FileName::unchecked_new("file_watcher")
+    }
+
     pub fn valid_cache_dirs(&self) -> Vec<&FileName> {
-        vec![self.materializer_state_dir_name()]
+        vec![
+            self.materializer_state_dir_name(),
+            self.file_watcher_state_dir_name(),
+        ]
     }
 }
 
@@ -19,13 +19,29 @@ use async_trait::async_trait;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_core::rollout_percentage::RolloutPercentage;
 use dashmap::DashSet;
 
 use crate::cas_digest::CasDigestConfig;
 use crate::file_ops::RawDirEntry;
 use crate::file_ops::RawPathMetadata;
+use crate::legacy_configs::schema::ConfigKey;
+use crate::legacy_configs::schema::ConfigSchemaRegistration;
 use crate::legacy_configs::LegacyBuckConfig;
 
+static ALLOW_EDEN_IO: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "allow_eden_io",
+    "Whether to read files through Eden when the repository is an Eden checkout. Defaults to true on macOS.",
+)
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&ALLOW_EDEN_IO),
+    }
+}
+
 #[async_trait]
 pub trait IoProvider: Allocative + Send + Sync {
     async fn read_file_if_exists(
@@ -63,13 +79,12 @@ pub async fn create_io_provider(
 ) -> anyhow::Result<Arc<dyn IoProvider>> {
     #[cfg(any(fbcode_build, cargo_internal_build))]
     {
-        use buck2_core::rollout_percentage::RolloutPercentage;
-
         let allow_eden_io_default = RolloutPercentage::from_bool(cfg!(target_os = "macos"));
 
         let allow_eden_io = root_config
-            .and_then(|c| c.parse("buck2", "allow_eden_io").transpose())
+            .map(|c| c.read(&ALLOW_EDEN_IO))
             .transpose()?
+            .flatten()
             .unwrap_or(allow_eden_io_default)
             .roll();
 
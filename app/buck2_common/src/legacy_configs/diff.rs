@@ -0,0 +1,243 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Structured differences between the cells and buckconfigs of two commands (computed by the
+//! daemon) and between the constraints of a daemon and a client (computed by the client), used to
+//! explain why state was invalidated.
+
+use std::collections::BTreeMap;
+
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::CellResolver;
+use itertools::EitherOrBoth;
+use itertools::Itertools;
+
+use crate::legacy_configs::schema::ConfigSchema;
+use crate::legacy_configs::LegacyBuckConfig;
+use crate::legacy_configs::LegacyBuckConfigs;
+
+fn config_values(config: &LegacyBuckConfig) -> BTreeMap<(&str, &str), &str> {
+    config
+        .all_sections()
+        .flat_map(|(section, values)| {
+            values
+                .iter()
+                .map(move |(key, value)| ((section.as_str(), key), value.as_str()))
+        })
+        .collect()
+}
+
+/// Buckconfig changes end up in event logs, and arbitrary keys may hold secrets, so only the
+/// values of keys declared in `schema` are reported as is. Other values are replaced with a
+/// fingerprint, which still shows whether two values are the same.
+fn reported_value(schema: &ConfigSchema, section: &str, key: &str, value: &str) -> String {
+    if schema.get(section, key).is_some() {
+        value.to_owned()
+    } else {
+        format!(
+            "<redacted {}>",
+            &blake3::hash(value.as_bytes()).to_hex()[..8]
+        )
+    }
+}
+
+/// Every key whose resolved value differs between `previous` and `current`, across all cells,
+/// ordered by cell, section and key. Where a value came from is not considered. Values of keys
+/// not declared in `schema` are redacted.
+pub fn diff_configs(
+    previous: &LegacyBuckConfigs,
+    current: &LegacyBuckConfigs,
+    schema: &ConfigSchema,
+) -> Vec<buck2_data::BuckconfigChange> {
+    let empty = LegacyBuckConfig::empty();
+    let cells: BTreeMap<CellName, (&LegacyBuckConfig, &LegacyBuckConfig)> = previous
+        .iter()
+        .map(|(cell, _)| cell)
+        .chain(current.iter().map(|(cell, _)| cell))
+        .map(|cell| {
+            (
+                cell,
+                (
+                    previous.get(cell).unwrap_or(&empty),
+                    current.get(cell).unwrap_or(&empty),
+                ),
+            )
+        })
+        .collect();
+
+    let mut changes = Vec::new();
+    for (cell, (previous, current)) in cells {
+        if previous.compare(current) {
+            continue;
+        }
+        for ((section, key), previous, current) in
+            diff_maps(config_values(previous), config_values(current))
+        {
+            changes.push(buck2_data::BuckconfigChange {
+                cell: cell.as_str().to_owned(),
+                section: section.to_owned(),
+                key: key.to_owned(),
+                previous: previous.map(|v| reported_value(schema, section, key, v)),
+                current: current.map(|v| reported_value(schema, section, key, v)),
+            });
+        }
+    }
+    changes
+}
+
+/// Cells that were added, removed, or whose root moved between `previous` and `current`.
+pub fn diff_cells(previous: &CellResolver, current: &CellResolver) -> Vec<buck2_data::CellChange> {
+    let previous: BTreeMap<CellName, String> = previous
+        .cells()
+        .map(|(name, cell)| (name, cell.path().to_string()))
+        .collect();
+    let current: BTreeMap<CellName, String> = current
+        .cells()
+        .map(|(name, cell)| (name, cell.path().to_string()))
+        .collect();
+
+    diff_maps(previous, current)
+        .into_iter()
+        .map(
+            |(cell, previous_path, current_path)| buck2_data::CellChange {
+                cell: cell.as_str().to_owned(),
+                previous_path,
+                current_path,
+            },
+        )
+        .collect()
+}
+
+/// How a buckconfig change is named to users, e.g. `root//build.threads`.
+pub fn buckconfig_change_name(change: &buck2_data::BuckconfigChange) -> String {
+    format!("{}//{}.{}", change.cell, change.section, change.key)
+}
+
+/// Every key whose value differs between `previous` and `current`, ordered by key, with `None`
+/// where the key is absent. All the diffs reported in events are computed with this.
+pub fn diff_maps<K: Ord, V: PartialEq>(
+    previous: BTreeMap<K, V>,
+    current: BTreeMap<K, V>,
+) -> Vec<(K, Option<V>, Option<V>)> {
+    previous
+        .into_iter()
+        .merge_join_by(current.into_iter(), |(a, _), (b, _)| a.cmp(b))
+        .filter_map(|entry| match entry {
+            EitherOrBoth::Both((k, a), (_, b)) if a != b => Some((k, Some(a), Some(b))),
+            EitherOrBoth::Both(..) => None,
+            EitherOrBoth::Left((k, a)) => Some((k, Some(a), None)),
+            EitherOrBoth::Right((k, b)) => Some((k, None, Some(b))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::testing::CellResolverExt;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+
+    use super::*;
+    use crate::legacy_configs::schema::ConfigKey;
+    use crate::legacy_configs::testing::legacy_buck_config_from_entries;
+
+    fn configs(entries: &[(&str, &str, &str)]) -> LegacyBuckConfigs {
+        LegacyBuckConfigs::new(HashMap::from_iter([(
+            CellName::testing_new("root"),
+            legacy_buck_config_from_entries(entries.iter().copied()).unwrap(),
+        )]))
+    }
+
+    #[test]
+    fn test_diff_configs() {
+        let previous = configs(&[
+            ("build", "threads", "4"),
+            ("buck2", "file_watcher", "watchman"),
+            ("foo", "removed", "x"),
+        ]);
+        let current = configs(&[
+            ("build", "threads", "8"),
+            ("buck2", "file_watcher", "watchman"),
+            ("foo", "added", "y"),
+        ]);
+
+        static THREADS: ConfigKey<usize> = ConfigKey::new("build", "threads", "Threads.");
+        let mut schema = ConfigSchema::default();
+        schema.declare(&THREADS);
+
+        let changes = diff_configs(&previous, &current, &schema);
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|c| {
+                (
+                    c.section.as_str(),
+                    c.key.as_str(),
+                    c.previous.as_deref(),
+                    c.current.as_deref(),
+                )
+            })
+            .collect();
+        let y = reported_value(&schema, "foo", "added", "y");
+        let x = reported_value(&schema, "foo", "removed", "x");
+        assert_eq!(
+            vec![
+                ("build", "threads", Some("4"), Some("8")),
+                ("foo", "added", None, Some(y.as_str())),
+                ("foo", "removed", Some(x.as_str()), None),
+            ],
+            changes
+        );
+        // Undeclared keys are redacted, but equal values still look equal.
+        assert!(y.starts_with("<redacted "));
+        assert_ne!(x, y);
+        assert_eq!(y, reported_value(&schema, "bar", "baz", "y"));
+
+        assert!(diff_configs(&previous, &previous, &schema).is_empty());
+    }
+
+    #[test]
+    fn test_diff_maps() {
+        let previous = BTreeMap::from([("a", 1), ("b", 2), ("c", 3)]);
+        let current = BTreeMap::from([("b", 2), ("c", 4), ("d", 5)]);
+        assert_eq!(
+            vec![
+                ("a", Some(1), None),
+                ("c", Some(3), Some(4)),
+                ("d", None, Some(5)),
+            ],
+            diff_maps(previous, current)
+        );
+    }
+
+    #[test]
+    fn test_diff_cells() {
+        let root = CellName::testing_new("root");
+        let other = CellName::testing_new("other");
+        let path = |p: &str| CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new(p.into()));
+
+        let previous = CellResolver::of_names_and_paths(
+            root,
+            &[(root, path("")), (other, path("third-party/other"))],
+        );
+        let current =
+            CellResolver::of_names_and_paths(root, &[(root, path("")), (other, path("other"))]);
+
+        assert_eq!(
+            vec![buck2_data::CellChange {
+                cell: "other".to_owned(),
+                previous_path: Some("third-party/other".to_owned()),
+                current_path: Some("other".to_owned()),
+            }],
+            diff_cells(&previous, &current)
+        );
+        assert!(diff_cells(&current, &current).is_empty());
+    }
+}
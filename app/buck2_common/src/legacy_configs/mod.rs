@@ -12,7 +12,9 @@
 
 pub mod cells;
 pub mod dice;
+pub mod diff;
 pub(crate) mod path;
+pub mod schema;
 pub mod view;
 
 use std::collections::BTreeMap;
@@ -42,6 +44,7 @@ use regex::Regex;
 use thiserror::Error;
 
 use crate::legacy_configs::cells::BuckConfigBasedCells;
+use crate::legacy_configs::schema::ConfigKey;
 use crate::legacy_configs::view::LegacyBuckConfigView;
 use crate::legacy_configs::view::LegacyBuckConfigsView;
 use crate::target_aliases::BuckConfigTargetAliasResolver;
@@ -1170,24 +1173,25 @@ impl LegacyBuckConfig {
     where
         anyhow::Error: From<<T as FromStr>::Err>,
     {
-        /// A wrapper type so we can use .parse() on this.
-        struct ParseList<T>(Vec<T>);
-
-        impl<T> FromStr for ParseList<T>
-        where
-            T: FromStr,
-        {
-            type Err = <T as FromStr>::Err;
+        Ok(self.parse::<CommaSeparated<T>>(section, key)?.map(|l| l.0))
+    }
 
-            fn from_str(s: &str) -> Result<Self, Self::Err> {
-                Ok(Self(
-                    s.split(',').map(T::from_str).collect::<Result<_, _>>()?,
-                ))
-            }
-        }
+    /// Read a key declared with a schema, falling back to its default.
+    pub fn read<T: FromStr>(&self, key: &ConfigKey<T>) -> anyhow::Result<Option<T>>
+    where
+        anyhow::Error: From<<T as FromStr>::Err>,
+    {
+        key.parse(self.get(key.section, key.key))
+    }
 
-        Ok(self.parse::<ParseList<T>>(section, key)?.map(|l| l.0))
+    /// Read a key declared with a default, falling back to that default.
+    pub fn read_or_default<T: FromStr>(&self, key: &ConfigKey<T>) -> anyhow::Result<T>
+    where
+        anyhow::Error: From<<T as FromStr>::Err>,
+    {
+        key.read_or_default(Some(self))
     }
+
     pub fn sections(&self) -> impl Iterator<Item = &String> {
         self.0.values.keys()
     }
@@ -1201,6 +1205,23 @@ impl LegacyBuckConfig {
     }
 }
 
+/// A comma-separated list, so we can use `.parse()` on it.
+#[derive(Debug)]
+pub struct CommaSeparated<T>(pub Vec<T>);
+
+impl<T> FromStr for CommaSeparated<T>
+where
+    T: FromStr,
+{
+    type Err = <T as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(
+            s.split(',').map(T::from_str).collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
 // Options on how to exactly parse config files
 struct BuckConfigParseOptions {
     // Defines whether includes are followed, this can significantly reduce parse time.
@@ -0,0 +1,638 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Schemas for buckconfig keys.
+//!
+//! Components declare the keys they read, along with a type, a default and a description. This
+//! lets us check buckconfigs when they are loaded, and document them in
+//! `buck2 audit config --schema`.
+//!
+//! Rust components declare keys with a [`ConfigKey`] static, which they then use to read the
+//! value, and register it with `inventory::submit!`:
+//!
+//! ```ignore
+//! static FILE_WATCHER: ConfigKey<String> =
+//!     ConfigKey::new("buck2", "file_watcher", "Which file watcher to use.")
+//!         .with_default("watchman");
+//!
+//! inventory::submit! {
+//!     ConfigSchemaRegistration {
+//!         register: |schema| schema.declare(&FILE_WATCHER),
+//!     }
+//! }
+//!
+//! let file_watcher: String = root_config.read_or_default(&FILE_WATCHER)?;
+//! ```
+//!
+//! The default of a key is only declared in its schema: keys with a default are read with
+//! `read_or_default`, keys without one with `read`. Keys only read when the daemon starts are
+//! declared with `read_at_daemon_startup`, so that changes to them can be reported as needing a
+//! restart.
+//!
+//! Starlark declares keys when reading them with `read_typed_config` at the top level of a
+//! `.bzl` file. Those declarations are part of the evaluated module, so the schema including
+//! them is computed on DICE, per cell, from the modules the build files of that cell load
+//! implicitly. The builtin schema is all that is known before any Starlark is evaluated.
+//!
+//! Values of declared keys are checked against their type. Most keys are not declared (yet), so
+//! an undeclared key is only reported when it is a near-miss of a declared key in the same
+//! section (see [`ConfigSchema::validate`]); other undeclared keys are accepted silently.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use allocative::Allocative;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::legacy_configs::LegacyBuckConfig;
+use crate::legacy_configs::LegacyBuckConfigLocation;
+use crate::legacy_configs::LegacyBuckConfigValue;
+
+#[derive(Error, Debug)]
+enum ConfigSchemaError {
+    #[error(
+        "Buckconfig key `{section}.{key}` is declared as `{declared}`, but buck2 reads it as `{builtin}`"
+    )]
+    ConflictingType {
+        section: String,
+        key: String,
+        declared: String,
+        builtin: String,
+    },
+    #[error(
+        "Invalid config type `{0}`, expected one of `string`, `bool`, `int`, `float` or `list`"
+    )]
+    InvalidType(String),
+    #[error("Invalid default `{default}` for buckconfig key `{section}.{key}`, expected `{ty}`")]
+    InvalidDefault {
+        section: String,
+        key: String,
+        default: String,
+        ty: &'static str,
+    },
+    #[error(
+        "Buckconfig key `{section}.{key}` is read with its default, but has none (internal error)"
+    )]
+    NoDefault {
+        section: &'static str,
+        key: &'static str,
+    },
+}
+
+/// A buckconfig key read by a Rust component.
+pub struct ConfigKey<T> {
+    pub section: &'static str,
+    pub key: &'static str,
+    /// The value used when the key is not set, if any.
+    pub default: Option<&'static str>,
+    pub doc: &'static str,
+    /// Whether the key is only read when the daemon starts.
+    pub daemon_startup: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ConfigKey<T> {
+    pub const fn new(section: &'static str, key: &'static str, doc: &'static str) -> Self {
+        Self {
+            section,
+            key,
+            default: None,
+            doc,
+            daemon_startup: false,
+            _marker: PhantomData,
+        }
+    }
+
+    pub const fn with_default(self, default: &'static str) -> Self {
+        Self {
+            default: Some(default),
+            ..self
+        }
+    }
+
+    /// The key is only read when the daemon starts, so changing it takes effect once the daemon
+    /// restarts.
+    pub const fn read_at_daemon_startup(self) -> Self {
+        Self {
+            daemon_startup: true,
+            ..self
+        }
+    }
+}
+
+impl<T: FromStr> ConfigKey<T>
+where
+    anyhow::Error: From<<T as FromStr>::Err>,
+{
+    /// Parse the value of this key, falling back to its default.
+    pub fn parse(&self, value: Option<&str>) -> anyhow::Result<Option<T>> {
+        value
+            .or(self.default)
+            .map(|v| LegacyBuckConfig::parse_impl(self.section, self.key, v))
+            .transpose()
+    }
+
+    /// Parse the value of this key, falling back to its default, which it must have.
+    pub fn parse_or_default(&self, value: Option<&str>) -> anyhow::Result<T> {
+        self.parse(value)?.ok_or_else(|| {
+            ConfigSchemaError::NoDefault {
+                section: self.section,
+                key: self.key,
+            }
+            .into()
+        })
+    }
+
+    /// Read this key from `config`, falling back to its default, which it must have, when the
+    /// key is not set or there is no config.
+    pub fn read_or_default(&self, config: Option<&LegacyBuckConfig>) -> anyhow::Result<T> {
+        self.parse_or_default(config.and_then(|c| c.get(self.section, self.key)))
+    }
+}
+
+/// The types Starlark can declare buckconfig keys with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigValueType {
+    String,
+    Bool,
+    Int,
+    Float,
+    /// Comma-separated strings.
+    List,
+}
+
+impl FromStr for ConfigValueType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "string" => Ok(Self::String),
+            "bool" => Ok(Self::Bool),
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "list" => Ok(Self::List),
+            _ => Err(ConfigSchemaError::InvalidType(s.to_owned()).into()),
+        }
+    }
+}
+
+impl ConfigValueType {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Bool => "bool",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::List => "list",
+        }
+    }
+
+    fn check_fn(self) -> fn(&str) -> anyhow::Result<()> {
+        match self {
+            Self::String | Self::List => check::<String>,
+            Self::Bool => check::<bool>,
+            Self::Int => check::<i64>,
+            Self::Float => check::<f64>,
+        }
+    }
+}
+
+fn check<T: FromStr>(value: &str) -> anyhow::Result<()>
+where
+    anyhow::Error: From<<T as FromStr>::Err>,
+{
+    value.parse::<T>()?;
+    Ok(())
+}
+
+/// `std::any::type_name`, without the module paths.
+fn short_type_name<T>() -> String {
+    static MODULE_PATH: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[a-z0-9_]+::").unwrap());
+    MODULE_PATH
+        .replace_all(std::any::type_name::<T>(), "")
+        .into_owned()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Allocative)]
+pub enum ConfigSchemaOrigin {
+    /// Declared by buck2 itself.
+    Builtin,
+    /// Declared by Starlark, usually the prelude.
+    Starlark,
+}
+
+/// The schema of a single buckconfig key.
+#[derive(Clone, Debug, Allocative)]
+pub struct ConfigKeySchema {
+    pub section: String,
+    pub key: String,
+    /// Name of the type of the value, for humans.
+    pub ty: String,
+    pub default: Option<String>,
+    pub doc: String,
+    pub origin: ConfigSchemaOrigin,
+    /// Whether the key is only read when the daemon starts.
+    pub daemon_startup: bool,
+    #[allocative(skip)]
+    check: fn(&str) -> anyhow::Result<()>,
+}
+
+impl ConfigKeySchema {
+    /// A key declared from Starlark. Its default must be valid for its type, so that a bad
+    /// default is reported where it is declared rather than whenever the key is unset.
+    pub fn starlark(
+        section: &str,
+        key: &str,
+        ty: ConfigValueType,
+        default: Option<String>,
+        doc: &str,
+    ) -> anyhow::Result<Self> {
+        let check = ty.check_fn();
+        if let Some(default) = &default {
+            if check(default).is_err() {
+                return Err(ConfigSchemaError::InvalidDefault {
+                    section: section.to_owned(),
+                    key: key.to_owned(),
+                    default: default.clone(),
+                    ty: ty.name(),
+                }
+                .into());
+            }
+        }
+        // A key declared from Starlark can't also be declared by buck2 with a different type.
+        if let Some(builtin) = BUILTIN_SCHEMA.get(section, key) {
+            // Starlark can only express a few types, so only check the ones it can express.
+            let comparable = matches!(ty, ConfigValueType::String | ConfigValueType::Bool);
+            let builtin_ty = match builtin.ty.as_str() {
+                "String" => "string",
+                ty => ty,
+            };
+            if comparable && builtin_ty != ty.name() {
+                return Err(ConfigSchemaError::ConflictingType {
+                    section: section.to_owned(),
+                    key: key.to_owned(),
+                    declared: ty.name().to_owned(),
+                    builtin: builtin.ty.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(Self {
+            section: section.to_owned(),
+            key: key.to_owned(),
+            ty: ty.name().to_owned(),
+            default,
+            doc: doc.to_owned(),
+            origin: ConfigSchemaOrigin::Starlark,
+            daemon_startup: false,
+            check,
+        })
+    }
+
+    /// Check that `value` is valid for this key.
+    pub fn check(&self, value: &str) -> anyhow::Result<()> {
+        (self.check)(value)
+    }
+}
+
+/// A set of buckconfig key schemas.
+#[derive(Clone, Debug, Default, Allocative)]
+pub struct ConfigSchema {
+    keys: BTreeMap<(String, String), ConfigKeySchema>,
+}
+
+/// A function adding the keys of a Rust component to the builtin schema.
+pub struct ConfigSchemaRegistration {
+    pub register: fn(&mut ConfigSchema),
+}
+
+inventory::collect!(ConfigSchemaRegistration);
+
+static BUILTIN_SCHEMA: Lazy<ConfigSchema> = Lazy::new(|| {
+    let mut schema = ConfigSchema::default();
+    for registration in inventory::iter::<ConfigSchemaRegistration> {
+        (registration.register)(&mut schema);
+    }
+    schema
+});
+
+impl ConfigSchema {
+    /// The keys declared by buck2 itself.
+    pub fn builtin() -> &'static ConfigSchema {
+        &BUILTIN_SCHEMA
+    }
+
+    /// Add a key declared from Starlark. Keys declared by buck2 itself take precedence.
+    pub fn declare_starlark(&mut self, key: ConfigKeySchema) {
+        if self.get(&key.section, &key.key).map_or(true, |existing| {
+            existing.origin == ConfigSchemaOrigin::Starlark
+        }) {
+            self.insert(key);
+        }
+    }
+
+    /// Declare a key read by a Rust component.
+    pub fn declare<T: FromStr>(&mut self, key: &ConfigKey<T>)
+    where
+        anyhow::Error: From<<T as FromStr>::Err>,
+    {
+        self.insert(ConfigKeySchema {
+            section: key.section.to_owned(),
+            key: key.key.to_owned(),
+            ty: short_type_name::<T>(),
+            default: key.default.map(ToOwned::to_owned),
+            doc: key.doc.to_owned(),
+            origin: ConfigSchemaOrigin::Builtin,
+            daemon_startup: key.daemon_startup,
+            check: check::<T>,
+        })
+    }
+
+    fn insert(&mut self, key: ConfigKeySchema) {
+        self.keys
+            .insert((key.section.clone(), key.key.clone()), key);
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigKeySchema> {
+        self.keys.get(&(section.to_owned(), key.to_owned()))
+    }
+
+    /// Iterate the keys, sorted by section and key.
+    pub fn iter(&self) -> impl Iterator<Item = &ConfigKeySchema> {
+        self.keys.values()
+    }
+
+    /// The known key in `section` that `key` is most likely a typo of.
+    fn suggest(&self, section: &str, key: &str) -> Option<&str> {
+        let key = key.to_lowercase();
+        self.keys
+            .range((section.to_owned(), String::new())..)
+            .take_while(|((s, _), _)| s == section)
+            .map(|((_, known), _)| (known, strsim::levenshtein(&key, known)))
+            // Only suggest keys that are close relative to their length, so that short keys
+            // don't match everything.
+            .filter(|(known, distance)| *distance <= 2 && *distance * 4 <= known.len())
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(known, _)| known.as_str())
+    }
+
+    /// Check `config` against this schema: values of known keys must parse, and unknown keys
+    /// that look like typos of known keys are reported.
+    ///
+    /// Other unknown keys are not reported, even in sections with known keys: most keys buck2 and
+    /// the prelude read are not declared, so they would all be false positives.
+    pub fn validate(&self, config: &LegacyBuckConfig) -> Vec<ConfigDiagnostic> {
+        let mut diagnostics = Vec::new();
+        for (section, values) in config.all_sections() {
+            for (key, value) in values.iter() {
+                let kind = match self.get(section, key) {
+                    Some(schema) => match schema.check(value.as_str()) {
+                        Ok(()) => continue,
+                        Err(e) => ConfigDiagnosticKind::InvalidValue {
+                            value: value.as_str().to_owned(),
+                            ty: schema.ty.clone(),
+                            error: format!("{:#}", e),
+                        },
+                    },
+                    None => match self.suggest(section, key) {
+                        Some(suggestion) => ConfigDiagnosticKind::UnknownKey {
+                            suggestion: suggestion.to_owned(),
+                        },
+                        None => continue,
+                    },
+                };
+                diagnostics.push(ConfigDiagnostic {
+                    section: section.clone(),
+                    key: key.to_owned(),
+                    kind,
+                    location: format_location(&value),
+                });
+            }
+        }
+        diagnostics
+    }
+}
+
+fn format_location(value: &LegacyBuckConfigValue) -> String {
+    let mut location = String::new();
+    for (i, loc) in value.location_stack().iter().enumerate() {
+        let keyword = if i == 0 { "at" } else { ", included from" };
+        if let LegacyBuckConfigLocation::File(file, line) = loc {
+            location.push_str(&format!("{} {}:{}", keyword, file, line));
+        }
+    }
+    if location.is_empty() {
+        location.push_str("on the command line");
+    }
+    location
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigDiagnosticKind {
+    UnknownKey {
+        suggestion: String,
+    },
+    InvalidValue {
+        value: String,
+        ty: String,
+        error: String,
+    },
+}
+
+/// A problem found when validating a buckconfig against a schema.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub section: String,
+    pub key: String,
+    pub kind: ConfigDiagnosticKind,
+    /// Where the key was set, including the stack of includes.
+    pub location: String,
+}
+
+impl Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ConfigDiagnosticKind::UnknownKey { suggestion } => write!(
+                f,
+                "Unknown buckconfig key `{}.{}` (set {}), did you mean `{}.{}`?",
+                self.section, self.key, self.location, self.section, suggestion
+            ),
+            ConfigDiagnosticKind::InvalidValue { value, ty, error } => write!(
+                f,
+                "Invalid value `{}` for buckconfig key `{}.{}` (set {}), expected `{}`: {}",
+                value, self.section, self.key, self.location, ty, error
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy_configs::testing::parse;
+
+    static FILE_WATCHER: ConfigKey<String> =
+        ConfigKey::new("buck2", "file_watcher", "Which file watcher to use.")
+            .with_default("watchman");
+    static FORKSERVER_CGROUPS: ConfigKey<bool> =
+        ConfigKey::new("buck2", "forkserver_cgroups", "Whether to use cgroups.")
+            .read_at_daemon_startup();
+
+    fn schema() -> ConfigSchema {
+        let mut schema = ConfigSchema::default();
+        schema.declare(&FILE_WATCHER);
+        schema.declare(&FORKSERVER_CGROUPS);
+        schema
+    }
+
+    #[test]
+    fn test_declare() {
+        let schema = schema();
+        let key = schema.get("buck2", "forkserver_cgroups").unwrap();
+        assert_eq!(key.ty, "bool");
+        assert_eq!(key.default, None);
+        assert!(key.daemon_startup);
+        let key = schema.get("buck2", "file_watcher").unwrap();
+        assert_eq!(key.ty, "String");
+        assert!(!key.daemon_startup);
+        assert!(schema.get("buck2", "file_wacher").is_none());
+    }
+
+    #[test]
+    fn test_parse_default() -> anyhow::Result<()> {
+        assert_eq!(FILE_WATCHER.parse(None)?.as_deref(), Some("watchman"));
+        assert_eq!(
+            FILE_WATCHER.parse(Some("notify"))?.as_deref(),
+            Some("notify")
+        );
+        assert_eq!(FORKSERVER_CGROUPS.parse(None)?, None);
+        assert!(FORKSERVER_CGROUPS.parse(Some("maybe")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_or_default() -> anyhow::Result<()> {
+        let config = parse(
+            &[("/config", "[buck2]\n  forkserver_cgroups = true\n")],
+            "/config",
+        )?;
+        assert_eq!(FILE_WATCHER.read_or_default(Some(&config))?, "watchman");
+        assert_eq!(FILE_WATCHER.read_or_default(None)?, "watchman");
+        assert!(FORKSERVER_CGROUPS.read_or_default(Some(&config))?);
+        // Keys without a default have to be read with `read`.
+        assert!(FORKSERVER_CGROUPS.read_or_default(None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> anyhow::Result<()> {
+        let config = parse(
+            &[(
+                "/config",
+                indoc::indoc!(
+                    r#"
+                    [buck2]
+                        file_wacher = notify
+                        forkserver_cgroups = maybe
+                        something_else = 1
+                    [other]
+                        file_wacher = notify
+                "#
+                ),
+            )],
+            "/config",
+        )?;
+
+        let diagnostics = schema().validate(&config);
+        assert_eq!(
+            vec![
+                (
+                    "file_wacher",
+                    &ConfigDiagnosticKind::UnknownKey {
+                        suggestion: "file_watcher".to_owned(),
+                    }
+                ),
+                (
+                    "forkserver_cgroups",
+                    &ConfigDiagnosticKind::InvalidValue {
+                        value: "maybe".to_owned(),
+                        ty: "bool".to_owned(),
+                        error: "provided string was not `true` or `false`".to_owned(),
+                    }
+                ),
+            ],
+            diagnostics
+                .iter()
+                .map(|d| (d.key.as_str(), &d.kind))
+                .collect::<Vec<_>>()
+        );
+        assert!(diagnostics[0].location.ends_with("/config:2"));
+        assert!(diagnostics[1].location.ends_with("/config:3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggest_ignores_distant_keys() {
+        let schema = schema();
+        assert_eq!(
+            schema.suggest("buck2", "FILE_WATCHER"),
+            Some("file_watcher")
+        );
+        assert_eq!(schema.suggest("buck2", "forkserver"), None);
+        assert_eq!(schema.suggest("buck3", "file_watcher"), None);
+    }
+
+    #[test]
+    fn test_config_value_type() -> anyhow::Result<()> {
+        assert_eq!(ConfigValueType::Bool, "bool".parse()?);
+        assert!("boolean".parse::<ConfigValueType>().is_err());
+        let key = ConfigKeySchema::starlark("a", "b", ConfigValueType::Int, None, "")?;
+        assert!(key.check("12").is_ok());
+        assert!(key.check("twelve").is_err());
+        assert!(
+            ConfigKeySchema::starlark("a", "b", ConfigValueType::Int, Some("1".to_owned()), "")
+                .is_ok()
+        );
+        assert!(
+            ConfigKeySchema::starlark("a", "b", ConfigValueType::Int, Some("x".to_owned()), "")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_declare_starlark() -> anyhow::Result<()> {
+        let mut schema = schema();
+        schema.declare_starlark(ConfigKeySchema::starlark(
+            "buck2",
+            "file_watcher",
+            ConfigValueType::String,
+            None,
+            "",
+        )?);
+        schema.declare_starlark(ConfigKeySchema::starlark(
+            "buck2",
+            "other",
+            ConfigValueType::Int,
+            None,
+            "",
+        )?);
+        assert_eq!(
+            ConfigSchemaOrigin::Builtin,
+            schema.get("buck2", "file_watcher").unwrap().origin
+        );
+        assert_eq!("int", schema.get("buck2", "other").unwrap().ty);
+        Ok(())
+    }
+}
@@ -126,6 +126,15 @@ impl<K, V> UnorderedMap<K, V> {
     }
 }
 
+impl<K: Ord, V> UnorderedMap<K, V> {
+    /// Entries sorted by key, so the order does not depend on the hash table.
+    pub fn entries_sorted(&self) -> Vec<(&K, &V)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries
+    }
+}
+
 impl<K: Debug, V: Debug> Debug for UnorderedMap<K, V> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_map().entries(self.iter()).finish()
@@ -207,4 +216,10 @@ mod tests {
         assert_eq!(map.remove(&1), Some(3));
         assert_eq!(UnorderedMap::new(), map);
     }
+
+    #[test]
+    fn test_entries_sorted() {
+        let map = UnorderedMap::from_iter([(3, 4), (1, 2), (5, 6)]);
+        assert_eq!(vec![(&1, &2), (&3, &4), (&5, &6)], map.entries_sorted());
+    }
 }
@@ -14,7 +14,27 @@ use std::collections::HashMap;
 
 use tracing::Level;
 
+/// A structured metric reported by a process, e.g. the number of test cases it ran.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+    pub key: String,
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+/// Identifies a span a process started with [`DownwardApi::span_start`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DownwardSpanId(pub u64);
+
+/// The environment variable set, for commands Buck runs locally, to the path of a Unix socket
+/// serving them the downward API over gRPC.
+pub const DOWNWARD_API_SOCKET_ENV: &str = "BUCK2_DOWNWARD_API_SOCKET";
+
 /// The API available to processes that Buck will need to handle
+///
+/// Progress, metrics and spans reported without a span are attached to the span of the process
+/// that reported them: that of the command Buck ran locally for an action or a test, or a span for
+/// the test executor.
 #[async_trait::async_trait]
 pub trait DownwardApi {
     /// indicates to print to the console at a specific log level
@@ -27,5 +47,31 @@ pub trait DownwardApi {
     /// reports an externally consumable event containing some data that will be untouched by buck
     async fn external(&self, data: HashMap<String, String>) -> anyhow::Result<()>;
 
+    /// reports what the process is currently doing and, if known, how far along it is as a
+    /// percentage. If `span` is set, the progress is for that span.
+    async fn progress(
+        &self,
+        step: String,
+        percent: Option<f64>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()>;
+
+    /// attaches structured metrics to the process's events, or to `span` if set
+    async fn metrics(
+        &self,
+        metrics: Vec<Metric>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()>;
+
+    /// starts a span, nested under `parent` if set. The span must be ended with `span_end`.
+    async fn span_start(
+        &self,
+        name: String,
+        parent: Option<DownwardSpanId>,
+    ) -> anyhow::Result<DownwardSpanId>;
+
+    /// ends a span started with `span_start`
+    async fn span_end(&self, span: DownwardSpanId, success: bool) -> anyhow::Result<()>;
+
     // TODO map the StepEvent and TraceEvents in buckv1 to something. Maybe just a single trace event
 }
@@ -302,6 +302,7 @@ pub fn display_event(event: &BuckEvent, opts: TargetDisplayOptions) -> anyhow::R
             Data::ConnectToInstaller(buck2_data::ConnectToInstallerStart { tcp_port }) => {
                 Ok(format!("Connecting to installer on port {}", tcp_port))
             }
+            Data::DownwardApi(span) => Ok(span.name.clone()),
             Data::Fake(fake) => Ok(format!("{} -- speak of the devil", fake.caramba)),
         };
 
@@ -316,6 +317,7 @@ fn display_file_watcher(provider: i32) -> &'static str {
     match buck2_data::FileWatcherProvider::from_i32(provider) {
         Some(buck2_data::FileWatcherProvider::Watchman) => "Watchman",
         Some(buck2_data::FileWatcherProvider::RustNotify) => "notify",
+        Some(buck2_data::FileWatcherProvider::Inotify) => "inotify",
         None => "unknown mechanism",
     }
 }
@@ -363,6 +365,96 @@ pub fn display_file_watcher_end(file_watcher_end: &buck2_data::FileWatcherEnd) -
     res
 }
 
+fn display_optional_value(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("`{}`", value),
+        None => "unset".to_owned(),
+    }
+}
+
+pub fn display_buckconfig_change(change: &buck2_data::BuckconfigChange) -> String {
+    format!(
+        "{}//{}.{}: {} -> {}",
+        change.cell,
+        change.section,
+        change.key,
+        display_optional_value(change.previous.as_deref()),
+        display_optional_value(change.current.as_deref()),
+    )
+}
+
+pub fn display_cell_change(change: &buck2_data::CellChange) -> String {
+    format!(
+        "cell `{}`: {} -> {}",
+        change.cell,
+        display_optional_value(change.previous_path.as_deref()),
+        display_optional_value(change.current_path.as_deref()),
+    )
+}
+
+pub fn display_configuration_changed(changed: &buck2_data::ConfigurationChanged) -> Vec<String> {
+    const MAX_PRINT_MESSAGES: usize = 5;
+    let mut res = Vec::new();
+
+    let changes: Vec<String> = changed
+        .cell_changes
+        .iter()
+        .map(display_cell_change)
+        .chain(changed.config_changes.iter().map(display_buckconfig_change))
+        .collect();
+    for change in changes.iter().take(MAX_PRINT_MESSAGES) {
+        res.push(format!("Configuration changed: {}", change));
+    }
+    if changes.len() > MAX_PRINT_MESSAGES {
+        res.push(format!(
+            "{} additional configuration changes",
+            changes.len() - MAX_PRINT_MESSAGES
+        ));
+    }
+    // There is one invalidation per changed buckconfig, so these need truncating too.
+    for invalidated in changed.invalidated.iter().take(MAX_PRINT_MESSAGES) {
+        res.push(format!("Invalidated {}", invalidated));
+    }
+    if changed.invalidated.len() > MAX_PRINT_MESSAGES {
+        res.push(format!(
+            "{} additional invalidations",
+            changed.invalidated.len() - MAX_PRINT_MESSAGES
+        ));
+    }
+
+    res
+}
+
+pub fn display_daemon_restarted(restarted: &buck2_data::DaemonRestarted) -> Vec<String> {
+    if restarted.changes.is_empty() {
+        return vec!["Restarted buck2 daemon: constraints did not match".to_owned()];
+    }
+    restarted
+        .changes
+        .iter()
+        .map(|change| {
+            format!(
+                "Restarted buck2 daemon: {} changed from `{}` to `{}`",
+                change.constraint, change.previous, change.current
+            )
+        })
+        .collect()
+}
+
+pub fn display_downward_api_progress(progress: &buck2_data::DownwardApiProgress) -> String {
+    match progress.percent {
+        Some(percent) => format!("{} {:.0}%", progress.step, percent),
+        None => progress.step.clone(),
+    }
+}
+
+pub fn display_downward_api_metric(metric: &buck2_data::DownwardApiMetric) -> String {
+    match &metric.unit {
+        Some(unit) => format!("{}={}{}", metric.key, metric.value, unit),
+        None => format!("{}={}", metric.key, metric.value),
+    }
+}
+
 pub fn display_executor_stage(
     stage: &buck2_data::executor_stage_start::Stage,
 ) -> anyhow::Result<&'static str> {
@@ -576,13 +668,23 @@ fn failure_reason_for_command_execution(
                 }
             }
 
+            let oom_killed = command
+                .execution_stats
+                .and_then(|stats| stats.oom_killed)
+                .unwrap_or(false);
+
             format!(
-                "{}command returned non-zero exit code {}",
+                "{}command returned non-zero exit code {}{}",
                 locality,
                 OptionalExitCode {
                     code: command
                         .signed_exit_code
                         .or_else(|| command.exit_code.and_then(|e| e.try_into().ok()))
+                },
+                if oom_killed {
+                    " (killed by the OOM killer after exceeding its memory limit)"
+                } else {
+                    ""
                 }
             )
         }
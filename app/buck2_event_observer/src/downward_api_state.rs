@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+
+#[derive(Default)]
+struct SpanReport {
+    progress: Option<buck2_data::DownwardApiProgress>,
+    /// Latest value of each metric, in the order they were first reported.
+    metrics: Vec<buck2_data::DownwardApiMetric>,
+}
+
+/// The latest progress and metrics that actions and tests reported through the downward API, for
+/// each span they reported them for that is still open.
+#[derive(Default)]
+pub struct DownwardApiState {
+    spans: HashMap<SpanId, SpanReport>,
+}
+
+impl DownwardApiState {
+    pub(crate) fn update_progress(
+        &mut self,
+        event: &BuckEvent,
+        progress: &buck2_data::DownwardApiProgress,
+    ) {
+        if let Some(span) = event.parent_id() {
+            self.spans.entry(span).or_default().progress = Some(progress.clone());
+        }
+    }
+
+    pub(crate) fn update_metrics(
+        &mut self,
+        event: &BuckEvent,
+        metrics: &buck2_data::DownwardApiMetrics,
+    ) {
+        if let Some(span) = event.parent_id() {
+            let report = self.spans.entry(span).or_default();
+            for metric in &metrics.metrics {
+                match report.metrics.iter_mut().find(|m| m.key == metric.key) {
+                    Some(existing) => *existing = metric.clone(),
+                    None => report.metrics.push(metric.clone()),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn span_end(&mut self, event: &BuckEvent) {
+        if let Some(span) = event.span_id() {
+            self.spans.remove(&span);
+        }
+    }
+
+    pub fn progress(&self, span: SpanId) -> Option<&buck2_data::DownwardApiProgress> {
+        self.spans.get(&span)?.progress.as_ref()
+    }
+
+    pub fn metrics(&self, span: SpanId) -> &[buck2_data::DownwardApiMetric] {
+        self.spans.get(&span).map_or(&[], |r| &r.metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn event(
+        span_id: Option<SpanId>,
+        parent_id: Option<SpanId>,
+        data: buck2_data::buck_event::Data,
+    ) -> BuckEvent {
+        BuckEvent::new(SystemTime::now(), TraceId::new(), span_id, parent_id, data)
+    }
+
+    fn metric(key: &str, value: f64) -> buck2_data::DownwardApiMetric {
+        buck2_data::DownwardApiMetric {
+            key: key.to_owned(),
+            value,
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn test_downward_api_state() {
+        let span = SpanId::new();
+        let mut state = DownwardApiState::default();
+
+        let instant = event(
+            None,
+            Some(span),
+            buck2_data::InstantEvent { data: None }.into(),
+        );
+        let progress = buck2_data::DownwardApiProgress {
+            step: "compiling".to_owned(),
+            percent: Some(40.0),
+        };
+        state.update_progress(&instant, &progress);
+        state.update_metrics(
+            &instant,
+            &buck2_data::DownwardApiMetrics {
+                metrics: vec![metric("tests", 1.0), metric("failures", 0.0)],
+            },
+        );
+        state.update_metrics(
+            &instant,
+            &buck2_data::DownwardApiMetrics {
+                metrics: vec![metric("tests", 2.0)],
+            },
+        );
+
+        assert_eq!(state.progress(span), Some(&progress));
+        assert_eq!(
+            state.metrics(span),
+            &[metric("tests", 2.0), metric("failures", 0.0)]
+        );
+
+        // Progress reported outside of a span isn't tracked.
+        let unparented = event(None, None, buck2_data::InstantEvent { data: None }.into());
+        state.update_progress(&unparented, &progress);
+        assert_eq!(state.spans.len(), 1);
+
+        state.span_end(&event(
+            Some(span),
+            None,
+            buck2_data::SpanEndEvent::default().into(),
+        ));
+        assert_eq!(state.progress(span), None);
+        assert!(state.metrics(span).is_empty());
+    }
+}
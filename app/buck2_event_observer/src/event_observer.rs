@@ -17,6 +17,7 @@ use buck2_wrapper_common::invocation_id::TraceId;
 use crate::action_stats::ActionStats;
 use crate::debug_events::DebugEventsState;
 use crate::dice_state::DiceState;
+use crate::downward_api_state::DownwardApiState;
 use crate::io_state::IoState;
 use crate::re_state::ReState;
 use crate::session_info::SessionInfo;
@@ -32,6 +33,7 @@ pub struct EventObserver<E> {
     session_info: SessionInfo,
     io_state: IoState,
     test_state: TestState,
+    downward_api_state: DownwardApiState,
     /// When running without the Superconsole, we skip some state that we don't need. This might be
     /// premature optimization.
     extra: E,
@@ -53,6 +55,7 @@ where
             },
             io_state: IoState::default(),
             test_state: TestState::default(),
+            downward_api_state: DownwardApiState::default(),
             extra: E::new(),
         }
     }
@@ -73,6 +76,8 @@ where
                         }
                         _ => {}
                     }
+
+                    self.downward_api_state.span_end(event);
                 }
                 Instant(instant) => {
                     use buck2_data::instant_event::Data::*;
@@ -109,6 +114,23 @@ where
                         TestResult(result) => {
                             self.test_state.update(result)?;
                         }
+                        DownwardApiProgress(progress) => {
+                            self.downward_api_state.update_progress(event, progress);
+                        }
+                        DownwardApiMetrics(metrics) => {
+                            self.downward_api_state.update_metrics(event, metrics);
+                        }
+                        InstallProgress(progress) => {
+                            // Rendered alongside the file's install span, like downward API
+                            // progress.
+                            self.downward_api_state.update_progress(
+                                event,
+                                &buck2_data::DownwardApiProgress {
+                                    step: progress.message.clone(),
+                                    percent: progress.percent,
+                                },
+                            );
+                        }
                         _ => {}
                     }
                 }
@@ -149,6 +171,10 @@ where
         &self.test_state
     }
 
+    pub fn downward_api_state(&self) -> &DownwardApiState {
+        &self.downward_api_state
+    }
+
     pub fn extra(&self) -> &E {
         &self.extra
     }
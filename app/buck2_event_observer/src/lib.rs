@@ -13,6 +13,7 @@ pub mod action_stats;
 pub mod debug_events;
 pub mod dice_state;
 pub mod display;
+pub mod downward_api_state;
 pub mod event_observer;
 pub mod humanized_bytes;
 pub mod io_state;
@@ -411,7 +411,8 @@ impl SpanTrackable for Arc<BuckEvent> {
                 | Data::BxlExecution(..)
                 | Data::BxlDiceInvocation(..)
                 | Data::ReUpload(..)
-                | Data::ConnectToInstaller(..),
+                | Data::ConnectToInstaller(..)
+                | Data::DownwardApi(..),
             ) => true,
             None => false,
         }
@@ -97,6 +97,18 @@ impl EventDispatcher {
         self.event_with_span_id(instant, None, current_span());
     }
 
+    /// Emits an InstantEvent whose parent is `span`, regardless of the current span.
+    pub fn instant_event_in_span<E: Into<buck2_data::instant_event::Data>>(
+        &self,
+        data: E,
+        span: SpanId,
+    ) {
+        let instant = buck2_data::InstantEvent {
+            data: Some(data.into()),
+        };
+        self.event_with_span_id(instant, None, Some(span));
+    }
+
     pub fn console_message(&self, message: String) {
         self.instant_event(buck2_data::ConsoleMessage { message })
     }
@@ -197,6 +209,14 @@ impl EventDispatcher {
         Span::start(self.dupe(), start)
     }
 
+    /// Like `create_span`, but the new span's parent is `parent`, regardless of the current span.
+    pub fn create_child_span<Start>(&self, start: Start, parent: SpanId) -> Span
+    where
+        Start: Into<span_start_event::Data>,
+    {
+        Span::start_impl(self.dupe(), start, Some(parent))
+    }
+
     /// Returns the traceid for this event dispatcher.
     pub fn trace_id(&self) -> &TraceId {
         &self.trace_id
@@ -327,6 +347,11 @@ impl Span {
         r
     }
 
+    /// Emits an InstantEvent whose parent is this span, regardless of the current span.
+    pub fn instant_event<E: Into<buck2_data::instant_event::Data>>(&self, data: E) {
+        self.dispatcher.instant_event_in_span(data, self.span_id);
+    }
+
     pub fn create_child(&self, data: impl Into<span_start_event::Data>) -> Span {
         Span::start_impl(self.dispatcher.dupe(), data, Some(self.span_id))
     }
@@ -358,6 +358,7 @@ mod fbcode {
                     Some(Data::BxlDiceInvocation(_)) => false,
                     Some(Data::ReUpload(_)) => false,
                     Some(Data::ConnectToInstaller(_)) => false,
+                    Some(Data::DownwardApi(_)) => false,
                     Some(Data::Fake(..)) => false,
                     None => false,
                 }
@@ -404,6 +405,7 @@ mod fbcode {
                     Some(Data::BxlDiceInvocation(_)) => false,
                     Some(Data::ReUpload(_)) => false,
                     Some(Data::ConnectToInstaller(_)) => false,
+                    Some(Data::DownwardApi(_)) => false,
                     Some(Data::Fake(..)) => true,
                     None => false,
                 }
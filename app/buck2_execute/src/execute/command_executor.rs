@@ -173,8 +173,6 @@ fn re_create_action(
     digest_config: DigestConfig,
     output_paths_behavior: OutputPathsBehavior,
 ) -> anyhow::Result<PreparedAction> {
-    let _ignored = timeout; // TODO (torozco): Fix me.
-
     let mut command = RE::Command {
         arguments: args,
         platform: Some(platform),
@@ -245,7 +243,7 @@ fn re_create_action(
                 .add_protobuf_message(&command, digest_config)
                 .to_grpc(),
         ),
-        timeout: None,
+        timeout: timeout.map(|t| t.try_into()).transpose()?,
         do_not_cache,
         ..Default::default()
     };
@@ -38,6 +38,7 @@ use crate::directory::ActionDirectoryMember;
 use crate::directory::ActionImmutableDirectory;
 use crate::execute::environment_inheritance::EnvironmentInheritance;
 use crate::execute::inputs_directory::inputs_directory;
+use crate::execute::result::CommandExecutionStatus;
 
 #[derive(Clone)]
 pub struct ActionMetadataBlob {
@@ -226,6 +227,89 @@ impl CommandExecutionPaths {
     }
 }
 
+/// Limits on the resources a command may use. These are only enforced for local execution, when
+/// the forkserver runs commands in cgroups.
+#[derive(Debug, Default, Clone, Copy, Dupe, PartialEq, Eq, Allocative)]
+pub struct ResourceLimits {
+    pub memory_max_bytes: Option<u64>,
+    /// 1000 millicores is one full CPU.
+    pub cpu_max_millicores: Option<u64>,
+    pub pids_max: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for ResourceLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limits = [
+            ("memory_max_bytes", self.memory_max_bytes),
+            ("cpu_max_millicores", self.cpu_max_millicores),
+            ("pids_max", self.pids_max),
+        ];
+        write!(f, "[")?;
+        let mut first = true;
+        for (name, value) in limits {
+            if let Some(value) = value {
+                if !first {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}={}", name, value)?;
+                first = false;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// When to run a command again after a failed attempt.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Allocative)]
+pub struct RetryPolicy {
+    /// How many times the command may be run again.
+    pub max_retries: u32,
+    /// Exit codes of the command on which to retry.
+    pub exit_codes: Vec<i32>,
+    /// Whether to retry when the command could not be run, e.g. because of an RE error.
+    pub infra_errors: bool,
+}
+
+impl RetryPolicy {
+    /// Whether to retry after an attempt which finished with `status` and `exit_code`, given
+    /// `retries` were already made.
+    pub fn should_retry(
+        &self,
+        retries: u32,
+        status: &CommandExecutionStatus,
+        exit_code: Option<i32>,
+    ) -> bool {
+        if retries >= self.max_retries {
+            return false;
+        }
+        match status {
+            CommandExecutionStatus::Failure { .. } => {
+                exit_code.map_or(false, |code| self.exit_codes.contains(&code))
+            }
+            CommandExecutionStatus::Error { .. } => self.infra_errors,
+            CommandExecutionStatus::Success { .. }
+            | CommandExecutionStatus::TimedOut { .. }
+            | CommandExecutionStatus::Cancelled => false,
+        }
+    }
+}
+
+impl Display for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[max_retries={}, exit_codes={:?}, infra_errors={}]",
+            self.max_retries, self.exit_codes, self.infra_errors
+        )
+    }
+}
+
 /// The data contains the information about the command to be executed.
 pub struct CommandExecutionRequest {
     args: Vec<String>,
@@ -251,6 +335,12 @@ pub struct CommandExecutionRequest {
     /// Whether this command should override the fallback-only behavior on an hybrid executor and
     /// thus always run as if the executor was full-hybrid, assuming it is capable.
     force_full_hybrid_if_capable: bool,
+    /// Limits to apply when running this command locally.
+    resource_limits: ResourceLimits,
+    /// Declared memory footprint, used to admit this command locally.
+    memory_estimate: Option<u64>,
+    /// When to run this command again if it fails.
+    retry_policy: RetryPolicy,
 }
 
 impl CommandExecutionRequest {
@@ -273,6 +363,9 @@ impl CommandExecutionRequest {
             local_environment_inheritance: None,
             allow_cache_upload: false,
             force_full_hybrid_if_capable: false,
+            resource_limits: ResourceLimits::default(),
+            memory_estimate: None,
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -391,6 +484,36 @@ impl CommandExecutionRequest {
     pub fn force_full_hybrid_if_capable(&self) -> bool {
         self.force_full_hybrid_if_capable
     }
+
+    pub fn with_resource_limits(mut self, resource_limits: ResourceLimits) -> Self {
+        self.resource_limits = resource_limits;
+        self
+    }
+
+    pub fn resource_limits(&self) -> &ResourceLimits {
+        &self.resource_limits
+    }
+
+    pub fn with_memory_estimate(mut self, memory_estimate: Option<u64>) -> Self {
+        self.memory_estimate = memory_estimate;
+        self
+    }
+
+    /// The declared memory footprint, falling back to the memory limit since the command cannot
+    /// use more than that.
+    pub fn memory_estimate(&self) -> Option<u64> {
+        self.memory_estimate
+            .or(self.resource_limits.memory_max_bytes)
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
 }
 
 /// Is an output a file or a directory
@@ -544,3 +667,43 @@ impl ResolvedCommandExecutionOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::action_digest::ActionDigest;
+    use crate::execute::kind::CommandExecutionKind;
+
+    #[test]
+    fn test_retry_policy() {
+        let failure = CommandExecutionStatus::Failure {
+            execution_kind: CommandExecutionKind::Remote {
+                digest: ActionDigest::empty(DigestConfig::testing_default().cas_digest_config()),
+            },
+        };
+        let error = CommandExecutionStatus::Error {
+            stage: "re_execute",
+            error: anyhow::anyhow!("RE error"),
+        };
+        let policy = RetryPolicy {
+            max_retries: 2,
+            exit_codes: vec![75],
+            infra_errors: false,
+        };
+
+        assert!(policy.should_retry(0, &failure, Some(75)));
+        assert!(policy.should_retry(1, &failure, Some(75)));
+        assert!(!policy.should_retry(2, &failure, Some(75)));
+        assert!(!policy.should_retry(0, &failure, Some(1)));
+        assert!(!policy.should_retry(0, &failure, None));
+        assert!(!policy.should_retry(0, &error, None));
+        assert!(!policy.should_retry(0, &CommandExecutionStatus::Cancelled, None));
+
+        let policy = RetryPolicy {
+            infra_errors: true,
+            ..policy
+        };
+        assert!(policy.should_retry(0, &error, None));
+        assert!(!RetryPolicy::default().should_retry(0, &error, None));
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context as _;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum DownloadCacheError {
+    #[error("Invalid sha256 `{0}`")]
+    InvalidSha256(String),
+}
+
+/// A content-addressed cache of downloaded files, keyed by their sha256. It lives outside of
+/// buck-out, so it can be shared by every checkout and daemon on a machine, and survives
+/// `buck2 clean`.
+///
+/// Entries are only ever added by atomically renaming a complete file into place, so concurrent
+/// readers never observe a partial entry. Entries are still validated when read.
+#[derive(Debug)]
+pub struct DownloadCache {
+    root: AbsNormPathBuf,
+}
+
+impl DownloadCache {
+    pub fn new(root: AbsNormPathBuf) -> Self {
+        Self { root }
+    }
+
+    fn entry_path(&self, sha256: &str) -> anyhow::Result<AbsNormPathBuf> {
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(DownloadCacheError::InvalidSha256(sha256.to_owned()).into());
+        }
+        Ok(self.root.join(ForwardRelativePath::new(&format!(
+            "sha256/{}",
+            sha256.to_ascii_lowercase()
+        ))?))
+    }
+
+    /// The path of the cache entry for `sha256`, if there is one.
+    pub fn get(&self, sha256: &str) -> Option<AbsNormPathBuf> {
+        let path = self.entry_path(sha256).ok()?;
+        if path.exists() { Some(path) } else { None }
+    }
+
+    /// Add a copy of `src`, whose sha256 must already have been validated, to the cache.
+    pub fn insert(&self, sha256: &str, src: &AbsNormPath) -> anyhow::Result<()> {
+        static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.entry_path(sha256)?;
+        if path.exists() {
+            return Ok(());
+        }
+
+        let dir = path.parent().context("Cache entry has no parent")?;
+        fs_util::create_dir_all(dir)?;
+
+        let temp = dir.join(ForwardRelativePath::new(&format!(
+            "{}.{}.{}.tmp",
+            sha256,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))?);
+        let res = fs_util::copy(src, &temp).and_then(|_| fs_util::rename(&temp, &path));
+        if res.is_err() {
+            let _ignored = fs_util::remove_file(&temp);
+        }
+        res.with_context(|| format!("Error adding `{}` to the download cache", src))
+    }
+
+    /// Remove the entry for `sha256`, e.g. because it turned out to be corrupt.
+    pub fn remove(&self, sha256: &str) -> anyhow::Result<()> {
+        fs_util::remove_file(self.entry_path(sha256)?)
+    }
+}
@@ -7,17 +7,22 @@
  * of this source tree.
  */
 
+use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
+use std::sync::RwLock;
 use std::time::Duration;
 
 use allocative::Allocative;
 use anyhow::Context as _;
 use buck2_common::cas_digest::CasDigestConfig;
 use buck2_common::cas_digest::DigestAlgorithm;
+use buck2_common::cas_digest::Digester;
 use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileDigestKind;
 use buck2_common::file_ops::TrackedFileDigest;
 use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::is_open_source;
@@ -31,6 +36,7 @@ use reqwest::Client;
 use reqwest::RequestBuilder;
 use reqwest::Response;
 use reqwest::StatusCode;
+use reqwest::Url;
 use sha1::Digest;
 use sha1::Sha1;
 use sha2::Sha256;
@@ -38,6 +44,8 @@ use smallvec::SmallVec;
 use thiserror::Error;
 
 use crate::digest_config::DigestConfig;
+use crate::materialize::download_cache::DownloadCache;
+use crate::materialize::netrc::Netrc;
 
 #[derive(Debug, Clone, Dupe, Allocative)]
 pub enum Checksum {
@@ -173,7 +181,53 @@ impl AsHttpError for HttpDownloadError {
     }
 }
 
-pub fn http_client() -> anyhow::Result<Client> {
+/// Process-wide settings for HTTP downloads, from the root buckconfig when the daemon starts.
+#[derive(Debug, Default)]
+pub struct DownloadConfig {
+    /// Credentials to use for each host.
+    pub netrc: Option<Netrc>,
+    /// Cache of downloaded files shared with other checkouts and daemons on this machine.
+    pub cache: Option<DownloadCache>,
+}
+
+static DOWNLOAD_CONFIG: RwLock<Option<Arc<DownloadConfig>>> = RwLock::new(None);
+
+/// Set the config used by clients returned by `http_client` from now on.
+pub fn set_download_config(config: DownloadConfig) {
+    *DOWNLOAD_CONFIG.write().unwrap() = Some(Arc::new(config));
+}
+
+pub struct HttpClient {
+    client: Client,
+    config: Arc<DownloadConfig>,
+}
+
+impl HttpClient {
+    pub fn download_cache(&self) -> Option<&DownloadCache> {
+        self.config.cache.as_ref()
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.with_credentials(self.client.get(url), url)
+    }
+
+    fn head(&self, url: &str) -> RequestBuilder {
+        self.with_credentials(self.client.head(url), url)
+    }
+
+    fn with_credentials(&self, req: RequestBuilder, url: &str) -> RequestBuilder {
+        let credentials = self.config.netrc.as_ref().and_then(|netrc| {
+            let url = Url::parse(url).ok()?;
+            netrc.credentials(url.host_str()?, url.scheme() == "https")
+        });
+        match credentials {
+            Some(credentials) => req.basic_auth(&credentials.login, credentials.password.as_ref()),
+            None => req,
+        }
+    }
+}
+
+pub fn http_client() -> anyhow::Result<HttpClient> {
     let mut builder = Client::builder();
 
     if !is_open_source() {
@@ -185,7 +239,14 @@ pub fn http_client() -> anyhow::Result<Client> {
         builder = builder.no_proxy();
     }
 
-    builder.build().context("Error creating http client")
+    Ok(HttpClient {
+        client: builder.build().context("Error creating http client")?,
+        config: DOWNLOAD_CONFIG
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or_else(Default::default, Dupe::dupe),
+    })
 }
 
 async fn http_dispatch(req: RequestBuilder, url: &str) -> Result<Response, HttpError> {
@@ -215,20 +276,70 @@ async fn http_dispatch(req: RequestBuilder, url: &str) -> Result<Response, HttpE
     Ok(response)
 }
 
-pub async fn http_head(client: &Client, url: &str) -> anyhow::Result<Response> {
-    Ok(http_retry(|| async {
-        let response = http_dispatch(client.head(url), url).await?;
-        Result::<_, HttpHeadError>::Ok(response)
+/// Try `url`, then each of `mirrors` in turn, until one succeeds.
+async fn with_mirrors<'a, Exec, F, T>(
+    url: &'a str,
+    mirrors: &'a [Arc<str>],
+    exec: Exec,
+) -> anyhow::Result<T>
+where
+    Exec: Fn(&'a str) -> F,
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let primary = url;
+    let mut urls = std::iter::once(url)
+        .chain(mirrors.iter().map(|m| &**m))
+        .peekable();
+
+    while let Some(url) = urls.next() {
+        match exec(url).await {
+            Ok(v) => return Ok(v),
+            Err(e) => match urls.peek() {
+                Some(next) => {
+                    tracing::warn!(
+                        "Request to `{}` failed, trying mirror `{}`: {:#}",
+                        url,
+                        next,
+                        e
+                    );
+                }
+                None if mirrors.is_empty() => return Err(e),
+                None => {
+                    return Err(e.context(format!(
+                        "Request failed for all {} mirrors of `{}`",
+                        mirrors.len(),
+                        primary
+                    )));
+                }
+            },
+        }
+    }
+
+    unreachable!("The loop above will exit before we get to the end")
+}
+
+pub async fn http_head(
+    client: &HttpClient,
+    url: &str,
+    mirrors: &[Arc<str>],
+) -> anyhow::Result<Response> {
+    with_mirrors(url, mirrors, |url| async move {
+        Ok(http_retry(|| async {
+            let response = http_dispatch(client.head(url), url).await?;
+            Result::<_, HttpHeadError>::Ok(response)
+        })
+        .await?)
     })
-    .await?)
+    .await
 }
 
 pub async fn http_download(
-    client: &Client,
+    client: &HttpClient,
     fs: &ProjectRoot,
     digest_config: DigestConfig,
     path: &ProjectRelativePath,
     url: &str,
+    mirrors: &[Arc<str>],
     checksum: &Checksum,
     executable: bool,
 ) -> anyhow::Result<TrackedFileDigest> {
@@ -237,87 +348,213 @@ pub async fn http_download(
         fs_util::create_dir_all(fs.resolve(dir))?;
     }
 
-    Ok(http_retry(|| async {
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path.to_string())
-            .with_context(|| format!("open({})", abs_path))
-            .map_err(HttpDownloadError::IoError)?;
-
-        let response = http_dispatch(client.get(url), url).await?;
+    let cache = client.download_cache().zip(checksum.sha256());
+
+    if let Some((cache, sha256)) = cache {
+        if let Some(cached) = cache.get(sha256) {
+            match copy_from_cache(&cached, &abs_path, digest_config, checksum) {
+                Ok(digest) => {
+                    if executable {
+                        fs.set_executable(path)?;
+                    }
+                    return Ok(TrackedFileDigest::new(
+                        digest,
+                        digest_config.cas_digest_config(),
+                    ));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Removing invalid download cache entry `{}`: {:#}",
+                        cached,
+                        e
+                    );
+                    let _ignored = cache.remove(sha256);
+                }
+            }
+        }
+    }
 
-        let stream = response.bytes_stream();
-        let buf_writer = std::io::BufWriter::new(file);
+    let digest = with_mirrors(url, mirrors, |url| {
+        let abs_path = &abs_path;
+        async move {
+            Ok(http_retry(|| async {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(path.to_string())
+                    .with_context(|| format!("open({})", abs_path))
+                    .map_err(HttpDownloadError::IoError)?;
+
+                let response = http_dispatch(client.get(url), url).await?;
+
+                let stream = response.bytes_stream();
+                let buf_writer = std::io::BufWriter::new(file);
+
+                let digest = copy_and_hash(
+                    url,
+                    abs_path,
+                    stream,
+                    buf_writer,
+                    digest_config.cas_digest_config(),
+                    checksum,
+                )
+                .await?;
+
+                if executable {
+                    fs.set_executable(path)
+                        .map_err(HttpDownloadError::IoError)?;
+                }
 
-        let digest = copy_and_hash(
-            url,
-            &abs_path,
-            stream,
-            buf_writer,
-            digest_config.cas_digest_config(),
-            checksum,
-        )
-        .await?;
+                Result::<_, HttpDownloadError>::Ok(TrackedFileDigest::new(
+                    digest,
+                    digest_config.cas_digest_config(),
+                ))
+            })
+            .await?)
+        }
+    })
+    .await?;
 
-        if executable {
-            fs.set_executable(path)
-                .map_err(HttpDownloadError::IoError)?;
+    if let Some((cache, sha256)) = cache {
+        if let Err(e) = cache.insert(sha256, &abs_path) {
+            tracing::warn!("{:#}", e);
         }
+    }
 
-        Result::<_, HttpDownloadError>::Ok(TrackedFileDigest::new(
-            digest,
-            digest_config.cas_digest_config(),
-        ))
-    })
-    .await?)
+    Ok(digest)
 }
 
-/// Copy a stream into a writer while producing its digest and checksumming it.
-async fn copy_and_hash(
-    url: &str,
-    abs_path: &(impl std::fmt::Display + ?Sized),
-    mut stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + Unpin,
-    mut writer: impl Write,
-    digest_config: CasDigestConfig,
+/// Copy a cache entry to `abs_path`, validating it against `checksum` on the way.
+fn copy_from_cache(
+    cached: &AbsNormPath,
+    abs_path: &AbsNormPath,
+    digest_config: DigestConfig,
     checksum: &Checksum,
 ) -> Result<FileDigest, HttpDownloadError> {
-    let mut digester = FileDigest::digester(digest_config);
+    let io_error = |e: std::io::Error, op: &str, path: &AbsNormPath| {
+        HttpDownloadError::IoError(anyhow::Error::from(e).context(format!("{}({})", op, path)))
+    };
+
+    let mut reader = std::fs::File::open(cached).map_err(|e| io_error(e, "open", cached))?;
+    let mut writer = std::io::BufWriter::new(
+        std::fs::File::create(abs_path).map_err(|e| io_error(e, "open", abs_path))?,
+    );
+    let mut hasher = ChecksumHasher::new(digest_config.cas_digest_config(), checksum);
+
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| io_error(e, "read", cached))?;
+        if n == 0 {
+            break;
+        }
+        writer
+            .write_all(&buf[..n])
+            .map_err(|e| io_error(e, "write", abs_path))?;
+        hasher.update(&buf[..n]);
+    }
+    writer.flush().map_err(|e| io_error(e, "flush", abs_path))?;
 
-    // For each checksum entry we have, we're going to add a validator. We might have to create
-    // a new hasher, or reuse the `FileDigest::digester` if it matches.
+    hasher.finish(&cached.to_string())
+}
 
-    enum Validator {
-        PrimaryDigest,
-        ExtraDigest(Box<dyn DynDigest + Send>),
-    }
+/// Produces the digest of a download while validating it against a checksum.
+struct ChecksumHasher<'a> {
+    digester: Digester<FileDigestKind>,
+    validators: SmallVec<[(Validator, &'a str, &'static str); 2]>,
+}
 
-    let mut validators = SmallVec::<[_; 2]>::new();
+// For each checksum entry we have, we're going to add a validator. We might have to create
+// a new hasher, or reuse the `FileDigest::digester` if it matches.
+enum Validator {
+    PrimaryDigest,
+    ExtraDigest(Box<dyn DynDigest + Send>),
+}
 
-    if let Some(sha1) = checksum.sha1() {
-        let validator = if digester.algorithm() == DigestAlgorithm::Sha1 {
-            Validator::PrimaryDigest
-        } else {
-            Validator::ExtraDigest(Box::new(Sha1::new()) as _)
-        };
+impl<'a> ChecksumHasher<'a> {
+    fn new(digest_config: CasDigestConfig, checksum: &'a Checksum) -> Self {
+        let digester = FileDigest::digester(digest_config);
+        let mut validators = SmallVec::new();
+
+        if let Some(sha1) = checksum.sha1() {
+            let validator = if digester.algorithm() == DigestAlgorithm::Sha1 {
+                Validator::PrimaryDigest
+            } else {
+                Validator::ExtraDigest(Box::new(Sha1::new()) as _)
+            };
+
+            validators.push((validator, sha1, "sha1"));
+        }
+
+        if let Some(sha256) = checksum.sha256() {
+            let validator = if digester.algorithm() == DigestAlgorithm::Sha256 {
+                Validator::PrimaryDigest
+            } else {
+                Validator::ExtraDigest(Box::new(Sha256::new()) as _)
+            };
+
+            validators.push((validator, sha256, "sha256"));
+        }
 
-        validators.push((validator, sha1, "sha1"));
+        Self {
+            digester,
+            validators,
+        }
     }
 
-    if let Some(sha256) = checksum.sha256() {
-        let validator = if digester.algorithm() == DigestAlgorithm::Sha256 {
-            Validator::PrimaryDigest
-        } else {
-            Validator::ExtraDigest(Box::new(Sha256::new()) as _)
-        };
+    fn bytes_read(&self) -> u64 {
+        self.digester.bytes_read()
+    }
 
-        validators.push((validator, sha256, "sha256"));
+    fn update(&mut self, chunk: &[u8]) {
+        self.digester.update(chunk);
+        for (validator, _expected, _kind) in self.validators.iter_mut() {
+            if let Validator::ExtraDigest(hasher) = validator {
+                hasher.update(chunk);
+            }
+        }
     }
 
+    fn finish(self, url: &str) -> Result<FileDigest, HttpDownloadError> {
+        let digest = self.digester.finalize();
+
+        // Validate
+        for (validator, expected, kind) in self.validators {
+            let obtained = match validator {
+                Validator::PrimaryDigest => digest.raw_digest().to_string(),
+                Validator::ExtraDigest(hasher) => hex::encode(hasher.finalize()),
+            };
+
+            if expected != obtained {
+                return Err(HttpDownloadError::InvalidChecksum(
+                    kind,
+                    expected.to_owned(),
+                    obtained,
+                    url.to_owned(),
+                ));
+            }
+        }
+
+        Ok(digest)
+    }
+}
+
+/// Copy a stream into a writer while producing its digest and checksumming it.
+async fn copy_and_hash(
+    url: &str,
+    abs_path: &(impl std::fmt::Display + ?Sized),
+    mut stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + Unpin,
+    mut writer: impl Write,
+    digest_config: CasDigestConfig,
+    checksum: &Checksum,
+) -> Result<FileDigest, HttpDownloadError> {
+    let mut hasher = ChecksumHasher::new(digest_config, checksum);
+
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.map_err(|source| HttpError::HttpTransferError {
-            received: digester.bytes_read(),
+            received: hasher.bytes_read(),
             url: url.to_owned(),
             source,
         })?;
@@ -326,38 +563,14 @@ async fn copy_and_hash(
             .with_context(|| format!("write({})", abs_path))
             .map_err(HttpDownloadError::IoError)?;
 
-        digester.update(&chunk);
-        for (validator, _expected, _kind) in validators.iter_mut() {
-            if let Validator::ExtraDigest(hasher) = validator {
-                hasher.update(&chunk);
-            }
-        }
+        hasher.update(&chunk);
     }
     writer
         .flush()
         .with_context(|| format!("flush({})", abs_path))
         .map_err(HttpDownloadError::IoError)?;
 
-    let digest = digester.finalize();
-
-    // Validate
-    for (validator, expected, kind) in validators {
-        let obtained = match validator {
-            Validator::PrimaryDigest => digest.raw_digest().to_string(),
-            Validator::ExtraDigest(hasher) => hex::encode(hasher.finalize()),
-        };
-
-        if expected != obtained {
-            return Err(HttpDownloadError::InvalidChecksum(
-                kind,
-                expected.to_owned(),
-                obtained,
-                url.to_owned(),
-            ));
-        }
-    }
-
-    Ok(digest)
+    hasher.finish(url)
 }
 
 async fn http_retry<Exec, F, T, E>(exec: Exec) -> Result<T, E>
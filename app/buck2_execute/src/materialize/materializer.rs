@@ -8,12 +8,15 @@
  */
 
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_common::executor_config::RemoteExecutorUseCase;
 use buck2_common::file_ops::FileMetadata;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::base_deferred_key_dyn::BaseDeferredKeyDyn;
 use buck2_core::directory::DirectoryEntry;
@@ -505,6 +508,9 @@ pub struct HttpDownloadInfo {
     /// URL to download the file from.
     pub url: Arc<str>,
 
+    /// URLs to try, in order, if downloading from `url` fails.
+    pub mirrors: Arc<[Arc<str>]>,
+
     /// Size, whether the file is executable. Also contains a digest, which is a bit of a shame
     /// since it's duplicative of checksum.
     pub metadata: FileMetadata,
@@ -594,22 +600,40 @@ pub enum MaterializationMethodError {
     InvalidValueForConfig(String),
 }
 
+static MATERIALIZATIONS: ConfigKey<MaterializationMethod> = ConfigKey::new(
+    "buck2",
+    "materializations",
+    "How to materialize outputs: `all`, `deferred`, `deferred_skip_final_artifacts` or `eden`.",
+)
+.with_default("all")
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&MATERIALIZATIONS),
+    }
+}
+
 impl MaterializationMethod {
     pub fn try_new_from_config(legacy_config: Option<&LegacyBuckConfig>) -> anyhow::Result<Self> {
-        Self::try_new_from_config_value(
-            legacy_config.and_then(|c| c.get("buck2", "materializations")),
-        )
+        MATERIALIZATIONS.read_or_default(legacy_config)
     }
+}
+
+impl FromStr for MaterializationMethod {
+    type Err = MaterializationMethodError;
 
-    fn try_new_from_config_value(config_value: Option<&str>) -> anyhow::Result<Self> {
-        match config_value {
-            None | Some("") | Some("all") => Ok(MaterializationMethod::Immediate),
-            Some("deferred") => Ok(MaterializationMethod::Deferred),
-            Some("deferred_skip_final_artifacts") => {
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "all" => Ok(MaterializationMethod::Immediate),
+            "deferred" => Ok(MaterializationMethod::Deferred),
+            "deferred_skip_final_artifacts" => {
                 Ok(MaterializationMethod::DeferredSkipFinalArtifacts)
             }
-            Some("eden") => Ok(MaterializationMethod::Eden),
-            Some(v) => Err(MaterializationMethodError::InvalidValueForConfig(v.to_owned()).into()),
+            "eden" => Ok(MaterializationMethod::Eden),
+            v => Err(MaterializationMethodError::InvalidValueForConfig(
+                v.to_owned(),
+            )),
         }
     }
 }
@@ -662,6 +686,10 @@ pub trait DeferredMaterializerExtensions: Send + Sync {
 
     fn queue_size(&self) -> usize;
 
+    /// The files downloaded so far and the names of the blobs holding their contents, or `None` if
+    /// downloads aren't being traced.
+    fn traced_downloads(&self) -> Option<Vec<(ProjectRelativePathBuf, String)>>;
+
     /// Create a new DeferredMaterializerSubscription.
     async fn create_subscription(
         &self,
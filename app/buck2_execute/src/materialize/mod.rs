@@ -7,9 +7,11 @@
  * of this source tree.
  */
 
+pub mod download_cache;
 #[cfg(any(fbcode_build, cargo_internal_build))]
 pub mod eden_api;
 pub mod http;
 
 pub mod materializer;
+pub mod netrc;
 pub mod nodisk;
@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Parsing of netrc files, used to find credentials for HTTP downloads by host.
+
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum NetrcError {
+    #[error("Expected a value after `{0}`")]
+    MissingValue(String),
+    #[error("Unexpected token `{0}`, expected `machine`, `default` or `macdef`")]
+    UnexpectedToken(String),
+    #[error("`{0}` must follow a `machine` or `default` entry")]
+    NoMachine(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NetrcCredentials {
+    pub login: String,
+    pub password: Option<String>,
+}
+
+/// Credentials from a netrc file, by host.
+#[derive(Debug, Default)]
+pub struct Netrc {
+    hosts: HashMap<String, NetrcCredentials>,
+    default: Option<NetrcCredentials>,
+}
+
+impl Netrc {
+    pub fn from_file(path: &AbsNormPath) -> anyhow::Result<Self> {
+        let contents = fs_util::read_to_string(path)?;
+        Self::parse(&contents).with_context(|| format!("Error parsing netrc file `{}`", path))
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        enum Entry {
+            None,
+            Machine(String),
+            Default,
+        }
+
+        let mut netrc = Netrc::default();
+        let mut entry = Entry::None;
+        let mut credentials = NetrcCredentials::default();
+
+        let mut finish = |entry: Entry, credentials: NetrcCredentials| match entry {
+            Entry::None => {}
+            Entry::Machine(host) => {
+                // Like curl, the first entry for a host wins.
+                netrc.hosts.entry(host).or_insert(credentials);
+            }
+            Entry::Default => {
+                netrc.default.get_or_insert(credentials);
+            }
+        };
+
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                if token.starts_with('#') {
+                    break;
+                }
+
+                let mut value = || {
+                    tokens
+                        .next()
+                        .map(str::to_owned)
+                        .ok_or_else(|| NetrcError::MissingValue(token.to_owned()))
+                };
+
+                match token {
+                    "machine" => {
+                        let host = value()?;
+                        finish(
+                            std::mem::replace(&mut entry, Entry::Machine(host)),
+                            std::mem::take(&mut credentials),
+                        );
+                    }
+                    "default" => {
+                        finish(
+                            std::mem::replace(&mut entry, Entry::Default),
+                            std::mem::take(&mut credentials),
+                        );
+                    }
+                    "login" | "password" | "account" => {
+                        let v = value()?;
+                        if matches!(entry, Entry::None) {
+                            return Err(NetrcError::NoMachine(token.to_owned()).into());
+                        }
+                        match token {
+                            "login" => credentials.login = v,
+                            "password" => credentials.password = Some(v),
+                            _ => {}
+                        }
+                    }
+                    "macdef" => {
+                        value()?;
+                        // A macro definition runs until the next empty line.
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => return Err(NetrcError::UnexpectedToken(token.to_owned()).into()),
+                }
+            }
+        }
+        finish(entry, credentials);
+
+        Ok(netrc)
+    }
+
+    /// The credentials to use for `host`, falling back to the `default` entry. The `default`
+    /// entry applies to any host, including mirrors picked by rule authors, so it is only used
+    /// for `https` requests, where it can't be sent in the clear.
+    pub fn credentials(&self, host: &str, https: bool) -> Option<&NetrcCredentials> {
+        self.hosts
+            .get(host)
+            .or(self.default.as_ref().filter(|_| https))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let netrc = Netrc::parse(
+            "# Comment\n\
+             machine example.com login alice password secret\n\
+             machine other.com\n\
+             \tlogin bob # trailing comment\n\
+             \tpassword hunter2\n\
+             \taccount ignored\n\
+             macdef init\n\
+             cd /pub\n\
+             machine example.com login ignored\n\
+             \n\
+             default login anonymous password guest\n",
+        )?;
+
+        assert_eq!(
+            Some(&NetrcCredentials {
+                login: "alice".to_owned(),
+                password: Some("secret".to_owned()),
+            }),
+            netrc.credentials("example.com", false)
+        );
+        assert_eq!(
+            Some(&NetrcCredentials {
+                login: "bob".to_owned(),
+                password: Some("hunter2".to_owned()),
+            }),
+            netrc.credentials("other.com", true)
+        );
+        assert_eq!(
+            Some(&NetrcCredentials {
+                login: "anonymous".to_owned(),
+                password: Some("guest".to_owned()),
+            }),
+            netrc.credentials("unknown.com", true)
+        );
+        assert_eq!(None, netrc.credentials("unknown.com", false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Netrc::parse("machine").is_err());
+        assert!(Netrc::parse("login alice").is_err());
+        assert!(Netrc::parse("machine example.com user alice").is_err());
+        assert!(
+            Netrc::parse("")
+                .unwrap()
+                .credentials("example.com", true)
+                .is_none()
+        );
+    }
+}
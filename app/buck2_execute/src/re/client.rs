@@ -256,7 +256,7 @@ impl RemoteExecutionClient {
 
     pub async fn upload(
         &self,
-        materializer: &Arc<dyn Materializer>,
+        materializer: &dyn Materializer,
         blobs: &ActionBlobs,
         dir_path: &ProjectRelativePath,
         input_dir: &ActionImmutableDirectory,
@@ -675,7 +675,7 @@ impl RemoteExecutionClientImpl {
 
     async fn upload(
         &self,
-        materializer: &Arc<dyn Materializer>,
+        materializer: &dyn Materializer,
         blobs: &ActionBlobs,
         dir_path: &ProjectRelativePath,
         input_dir: &ActionImmutableDirectory,
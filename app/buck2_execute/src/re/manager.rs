@@ -318,7 +318,7 @@ impl ManagedRemoteExecutionClient {
 
     pub async fn upload(
         &self,
-        materializer: &Arc<dyn Materializer>,
+        materializer: &dyn Materializer,
         blobs: &ActionBlobs,
         dir_path: &ProjectRelativePath,
         input_dir: &ActionImmutableDirectory,
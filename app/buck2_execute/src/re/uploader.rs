@@ -9,7 +9,6 @@
 
 use std::collections::HashSet;
 use std::str::FromStr;
-use std::sync::Arc;
 
 use anyhow::Context;
 use buck2_common::cas_digest::TrackedCasDigest;
@@ -158,7 +157,7 @@ impl Uploader {
     }
     pub async fn upload(
         client: &REClient,
-        materializer: &Arc<dyn Materializer>,
+        materializer: &dyn Materializer,
         dir_path: &ProjectRelativePath,
         input_dir: &ActionImmutableDirectory,
         blobs: &ActionBlobs,
@@ -0,0 +1,288 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The downward API as Buck serves it: what a process reports becomes events nested under the span
+//! of whatever Buck ran it for.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_data::span_start_event;
+use buck2_downward_api::DownwardApi;
+use buck2_downward_api::DownwardSpanId;
+use buck2_downward_api::Metric;
+use buck2_events::dispatch::EventDispatcher;
+use buck2_events::dispatch::Span;
+use buck2_events::span::SpanId;
+use dashmap::DashMap;
+use tokio::task::JoinHandle;
+use tracing::Level;
+
+/// Turns what a process reports into events. Reports without a span are attached to the span of
+/// the process. Spans the process started but never ended are reported as cancelled once this is
+/// dropped.
+pub struct BuckDownwardApi {
+    dispatcher: EventDispatcher,
+    span_id: SpanId,
+    /// Set if the span of the process was started for it, in which case it's ended on drop.
+    own_span: Option<Span>,
+    spans: DashMap<DownwardSpanId, Span>,
+    next_span_id: AtomicU64,
+}
+
+impl BuckDownwardApi {
+    /// For a process that runs under the existing span `span_id`.
+    pub fn in_span(dispatcher: EventDispatcher, span_id: SpanId) -> Self {
+        Self {
+            dispatcher,
+            span_id,
+            own_span: None,
+            spans: DashMap::new(),
+            next_span_id: AtomicU64::new(0),
+        }
+    }
+
+    /// For a process that gets a span of its own, nested under the current span.
+    pub fn with_span(
+        dispatcher: EventDispatcher,
+        start: impl Into<span_start_event::Data>,
+    ) -> Self {
+        let span = dispatcher.create_span(start);
+        let mut api = Self::in_span(dispatcher, span.span_id());
+        api.own_span = Some(span);
+        api
+    }
+
+    fn instant_event(
+        &self,
+        span: Option<DownwardSpanId>,
+        data: impl Into<buck2_data::instant_event::Data>,
+    ) -> anyhow::Result<()> {
+        match span {
+            Some(id) => self.span(id)?.instant_event(data),
+            None => self.dispatcher.instant_event_in_span(data, self.span_id),
+        }
+        Ok(())
+    }
+
+    fn span(
+        &self,
+        id: DownwardSpanId,
+    ) -> anyhow::Result<dashmap::mapref::one::Ref<'_, DownwardSpanId, Span>> {
+        self.spans
+            .get(&id)
+            .with_context(|| format!("Unknown span `{}`", id.0))
+    }
+}
+
+impl Drop for BuckDownwardApi {
+    fn drop(&mut self) {
+        // End the process's span after the spans nested under it.
+        self.spans.clear();
+        if let Some(span) = self.own_span.take() {
+            span.end(buck2_data::DownwardApiSpanEnd { success: true });
+        }
+    }
+}
+
+#[async_trait]
+impl DownwardApi for BuckDownwardApi {
+    async fn console(&self, _level: Level, msg: String) -> anyhow::Result<()> {
+        // TODO(brasselsprouts): use the level
+        self.instant_event(None, buck2_data::ConsoleMessage { message: msg })
+    }
+
+    async fn log(&self, level: Level, msg: String) -> anyhow::Result<()> {
+        if level == Level::ERROR {
+            tracing::error!("{}", msg);
+        } else if level == Level::WARN {
+            tracing::warn!("{}", msg);
+        } else if level == Level::INFO {
+            tracing::info!("{}", msg);
+        } else if level == Level::DEBUG {
+            tracing::debug!("{}", msg);
+        } else {
+            tracing::trace!("{}", msg);
+        }
+        Ok(())
+    }
+
+    async fn external(&self, _data: HashMap<String, String>) -> anyhow::Result<()> {
+        // TODO: needs an event to carry this.
+        Err(anyhow::anyhow!("External events are not supported yet"))
+    }
+
+    async fn progress(
+        &self,
+        step: String,
+        percent: Option<f64>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()> {
+        self.instant_event(span, buck2_data::DownwardApiProgress { step, percent })
+    }
+
+    async fn metrics(
+        &self,
+        metrics: Vec<Metric>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()> {
+        let metrics = buck2_data::DownwardApiMetrics {
+            metrics: metrics
+                .into_iter()
+                .map(
+                    |Metric { key, value, unit }| buck2_data::DownwardApiMetric {
+                        key,
+                        value,
+                        unit,
+                    },
+                )
+                .collect(),
+        };
+        self.instant_event(span, metrics)
+    }
+
+    async fn span_start(
+        &self,
+        name: String,
+        parent: Option<DownwardSpanId>,
+    ) -> anyhow::Result<DownwardSpanId> {
+        let start = buck2_data::DownwardApiSpanStart { name };
+        let span = match parent {
+            Some(parent) => self.span(parent)?.create_child(start),
+            None => self.dispatcher.create_child_span(start, self.span_id),
+        };
+        let id = DownwardSpanId(self.next_span_id.fetch_add(1, Ordering::Relaxed));
+        self.spans.insert(id, span);
+        Ok(id)
+    }
+
+    async fn span_end(&self, span: DownwardSpanId, success: bool) -> anyhow::Result<()> {
+        let (_, span) = self
+            .spans
+            .remove(&span)
+            .with_context(|| format!("Unknown span `{}`", span.0))?;
+        span.end(buck2_data::DownwardApiSpanEnd { success });
+        Ok(())
+    }
+}
+
+/// The downward API served to a single command on a Unix socket in a directory of its own. The
+/// server is stopped and the socket removed on drop.
+pub(crate) struct DownwardApiSocket {
+    path: PathBuf,
+    server: JoinHandle<()>,
+    // Removes the socket on drop, so it comes after the server.
+    _dir: tempfile::TempDir,
+}
+
+impl DownwardApiSocket {
+    /// Serve `api`. The socket lives in the system's temporary directory rather than in buck-out,
+    /// since the path of a Unix socket is limited to about 100 bytes.
+    #[cfg(unix)]
+    pub(crate) fn serve(api: BuckDownwardApi) -> anyhow::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("buck2-")
+            .tempdir()
+            .context("Error creating a directory for the socket")?;
+        let path = dir.path().join("downward_api");
+        let listener = tokio::net::UnixListener::bind(&path)
+            .with_context(|| format!("Error binding `{}`", path.display()))?;
+
+        let server = tokio::spawn(async move {
+            let res = tonic::transport::Server::builder()
+                .add_service(buck2_test_api::grpc::downward_api_service(api))
+                .serve_with_incoming(tokio_stream::wrappers::UnixListenerStream::new(listener))
+                .await;
+            if let Err(e) = res {
+                tracing::warn!("Downward API server exited with an error: {:#}", e);
+            }
+        });
+
+        Ok(Self {
+            path,
+            server,
+            _dir: dir,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn serve(api: BuckDownwardApi) -> anyhow::Result<Self> {
+        let _unused = api;
+        Err(anyhow::anyhow!("The downward API is only served on Unix"))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DownwardApiSocket {
+    fn drop(&mut self) {
+        // Requests the command made have been answered by the time it exits, and it isn't
+        // waiting on any more, so there is nothing to wait for.
+        self.server.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_events::create_source_sink_pair;
+    use buck2_events::BuckEvent;
+    use buck2_events::EventSource;
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn next_event(source: &mut impl EventSource) -> BuckEvent {
+        source
+            .try_receive()
+            .and_then(|event| event.unpack_buck().cloned())
+            .expect("Expected a buck event")
+    }
+
+    #[tokio::test]
+    async fn test_reports_attach_to_process_span() -> anyhow::Result<()> {
+        let (mut source, sink) = create_source_sink_pair();
+        let dispatcher = EventDispatcher::new(TraceId::new(), sink);
+        let parent = dispatcher.create_span(buck2_data::DownwardApiSpanStart {
+            name: "action".to_owned(),
+        });
+        let parent_id = next_event(&mut source).span_id().unwrap();
+
+        let api = BuckDownwardApi::in_span(dispatcher, parent_id);
+
+        api.progress("step".to_owned(), None, None).await?;
+        assert_eq!(next_event(&mut source).parent_id(), Some(parent_id));
+
+        let span = api.span_start("child".to_owned(), None).await?;
+        let child_id = {
+            let event = next_event(&mut source);
+            assert_eq!(event.parent_id(), Some(parent_id));
+            event.span_id().unwrap()
+        };
+
+        api.metrics(Vec::new(), Some(span)).await?;
+        assert_eq!(next_event(&mut source).parent_id(), Some(child_id));
+
+        assert!(api.span_end(DownwardSpanId(42), true).await.is_err());
+
+        // Spans left open are cancelled, but the process's span isn't this one's to end.
+        drop(api);
+        assert_eq!(next_event(&mut source).span_id(), Some(child_id));
+        assert!(source.try_receive().is_none());
+
+        parent.end(buck2_data::DownwardApiSpanEnd { success: true });
+        Ok(())
+    }
+}
@@ -31,6 +31,9 @@ use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::quiet_soft_error;
+use buck2_downward_api::DOWNWARD_API_SOCKET_ENV;
+use buck2_events::dispatch::current_span;
+use buck2_events::dispatch::get_dispatcher;
 use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::extract_artifact_value;
@@ -56,6 +59,7 @@ use buck2_execute::execute::request::CommandExecutionInput;
 use buck2_execute::execute::request::CommandExecutionOutput;
 use buck2_execute::execute::request::CommandExecutionOutputRef;
 use buck2_execute::execute::request::CommandExecutionRequest;
+use buck2_execute::execute::request::ResourceLimits;
 use buck2_execute::execute::result::CommandExecutionMetadata;
 use buck2_execute::execute::result::CommandExecutionResult;
 use buck2_execute::knobs::ExecutorGlobalKnobs;
@@ -81,6 +85,9 @@ use more_futures::cancellable_future::CancellationObserver;
 use thiserror::Error;
 use tracing::info;
 
+use crate::downward_api::BuckDownwardApi;
+use crate::downward_api::DownwardApiSocket;
+
 #[derive(Debug, Error)]
 enum LocalExecutionError {
     #[error("Args list was empty")]
@@ -134,6 +141,7 @@ impl LocalExecutor {
         working_directory: Option<&'a ProjectRelativePath>,
         timeout: Option<Duration>,
         env_inheritance: Option<&'a EnvironmentInheritance>,
+        resource_limits: &'a ResourceLimits,
         liveliness_observer: impl LivelinessObserver + 'static,
     ) -> impl futures::future::Future<
         Output = anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>,
@@ -159,6 +167,7 @@ impl LocalExecutor {
                             working_directory,
                             timeout,
                             env_inheritance,
+                            resource_limits,
                             liveliness_observer,
                             self.knobs.enable_miniperf,
                         )
@@ -167,7 +176,7 @@ impl LocalExecutor {
 
                     #[cfg(not(unix))]
                     {
-                        let _unused = forkserver;
+                        let _unused = (forkserver, resource_limits);
                         Err(anyhow::anyhow!("Forkserver is not supported off-UNIX"))
                     }
                 }
@@ -319,6 +328,15 @@ impl LocalExecutor {
 
         let daemon_uuid: &str = &buck2_events::metadata::DAEMON_UUID.to_string();
 
+        // What the command reports through the downward API is attached to the span it runs in.
+        let downward_api_socket = current_span().filter(|_| cfg!(unix)).and_then(|span_id| {
+            DownwardApiSocket::serve(BuckDownwardApi::in_span(get_dispatcher(), span_id))
+                .map_err(|e| {
+                    tracing::warn!("Not serving the downward API to `{}`: {:#}", args[0], e)
+                })
+                .ok()
+        });
+
         let iter_env = || {
             tmpdirs
                 .iter()
@@ -333,6 +351,12 @@ impl LocalExecutor {
                     "BUCK2_DAEMON_UUID",
                     StrOrOsStr::from(daemon_uuid),
                 )))
+                .chain(downward_api_socket.as_ref().map(|socket| {
+                    (
+                        DOWNWARD_API_SOCKET_ENV,
+                        StrOrOsStr::from(socket.path().as_os_str()),
+                    )
+                }))
         };
 
         let liveliness_observer = manager.liveliness_observer.dupe().and(cancellation);
@@ -369,6 +393,7 @@ impl LocalExecutor {
                         request.working_directory(),
                         request.timeout(),
                         request.local_environment_inheritance(),
+                        request.resource_limits(),
                         liveliness_observer,
                     )
                     .await;
@@ -388,6 +413,8 @@ impl LocalExecutor {
         )
         .await;
 
+        drop(downward_api_socket);
+
         let execution_kind = CommandExecutionKind::Local {
             digest: action_digest.dupe(),
             command: args.to_vec(),
@@ -479,8 +506,7 @@ impl LocalExecutor {
         for output in request.outputs() {
             let path = output.resolve(&self.artifact_fs).into_path();
             let abspath = self.root.join(&path);
-            let entry = self
-                .build_entry_from_disk(abspath, digest_config)
+            let entry = build_entry_from_disk(abspath, digest_config)
                 .with_context(|| format!("collecting output {:?}", path))?;
             if let Some(entry) = entry {
                 insert_entry(&mut builder, &path, entry)?;
@@ -514,82 +540,82 @@ impl LocalExecutor {
 
         Ok(mapped_outputs)
     }
+}
 
-    fn build_entry_from_disk(
-        &self,
-        mut path: AbsNormPathBuf,
+/// Build the directory entry for whatever is at `path` on disk, hashing every file.
+pub(crate) fn build_entry_from_disk(
+    mut path: AbsNormPathBuf,
+    digest_config: DigestConfig,
+) -> anyhow::Result<Option<ActionDirectoryEntry<ActionDirectoryBuilder>>> {
+    fn build_dir_from_disk(
+        disk_path: &mut AbsNormPathBuf,
         digest_config: DigestConfig,
-    ) -> anyhow::Result<Option<ActionDirectoryEntry<ActionDirectoryBuilder>>> {
-        fn build_dir_from_disk(
-            disk_path: &mut AbsNormPathBuf,
-            digest_config: DigestConfig,
-        ) -> anyhow::Result<ActionDirectoryBuilder> {
-            let mut builder = ActionDirectoryBuilder::empty();
-
-            for file in fs_util::read_dir(&disk_path)? {
-                let file = file?;
-                let filetype = file.file_type()?;
-                let filename = file.file_name();
-
-                let filename = filename
-                    .to_str()
-                    .context("Filename is not UTF-8")
-                    .and_then(|f| FileNameBuf::try_from(f.to_owned()))
-                    .with_context(|| format!("Invalid filename: {}", disk_path.display()))?;
-
-                disk_path.push(&filename);
-
-                if filetype.is_dir() {
-                    let dir = build_dir_from_disk(disk_path, digest_config)?;
-                    builder.insert(filename, DirectoryEntry::Dir(dir))?;
-                } else if filetype.is_symlink() {
-                    builder.insert(
-                        filename,
-                        DirectoryEntry::Leaf(new_symlink(fs_util::read_link(&disk_path)?)?),
-                    )?;
-                } else if filetype.is_file() {
-                    let metadata = FileMetadata {
-                        digest: TrackedFileDigest::new(
-                            FileDigest::from_file(&disk_path, digest_config.cas_digest_config())?,
-                            digest_config.cas_digest_config(),
-                        ),
-                        is_executable: file.path().executable(),
-                    };
-                    builder.insert(
-                        filename,
-                        DirectoryEntry::Leaf(ActionDirectoryMember::File(metadata)),
-                    )?;
-                }
-                disk_path.pop();
+    ) -> anyhow::Result<ActionDirectoryBuilder> {
+        let mut builder = ActionDirectoryBuilder::empty();
+
+        for file in fs_util::read_dir(&disk_path)? {
+            let file = file?;
+            let filetype = file.file_type()?;
+            let filename = file.file_name();
+
+            let filename = filename
+                .to_str()
+                .context("Filename is not UTF-8")
+                .and_then(|f| FileNameBuf::try_from(f.to_owned()))
+                .with_context(|| format!("Invalid filename: {}", disk_path.display()))?;
+
+            disk_path.push(&filename);
+
+            if filetype.is_dir() {
+                let dir = build_dir_from_disk(disk_path, digest_config)?;
+                builder.insert(filename, DirectoryEntry::Dir(dir))?;
+            } else if filetype.is_symlink() {
+                builder.insert(
+                    filename,
+                    DirectoryEntry::Leaf(new_symlink(fs_util::read_link(&disk_path)?)?),
+                )?;
+            } else if filetype.is_file() {
+                let metadata = FileMetadata {
+                    digest: TrackedFileDigest::new(
+                        FileDigest::from_file(&disk_path, digest_config.cas_digest_config())?,
+                        digest_config.cas_digest_config(),
+                    ),
+                    is_executable: file.path().executable(),
+                };
+                builder.insert(
+                    filename,
+                    DirectoryEntry::Leaf(ActionDirectoryMember::File(metadata)),
+                )?;
             }
-
-            Ok(builder)
+            disk_path.pop();
         }
 
-        // Get file metadata. If the file is missing, ignore it.
-        let m = match std::fs::symlink_metadata(&path) {
-            Ok(m) => m,
-            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
-            Err(err) => return Err(err.into()),
-        };
-
-        let value = if m.file_type().is_symlink() {
-            DirectoryEntry::Leaf(new_symlink(fs_util::read_link(&path)?)?)
-        } else if m.is_file() {
-            DirectoryEntry::Leaf(ActionDirectoryMember::File(FileMetadata {
-                digest: TrackedFileDigest::new(
-                    FileDigest::from_file(&path, digest_config.cas_digest_config())?,
-                    digest_config.cas_digest_config(),
-                ),
-                is_executable: path.executable(),
-            }))
-        } else if m.is_dir() {
-            DirectoryEntry::Dir(build_dir_from_disk(&mut path, digest_config)?)
-        } else {
-            unimplemented!("Path {:?} is of an unknown file type.", path)
-        };
-        Ok(Some(value))
+        Ok(builder)
     }
+
+    // Get file metadata. If the file is missing, ignore it.
+    let m = match std::fs::symlink_metadata(&path) {
+        Ok(m) => m,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let value = if m.file_type().is_symlink() {
+        DirectoryEntry::Leaf(new_symlink(fs_util::read_link(&path)?)?)
+    } else if m.is_file() {
+        DirectoryEntry::Leaf(ActionDirectoryMember::File(FileMetadata {
+            digest: TrackedFileDigest::new(
+                FileDigest::from_file(&path, digest_config.cas_digest_config())?,
+                digest_config.cas_digest_config(),
+            ),
+            is_executable: path.executable(),
+        }))
+    } else if m.is_dir() {
+        DirectoryEntry::Dir(build_dir_from_disk(&mut path, digest_config)?)
+    } else {
+        unimplemented!("Path {:?} is of an unknown file type.", path)
+    };
+    Ok(Some(value))
 }
 
 #[async_trait]
@@ -605,23 +631,29 @@ impl PreparedCommandExecutor for LocalExecutor {
 
         let PreparedCommand {
             request,
-            target: _,
+            target,
             prepared_action,
             digest_config,
         } = command;
 
+        // Digests change with inputs, so we learn memory usage per action instead.
+        let command_key = target.re_action_key();
+        let memory_permits = self
+            .host_sharing_broker
+            .requested_memory_permits(&command_key, request.memory_estimate());
+
         let _permit = executor_stage_async(
             buck2_data::LocalStage {
                 stage: Some(buck2_data::LocalQueued {}.into()),
             },
             self.host_sharing_broker
-                .acquire(request.host_sharing_requirements()),
+                .acquire_with_memory(request.host_sharing_requirements(), memory_permits),
         )
         .await;
 
         // If we start running something, we don't want this task to get dropped, because if we do
         // we might interfere with e.g. clean up.
-        with_structured_cancellation(|cancellation| {
+        let result = with_structured_cancellation(|cancellation| {
             Self::exec_request(
                 self,
                 &prepared_action.action,
@@ -631,7 +663,19 @@ impl PreparedCommandExecutor for LocalExecutor {
                 *digest_config,
             )
         })
-        .await
+        .await;
+
+        if let Some(peak) = result
+            .report
+            .timing
+            .execution_stats
+            .and_then(|stats| stats.peak_memory_bytes)
+        {
+            self.host_sharing_broker
+                .record_peak_memory(&command_key, peak);
+        }
+
+        result
     }
 }
 
@@ -888,6 +932,7 @@ mod unix {
         working_directory: &Path,
         comand_timeout: Option<Duration>,
         env_inheritance: Option<&EnvironmentInheritance>,
+        resource_limits: &ResourceLimits,
         liveliness_observer: impl LivelinessObserver + 'static,
         enable_miniperf: bool,
     ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)> {
@@ -905,6 +950,13 @@ mod unix {
             env: vec![],
             timeout: comand_timeout.try_map(|d| d.try_into())?,
             enable_miniperf,
+            resource_limits: (!resource_limits.is_empty()).then(|| {
+                buck2_forkserver_proto::ResourceLimits {
+                    memory_max_bytes: resource_limits.memory_max_bytes,
+                    cpu_max_millicores: resource_limits.cpu_max_millicores,
+                    pids_max: resource_limits.pids_max,
+                }
+            }),
         };
         apply_local_execution_environment(&mut req, working_directory, env, env_inheritance);
         forkserver
@@ -1111,6 +1163,7 @@ mod tests {
                 None,
                 None,
                 None,
+                &ResourceLimits::default(),
                 NoopLivelinessObserver::create(),
             )
             .await?;
@@ -1146,6 +1199,7 @@ mod tests {
                 None,
                 None,
                 Some(&EnvironmentInheritance::empty()),
+                &ResourceLimits::default(),
                 NoopLivelinessObserver::create(),
             )
             .await?;
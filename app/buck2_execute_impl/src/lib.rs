@@ -9,6 +9,7 @@
 
 #![feature(try_blocks)]
 
+pub mod downward_api;
 pub mod executors;
 pub mod low_pass_filter;
 pub mod materializers;
@@ -7,7 +7,9 @@
  * of this source tree.
  */
 
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use buck2_common::file_ops::FileType;
@@ -38,6 +40,7 @@ use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::ArtifactTree;
 use crate::materializers::deferred::DefaultIoHandler;
 use crate::materializers::deferred::DeferredMaterializerCommandProcessor;
+use crate::materializers::local_store::LocalStoreSweepStats;
 use crate::materializers::sqlite::MaterializerStateSqliteDb;
 
 #[derive(Derivative)]
@@ -75,6 +78,7 @@ impl ExtensionCommand<DefaultIoHandler> for CleanStaleArtifacts {
                 skip_clean_response_with_message(
                     "Skipping clean, set buck2.defer_write_actions to use clean --stale",
                 )
+                .map(|(futs, response)| (futs, None, response))
             } else {
                 gather_clean_futures_for_stale_artifacts(
                     &mut processor.tree,
@@ -85,16 +89,35 @@ impl ExtensionCommand<DefaultIoHandler> for CleanStaleArtifacts {
                     &processor.io,
                     processor.digest_config,
                 )
+                .and_then(|(futs, response)| {
+                    // This has to happen after stale artifacts were invalidated, which drops
+                    // their references to the local store.
+                    let sweep = if self.dry_run || response.stats.is_none() {
+                        None
+                    } else {
+                        gather_local_store_sweep(sqlite_db, &processor.io)?
+                    };
+                    Ok((futs, sweep, response))
+                })
             }
         } else {
             skip_clean_response_with_message(
                 "Skipping clean, set buck2.sqlite_materializer_state to use clean --stale",
             )
+            .map(|(futs, response)| (futs, None, response))
         };
         let fut = async move {
-            let (cleaning_futs, response) = res?;
+            let (cleaning_futs, sweep, mut response) = res?;
             futures::future::try_join_all(cleaning_futs).await?;
             tracing::trace!("finished cleaning stale artifacts");
+            if let Some(sweep) = sweep {
+                let sweep_stats = sweep.await?;
+                tracing::trace!(?sweep_stats, "finished sweeping local store");
+                if let Some(stats) = response.stats.as_mut() {
+                    stats.cleaned_local_store_blob_count = sweep_stats.blob_count;
+                    stats.cleaned_local_store_bytes = sweep_stats.bytes;
+                }
+            }
             Ok(response)
         }
         .boxed();
@@ -140,6 +163,8 @@ fn gather_clean_futures_for_stale_artifacts(
         cleaned_artifact_count: 0,
         cleaned_path_count: 0,
         cleaned_bytes: 0,
+        cleaned_local_store_blob_count: 0,
+        cleaned_local_store_bytes: 0,
     };
     let result = if tracked_only {
         find_stale_tracked_only(tree, keep_since_time, &mut stats)?
@@ -203,6 +228,37 @@ fn gather_clean_futures_for_stale_artifacts(
     ))
 }
 
+/// Returns a future that removes local store blobs no artifact references anymore, if the local
+/// store is in use.
+fn gather_local_store_sweep(
+    sqlite_db: &mut MaterializerStateSqliteDb,
+    io: &Arc<DefaultIoHandler>,
+) -> anyhow::Result<Option<BoxFuture<'static, anyhow::Result<LocalStoreSweepStats>>>> {
+    if io.local_store.is_none() {
+        return Ok(None);
+    }
+
+    let referenced: HashSet<String> = sqlite_db
+        .local_store_refs_table()
+        .refcounts()?
+        .into_keys()
+        .collect();
+    let changed_before = SystemTime::now();
+    let io = io.dupe();
+
+    Ok(Some(
+        async move {
+            io.io_executor
+                .execute_io_inline(|| match &io.local_store {
+                    Some(store) => store.sweep(&referenced, changed_before),
+                    None => Ok(LocalStoreSweepStats::default()),
+                })
+                .await
+        }
+        .boxed(),
+    ))
+}
+
 enum StaleFinderResult {
     CleanPath(ProjectRelativePathBuf),
     CleanChildren(Vec<ProjectRelativePathBuf>),
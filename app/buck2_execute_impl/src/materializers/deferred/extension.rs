@@ -359,6 +359,10 @@ impl DeferredMaterializerExtensions for DeferredMaterializer {
         self.command_sender.counters.queue_size()
     }
 
+    fn traced_downloads(&self) -> Option<Vec<(ProjectRelativePathBuf, String)>> {
+        self.download_trace.as_ref().map(|trace| trace.downloads())
+    }
+
     async fn create_subscription(
         &self,
     ) -> anyhow::Result<Box<dyn DeferredMaterializerSubscription>> {
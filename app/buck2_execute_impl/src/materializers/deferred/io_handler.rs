@@ -10,6 +10,8 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -20,7 +22,11 @@ use buck2_common::result::ToSharedResultExt;
 use buck2_core::directory::unordered_entry_walk;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::env_helper::EnvHelper;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::digest::CasDigestFromReExt;
@@ -63,7 +69,11 @@ use crate::materializers::deferred::SharedMaterializingError;
 use crate::materializers::deferred::Version;
 use crate::materializers::deferred::WriteFile;
 use crate::materializers::io::materialize_files;
+use crate::materializers::io::materialize_files_from_store;
 use crate::materializers::io::MaterializeTreeStructure;
+use crate::materializers::local_store::LocalStore;
+use crate::materializers::offline_blobs::materialize_files_from_offline_blobs;
+use crate::materializers::offline_blobs::DownloadTrace;
 
 pub(super) struct DefaultIoHandler {
     pub(super) fs: ProjectRoot,
@@ -72,8 +82,19 @@ pub(super) struct DefaultIoHandler {
     pub(super) re_client_manager: Arc<ReConnectionManager>,
     /// Executor for blocking IO operations
     pub(super) io_executor: Arc<dyn BlockingExecutor>,
+    /// If set, local copies are materialized via this store instead of copying bytes.
+    pub(super) local_store: Option<LocalStore>,
+    /// If set, downloads are served from the blobs of an imported offline archive in this
+    /// directory instead of the network.
+    pub(super) offline_blobs: Option<AbsNormPathBuf>,
+    /// If set, downloaded files are recorded here, so they can be put in an offline archive.
+    pub(super) download_trace: Option<Arc<DownloadTrace>>,
 }
 
+/// Directory under buck-out where artifacts that materialize atomically are written before being
+/// renamed into place. Anything in it is garbage once the daemon restarts.
+pub(super) const STAGING_DIR_NAME: &str = "materializer_staging";
+
 struct MaterializationStat {
     file_count: u64,
     total_bytes: u64,
@@ -121,6 +142,80 @@ impl DefaultIoHandler {
         method: Arc<ArtifactMaterializationMethod>,
         entry: ActionDirectoryEntry<ActionSharedDirectory>,
         stat: &mut MaterializationStat,
+    ) -> Result<(), MaterializeEntryError> {
+        let traced_entry = match &self.download_trace {
+            Some(_) if method.is_download() => Some(entry.dupe()),
+            _ => None,
+        };
+
+        let res = if method.materializes_atomically() {
+            self.materialize_entry_staged(&path, &method, entry, stat)
+                .await
+        } else {
+            self.materialize_entry_at(path.clone(), &method, entry, stat)
+                .await
+        };
+
+        if let (Ok(()), Some(trace), Some(entry)) = (&res, &self.download_trace, traced_entry) {
+            trace.record(&path, entry.as_ref());
+        }
+        res
+    }
+
+    /// Materializes an `entry` into a staging path, and renames it to `path` once complete.
+    async fn materialize_entry_staged(
+        &self,
+        path: &ProjectRelativePath,
+        method: &ArtifactMaterializationMethod,
+        entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        stat: &mut MaterializationStat,
+    ) -> Result<(), MaterializeEntryError> {
+        static NEXT_STAGING_ID: AtomicU64 = AtomicU64::new(0);
+        // Include the pid so that paths are never reused across daemons, even if a previous
+        // daemon's staging directory could not be wiped.
+        let staging_path = self
+            .buck_out_path
+            .join(ProjectRelativePath::unchecked_new(STAGING_DIR_NAME))
+            .join(FileName::unchecked_new(&format!(
+                "{}-{}",
+                std::process::id(),
+                NEXT_STAGING_ID.fetch_add(1, Ordering::Relaxed)
+            )));
+
+        // Never merge into whatever may already be there.
+        self.io_executor
+            .execute_io_inline(|| self.fs.remove_path_recursive(&staging_path))
+            .await?;
+
+        let res = self
+            .materialize_entry_at(staging_path.clone(), method, entry, stat)
+            .await;
+        let succeeded = res.is_ok();
+
+        self.io_executor
+            .execute_io_inline(|| {
+                if !succeeded {
+                    // Best effort: the staging directory is also wiped when the materializer
+                    // starts.
+                    let _ignored = self.fs.remove_path_recursive(&staging_path);
+                    return Ok(());
+                }
+                cleanup_path(&self.fs, path)?;
+                fs_util::rename(self.fs.resolve(&staging_path), self.fs.resolve(path))
+                    .with_context(|| format!("Error moving staged artifact into `{}`", path))
+            })
+            .await?;
+
+        res
+    }
+
+    /// Materializes an `entry` at `path`, which is either its final location or a staging path.
+    async fn materialize_entry_at(
+        &self,
+        path: ProjectRelativePathBuf,
+        method: &ArtifactMaterializationMethod,
+        entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        stat: &mut MaterializationStat,
     ) -> Result<(), MaterializeEntryError> {
         // Materialize the dir structure, and symlinks
         self.io_executor
@@ -130,8 +225,24 @@ impl DefaultIoHandler {
             }))
             .await?;
 
+        if let (Some(blobs), true) = (&self.offline_blobs, method.is_download()) {
+            let count_and_bytes = entry.calc_output_count_and_bytes();
+            stat.file_count = count_and_bytes.count;
+            stat.total_bytes = count_and_bytes.bytes;
+            self.io_executor
+                .execute_io_inline(|| {
+                    materialize_files_from_offline_blobs(
+                        entry.as_ref(),
+                        &self.fs.resolve(&path),
+                        blobs,
+                    )
+                })
+                .await?;
+            return Ok(());
+        }
+
         // Materialize files
-        match method.as_ref() {
+        match method {
             ArtifactMaterializationMethod::CasDownload { info } => {
                 let mut files = Vec::new();
 
@@ -190,6 +301,7 @@ impl DefaultIoHandler {
                         self.digest_config,
                         &path,
                         &info.url,
+                        &info.mirrors,
                         &info.checksum,
                         info.metadata.is_executable,
                     )
@@ -227,11 +339,17 @@ impl DefaultIoHandler {
                             stat.file_count += count_and_bytes.count;
                             stat.total_bytes += count_and_bytes.bytes;
 
-                            materialize_files(
-                                a.dest_entry.as_ref(),
-                                &self.fs.root().join(&a.src),
-                                &self.fs.root().join(&a.dest),
-                            )?;
+                            let src = self.fs.root().join(&a.src);
+                            let dest = self.fs.root().join(&a.dest);
+                            match &self.local_store {
+                                Some(store) => materialize_files_from_store(
+                                    a.dest_entry.as_ref(),
+                                    &src,
+                                    &dest,
+                                    store,
+                                )?,
+                                None => materialize_files(a.dest_entry.as_ref(), &src, &dest)?,
+                            }
                         }
                         Ok(())
                     })
@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Recovery of materializations that were in progress when the daemon last stopped.
+//!
+//! Every materialization is journaled in the `materializer_pending` table before it starts and
+//! removed from it once its outcome is recorded. Rows left over on startup belong to
+//! materializations that were interrupted, and whatever they left on disk can't be trusted unless
+//! it was renamed into place from a staging path. Even then, the row is written before the
+//! previous artifact at that path is cleaned up, so a staged artifact is only recovered if its
+//! contents match the journaled metadata.
+
+use std::collections::HashSet;
+
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::FingerprintedDirectory;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::digest_config::DigestConfig;
+
+use crate::executors::local::build_entry_from_disk;
+use crate::materializers::deferred::ArtifactMetadata;
+use crate::materializers::sqlite::MaterializerState;
+use crate::materializers::sqlite::MaterializerStateSqliteDb;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct JournalRecovery {
+    /// Interrupted materializations whose complete output was found at its final path, and that
+    /// were recorded as materialized.
+    pub(super) num_recovered: u64,
+    /// Interrupted materializations whose possibly partial or stale output was deleted. They will be
+    /// materialized again when next requested.
+    pub(super) num_discarded: u64,
+}
+
+/// Reconcile the pending materializations in `sqlite_db` with what's on disk. Recovered artifacts
+/// are added to both the state table and `state`.
+pub(super) fn recover_pending_materializations(
+    fs: &ProjectRoot,
+    sqlite_db: &mut MaterializerStateSqliteDb,
+    state: &mut MaterializerState,
+    digest_config: DigestConfig,
+) -> anyhow::Result<JournalRecovery> {
+    let pending = sqlite_db.pending_table().read_all(digest_config)?;
+    if pending.is_empty() {
+        return Ok(JournalRecovery::default());
+    }
+
+    let known: HashSet<ProjectRelativePathBuf> =
+        state.iter().map(|(path, _)| path.clone()).collect();
+
+    let mut recovery = JournalRecovery::default();
+    let mut finished = Vec::with_capacity(pending.len());
+    for pending in pending {
+        if known.contains(&pending.path) {
+            // The outcome was recorded, we just didn't get to remove the journal entry.
+        } else if pending.staged
+            && matches_disk(fs, &pending.path, &pending.metadata, digest_config)?
+        {
+            tracing::debug!(path = %pending.path, "recovering staged materialization");
+            sqlite_db.materializer_state_table().insert(
+                &pending.path,
+                &pending.metadata,
+                pending.started_at,
+            )?;
+            state.push((pending.path.clone(), (pending.metadata, pending.started_at)));
+            recovery.num_recovered += 1;
+        } else {
+            tracing::debug!(path = %pending.path, "discarding interrupted materialization");
+            fs.remove_path_recursive(&pending.path)?;
+            recovery.num_discarded += 1;
+        }
+        finished.push(pending.path);
+    }
+
+    sqlite_db.pending_table().delete(&finished)?;
+
+    Ok(recovery)
+}
+
+/// Whether what's at `path` is exactly the artifact described by `metadata`.
+fn matches_disk(
+    fs: &ProjectRoot,
+    path: &ProjectRelativePath,
+    metadata: &ArtifactMetadata,
+    digest_config: DigestConfig,
+) -> anyhow::Result<bool> {
+    let entry = match build_entry_from_disk(fs.resolve(path), digest_config)? {
+        Some(entry) => entry,
+        None => return Ok(false),
+    };
+    Ok(match (&metadata.0, entry) {
+        (DirectoryEntry::Dir(expected), DirectoryEntry::Dir(actual)) => {
+            actual
+                .fingerprint(digest_config.as_directory_serializer())
+                .fingerprint()
+                == &expected.fingerprint
+        }
+        (DirectoryEntry::Leaf(expected), DirectoryEntry::Leaf(actual)) => *expected == actual,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use buck2_common::file_ops::FileMetadata;
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_core::directory::DirectoryEntry;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_execute::directory::ActionDirectoryMember;
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_recover_pending_materializations() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        let digest_config = DigestConfig::testing_default();
+        let buck_out = ProjectRelativePath::unchecked_new("buck-out/v2");
+
+        let (mut db, state) = MaterializerStateSqliteDb::initialize_impl(
+            fs.resolve(&buck_out.join(ProjectRelativePath::unchecked_new(
+                "cache/materializer_state",
+            ))),
+            HashMap::new(),
+            HashMap::new(),
+            digest_config,
+        )?;
+        assert!(state.is_err());
+        let mut state = MaterializerState::new();
+
+        let metadata = ArtifactMetadata(DirectoryEntry::Leaf(ActionDirectoryMember::File(
+            FileMetadata {
+                digest: TrackedFileDigest::from_content(b"foo", digest_config.cas_digest_config()),
+                is_executable: false,
+            },
+        )));
+        let now = Utc::now();
+
+        let staged = ProjectRelativePath::unchecked_new("buck-out/v2/gen/staged");
+        let partial = ProjectRelativePath::unchecked_new("buck-out/v2/gen/partial");
+        let missing = ProjectRelativePath::unchecked_new("buck-out/v2/gen/missing");
+        let stale = ProjectRelativePath::unchecked_new("buck-out/v2/gen/stale");
+        fs.write_file(staged, "foo", false)?;
+        fs.write_file(partial, "f", false)?;
+        // The previous artifact at this path, which wasn't cleaned up before the crash.
+        fs.write_file(stale, "bar", false)?;
+
+        db.pending_table().insert(staged, &metadata, true, now)?;
+        db.pending_table().insert(partial, &metadata, false, now)?;
+        db.pending_table().insert(missing, &metadata, true, now)?;
+        db.pending_table().insert(stale, &metadata, true, now)?;
+
+        let recovery = recover_pending_materializations(fs, &mut db, &mut state, digest_config)?;
+        assert_eq!(
+            recovery,
+            JournalRecovery {
+                num_recovered: 1,
+                num_discarded: 3,
+            }
+        );
+
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[0].0, staged);
+        assert!(fs.resolve(staged).exists());
+        assert!(!fs.resolve(partial).exists());
+        assert!(!fs.resolve(stale).exists());
+
+        assert!(db.pending_table().read_all(digest_config)?.is_empty());
+        assert_eq!(
+            db.materializer_state_table().read_all(digest_config)?.len(),
+            1
+        );
+
+        Ok(())
+    }
+}
@@ -11,6 +11,7 @@ mod clean_stale;
 mod extension;
 mod file_tree;
 mod io_handler;
+mod journal;
 mod subscriptions;
 
 #[cfg(test)]
@@ -37,6 +38,7 @@ use buck2_common::result::ToUnsharedResultExt;
 use buck2_core::directory::unordered_entry_walk;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::env_helper::EnvHelper;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::RelativePathBuf;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
@@ -97,9 +99,15 @@ use crate::materializers::deferred::extension::ExtensionCommand;
 use crate::materializers::deferred::file_tree::FileTree;
 use crate::materializers::deferred::io_handler::DefaultIoHandler;
 use crate::materializers::deferred::io_handler::IoHandler;
+use crate::materializers::deferred::io_handler::STAGING_DIR_NAME;
+use crate::materializers::deferred::journal::recover_pending_materializations;
+use crate::materializers::deferred::journal::JournalRecovery;
 use crate::materializers::deferred::subscriptions::MaterializerSubscriptionOperation;
 use crate::materializers::deferred::subscriptions::MaterializerSubscriptions;
 use crate::materializers::immediate;
+use crate::materializers::local_store::LocalStore;
+use crate::materializers::local_store::LocalStoreMode;
+use crate::materializers::offline_blobs::DownloadTrace;
 use crate::materializers::sqlite::MaterializerState;
 use crate::materializers::sqlite::MaterializerStateSqliteDb;
 
@@ -144,6 +152,10 @@ pub struct DeferredMaterializer {
     io_executor: Arc<dyn BlockingExecutor>,
     digest_config: DigestConfig,
 
+    /// Files downloaded so far, if downloads are traced.
+    #[allocative(skip)]
+    download_trace: Option<Arc<DownloadTrace>>,
+
     /// Tracked for logging purposes.
     materializer_state_info: buck2_data::MaterializerStateInfo,
 }
@@ -159,6 +171,15 @@ pub struct DeferredMaterializerConfigs {
     pub materialize_final_artifacts: bool,
     pub defer_write_actions: bool,
     pub ttl_refresh: TtlRefreshConfiguration,
+    /// Materialize local copies via a content-addressed store under buck-out. This requires the
+    /// sqlite materializer state, which is where references to the store are tracked.
+    pub local_store: Option<LocalStoreMode>,
+    /// Serve CAS and HTTP downloads from the blobs of an imported offline archive in this
+    /// directory, instead of the network.
+    pub offline_blobs: Option<AbsNormPathBuf>,
+    /// Record the files that get downloaded, so that `buck2 debug offline-archive create` can
+    /// package them.
+    pub trace_downloads: bool,
 }
 
 pub struct TtlRefreshConfiguration {
@@ -239,6 +260,9 @@ struct DeferredMaterializerCommandProcessor<T: 'static> {
     io: Arc<T>,
     digest_config: DigestConfig,
     sqlite_db: Option<MaterializerStateSqliteDb>,
+    /// Whether local copies go through the local store, in which case we record the blobs they
+    /// reference in `sqlite_db`.
+    use_local_store: bool,
     /// The runtime the deferred materializer will spawn futures on. This is normally the runtime
     /// used by the rest of Buck.
     rt: Handle,
@@ -590,6 +614,34 @@ enum ArtifactMaterializationMethod {
     Test,
 }
 
+impl ArtifactMaterializationMethod {
+    /// Whether this method writes the artifact into a staging path and renames it into place once
+    /// complete, so that a partially downloaded artifact is never visible at its final path.
+    fn materializes_atomically(&self) -> bool {
+        match self {
+            ArtifactMaterializationMethod::CasDownload { .. }
+            | ArtifactMaterializationMethod::HttpDownload { .. } => cfg!(unix),
+            ArtifactMaterializationMethod::LocalCopy(..)
+            | ArtifactMaterializationMethod::Write(..) => false,
+            #[cfg(test)]
+            ArtifactMaterializationMethod::Test => false,
+        }
+    }
+
+    /// Whether this method fetches the artifact from outside the machine, which an offline build
+    /// can't do.
+    fn is_download(&self) -> bool {
+        match self {
+            ArtifactMaterializationMethod::CasDownload { .. }
+            | ArtifactMaterializationMethod::HttpDownload { .. } => true,
+            ArtifactMaterializationMethod::LocalCopy(..)
+            | ArtifactMaterializationMethod::Write(..) => false,
+            #[cfg(test)]
+            ArtifactMaterializationMethod::Test => false,
+        }
+    }
+}
+
 trait MaterializationMethodToProto {
     fn to_proto(&self) -> buck2_data::MaterializationMethod;
 }
@@ -859,8 +911,8 @@ impl DeferredMaterializer {
         re_client_manager: Arc<ReConnectionManager>,
         io_executor: Arc<dyn BlockingExecutor>,
         configs: DeferredMaterializerConfigs,
-        sqlite_db: Option<MaterializerStateSqliteDb>,
-        sqlite_state: Option<MaterializerState>,
+        mut sqlite_db: Option<MaterializerStateSqliteDb>,
+        mut sqlite_state: Option<MaterializerState>,
     ) -> anyhow::Result<Self> {
         let (high_priority_sender, high_priority_receiver) = mpsc::unbounded_channel();
         let (low_priority_sender, low_priority_receiver) = mpsc::unbounded_channel();
@@ -880,8 +932,27 @@ impl DeferredMaterializer {
         };
 
         let num_entries_from_sqlite = sqlite_state.as_ref().map_or(0, |s| s.len()) as u64;
+
+        // Nothing in the staging directory was ever renamed into place, so it's all garbage. This
+        // must happen whether or not the journal is enabled, since staging always is.
+        if let Err(e) = fs.remove_path_recursive(
+            &buck_out_path.join(ProjectRelativePath::unchecked_new(STAGING_DIR_NAME)),
+        ) {
+            quiet_soft_error!("materializer_staging_error", e).unwrap();
+        }
+
+        let mut recovery = JournalRecovery::default();
+        if let (Some(sqlite_db), Some(sqlite_state)) = (sqlite_db.as_mut(), sqlite_state.as_mut()) {
+            match recover_pending_materializations(&fs, sqlite_db, sqlite_state, digest_config) {
+                Ok(r) => recovery = r,
+                Err(e) => quiet_soft_error!("materializer_journal_error", e).unwrap(),
+            }
+        }
+
         let materializer_state_info = buck2_data::MaterializerStateInfo {
             num_entries_from_sqlite,
+            num_pending_recovered: recovery.num_recovered,
+            num_pending_discarded: recovery.num_discarded,
         };
 
         let mut tree = ArtifactTree::new();
@@ -902,6 +973,20 @@ impl DeferredMaterializer {
             }
         }
 
+        let local_store = match configs.local_store {
+            Some(mode) if sqlite_db.is_some() => Some(LocalStore::new(
+                fs.resolve(
+                    &buck_out_path.join(ProjectRelativePath::unchecked_new(LocalStore::DIR_NAME)),
+                ),
+                mode,
+            )),
+            _ => None,
+        };
+        let use_local_store = local_store.is_some();
+        let download_trace = configs
+            .trace_downloads
+            .then(|| Arc::new(DownloadTrace::default()));
+
         let command_processor = DeferredMaterializerCommandProcessor {
             io: Arc::new(DefaultIoHandler {
                 fs: fs.dupe(),
@@ -909,8 +994,12 @@ impl DeferredMaterializer {
                 buck_out_path,
                 re_client_manager,
                 io_executor: io_executor.dupe(),
+                local_store,
+                offline_blobs: configs.offline_blobs,
+                download_trace: download_trace.dupe(),
             }),
             digest_config,
+            use_local_store,
             sqlite_db,
             rt: Handle::current(),
             defer_write_actions: configs.defer_write_actions,
@@ -945,6 +1034,7 @@ impl DeferredMaterializer {
             fs,
             io_executor,
             digest_config,
+            download_trace,
             materializer_state_info,
         })
     }
@@ -1551,6 +1641,46 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 .collect::<Vec<_>>(),
         };
 
+        // Local copies materialized via the local store reference its blobs. Record this before
+        // materialization starts, so that `clean --stale` never removes blobs in use.
+        if self.use_local_store {
+            if let (Some(sqlite_db), Some((entry, method))) =
+                (self.sqlite_db.as_mut(), entry_and_method.as_ref())
+            {
+                if let ArtifactMaterializationMethod::LocalCopy(..) = method.as_ref() {
+                    if let Err(e) = sqlite_db
+                        .local_store_refs_table()
+                        .insert(path, &local_store_blobs(entry))
+                    {
+                        quiet_soft_error!(
+                            "materializer_local_store_error",
+                            e.context(self.log_buffer.clone())
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+
+        // Journal the materialization before it touches disk, so that if we crash before it
+        // finishes we know on startup that whatever is at `path` may be incomplete.
+        if let (Some(sqlite_db), Some((entry, method))) =
+            (self.sqlite_db.as_mut(), entry_and_method.as_ref())
+        {
+            if let Err(e) = sqlite_db.pending_table().insert(
+                path,
+                &ArtifactMetadata::new(entry),
+                method.materializes_atomically(),
+                Utc::now(),
+            ) {
+                quiet_soft_error!(
+                    "materializer_journal_error",
+                    e.context(self.log_buffer.clone())
+                )
+                .unwrap();
+            }
+        }
+
         // Create a task to await deps and materialize ourselves
         let path_buf = path.to_buf();
         let path_buf_dup = path_buf.clone();
@@ -1658,6 +1788,19 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                     return;
                 }
 
+                // Whatever the outcome, this materialization is no longer in progress.
+                if let (Some(sqlite_db), ArtifactMaterializationStage::Declared { .. }) =
+                    (self.sqlite_db.as_mut(), &info.stage)
+                {
+                    if let Err(e) = sqlite_db.pending_table().delete(&[artifact_path.clone()]) {
+                        quiet_soft_error!(
+                            "materializer_journal_error",
+                            e.context(self.log_buffer.clone())
+                        )
+                        .unwrap();
+                    }
+                }
+
                 if result.is_err() {
                     tracing::debug!("materialization failed, redeclaring artifact");
                     // Even though materialization failed, something may have still materialized at artifact_path,
@@ -1724,6 +1867,18 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
     }
 }
 
+/// The local store blobs that materializing `entry` uses.
+fn local_store_blobs(entry: &ActionDirectoryEntry<ActionSharedDirectory>) -> Vec<String> {
+    let mut blobs = Vec::new();
+    let mut walk = unordered_entry_walk(entry.as_ref());
+    while let Some((_path, entry)) = walk.next() {
+        if let DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) = entry {
+            blobs.push(LocalStore::blob_name(file));
+        }
+    }
+    blobs
+}
+
 /// Run callbacks for an artifact being materialized at `path`.
 fn on_materialization(
     sqlite_db: Option<&mut MaterializerStateSqliteDb>,
@@ -1890,6 +2045,15 @@ impl ArtifactTree {
         // the underlying nodes, because when materialization finishes we'll check the version
         // number.
         if let Some(sqlite_db) = sqlite_db {
+            if let Err(e) = sqlite_db
+                .local_store_refs_table()
+                .delete(&invalidated_paths)
+            {
+                quiet_soft_error!("materializer_invalidate_error", e).unwrap();
+            }
+            if let Err(e) = sqlite_db.pending_table().delete(&invalidated_paths) {
+                quiet_soft_error!("materializer_invalidate_error", e).unwrap();
+            }
             if let Err(e) = sqlite_db
                 .materializer_state_table()
                 .delete(invalidated_paths)
@@ -221,6 +221,7 @@ mod state_machine {
             DeferredMaterializerCommandProcessor {
                 io: Arc::new(StubIoHandler::new(materialization_config)),
                 sqlite_db: None,
+                use_local_store: false,
                 rt: Handle::current(),
                 defer_write_actions: true,
                 log_buffer: LogBuffer::new(1),
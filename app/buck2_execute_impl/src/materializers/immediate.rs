@@ -190,6 +190,7 @@ impl Materializer for ImmediateMaterializer {
             self.digest_config,
             &path,
             &info.url,
+            &info.mirrors,
             &info.checksum,
             info.metadata.is_executable,
         )
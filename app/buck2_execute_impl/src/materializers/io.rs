@@ -9,6 +9,7 @@
 
 use std::collections::HashMap;
 
+use buck2_core::directory::unordered_entry_walk;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
@@ -21,6 +22,8 @@ use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::directory::ActionSharedDirectory;
 use buck2_execute::execute::blocking::IoRequest;
 
+use crate::materializers::local_store::LocalStore;
+
 pub struct MaterializeTreeStructure {
     pub path: ProjectRelativePathBuf,
     pub entry: ActionDirectoryEntry<ActionSharedDirectory>,
@@ -103,6 +106,33 @@ where
     materialize(entry, dest, false, file_src)
 }
 
+/// Like [`materialize_files`], but files are materialized via the local `store` instead of
+/// being copied from `src`.
+pub(crate) fn materialize_files_from_store<P, D>(
+    entry: DirectoryEntry<&D, &ActionDirectoryMember>,
+    src: P,
+    dest: P,
+    store: &LocalStore,
+) -> anyhow::Result<()>
+where
+    P: AsRef<AbsNormPath>,
+    D: ActionDirectory + ?Sized,
+{
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    let mut walk = unordered_entry_walk(entry);
+    while let Some((path, entry)) = walk.next() {
+        if let DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) = entry {
+            let path = path.get();
+            let file_dest = dest.join(&path);
+            if fs_util::symlink_metadata(&file_dest).is_err() {
+                store.materialize_file(&src.join(&path), &file_dest, file)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Materializes the files of an entry rooted at `dest`.
 ///
 /// For a file at path `file_dest` in the entry, if `file_dest` exists in
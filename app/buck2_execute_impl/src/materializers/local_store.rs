@@ -0,0 +1,382 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A content-addressed store for local copies.
+//!
+//! When the deferred materializer copies an artifact locally, each file is first ingested into
+//! the store (once per distinct content), and the destination is then linked to the stored blob
+//! rather than copied byte by byte. Which artifacts reference which blobs is tracked in the
+//! materializer state sqlite db, and blobs nothing references are removed by `clean --stale`.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use buck2_common::file_ops::FileMetadata;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use dupe::Dupe;
+use thiserror::Error;
+
+/// How files are materialized out of the local store.
+#[derive(Copy, Clone, Dupe, Debug, PartialEq, Eq)]
+pub enum LocalStoreMode {
+    /// Hardlink to the stored blob. This is the cheapest option, but since the blob is shared,
+    /// materialized files are read-only. Falls back to a copy when the destination is on a
+    /// different filesystem or the blob has too many links.
+    Hardlink,
+    /// Clone the stored blob (`FICLONE` on Linux), falling back to a copy where the filesystem
+    /// does not support it. Materialized files are independent of the blob and writable.
+    Reflink,
+}
+
+#[derive(Debug, Error)]
+enum LocalStoreError {
+    #[error(
+        "Invalid value for buckconfig `[buck2] local_store`. Got `{0}`. Expected one of `hardlink` or `reflink`."
+    )]
+    InvalidValueForConfig(String),
+}
+
+impl FromStr for LocalStoreMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "hardlink" => Ok(Self::Hardlink),
+            "reflink" => Ok(Self::Reflink),
+            v => Err(LocalStoreError::InvalidValueForConfig(v.to_owned()).into()),
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub(crate) struct LocalStoreSweepStats {
+    pub(crate) blob_count: u64,
+    pub(crate) bytes: u64,
+}
+
+pub(crate) struct LocalStore {
+    root: AbsNormPathBuf,
+    mode: LocalStoreMode,
+    /// Used to give temporary files unique names.
+    tmp_counter: AtomicU64,
+}
+
+impl LocalStore {
+    /// Directory under buck-out that holds the store.
+    pub(crate) const DIR_NAME: &'static str = "local_store";
+
+    pub(crate) fn new(root: AbsNormPathBuf, mode: LocalStoreMode) -> Self {
+        Self {
+            root,
+            mode,
+            tmp_counter: AtomicU64::new(0),
+        }
+    }
+
+    /// The name of the blob holding a file, relative to the store root. This is what references
+    /// are recorded against. Executable files are stored separately, since hardlinks share
+    /// their mode with the blob.
+    pub(crate) fn blob_name(file: &FileMetadata) -> String {
+        let hash = file.digest.raw_digest().to_string();
+        format!(
+            "{}/{}_{}{}",
+            &hash[..2],
+            hash,
+            file.digest.size(),
+            if file.is_executable { "_x" } else { "" }
+        )
+    }
+
+    fn blob_path(&self, name: &str) -> AbsNormPathBuf {
+        self.root.join(ForwardRelativePath::unchecked_new(name))
+    }
+
+    /// Materialize `dest` from the store, ingesting `src` first if its contents aren't stored
+    /// yet. `src` must have the contents described by `file`.
+    pub(crate) fn materialize_file(
+        &self,
+        src: &AbsNormPath,
+        dest: &AbsNormPath,
+        file: &FileMetadata,
+    ) -> anyhow::Result<()> {
+        let blob = self.blob_path(&Self::blob_name(file));
+        if !fs_util::try_exists(&blob)? {
+            self.ingest(src, &blob, file.is_executable)?;
+        }
+        match self.link(&blob, dest, file.is_executable) {
+            // The blob may have been swept concurrently, in which case we just store it again.
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !blob.exists() => {
+                self.ingest(src, &blob, file.is_executable)?;
+                self.link(&blob, dest, file.is_executable)
+            }
+            res => res,
+        }
+        .with_context(|| format!("Error materializing `{}` from local store", dest))
+    }
+
+    /// Store a copy of `src` at `blob`. We never hardlink the source into the store, since the
+    /// source is itself an output that may be deleted or rewritten.
+    fn ingest(
+        &self,
+        src: &AbsNormPath,
+        blob: &AbsNormPath,
+        is_executable: bool,
+    ) -> anyhow::Result<()> {
+        let dir = blob.parent().context("Blob has no parent")?;
+        fs_util::create_dir_all(dir)?;
+
+        // Write to a temporary file first and rename, so that a blob is only ever visible once
+        // complete.
+        let tmp = dir.join(ForwardRelativePath::unchecked_new(&format!(
+            "tmp.{}.{}",
+            std::process::id(),
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed)
+        )));
+        let res: anyhow::Result<()> = try {
+            clone_or_copy(src, &tmp)
+                .with_context(|| format!("Error copying `{}` into local store", src))?;
+            set_mode(&tmp, false, is_executable)?;
+            fs::rename(&tmp, blob)
+                .with_context(|| format!("Error renaming `{}` to `{}`", tmp, blob))?;
+        };
+        if res.is_err() {
+            let _ignored = fs::remove_file(&tmp);
+        }
+        res
+    }
+
+    fn link(&self, blob: &AbsNormPath, dest: &AbsNormPath, is_executable: bool) -> io::Result<()> {
+        if self.mode == LocalStoreMode::Hardlink {
+            match fs::hard_link(blob, dest) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(e),
+                Err(e) => {
+                    tracing::debug!(blob = %blob, dest = %dest, "hardlink failed, copying: {}", e);
+                }
+            }
+        }
+        clone_or_copy(blob, dest)?;
+        set_mode(dest, true, is_executable)
+    }
+
+    /// Remove all blobs that aren't in `referenced`. Blobs (and temporary files) that were
+    /// created or linked after `changed_before` are kept, since a materialization may have
+    /// started using them after `referenced` was computed.
+    pub(crate) fn sweep(
+        &self,
+        referenced: &HashSet<String>,
+        changed_before: SystemTime,
+    ) -> anyhow::Result<LocalStoreSweepStats> {
+        let mut stats = LocalStoreSweepStats::default();
+        if !fs_util::try_exists(&self.root)? {
+            return Ok(stats);
+        }
+
+        for dir in fs_util::read_dir(&self.root)? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = dir.file_name();
+            let prefix = prefix.to_string_lossy();
+            for blob in fs_util::read_dir(dir.path())? {
+                let blob = blob?;
+                let name = format!("{}/{}", prefix, blob.file_name().to_string_lossy());
+                if referenced.contains(&name) {
+                    continue;
+                }
+                let metadata = blob.metadata()?;
+                if changed_time(&metadata)? >= changed_before {
+                    continue;
+                }
+                tracing::trace!(blob = %name, "removing unreferenced blob");
+                remove_blob(&blob.path())?;
+                stats.blob_count += 1;
+                stats.bytes += metadata.len();
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Copy `src` to `dest`, sharing extents between them if the filesystem supports it.
+fn clone_or_copy(src: &Path, dest: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if reflink(src, dest).is_ok() {
+            return Ok(());
+        }
+    }
+    fs::copy(src, dest).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // `_IOW(0x94, 9, int)`, from `linux/fs.h`.
+    const FICLONE: libc::c_ulong = 0x40049409;
+
+    let src_file = fs::File::open(src)?;
+    let dest_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest)?;
+    // SAFETY: both file descriptors are valid for the duration of the call.
+    let res = unsafe { libc::ioctl(dest_file.as_raw_fd(), FICLONE, src_file.as_raw_fd()) };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Blobs are read-only so that a hardlinked output can't be used to corrupt the store.
+#[cfg(unix)]
+fn set_mode(path: &Path, writable: bool, is_executable: bool) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = match (writable, is_executable) {
+        (true, true) => 0o755,
+        (true, false) => 0o644,
+        (false, true) => 0o555,
+        (false, false) => 0o444,
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, writable: bool, _is_executable: bool) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(!writable);
+    fs::set_permissions(path, permissions)
+}
+
+/// When the blob was created or last linked. On Unix, creating a hardlink updates the ctime of
+/// the inode, which is what makes this safe to use for sweeping.
+#[cfg(unix)]
+fn changed_time(metadata: &fs::Metadata) -> io::Result<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(SystemTime::UNIX_EPOCH
+        + std::time::Duration::new(
+            metadata.ctime().try_into().unwrap_or_default(),
+            metadata.ctime_nsec().try_into().unwrap_or_default(),
+        ))
+}
+
+#[cfg(not(unix))]
+fn changed_time(metadata: &fs::Metadata) -> io::Result<SystemTime> {
+    metadata.modified()
+}
+
+fn remove_blob(path: &Path) -> anyhow::Result<()> {
+    if cfg!(not(unix)) {
+        set_mode(path, true, false)?;
+    }
+    fs_util::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+    use buck2_execute::digest_config::DigestConfig;
+
+    use super::*;
+
+    fn file_metadata(content: &[u8], is_executable: bool) -> FileMetadata {
+        FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                content,
+                DigestConfig::testing_default().cas_digest_config(),
+            ),
+            is_executable,
+        }
+    }
+
+    #[test]
+    fn test_blob_name() {
+        let file = file_metadata(b"content", false);
+        let name = LocalStore::blob_name(&file);
+        let hash = file.digest.raw_digest().to_string();
+        assert_eq!(name, format!("{}/{}_7", &hash[..2], hash));
+
+        let exe = file_metadata(b"content", true);
+        assert_eq!(LocalStore::blob_name(&exe), format!("{}_x", name));
+    }
+
+    #[test]
+    fn test_materialize_and_sweep() -> anyhow::Result<()> {
+        for mode in [LocalStoreMode::Hardlink, LocalStoreMode::Reflink] {
+            let fs = ProjectRootTemp::new()?;
+            let path = |p: &str| fs.path().resolve(ProjectRelativePath::unchecked_new(p));
+            let store = LocalStore::new(path("buck-out/local_store"), mode);
+
+            let file = file_metadata(b"content", false);
+            fs_util::create_dir_all(path("out"))?;
+            fs_util::write(path("src"), b"content")?;
+
+            store.materialize_file(&path("src"), &path("out/a"), &file)?;
+            // The source isn't needed anymore once its contents are stored.
+            fs_util::remove_file(path("src"))?;
+            store.materialize_file(&path("src"), &path("out/b"), &file)?;
+
+            assert_eq!(fs_util::read_to_string(path("out/a"))?, "content");
+            assert_eq!(fs_util::read_to_string(path("out/b"))?, "content");
+            let blob = store.blob_path(&LocalStore::blob_name(&file));
+            assert!(blob.exists());
+
+            // Referenced blobs and recently changed ones are kept.
+            let referenced = HashSet::from([LocalStore::blob_name(&file)]);
+            assert_eq!(
+                store.sweep(
+                    &referenced,
+                    SystemTime::now() + std::time::Duration::from_secs(60)
+                )?,
+                LocalStoreSweepStats::default()
+            );
+            assert_eq!(
+                store.sweep(&HashSet::new(), SystemTime::UNIX_EPOCH)?,
+                LocalStoreSweepStats::default()
+            );
+            assert!(blob.exists());
+
+            let stats = store.sweep(
+                &HashSet::new(),
+                SystemTime::now() + std::time::Duration::from_secs(60),
+            )?;
+            assert_eq!(
+                stats,
+                LocalStoreSweepStats {
+                    blob_count: 1,
+                    bytes: 7
+                }
+            );
+            assert!(!blob.exists());
+
+            // Materialized files outlive the blob.
+            assert_eq!(fs_util::read_to_string(path("out/a"))?, "content");
+        }
+        Ok(())
+    }
+}
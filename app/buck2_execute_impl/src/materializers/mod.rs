@@ -13,4 +13,6 @@ pub mod eden;
 pub mod deferred;
 pub mod immediate;
 pub mod io;
+pub mod local_store;
+pub mod offline_blobs;
 pub mod sqlite;
@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Support for offline builds: recording which files the materializer downloaded (so that they
+//! can be packaged into an offline archive), and materializing downloads from the blobs of such
+//! an archive instead of the network.
+//!
+//! Blobs are named like the blobs of the [`LocalStore`].
+
+use std::collections::HashMap;
+
+use buck2_core::directory::unordered_entry_walk;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::directory::ActionDirectory;
+use buck2_execute::directory::ActionDirectoryMember;
+use parking_lot::Mutex;
+
+use crate::materializers::local_store::LocalStore;
+
+#[derive(Debug, thiserror::Error)]
+enum OfflineBlobsError {
+    #[error(
+        "`{path}` would be downloaded, but its contents (blob `{blob}`) are not in the offline blobs at `{root}`"
+    )]
+    MissingBlob {
+        path: String,
+        blob: String,
+        root: String,
+    },
+}
+
+/// The files the materializer downloaded, and the name of the blob holding each one's contents.
+#[derive(Default)]
+pub struct DownloadTrace {
+    downloads: Mutex<HashMap<ProjectRelativePathBuf, String>>,
+}
+
+impl DownloadTrace {
+    pub(crate) fn record<D: ActionDirectory + ?Sized>(
+        &self,
+        path: &ProjectRelativePath,
+        entry: DirectoryEntry<&D, &ActionDirectoryMember>,
+    ) {
+        let mut downloads = self.downloads.lock();
+        let mut walk = unordered_entry_walk(entry);
+        while let Some((entry_path, entry)) = walk.next() {
+            if let DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) = entry {
+                downloads.insert(path.join(&entry_path.get()), LocalStore::blob_name(file));
+            }
+        }
+    }
+
+    /// The downloaded files and their blob names, sorted by path.
+    pub fn downloads(&self) -> Vec<(ProjectRelativePathBuf, String)> {
+        let mut downloads: Vec<_> = self
+            .downloads
+            .lock()
+            .iter()
+            .map(|(path, blob)| (path.clone(), blob.clone()))
+            .collect();
+        downloads.sort();
+        downloads
+    }
+}
+
+/// Materialize the files of `entry` at `dest` by copying them from the blobs in `root`, failing if
+/// any is missing.
+pub(crate) fn materialize_files_from_offline_blobs<D: ActionDirectory + ?Sized>(
+    entry: DirectoryEntry<&D, &ActionDirectoryMember>,
+    dest: &AbsNormPath,
+    root: &AbsNormPath,
+) -> anyhow::Result<()> {
+    let mut walk = unordered_entry_walk(entry);
+    while let Some((path, entry)) = walk.next() {
+        if let DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) = entry {
+            let file_dest = dest.join(&path.get());
+            let blob = LocalStore::blob_name(file);
+            let blob_path = root.join(ForwardRelativePath::unchecked_new(&blob));
+            if !fs_util::try_exists(&blob_path)? {
+                return Err(OfflineBlobsError::MissingBlob {
+                    path: file_dest.to_string(),
+                    blob,
+                    root: root.to_string(),
+                }
+                .into());
+            }
+            fs_util::copy(&blob_path, &file_dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::file_ops::FileMetadata;
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_execute::digest_config::DigestConfig;
+    use buck2_execute::directory::ActionSharedDirectory;
+
+    use super::*;
+
+    #[test]
+    fn test_trace_and_materialize_from_offline_blobs() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        let file = FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                b"content",
+                DigestConfig::testing_default().cas_digest_config(),
+            ),
+            is_executable: false,
+        };
+        let member = ActionDirectoryMember::File(file.clone());
+        let entry = || DirectoryEntry::<&ActionSharedDirectory, _>::Leaf(&member);
+
+        let trace = DownloadTrace::default();
+        let path = ProjectRelativePath::unchecked_new("buck-out/v2/gen/downloaded");
+        trace.record(path, entry());
+        let blob = LocalStore::blob_name(&file);
+        assert_eq!(trace.downloads(), vec![(path.to_owned(), blob.clone())]);
+
+        let root = fs.resolve(ProjectRelativePath::unchecked_new("blobs"));
+        let dest = fs.resolve(ProjectRelativePath::unchecked_new("out"));
+        assert!(materialize_files_from_offline_blobs(entry(), &dest, &root).is_err());
+
+        fs.write_file(
+            &ProjectRelativePath::unchecked_new("blobs").join(ForwardRelativePath::new(&blob)?),
+            "content",
+            false,
+        )?;
+        materialize_files_from_offline_blobs(entry(), &dest, &root)?;
+        assert_eq!(fs_util::read_to_string(&dest)?, "content");
+
+        Ok(())
+    }
+}
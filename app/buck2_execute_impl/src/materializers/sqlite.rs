@@ -46,12 +46,25 @@ use crate::materializers::deferred::DirectoryMetadata;
 /// materializer state sqlite db schema! If you forget to bump this version,
 /// then you can fix forward by bumping the `buck2.sqlite_materializer_state_version`
 /// buckconfig in the project root's .buckconfig.
-pub const DB_SCHEMA_VERSION: u64 = 5;
+pub const DB_SCHEMA_VERSION: u64 = 7;
 
 const STATE_TABLE_NAME: &str = "materializer_state";
+const LOCAL_STORE_REFS_TABLE_NAME: &str = "local_store_refs";
+const PENDING_TABLE_NAME: &str = "materializer_pending";
 
 pub type MaterializerState = Vec<(ProjectRelativePathBuf, (ArtifactMetadata, DateTime<Utc>))>;
 
+/// A materialization that was started but not recorded as finished.
+#[derive(Debug, Clone)]
+pub struct PendingMaterialization {
+    pub path: ProjectRelativePathBuf,
+    pub metadata: ArtifactMetadata,
+    /// Whether the artifact is materialized into a staging path and renamed into place, in which
+    /// case anything found at `path` is complete.
+    pub staged: bool,
+    pub started_at: DateTime<Utc>,
+}
+
 #[derive(Error, Debug, PartialEq, Eq)]
 pub(crate) enum ArtifactMetadataSqliteConversionError {
     #[error("Internal error: expected field `{}` to be not null for artifact type '{}'", .field, .artifact_type)]
@@ -460,6 +473,265 @@ impl MaterializerStateSqliteTable {
     }
 }
 
+/// Records which local store blobs each artifact was materialized from. A blob's refcount is the
+/// number of artifacts referencing it.
+pub(crate) struct LocalStoreRefsSqliteTable {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl LocalStoreRefsSqliteTable {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> Self {
+        Self { connection }
+    }
+
+    pub(crate) fn create_table(&self) -> anyhow::Result<()> {
+        let sql = format!(
+            "CREATE TABLE {} (
+                path                    TEXT NOT NULL,
+                blob                    TEXT NOT NULL,
+                PRIMARY KEY (path, blob)
+            )",
+            LOCAL_STORE_REFS_TABLE_NAME,
+        );
+        tracing::trace!(sql = %*sql, "creating table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("creating sqlite table {}", LOCAL_STORE_REFS_TABLE_NAME))?;
+        Ok(())
+    }
+
+    /// Record that the artifact at `path` references `blobs`.
+    pub(crate) fn insert(
+        &self,
+        path: &ProjectRelativePath,
+        blobs: &[String],
+    ) -> anyhow::Result<()> {
+        static SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "INSERT OR IGNORE INTO {} (path, blob) VALUES (?1, ?2)",
+                LOCAL_STORE_REFS_TABLE_NAME
+            )
+        });
+        tracing::trace!(sql = %*SQL, path = %path, blobs = blobs.len(), "inserting into table");
+        let mut connection = self.connection.lock();
+        let tx = connection.transaction()?;
+        {
+            let mut stmt = tx.prepare(&SQL)?;
+            for blob in blobs {
+                stmt.execute(rusqlite::params![path.as_str(), blob])
+                    .with_context(|| {
+                        format!(
+                            "inserting `{}` into sqlite table {}",
+                            path, LOCAL_STORE_REFS_TABLE_NAME
+                        )
+                    })?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Number of artifacts referencing each blob. Blobs that aren't referenced are absent.
+    pub(crate) fn refcounts(&self) -> anyhow::Result<HashMap<String, u64>> {
+        static SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "SELECT blob, COUNT(*) FROM {} GROUP BY blob",
+                LOCAL_STORE_REFS_TABLE_NAME,
+            )
+        });
+        tracing::trace!(sql = %*SQL, "reading refcounts from table");
+        let connection = self.connection.lock();
+        let mut stmt = connection.prepare(&SQL)?;
+        let result = stmt
+            .query_map([], |row| -> rusqlite::Result<(String, u64)> {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()
+            .with_context(|| {
+                format!("reading from sqlite table {}", LOCAL_STORE_REFS_TABLE_NAME)
+            })?;
+        Ok(result)
+    }
+
+    /// Drop the references held by the artifacts at `paths`.
+    pub(crate) fn delete(&self, paths: &[ProjectRelativePathBuf]) -> anyhow::Result<usize> {
+        if paths.is_empty() {
+            return Ok(0);
+        }
+        let sql = format!(
+            "DELETE FROM {} WHERE path IN ({})",
+            LOCAL_STORE_REFS_TABLE_NAME,
+            itertools::repeat_n("?", paths.len()).join(","),
+        );
+        tracing::trace!(sql = %sql, paths = ?paths, "deleting from table");
+        let rows_deleted = self
+            .connection
+            .lock()
+            .execute(
+                &sql,
+                rusqlite::params_from_iter(paths.iter().map(|p| p.as_str())),
+            )
+            .with_context(|| {
+                format!("deleting from sqlite table {}", LOCAL_STORE_REFS_TABLE_NAME)
+            })?;
+        Ok(rows_deleted)
+    }
+}
+
+/// Journal of materializations that are in progress. A row is inserted before we start writing
+/// an artifact to disk and removed once its outcome is recorded in `materializer_state`, so any
+/// rows found on startup belong to materializations interrupted by a crash.
+pub(crate) struct MaterializerPendingSqliteTable {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl MaterializerPendingSqliteTable {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> Self {
+        Self { connection }
+    }
+
+    pub(crate) fn create_table(&self) -> anyhow::Result<()> {
+        let sql = format!(
+            "CREATE TABLE {} (
+                path                    TEXT NOT NULL PRIMARY KEY,
+                artifact_type           TEXT CHECK(artifact_type IN ('directory','file','symlink','external_symlink')) NOT NULL,
+                digest_size             INTEGER NULL DEFAULT NULL,
+                entry_hash              BLOB NULL DEFAULT NULL,
+                entry_hash_kind         INTEGER NULL DEFAULT NULL,
+                file_is_executable      INTEGER NULL DEFAULT NULL,
+                symlink_target          TEXT NULL DEFAULT NULL,
+                directory_size          INTEGER NULL DEFAULT NULL,
+                staged                  INTEGER NOT NULL,
+                started_at              INTEGER NOT NULL
+            )",
+            PENDING_TABLE_NAME,
+        );
+        tracing::trace!(sql = %*sql, "creating table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("creating sqlite table {}", PENDING_TABLE_NAME))?;
+        Ok(())
+    }
+
+    /// Record that we started materializing `metadata` at `path`, replacing any previous attempt.
+    pub(crate) fn insert(
+        &self,
+        path: &ProjectRelativePath,
+        metadata: &ArtifactMetadata,
+        staged: bool,
+        timestamp: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let entry: ArtifactMetadataSqliteEntry = metadata.into();
+        static SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "INSERT OR REPLACE INTO {} (path, artifact_type, digest_size, entry_hash, entry_hash_kind, file_is_executable, symlink_target, directory_size, staged, started_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                PENDING_TABLE_NAME
+            )
+        });
+        tracing::trace!(sql = %*SQL, entry = ?entry, "inserting into table");
+        self.connection
+            .lock()
+            .execute(
+                &SQL,
+                rusqlite::params![
+                    path.as_str(),
+                    entry.artifact_type,
+                    entry.entry_size,
+                    entry.entry_hash,
+                    entry.entry_hash_kind,
+                    entry.file_is_executable,
+                    entry.symlink_target,
+                    entry.directory_size,
+                    staged,
+                    timestamp.timestamp(),
+                ],
+            )
+            .with_context(|| {
+                format!(
+                    "inserting `{}` into sqlite table {}",
+                    path, PENDING_TABLE_NAME
+                )
+            })?;
+        Ok(())
+    }
+
+    pub(crate) fn read_all(
+        &self,
+        digest_config: DigestConfig,
+    ) -> anyhow::Result<Vec<PendingMaterialization>> {
+        static SQL: Lazy<String> = Lazy::new(|| {
+            format!(
+                "SELECT path, artifact_type, digest_size, entry_hash, entry_hash_kind, file_is_executable, symlink_target, directory_size, staged, started_at FROM {}",
+                PENDING_TABLE_NAME,
+            )
+        });
+        tracing::trace!(sql = %*SQL, "reading all from table");
+        let connection = self.connection.lock();
+        let mut stmt = connection.prepare(&SQL)?;
+        let result = stmt
+            .query_map(
+                [],
+                |row| -> rusqlite::Result<(String, ArtifactMetadataSqliteEntry, bool, i64)> {
+                    Ok((
+                        row.get(0)?,
+                        ArtifactMetadataSqliteEntry::new(
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                            row.get(5)?,
+                            row.get(6)?,
+                            row.get(7)?,
+                        ),
+                        row.get(8)?,
+                        row.get(9)?,
+                    ))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("reading from sqlite table {}", PENDING_TABLE_NAME))?;
+
+        result
+            .into_try_map(
+                |(path, entry, staged, started_at)| -> anyhow::Result<PendingMaterialization> {
+                    Ok(PendingMaterialization {
+                        path: ProjectRelativePathBuf::unchecked_new(path),
+                        metadata: convert_artifact_metadata(entry, digest_config)?,
+                        staged,
+                        started_at: Utc
+                            .timestamp_opt(started_at, 0)
+                            .single()
+                            .with_context(|| "invalid timestamp")?,
+                    })
+                },
+            )
+            .with_context(|| format!("error reading row of sqlite table {}", PENDING_TABLE_NAME))
+    }
+
+    pub(crate) fn delete(&self, paths: &[ProjectRelativePathBuf]) -> anyhow::Result<usize> {
+        if paths.is_empty() {
+            return Ok(0);
+        }
+        let sql = format!(
+            "DELETE FROM {} WHERE path IN ({})",
+            PENDING_TABLE_NAME,
+            itertools::repeat_n("?", paths.len()).join(","),
+        );
+        tracing::trace!(sql = %sql, paths = ?paths, "deleting from table");
+        let rows_deleted = self
+            .connection
+            .lock()
+            .execute(
+                &sql,
+                rusqlite::params_from_iter(paths.iter().map(|p| p.as_str())),
+            )
+            .with_context(|| format!("deleting from sqlite table {}", PENDING_TABLE_NAME))?;
+        Ok(rows_deleted)
+    }
+}
+
 #[derive(Error, Debug, PartialEq, Eq)]
 enum MaterializerStateSqliteDbError {
     #[error("Path {} does not exist", .0)]
@@ -478,6 +750,10 @@ enum MaterializerStateSqliteDbError {
 pub struct MaterializerStateSqliteDb {
     /// Table storing actual materializer state
     materializer_state_table: MaterializerStateSqliteTable,
+    /// Table storing which local store blobs are referenced by materialized artifacts.
+    local_store_refs_table: LocalStoreRefsSqliteTable,
+    /// Table journaling materializations that have started but not finished.
+    pending_table: MaterializerPendingSqliteTable,
     /// Table for holding any metadata used to check version match. When loading
     /// from an existing db, we check if the versions from this table match the
     /// versions this buck2 binary expects. If the versions don't match, we throw
@@ -521,11 +797,15 @@ impl MaterializerStateSqliteDb {
 
         let connection = Arc::new(Mutex::new(connection));
         let materializer_state_table = MaterializerStateSqliteTable::new(connection.dupe());
+        let local_store_refs_table = LocalStoreRefsSqliteTable::new(connection.dupe());
+        let pending_table = MaterializerPendingSqliteTable::new(connection.dupe());
         let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
         let created_by_table = KeyValueSqliteTable::new("created_by".to_owned(), connection.dupe());
         let last_read_by_table = KeyValueSqliteTable::new("last_read_by".to_owned(), connection);
         Ok(Self {
             materializer_state_table,
+            local_store_refs_table,
+            pending_table,
             versions_table,
             created_by_table,
             last_read_by_table,
@@ -633,8 +913,18 @@ impl MaterializerStateSqliteDb {
         &self.materializer_state_table
     }
 
+    pub(crate) fn local_store_refs_table(&mut self) -> &LocalStoreRefsSqliteTable {
+        &self.local_store_refs_table
+    }
+
+    pub(crate) fn pending_table(&self) -> &MaterializerPendingSqliteTable {
+        &self.pending_table
+    }
+
     pub(crate) fn create_all_tables(&self) -> anyhow::Result<()> {
         self.materializer_state_table.create_table()?;
+        self.local_store_refs_table.create_table()?;
+        self.pending_table.create_table()?;
         self.versions_table.create_table()?;
         self.created_by_table.create_table()?;
         self.last_read_by_table.create_table()?;
@@ -827,6 +1117,82 @@ mod tests {
         assert_eq!(artifacts, state.into_iter().collect::<HashMap<_, _>>());
     }
 
+    #[test]
+    fn test_local_store_refs_sqlite_table() {
+        let fs = ProjectRootTemp::new().unwrap();
+        let connection = Connection::open(
+            fs.path()
+                .resolve(ProjectRelativePath::unchecked_new("test.db")),
+        )
+        .unwrap();
+        let table = LocalStoreRefsSqliteTable::new(Arc::new(Mutex::new(connection)));
+
+        table.create_table().unwrap();
+
+        let a = ProjectRelativePath::unchecked_new("a").to_owned();
+        let b = ProjectRelativePath::unchecked_new("b").to_owned();
+        table.insert(&a, &["x".to_owned(), "y".to_owned()]).unwrap();
+        table.insert(&b, &["y".to_owned()]).unwrap();
+        // Inserting the same reference twice doesn't count it twice.
+        table.insert(&b, &["y".to_owned()]).unwrap();
+
+        assert_eq!(
+            table.refcounts().unwrap(),
+            HashMap::from([("x".to_owned(), 1), ("y".to_owned(), 2)])
+        );
+
+        assert_eq!(table.delete(&[a]).unwrap(), 2);
+        assert_eq!(
+            table.refcounts().unwrap(),
+            HashMap::from([("y".to_owned(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_pending_sqlite_table() {
+        let fs = ProjectRootTemp::new().unwrap();
+        let connection = Connection::open(
+            fs.path()
+                .resolve(ProjectRelativePath::unchecked_new("test.db")),
+        )
+        .unwrap();
+        let table = MaterializerPendingSqliteTable::new(Arc::new(Mutex::new(connection)));
+
+        table.create_table().unwrap();
+
+        let digest_config = DigestConfig::testing_default();
+        let metadata = ArtifactMetadata(DirectoryEntry::Leaf(ActionDirectoryMember::File(
+            FileMetadata {
+                digest: TrackedFileDigest::from_content(b"foo", digest_config.cas_digest_config()),
+                is_executable: false,
+            },
+        )));
+        let now = Utc.timestamp_opt(Utc::now().timestamp(), 0).unwrap();
+        let a = ProjectRelativePath::unchecked_new("a").to_owned();
+        let b = ProjectRelativePath::unchecked_new("b").to_owned();
+
+        table.insert(&a, &metadata, false, now).unwrap();
+        table.insert(&b, &metadata, false, now).unwrap();
+        // Restarting a materialization replaces its row.
+        table.insert(&b, &metadata, true, now).unwrap();
+
+        let mut pending = table
+            .read_all(digest_config)
+            .unwrap()
+            .into_map(|p| (p.path, p.metadata, p.staged, p.started_at));
+        pending.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(
+            pending,
+            vec![
+                (a.clone(), metadata.clone(), false, now),
+                (b, metadata, true, now),
+            ]
+        );
+
+        assert_eq!(table.delete(&[a]).unwrap(), 1);
+        assert_eq!(table.read_all(digest_config).unwrap().len(), 1);
+    }
+
     fn testing_materializer_state_sqlite_db(
         fs: &ProjectRoot,
         versions: HashMap<String, String>,
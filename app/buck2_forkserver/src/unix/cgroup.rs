@@ -0,0 +1,555 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Runs each command in its own cgroup v2, so that we can apply resource limits to it and report
+//! how much memory and CPU it used.
+//!
+//! The forkserver creates a `buck2-actions-<pid>` cgroup next to its own, and one child cgroup per
+//! command in there. Limits can only be applied if the relevant controllers can be enabled, which
+//! cgroup v2 only allows in a cgroup that has no processes of its own. When the forkserver and the
+//! daemon are the only processes in their cgroup (e.g. because the daemon was started in a
+//! delegated scope), we move them to a `buck2-daemon` leaf to make this possible. Otherwise, we
+//! still create per-command cgroups but only report CPU time.
+//!
+//! When the forkserver exits, it removes its cgroups and moves whatever is left in the leaf back.
+
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use buck2_forkserver_proto::ResourceLimits;
+
+use crate::run::status_decoder::DecodedStatus;
+use crate::run::status_decoder::StatusDecoder;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The controllers we need for the limits we support.
+const CONTROLLERS: &[&str] = &["cpu", "memory", "pids"];
+
+/// `cpu.max` takes a quota over a period, both in microseconds.
+const CPU_MAX_PERIOD_USEC: u64 = 100_000;
+
+#[derive(Debug, thiserror::Error)]
+enum CgroupError {
+    #[error("No cgroup v2 entry in `/proc/self/cgroup`")]
+    NoUnifiedHierarchy,
+    #[error("`{}` is not a cgroup v2 mount", CGROUP_ROOT)]
+    NotCgroupV2,
+}
+
+/// The cgroup under which the forkserver creates one cgroup per command.
+pub(crate) struct ActionCgroups {
+    dir: PathBuf,
+    /// Set if we moved the daemon and the forkserver to a leaf to enable controllers.
+    daemon_leaf: Option<DaemonLeaf>,
+    /// Controllers enabled for the per-command cgroups.
+    controllers: HashSet<String>,
+    next_id: AtomicU64,
+    warned_unsupported_limits: AtomicBool,
+}
+
+impl ActionCgroups {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let own =
+            fs::read_to_string("/proc/self/cgroup").context("Error reading `/proc/self/cgroup`")?;
+        let own = parse_unified_cgroup(&own).ok_or(CgroupError::NoUnifiedHierarchy)?;
+        let own = Path::new(CGROUP_ROOT).join(own.trim_start_matches('/'));
+
+        if !own.join("cgroup.controllers").exists() {
+            return Err(CgroupError::NotCgroupV2.into());
+        }
+
+        let daemon_leaf = match enable_controllers(&own) {
+            Ok(daemon_leaf) => Some(daemon_leaf),
+            Err(e) => {
+                tracing::info!(
+                    "Resource limits are unavailable for local actions: {:#}",
+                    e.context(format!("Error enabling controllers in `{}`", own.display()))
+                );
+                None
+            }
+        };
+
+        // Constructed first so that everything is undone if the rest fails.
+        let mut cgroups = Self {
+            dir: own.join(format!("buck2-actions-{}", std::process::id())),
+            daemon_leaf,
+            controllers: HashSet::new(),
+            next_id: AtomicU64::new(0),
+            warned_unsupported_limits: AtomicBool::new(false),
+        };
+        create_cgroup(&cgroups.dir)?;
+
+        enable_subtree_controllers(&cgroups.dir)?;
+        cgroups.controllers = read_controllers(&cgroups.dir.join("cgroup.subtree_control"))?;
+
+        Ok(cgroups)
+    }
+
+    /// Create a cgroup for a command, with the given limits.
+    pub(crate) fn create(&self, limits: Option<&ResourceLimits>) -> anyhow::Result<ActionCgroup> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let dir = self.dir.join(format!("action-{}", id));
+        create_cgroup(&dir)?;
+
+        let cgroup = ActionCgroup {
+            procs: File::options()
+                .write(true)
+                .open(dir.join("cgroup.procs"))
+                .with_context(|| format!("Error opening `{}/cgroup.procs`", dir.display()))?,
+            dir,
+        };
+
+        if let Some(limits) = limits {
+            for (controller, file, value) in limit_writes(limits) {
+                if self.controllers.contains(controller) {
+                    write_file(&cgroup.dir.join(file), &value)?;
+                } else if !self.warned_unsupported_limits.swap(true, Ordering::Relaxed) {
+                    tracing::warn!(
+                        "Ignoring `{}` limit: the `{}` cgroup controller is not available",
+                        file,
+                        controller
+                    );
+                }
+            }
+
+            if limits.memory_max_bytes.is_some() && self.controllers.contains("memory") {
+                // Don't let the command swap instead of getting OOM-killed. Best-effort, since
+                // `memory.swap.max` is missing without swap accounting.
+                let _ignored = write_file(&cgroup.dir.join("memory.swap.max"), "0");
+            }
+        }
+
+        Ok(cgroup)
+    }
+}
+
+impl Drop for ActionCgroups {
+    fn drop(&mut self) {
+        // Per-command cgroups are removed once their command is done, so there is only something
+        // left here if the forkserver exits while commands are still running.
+        let _ignored = write_file(&self.dir.join("cgroup.kill"), "1");
+        if let Ok(children) = fs::read_dir(&self.dir) {
+            for child in children.flatten() {
+                if child.file_type().map_or(false, |t| t.is_dir()) {
+                    let _ignored = fs::remove_dir(child.path());
+                }
+            }
+        }
+        if let Err(e) = fs::remove_dir(&self.dir) {
+            tracing::warn!("Error removing cgroup `{}`: {}", self.dir.display(), e);
+        }
+
+        if let Some(daemon_leaf) = self.daemon_leaf.take() {
+            if let Err(e) = daemon_leaf.restore() {
+                tracing::warn!(
+                    "Error moving processes out of `{}`: {:#}",
+                    daemon_leaf.leaf.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// The leaf cgroup we moved the daemon and the forkserver to, so that they can be moved back.
+struct DaemonLeaf {
+    own: PathBuf,
+    leaf: PathBuf,
+    /// Controllers we enabled for the children of `own`.
+    enabled: Vec<String>,
+}
+
+impl DaemonLeaf {
+    fn restore(&self) -> anyhow::Result<()> {
+        // Processes can't be moved back while controllers are enabled for the children.
+        if !self.enabled.is_empty() {
+            let disable = self
+                .enabled
+                .iter()
+                .map(|c| format!("-{}", c))
+                .collect::<Vec<_>>();
+            write_file(&self.own.join("cgroup.subtree_control"), &disable.join(" "))?;
+        }
+
+        let procs = fs::read_to_string(self.leaf.join("cgroup.procs"))
+            .with_context(|| format!("Error reading `{}/cgroup.procs`", self.leaf.display()))?;
+        for pid in procs.lines() {
+            write_file(&self.own.join("cgroup.procs"), pid.trim())?;
+        }
+
+        fs::remove_dir(&self.leaf)
+            .with_context(|| format!("Error removing cgroup `{}`", self.leaf.display()))
+    }
+}
+
+/// A cgroup holding a single command. Removed once the command is done.
+pub(crate) struct ActionCgroup {
+    dir: PathBuf,
+    /// Kept open so that the child can join the cgroup without having to allocate.
+    procs: File,
+}
+
+impl ActionCgroup {
+    /// Make the command join this cgroup before it execs.
+    pub(crate) fn enter_on_spawn(&self, cmd: &mut Command) {
+        let fd = self.procs.as_raw_fd();
+
+        unsafe {
+            cmd.pre_exec(move || {
+                // Writing `0` moves the writing process. This only uses a write syscall so it is
+                // safe to run after fork.
+                if libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    fn stats(&self) -> CgroupStats {
+        let read = |file: &str| fs::read_to_string(self.dir.join(file)).ok();
+
+        let cpu_stat = read("cpu.stat");
+        let memory_events = read("memory.events");
+
+        CgroupStats {
+            peak_memory_bytes: read("memory.peak").and_then(|s| s.trim().parse().ok()),
+            cpu_user_usec: cpu_stat
+                .as_deref()
+                .and_then(|s| parse_flat_keyed(s, "user_usec")),
+            cpu_system_usec: cpu_stat
+                .as_deref()
+                .and_then(|s| parse_flat_keyed(s, "system_usec")),
+            oom_killed: memory_events
+                .as_deref()
+                .and_then(|s| parse_flat_keyed(s, "oom_kill"))
+                .map(|count| count > 0),
+        }
+    }
+
+    /// Kill anything left in the cgroup (e.g. processes the command daemonized) and remove it.
+    async fn remove(self) {
+        // `cgroup.kill` is only available on Linux 5.14+, so this is best-effort.
+        let _ignored = write_file(&self.dir.join("cgroup.kill"), "1");
+
+        // The kill is asynchronous, so the cgroup might not be empty yet.
+        let mut res = Ok(());
+        for _ in 0..10 {
+            res = fs::remove_dir(&self.dir);
+            match &res {
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                _ => break,
+            }
+        }
+
+        if let Err(e) = res {
+            tracing::warn!("Error removing cgroup `{}`: {}", self.dir.display(), e);
+        }
+    }
+}
+
+impl Drop for ActionCgroup {
+    fn drop(&mut self) {
+        // If the command never ran (e.g. spawn failed), nothing ever called `remove`, and the
+        // cgroup is empty so this is enough to get rid of it.
+        let _ignored = fs::remove_dir(&self.dir);
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CgroupStats {
+    peak_memory_bytes: Option<u64>,
+    cpu_user_usec: Option<u64>,
+    cpu_system_usec: Option<u64>,
+    oom_killed: Option<bool>,
+}
+
+impl CgroupStats {
+    fn merge_into(
+        self,
+        stats: Option<buck2_data::CommandExecutionStats>,
+    ) -> buck2_data::CommandExecutionStats {
+        buck2_data::CommandExecutionStats {
+            peak_memory_bytes: self.peak_memory_bytes,
+            cpu_user_usec: self.cpu_user_usec,
+            cpu_system_usec: self.cpu_system_usec,
+            oom_killed: self.oom_killed,
+            ..stats.unwrap_or_default()
+        }
+    }
+}
+
+/// Wraps another decoder to add the stats of the cgroup the command ran in, if any.
+pub(crate) struct CgroupStatusDecoder<D> {
+    inner: D,
+    cgroup: Option<ActionCgroup>,
+}
+
+impl<D> CgroupStatusDecoder<D> {
+    pub(crate) fn new(inner: D, cgroup: Option<ActionCgroup>) -> Self {
+        Self { inner, cgroup }
+    }
+}
+
+#[async_trait]
+impl<D: StatusDecoder + Send> StatusDecoder for CgroupStatusDecoder<D> {
+    async fn decode_status(self, status: ExitStatus) -> anyhow::Result<DecodedStatus> {
+        let decoded = self.inner.decode_status(status).await;
+
+        let cgroup = match self.cgroup {
+            Some(cgroup) => cgroup,
+            None => return decoded,
+        };
+        let stats = cgroup.stats();
+        cgroup.remove().await;
+
+        Ok(match decoded? {
+            DecodedStatus::Status {
+                exit_code,
+                execution_stats,
+            } => DecodedStatus::Status {
+                exit_code,
+                execution_stats: Some(stats.merge_into(execution_stats)),
+            },
+            DecodedStatus::SpawnFailed(e) => DecodedStatus::SpawnFailed(e),
+        })
+    }
+
+    async fn cancel(self) -> anyhow::Result<()> {
+        let res = self.inner.cancel().await;
+        if let Some(cgroup) = self.cgroup {
+            cgroup.remove().await;
+        }
+        res
+    }
+}
+
+/// If our cgroup only contains the forkserver and the daemon that spawned it, move both to a leaf
+/// cgroup so that controllers can be enabled for our children.
+fn enable_controllers(own: &Path) -> anyhow::Result<DaemonLeaf> {
+    let ours = [std::process::id(), std::os::unix::process::parent_id()];
+
+    let procs = fs::read_to_string(own.join("cgroup.procs"))?;
+    let others = procs
+        .lines()
+        .filter_map(|pid| pid.trim().parse::<u32>().ok())
+        .filter(|pid| !ours.contains(pid))
+        .count();
+    if others > 0 {
+        return Err(anyhow::anyhow!(
+            "{} other processes share the cgroup; start the daemon in a dedicated, delegated cgroup to enable them",
+            others
+        ));
+    }
+
+    let leaf = own.join("buck2-daemon");
+    create_cgroup(&leaf)?;
+    for pid in ours {
+        // Our parent might have exited or might be elsewhere already.
+        let _ignored = write_file(&leaf.join("cgroup.procs"), &pid.to_string());
+    }
+
+    let mut daemon_leaf = DaemonLeaf {
+        own: own.to_owned(),
+        leaf,
+        enabled: Vec::new(),
+    };
+    match enable_subtree_controllers(own) {
+        Ok(enabled) => {
+            daemon_leaf.enabled = enabled;
+            Ok(daemon_leaf)
+        }
+        Err(e) => {
+            let _ignored = daemon_leaf.restore();
+            Err(e)
+        }
+    }
+}
+
+/// Enable the controllers we need (those that are available) for the children of `dir`. Returns
+/// those that weren't enabled already.
+fn enable_subtree_controllers(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let available = read_controllers(&dir.join("cgroup.controllers"))?;
+    let enabled = read_controllers(&dir.join("cgroup.subtree_control"))?;
+    let wanted = CONTROLLERS
+        .iter()
+        .filter(|c| available.contains(**c) && !enabled.contains(**c))
+        .map(|c| (*c).to_owned())
+        .collect::<Vec<_>>();
+    if !wanted.is_empty() {
+        let enable = wanted.iter().map(|c| format!("+{}", c)).collect::<Vec<_>>();
+        write_file(&dir.join("cgroup.subtree_control"), &enable.join(" "))?;
+    }
+    Ok(wanted)
+}
+
+fn create_cgroup(dir: &Path) -> anyhow::Result<()> {
+    match fs::create_dir(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => {
+            Err(anyhow::Error::from(e)
+                .context(format!("Error creating cgroup `{}`", dir.display())))
+        }
+    }
+}
+
+fn write_file(path: &Path, value: &str) -> anyhow::Result<()> {
+    fs::write(path, value)
+        .with_context(|| format!("Error writing `{}` to `{}`", value, path.display()))
+}
+
+fn read_controllers(path: &Path) -> anyhow::Result<HashSet<String>> {
+    Ok(fs::read_to_string(path)
+        .with_context(|| format!("Error reading `{}`", path.display()))?
+        .split_whitespace()
+        .map(|c| c.to_owned())
+        .collect())
+}
+
+/// The controller, file and value to write for each limit that is set.
+fn limit_writes(limits: &ResourceLimits) -> Vec<(&'static str, &'static str, String)> {
+    let mut writes = Vec::new();
+    if let Some(bytes) = limits.memory_max_bytes {
+        writes.push(("memory", "memory.max", bytes.to_string()));
+    }
+    if let Some(millicores) = limits.cpu_max_millicores {
+        let quota = millicores.saturating_mul(CPU_MAX_PERIOD_USEC) / 1000;
+        writes.push((
+            "cpu",
+            "cpu.max",
+            format!("{} {}", quota.max(1000), CPU_MAX_PERIOD_USEC),
+        ));
+    }
+    if let Some(pids) = limits.pids_max {
+        writes.push(("pids", "pids.max", pids.to_string()));
+    }
+    writes
+}
+
+/// Find our cgroup in the unified hierarchy, from the contents of `/proc/self/cgroup`.
+fn parse_unified_cgroup(proc_cgroup: &str) -> Option<&str> {
+    proc_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Read a key from a flat keyed file such as `cpu.stat` or `memory.events`.
+fn parse_flat_keyed(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        if k == key {
+            v.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unified_cgroup() {
+        assert_eq!(
+            parse_unified_cgroup("0::/user.slice/user-1000.slice/session-1.scope\n"),
+            Some("/user.slice/user-1000.slice/session-1.scope")
+        );
+        assert_eq!(
+            parse_unified_cgroup("12:pids:/foo\n1:name=systemd:/foo\n0::/bar\n"),
+            Some("/bar")
+        );
+        assert_eq!(parse_unified_cgroup("12:pids:/foo\n"), None);
+    }
+
+    #[test]
+    fn test_parse_flat_keyed() {
+        let cpu_stat = "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\n";
+        assert_eq!(parse_flat_keyed(cpu_stat, "user_usec"), Some(1000));
+        assert_eq!(parse_flat_keyed(cpu_stat, "system_usec"), Some(500));
+        assert_eq!(parse_flat_keyed(cpu_stat, "nr_throttled"), None);
+
+        let memory_events = "low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(parse_flat_keyed(memory_events, "oom_kill"), Some(1));
+    }
+
+    #[test]
+    fn test_limit_writes() {
+        let limits = ResourceLimits {
+            memory_max_bytes: Some(1 << 30),
+            cpu_max_millicores: Some(2500),
+            pids_max: None,
+        };
+        assert_eq!(
+            limit_writes(&limits),
+            vec![
+                ("memory", "memory.max", "1073741824".to_owned()),
+                ("cpu", "cpu.max", "250000 100000".to_owned()),
+            ]
+        );
+
+        let limits = ResourceLimits {
+            memory_max_bytes: None,
+            cpu_max_millicores: Some(1),
+            pids_max: Some(64),
+        };
+        assert_eq!(
+            limit_writes(&limits),
+            vec![
+                ("cpu", "cpu.max", "1000 100000".to_owned()),
+                ("pids", "pids.max", "64".to_owned()),
+            ]
+        );
+
+        let limits = ResourceLimits {
+            memory_max_bytes: None,
+            cpu_max_millicores: Some(u64::MAX),
+            pids_max: None,
+        };
+        assert_eq!(
+            limit_writes(&limits),
+            vec![("cpu", "cpu.max", format!("{} 100000", u64::MAX / 1000))]
+        );
+    }
+
+    #[test]
+    fn test_merge_stats() {
+        let stats = CgroupStats {
+            peak_memory_bytes: Some(100),
+            cpu_user_usec: Some(10),
+            cpu_system_usec: Some(5),
+            oom_killed: Some(false),
+        };
+        let merged = stats.merge_into(Some(buck2_data::CommandExecutionStats {
+            cpu_instructions_user: Some(1),
+            ..Default::default()
+        }));
+        assert_eq!(merged.cpu_instructions_user, Some(1));
+        assert_eq!(merged.peak_memory_bytes, Some(100));
+        assert_eq!(merged.oom_killed, Some(false));
+    }
+}
@@ -24,6 +24,7 @@ pub async fn run_forkserver(
     fd: RawFd,
     log_reload_handle: Box<dyn LogConfigurationReloadHandle>,
     state_dir: AbsNormPathBuf,
+    enable_cgroups: bool,
 ) -> anyhow::Result<()> {
     // SAFETY: At worst, we just read (or close) the wrong FD.
     let io = UnixStream::from_std(unsafe { StdUnixStream::from_raw_fd(fd) })
@@ -34,7 +35,7 @@ pub async fn run_forkserver(
         DuplexChannel::new(read, write)
     };
 
-    let service = UnixForkserverService::new(log_reload_handle, &state_dir)
+    let service = UnixForkserverService::new(log_reload_handle, &state_dir, enable_cgroups)
         .context("Failed to create UnixForkserverService")?;
 
     let router = tonic::transport::Server::builder()
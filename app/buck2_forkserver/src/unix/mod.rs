@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+mod cgroup;
 mod command;
 mod launch;
 mod service;
@@ -46,6 +46,8 @@ use crate::run::stream_command_events;
 use crate::run::timeout_into_cancellation;
 use crate::run::DefaultKillProcess;
 use crate::run::GatherOutputStatus;
+use crate::unix::cgroup::ActionCgroups;
+use crate::unix::cgroup::CgroupStatusDecoder;
 
 // Not quite BoxStream: it has to be Sync (...)
 type RunStream =
@@ -56,18 +58,35 @@ pub struct UnixForkserverService {
 
     /// State for Miniperf.
     miniperf: Option<MiniperfContainer>,
+
+    /// Where to put commands, if we run them in cgroups.
+    cgroups: Option<ActionCgroups>,
 }
 
 impl UnixForkserverService {
     pub fn new(
         log_reload_handle: Box<dyn LogConfigurationReloadHandle>,
         state_dir: &AbsNormPath,
+        enable_cgroups: bool,
     ) -> anyhow::Result<Self> {
         let miniperf = MiniperfContainer::new(state_dir)?;
 
+        let cgroups = if enable_cgroups {
+            match ActionCgroups::new() {
+                Ok(cgroups) => Some(cgroups),
+                Err(e) => {
+                    tracing::warn!("Not running commands in cgroups: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Ok(Self {
             log_reload_handle,
             miniperf,
+            cgroups,
         })
     }
 }
@@ -108,6 +127,7 @@ impl Forkserver for UnixForkserverService {
                 cwd,
                 timeout,
                 enable_miniperf,
+                resource_limits,
             } = msg;
 
             let exe = OsStr::from_bytes(&exe);
@@ -152,6 +172,16 @@ impl Forkserver for UnixForkserverService {
                 }
             }
 
+            let cgroup = self
+                .cgroups
+                .as_ref()
+                .map(|cgroups| cgroups.create(resource_limits.as_ref()))
+                .transpose()
+                .context("Error creating cgroup for command")?;
+            if let Some(cgroup) = &cgroup {
+                cgroup.enter_on_spawn(&mut cmd);
+            }
+
             let mut cmd = prepare_command(cmd);
             let child = cmd.spawn();
 
@@ -163,14 +193,14 @@ impl Forkserver for UnixForkserverService {
                 Some(out) => stream_command_events(
                     child,
                     cancellation,
-                    MiniperfStatusDecoder::new(out),
+                    CgroupStatusDecoder::new(MiniperfStatusDecoder::new(out), cgroup),
                     DefaultKillProcess,
                 )?
                 .left_stream(),
                 None => stream_command_events(
                     child,
                     cancellation,
-                    DefaultStatusDecoder,
+                    CgroupStatusDecoder::new(DefaultStatusDecoder, cgroup),
                     DefaultKillProcess,
                 )?
                 .right_stream(),
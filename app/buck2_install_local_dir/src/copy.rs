@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CopyError {
+    #[error("Install was cancelled")]
+    Cancelled,
+
+    #[error("Failed to copy `{}`", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl CopyError {
+    /// Whether copying again may succeed.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            CopyError::Cancelled => false,
+            CopyError::Io { source, .. } => matches!(
+                source.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ),
+        }
+    }
+}
+
+trait IoResultExt<T> {
+    fn at(self, path: &Path) -> Result<T, CopyError>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn at(self, path: &Path) -> Result<T, CopyError> {
+        self.map_err(|source| CopyError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+/// Copies files while reporting how many bytes were copied so far, and stops as soon as it
+/// notices the install was cancelled.
+pub(crate) struct Copier<'a> {
+    cancelled: &'a AtomicBool,
+    progress: &'a mut dyn FnMut(u64, u64),
+    done: u64,
+    total: u64,
+}
+
+impl<'a> Copier<'a> {
+    /// `progress` is called with the number of bytes copied so far and the total.
+    pub(crate) fn new(cancelled: &'a AtomicBool, progress: &'a mut dyn FnMut(u64, u64)) -> Self {
+        Self {
+            cancelled,
+            progress,
+            done: 0,
+            total: 0,
+        }
+    }
+
+    /// Copy `src`, which may be a file, a directory or a symlink, to `dst`, replacing whatever is
+    /// there. The copy is made under a temporary name next to `dst` and renamed into place, so a
+    /// failed or cancelled copy never leaves a partial artifact at `dst`.
+    pub(crate) fn copy_artifact(&mut self, src: &Path, dst: &Path) -> Result<(), CopyError> {
+        self.total = size_of(src)?;
+        self.done = 0;
+        (self.progress)(self.done, self.total);
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).at(parent)?;
+        }
+
+        let mut tmp_name = dst.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp = dst.with_file_name(tmp_name);
+        remove_if_exists(&tmp)?;
+
+        if let Err(e) = self.copy_tree(src, &tmp) {
+            let _ignored = remove_if_exists(&tmp);
+            return Err(e);
+        }
+
+        remove_if_exists(dst)?;
+        fs::rename(&tmp, dst).at(dst)
+    }
+
+    fn copy_tree(&mut self, src: &Path, dst: &Path) -> Result<(), CopyError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(CopyError::Cancelled);
+        }
+
+        let metadata = fs::symlink_metadata(src).at(src)?;
+        if metadata.is_symlink() {
+            copy_symlink(src, dst)
+        } else if metadata.is_dir() {
+            fs::create_dir(dst).at(dst)?;
+            for entry in fs::read_dir(src).at(src)? {
+                let entry = entry.at(src)?;
+                self.copy_tree(&entry.path(), &dst.join(entry.file_name()))?;
+            }
+            Ok(())
+        } else {
+            self.copy_file(src, dst)?;
+            fs::set_permissions(dst, metadata.permissions()).at(dst)
+        }
+    }
+
+    fn copy_file(&mut self, src: &Path, dst: &Path) -> Result<(), CopyError> {
+        let mut reader = File::open(src).at(src)?;
+        let mut writer = File::create(dst).at(dst)?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return Err(CopyError::Cancelled);
+            }
+            let n = reader.read(&mut buf).at(src)?;
+            if n == 0 {
+                return Ok(());
+            }
+            writer.write_all(&buf[..n]).at(dst)?;
+            self.done += n as u64;
+            (self.progress)(self.done, self.total);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<(), CopyError> {
+    let target = fs::read_link(src).at(src)?;
+    std::os::unix::fs::symlink(target, dst).at(dst)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<(), CopyError> {
+    // Creating symlinks may require privileges we don't have, so copy what they point to.
+    fs::copy(src, dst).at(dst).map(|_| ())
+}
+
+/// The number of bytes that copying `path` will write.
+fn size_of(path: &Path) -> Result<u64, CopyError> {
+    let metadata = fs::symlink_metadata(path).at(path)?;
+    if metadata.is_dir() {
+        let mut size = 0;
+        for entry in fs::read_dir(path).at(path)? {
+            size += size_of(&entry.at(path)?.path())?;
+        }
+        Ok(size)
+    } else if metadata.is_symlink() {
+        Ok(0)
+    } else {
+        Ok(metadata.len())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), CopyError> {
+    let res = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    };
+    res.at(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_artifact() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("dir"))?;
+        fs::write(src.join("a"), "aaa")?;
+        fs::write(src.join("dir/b"), "bb")?;
+
+        let dst = tempdir.path().join("dst/app");
+        fs::create_dir_all(&dst)?;
+        fs::write(dst.join("stale"), "")?;
+
+        let cancelled = AtomicBool::new(false);
+        let mut reported = Vec::new();
+        let mut progress = |done, total| reported.push((done, total));
+        Copier::new(&cancelled, &mut progress).copy_artifact(&src, &dst)?;
+
+        assert_eq!(fs::read_to_string(dst.join("a"))?, "aaa");
+        assert_eq!(fs::read_to_string(dst.join("dir/b"))?, "bb");
+        assert!(!dst.join("stale").exists());
+        assert!(!tempdir.path().join("dst/app.tmp").exists());
+        assert_eq!(reported.first(), Some(&(0, 5)));
+        assert_eq!(reported.last(), Some(&(5, 5)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_artifact_cancelled() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let src = tempdir.path().join("src");
+        fs::write(&src, "aaa")?;
+        let dst = tempdir.path().join("dst");
+        fs::write(&dst, "previous")?;
+
+        let cancelled = AtomicBool::new(true);
+        let mut progress = |_, _| {};
+        let res = Copier::new(&cancelled, &mut progress).copy_artifact(&src, &dst);
+
+        assert!(matches!(res, Err(CopyError::Cancelled)));
+        assert_eq!(fs::read_to_string(&dst)?, "previous");
+        assert!(!tempdir.path().join("dst.tmp").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A reference installer, which "deploys" artifacts by copying them into a local directory.
+//!
+//! It implements the whole `install.proto` protocol (including progress and cancellation), so it
+//! serves both as an example for writing installers and as a stand-in for a real one.
+
+use std::fs;
+use std::fs::File;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use buck2_install_proto::installer_server::InstallerServer;
+use clap::Parser;
+use tokio::sync::oneshot;
+use tonic::transport::Server;
+
+use crate::service::LocalDirInstaller;
+
+mod copy;
+mod service;
+
+#[derive(Debug, Parser)]
+#[clap(about = "Install artifacts by copying them into a local directory")]
+struct Opt {
+    /// Directory to copy artifacts into. Each artifact is copied to `<dst>/<name>`.
+    #[clap(long)]
+    dst: PathBuf,
+
+    /// Port to serve the installer API on (passed by Buck).
+    #[clap(long)]
+    tcp_port: u16,
+
+    /// File to write logs to (passed by Buck).
+    #[clap(long)]
+    log_path: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+
+    if let Some(log_path) = &opt.log_path {
+        let log = File::create(log_path)
+            .with_context(|| format!("Failed to create log file `{}`", log_path.display()))?;
+        tracing_subscriber::fmt()
+            .with_writer(Mutex::new(log))
+            .with_ansi(false)
+            .init();
+    }
+
+    fs::create_dir_all(&opt.dst)
+        .with_context(|| format!("Failed to create `{}`", opt.dst.display()))?;
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), opt.tcp_port);
+    tracing::info!(
+        "Serving on {}, installing into `{}`",
+        addr,
+        opt.dst.display()
+    );
+
+    Server::builder()
+        .add_service(InstallerServer::new(LocalDirInstaller::new(
+            opt.dst,
+            shutdown_tx,
+        )))
+        .serve_with_shutdown(addr, async {
+            let _ignored = shutdown_rx.await;
+        })
+        .await
+        .context("Installer server failed")?;
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashSet;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use buck2_install_proto::installer_server::Installer;
+use buck2_install_proto::CancelRequest;
+use buck2_install_proto::CancelResponse;
+use buck2_install_proto::ErrorDetail;
+use buck2_install_proto::FileReadyRequest;
+use buck2_install_proto::FileResponse;
+use buck2_install_proto::InstallInfoRequest;
+use buck2_install_proto::InstallResponse;
+use buck2_install_proto::ProgressRequest;
+use buck2_install_proto::ProgressResponse;
+use buck2_install_proto::ShutdownRequest;
+use buck2_install_proto::ShutdownResponse;
+use futures::stream::Stream;
+use futures::stream::StreamExt;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+use crate::copy::Copier;
+use crate::copy::CopyError;
+
+type ProgressStream = Pin<Box<dyn Stream<Item = Result<ProgressResponse, Status>> + Send>>;
+
+#[derive(Debug, thiserror::Error)]
+enum InstallFileError {
+    #[error("Install `{0}` was never started")]
+    UnknownInstall(String),
+
+    #[error("`{0}` is not a relative path without `..`, so it can't be installed")]
+    InvalidName(String),
+
+    #[error(transparent)]
+    Copy(#[from] CopyError),
+
+    #[error("Copy task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+impl InstallFileError {
+    fn is_transient(&self) -> bool {
+        match self {
+            InstallFileError::Copy(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Fans progress out to all the clients streaming it.
+#[derive(Default)]
+struct ProgressSubscribers {
+    senders: Mutex<Vec<mpsc::UnboundedSender<ProgressResponse>>>,
+}
+
+impl ProgressSubscribers {
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<ProgressResponse> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn send(&self, progress: ProgressResponse) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(progress.clone()).is_ok());
+    }
+
+    /// End all the streams.
+    fn close(&self) {
+        self.senders.lock().unwrap().clear();
+    }
+}
+
+/// Installs each file by copying it to `<dst>/<name>`.
+pub(crate) struct LocalDirInstaller {
+    dst: PathBuf,
+    /// The install ids we were told about.
+    installs: Mutex<HashSet<String>>,
+    progress: Arc<ProgressSubscribers>,
+    cancelled: Arc<AtomicBool>,
+    shutdown: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl LocalDirInstaller {
+    pub(crate) fn new(dst: PathBuf, shutdown: oneshot::Sender<()>) -> Self {
+        Self {
+            dst,
+            installs: Mutex::new(HashSet::new()),
+            progress: Arc::new(ProgressSubscribers::default()),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            shutdown: Mutex::new(Some(shutdown)),
+        }
+    }
+
+    async fn install_file(&self, request: &FileReadyRequest) -> Result<(), InstallFileError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(CopyError::Cancelled.into());
+        }
+        if !self.installs.lock().unwrap().contains(&request.install_id) {
+            return Err(InstallFileError::UnknownInstall(request.install_id.clone()));
+        }
+        if !is_normal_relative_path(Path::new(&request.name)) {
+            return Err(InstallFileError::InvalidName(request.name.clone()));
+        }
+
+        let src = PathBuf::from(&request.path);
+        let dst = self.dst.join(&request.name);
+        let cancelled = self.cancelled.clone();
+        let subscribers = self.progress.clone();
+        let install_id = request.install_id.clone();
+        let name = request.name.clone();
+
+        tracing::info!("Copying `{}` to `{}`", src.display(), dst.display());
+        tokio::task::spawn_blocking(move || {
+            let mut progress = |done, total| {
+                subscribers.send(ProgressResponse {
+                    install_id: install_id.clone(),
+                    name: Some(name.clone()),
+                    message: "copying".to_owned(),
+                    bytes_done: Some(done),
+                    bytes_total: Some(total),
+                })
+            };
+            Copier::new(&cancelled, &mut progress).copy_artifact(&src, &dst)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+fn is_normal_relative_path(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[async_trait::async_trait]
+impl Installer for LocalDirInstaller {
+    type ProgressStream = ProgressStream;
+
+    async fn install(
+        &self,
+        request: Request<InstallInfoRequest>,
+    ) -> Result<Response<InstallResponse>, Status> {
+        let request = request.into_inner();
+        tracing::info!(
+            "Starting install `{}` of {} files",
+            request.install_id,
+            request.files.len()
+        );
+        self.installs
+            .lock()
+            .unwrap()
+            .insert(request.install_id.clone());
+        Ok(Response::new(InstallResponse {
+            install_id: request.install_id,
+        }))
+    }
+
+    async fn file_ready(
+        &self,
+        request: Request<FileReadyRequest>,
+    ) -> Result<Response<FileResponse>, Status> {
+        let request = request.into_inner();
+        let error_detail = match self.install_file(&request).await {
+            Ok(()) => None,
+            Err(e) => {
+                let retryable = e.is_transient();
+                let message = format!("{:#}", anyhow::Error::from(e));
+                tracing::warn!("Failed to install `{}`: {}", request.name, message);
+                Some(ErrorDetail { message, retryable })
+            }
+        };
+        Ok(Response::new(FileResponse {
+            install_id: request.install_id,
+            name: request.name,
+            path: request.path,
+            error_detail,
+        }))
+    }
+
+    async fn progress(
+        &self,
+        _request: Request<ProgressRequest>,
+    ) -> Result<Response<Self::ProgressStream>, Status> {
+        let stream = UnboundedReceiverStream::new(self.progress.subscribe()).map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<CancelRequest>,
+    ) -> Result<Response<CancelResponse>, Status> {
+        tracing::info!("Cancelling installs: {}", request.into_inner().reason);
+        self.cancelled.store(true, Ordering::Relaxed);
+        Ok(Response::new(CancelResponse {}))
+    }
+
+    async fn shutdown_server(
+        &self,
+        _request: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        tracing::info!("Shutting down");
+        self.progress.close();
+        if let Some(shutdown) = self.shutdown.lock().unwrap().take() {
+            let _ignored = shutdown.send(());
+        }
+        Ok(Response::new(ShutdownResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_normal_relative_path() {
+        assert!(is_normal_relative_path(Path::new("app.apk")));
+        assert!(is_normal_relative_path(Path::new("lib/foo.so")));
+        assert!(!is_normal_relative_path(Path::new("")));
+        assert!(!is_normal_relative_path(Path::new("/etc/passwd")));
+        assert!(!is_normal_relative_path(Path::new("../foo")));
+        assert!(!is_normal_relative_path(Path::new("./foo")));
+    }
+}
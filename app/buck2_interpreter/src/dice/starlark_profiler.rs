@@ -11,6 +11,8 @@ use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::result::SharedResult;
 use dice::DiceComputations;
 use dice::DiceTransactionUpdater;
@@ -22,6 +24,18 @@ use starlark::eval::ProfileMode;
 use crate::starlark_profiler::StarlarkProfileModeOrInstrumentation;
 use crate::starlark_profiler::StarlarkProfilerInstrumentation;
 
+static STARLARK_INSTRUMENTATION_MODE: ConfigKey<ProfileMode> = ConfigKey::new(
+    "buck2",
+    "starlark_instrumentation_mode",
+    "Instrument Starlark for this profiling mode, so that it can be profiled without restarting the daemon.",
+);
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&STARLARK_INSTRUMENTATION_MODE),
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 enum StarlarkProfilerError {
     #[error("profiler is not configured to profile last element (internal error)")]
@@ -180,8 +194,8 @@ impl Key for StarlarkProfilerConfigurationKey {
             let instr = ctx
                 .parse_legacy_config_property::<ProfileMode>(
                     cell_resolver.root_cell(),
-                    "buck2",
-                    "starlark_instrumentation_mode",
+                    STARLARK_INSTRUMENTATION_MODE.section,
+                    STARLARK_INSTRUMENTATION_MODE.key,
                 )
                 .await?;
 
@@ -10,6 +10,7 @@
 use std::sync::Arc;
 
 use allocative::Allocative;
+use buck2_common::legacy_configs::schema::ConfigKeySchema;
 use buck2_core::bzl::ImportPath;
 use buck2_core::collections::ordered_map::OrderedMap;
 use derivative::Derivative;
@@ -68,6 +69,8 @@ struct LoadedModuleData {
     loaded_modules: LoadedModules,
     #[derivative(Debug = "ignore")]
     env: FrozenModule,
+    /// Buckconfig keys declared while evaluating this module, not including its loads.
+    declared_config_keys: Vec<ConfigKeySchema>,
 }
 
 impl LoadedModule {
@@ -75,11 +78,13 @@ impl LoadedModule {
         path: OwnedStarlarkModulePath,
         loaded_modules: LoadedModules,
         env: FrozenModule,
+        declared_config_keys: Vec<ConfigKeySchema>,
     ) -> Self {
         Self(Arc::new(LoadedModuleData {
             path,
             loaded_modules,
             env,
+            declared_config_keys,
         }))
     }
 
@@ -98,6 +103,10 @@ impl LoadedModule {
     pub fn env(&self) -> &FrozenModule {
         &self.0.env
     }
+
+    pub fn declared_config_keys(&self) -> &[ConfigKeySchema] {
+        &self.0.declared_config_keys
+    }
 }
 
 pub struct InterpreterFileLoader {
@@ -195,6 +204,7 @@ mod tests {
                 import_path.clone(),
                 LoadedModules::default(),
                 env(import_path.borrow()),
+                Vec::new(),
             );
             loaded_modules.map.insert(import_path, module);
         };
@@ -7,8 +7,10 @@
  * of this source tree.
  */
 
+use std::cell::RefCell;
 use std::fmt::Debug;
 
+use buck2_common::legacy_configs::schema::ConfigKeySchema;
 use buck2_common::legacy_configs::view::LegacyBuckConfigView;
 use buck2_core::package::PackageLabel;
 use buck2_interpreter::extra::buckconfig::LegacyBuckConfigForStarlark;
@@ -140,6 +142,9 @@ pub struct BuildContext<'a> {
     /// Context specific to type type.
     pub(crate) additional: PerFileTypeContext,
 
+    /// Buckconfig keys declared with `read_typed_config` while evaluating this module.
+    pub(crate) declared_config_keys: RefCell<Vec<ConfigKeySchema>>,
+
     /// When true, rule function is no-op.
     pub ignore_attrs_for_profiling: bool,
 }
@@ -165,6 +170,7 @@ impl<'a> BuildContext<'a> {
             starlark_path,
             host_info,
             additional,
+            declared_config_keys: RefCell::new(Vec::new()),
             ignore_attrs_for_profiling,
         }
     }
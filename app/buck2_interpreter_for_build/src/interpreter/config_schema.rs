@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The buckconfig schema of a cell, including the keys declared from Starlark.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_common::legacy_configs::schema::ConfigSchema;
+use buck2_common::result::SharedResult;
+use buck2_common::result::ToUnsharedResultExt;
+use buck2_core::cells::build_file_cell::BuildFileCell;
+use buck2_core::cells::name::CellName;
+use buck2_interpreter::import_paths::HasImportPaths;
+use buck2_interpreter::path::OwnedStarlarkModulePath;
+use derive_more::Display;
+use dice::DiceComputations;
+use dice::Key;
+use dupe::Dupe;
+
+use crate::interpreter::calculation::InterpreterCalculation;
+use crate::interpreter::global_interpreter_state::HasGlobalInterpreterState;
+
+#[async_trait]
+pub trait HasConfigSchema {
+    /// The schema of the buckconfig of `cell`: the keys declared by buck2 itself, and those
+    /// declared from Starlark by the prelude, the root import of the cell, and the files they
+    /// load. Those are evaluated if they haven't been already.
+    async fn get_config_schema(&self, cell: CellName) -> anyhow::Result<Arc<ConfigSchema>>;
+}
+
+#[async_trait]
+impl HasConfigSchema for DiceComputations {
+    async fn get_config_schema(&self, cell: CellName) -> anyhow::Result<Arc<ConfigSchema>> {
+        #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
+        #[display(fmt = "ConfigSchema({})", _0)]
+        struct ConfigSchemaKey(CellName);
+
+        #[async_trait]
+        impl Key for ConfigSchemaKey {
+            type Value = SharedResult<Arc<ConfigSchema>>;
+
+            async fn compute(&self, ctx: &DiceComputations) -> Self::Value {
+                let global_state = ctx.get_global_interpreter_state().await?;
+                let import_paths = ctx
+                    .import_paths_for_cell(BuildFileCell::new(self.0))
+                    .await?;
+
+                let mut modules = futures::future::try_join_all(
+                    global_state
+                        .configuror()
+                        .prelude_import()
+                        .into_iter()
+                        .chain(import_paths.root_import())
+                        .map(|import| ctx.get_loaded_module_from_import_path(import)),
+                )
+                .await?;
+
+                let mut schema = ConfigSchema::builtin().clone();
+                let mut visited: HashSet<OwnedStarlarkModulePath> = HashSet::new();
+                while let Some(module) = modules.pop() {
+                    if !visited.insert(module.path().to_owned()) {
+                        continue;
+                    }
+                    for key in module.declared_config_keys() {
+                        schema.declare_starlark(key.clone());
+                    }
+                    modules.extend(module.loaded_modules().map.values().cloned());
+                }
+                Ok(Arc::new(schema))
+            }
+
+            fn equality(_: &Self::Value, _: &Self::Value) -> bool {
+                false
+            }
+
+            fn validity(x: &Self::Value) -> bool {
+                x.is_ok()
+            }
+        }
+
+        self.compute(&ConfigSchemaKey(cell)).await?.unshared_error()
+    }
+}
@@ -260,7 +260,7 @@ impl<'c> DiceCalculationDelegate<'c> {
         let buckconfig = self.get_legacy_buck_config_for_starlark().await?;
         let root_buckconfig = self.ctx.get_legacy_root_config_on_dice().await?;
 
-        let evaluation = self
+        let (evaluation, declared_config_keys) = self
             .configs
             .eval_module(
                 starlark_file,
@@ -278,6 +278,7 @@ impl<'c> DiceCalculationDelegate<'c> {
             OwnedStarlarkModulePath::new(starlark_file),
             loaded_modules,
             evaluation,
+            declared_config_keys,
         ))
     }
 
@@ -7,16 +7,95 @@
  * of this source tree.
  */
 
+use buck2_common::legacy_configs::schema::ConfigKeySchema;
+use buck2_common::legacy_configs::schema::ConfigValueType;
+use buck2_common::legacy_configs::LegacyBuckConfig;
+use buck2_interpreter::path::StarlarkPath;
 use starlark::environment::GlobalsBuilder;
 use starlark::eval::Evaluator;
 use starlark::starlark_module;
+use starlark::values::float::StarlarkFloat;
+use starlark::values::list::AllocList;
+use starlark::values::list::ListRef;
 use starlark::values::none::NoneOr;
+use starlark::values::Heap;
 use starlark::values::StringValue;
 use starlark::values::StringValueLike;
+use starlark::values::UnpackValue;
 use starlark::values::Value;
+use starlark::values::ValueLike;
+use thiserror::Error;
 
 use crate::interpreter::build_context::BuildContext;
 
+#[derive(Debug, Error)]
+enum ReadTypedConfigError {
+    #[error("Default of buckconfig `{section}.{key}` must be a `{ty}`, got `{default}`")]
+    DefaultType {
+        section: String,
+        key: String,
+        ty: &'static str,
+        default: String,
+    },
+}
+
+/// The buckconfig representation of `default`, which must be a value of type `ty`.
+fn typed_config_default(
+    section: &str,
+    key: &str,
+    ty: ConfigValueType,
+    default: Value,
+) -> anyhow::Result<String> {
+    let value = match ty {
+        ConfigValueType::String => default.unpack_str().map(ToOwned::to_owned),
+        ConfigValueType::Bool => default.unpack_bool().map(|b| b.to_string()),
+        ConfigValueType::Int => i64::unpack_value(default).map(|i| i.to_string()),
+        ConfigValueType::Float => default
+            .downcast_ref::<StarlarkFloat>()
+            .map(|f| f.0.to_string())
+            .or_else(|| i64::unpack_value(default).map(|i| i.to_string())),
+        ConfigValueType::List => ListRef::from_value(default).and_then(|list| {
+            list.iter()
+                .map(|v| v.unpack_str())
+                .collect::<Option<Vec<_>>>()
+                .map(|v| v.join(","))
+        }),
+    };
+    value.ok_or_else(|| {
+        ReadTypedConfigError::DefaultType {
+            section: section.to_owned(),
+            key: key.to_owned(),
+            ty: ty.name(),
+            default: default.to_repr(),
+        }
+        .into()
+    })
+}
+
+fn parse_typed_config<'v>(
+    section: &str,
+    key: &str,
+    ty: ConfigValueType,
+    value: &str,
+    heap: &'v Heap,
+) -> anyhow::Result<Value<'v>> {
+    Ok(match ty {
+        ConfigValueType::String => heap.alloc(value),
+        ConfigValueType::Bool => {
+            Value::new_bool(LegacyBuckConfig::parse_impl::<bool>(section, key, value)?)
+        }
+        ConfigValueType::Int => {
+            heap.alloc(LegacyBuckConfig::parse_impl::<i64>(section, key, value)?)
+        }
+        ConfigValueType::Float => {
+            heap.alloc(LegacyBuckConfig::parse_impl::<f64>(section, key, value)?)
+        }
+        ConfigValueType::List => heap.alloc(AllocList(
+            value.split(',').map(str::trim).filter(|v| !v.is_empty()),
+        )),
+    })
+}
+
 #[starlark_module]
 pub fn register_read_config(globals: &mut GlobalsBuilder) {
     #[starlark(speculative_exec_safe)]
@@ -47,4 +126,43 @@ pub fn register_read_config(globals: &mut GlobalsBuilder) {
             None => Ok(default),
         }
     }
+
+    /// Read a buckconfig value and parse it as `type`, one of `"string"`, `"bool"`, `"int"`,
+    /// `"float"` or `"list"` (comma-separated). `default` is returned when the key is not set,
+    /// and must be a value of that type (a list of strings for `"list"`).
+    ///
+    /// When called while a `.bzl` file is loaded, rather than from a macro called by a build
+    /// file, this also declares the key, so that `buck2 audit config --schema` lists it along
+    /// with `doc` for the cells whose build files load that file implicitly.
+    fn read_typed_config<'v>(
+        #[starlark(require = pos)] section: StringValue,
+        #[starlark(require = pos)] key: StringValue,
+        #[starlark(require = named, default = "string")] r#type: &str,
+        #[starlark(require = named, default = NoneOr::None)] default: NoneOr<Value<'v>>,
+        #[starlark(require = named, default = "")] doc: &str,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let ty = r#type.parse::<ConfigValueType>()?;
+        let default = default
+            .into_option()
+            .map(|d| typed_config_default(section.as_str(), key.as_str(), ty, d))
+            .transpose()?;
+        let schema =
+            ConfigKeySchema::starlark(section.as_str(), key.as_str(), ty, default.clone(), doc)?;
+
+        let build_context = BuildContext::from_context(eval)?;
+        // Only the evaluation of `.bzl` files is recorded in modules, which is where
+        // declarations are collected from.
+        if let StarlarkPath::LoadFile(_) = build_context.starlark_path {
+            build_context.declared_config_keys.borrow_mut().push(schema);
+        }
+        let value = match build_context.buckconfig.get(section, key)? {
+            Some(v) => v.as_str().to_owned(),
+            None => match default {
+                Some(d) => d,
+                None => return Ok(Value::new_none()),
+            },
+        };
+        parse_typed_config(section.as_str(), key.as_str(), ty, &value, eval.heap())
+    }
 }
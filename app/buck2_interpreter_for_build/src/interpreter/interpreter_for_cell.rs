@@ -15,6 +15,7 @@ use std::sync::Arc;
 
 use allocative::Allocative;
 use anyhow::Context;
+use buck2_common::legacy_configs::schema::ConfigKeySchema;
 use buck2_common::legacy_configs::view::LegacyBuckConfigView;
 use buck2_common::package_listing::listing::PackageListing;
 use buck2_core::build_file_path::BuildFilePath;
@@ -452,7 +453,7 @@ impl InterpreterForCell {
         loaded_modules: LoadedModules,
         extra_context: PerFileTypeContext,
         profiler: &mut StarlarkProfilerOrInstrumentation,
-    ) -> anyhow::Result<PerFileTypeContext> {
+    ) -> anyhow::Result<(PerFileTypeContext, Vec<ConfigKeySchema>)> {
         let globals = self.global_state.globals_for_file_type(import.file_type());
         let file_loader =
             InterpreterFileLoader::new(loaded_modules, Arc::new(self.load_resolver(import)));
@@ -489,12 +490,12 @@ impl InterpreterForCell {
                 .context("Profiler heap visitation failed")?;
         }
 
-        Ok(extra.additional)
+        Ok((extra.additional, extra.declared_config_keys.into_inner()))
     }
 
     /// Evaluates the AST for a parsed module. Loaded modules must contain the loaded
     /// environment for all (transitive) required imports.
-    /// Returns the FrozenModule for the module.
+    /// Returns the FrozenModule for the module, and the buckconfig keys it declared.
     pub(crate) fn eval_module(
         self: &Arc<Self>,
         starlark_path: StarlarkModulePath<'_>,
@@ -503,9 +504,9 @@ impl InterpreterForCell {
         ast: AstModule,
         loaded_modules: LoadedModules,
         starlark_profiler_instrumentation: Option<StarlarkProfilerInstrumentation>,
-    ) -> anyhow::Result<FrozenModule> {
+    ) -> anyhow::Result<(FrozenModule, Vec<ConfigKeySchema>)> {
         let env = self.create_env(starlark_path.into(), &loaded_modules)?;
-        self.eval(
+        let (_, declared_config_keys) = self.eval(
             &env,
             ast,
             StarlarkPath::from(starlark_path),
@@ -517,7 +518,7 @@ impl InterpreterForCell {
                 starlark_profiler_instrumentation,
             ),
         )?;
-        env.freeze()
+        Ok((env.freeze()?, declared_config_keys))
     }
 
     pub(crate) fn eval_package_file(
@@ -538,9 +539,9 @@ impl InterpreterForCell {
         let package_values = env.heap().alloc_complex_no_freeze(PackageValues::default());
         env.set_extra_value(package_values);
 
-        let extra_context = PerFileTypeContext::Package(PackageFileEvalCtx { parent });
+        let extra_context = PerFileTypeContext::Package(PackageFileEvalCtx::new(parent));
 
-        let per_file_context = self.eval(
+        let (per_file_context, _) = self.eval(
             &env,
             ast,
             StarlarkPath::PackageFile(package_file_path),
@@ -623,18 +624,17 @@ impl InterpreterForCell {
             package_boundary_exception,
             &loaded_modules,
         )?;
-        let internals = self
-            .eval(
-                &env,
-                ast,
-                StarlarkPath::BuildFile(build_file),
-                buckconfig,
-                root_buckconfig,
-                loaded_modules,
-                PerFileTypeContext::Build(internals),
-                profiler,
-            )?
-            .into_build()?;
+        let (per_file_context, _) = self.eval(
+            &env,
+            ast,
+            StarlarkPath::BuildFile(build_file),
+            buckconfig,
+            root_buckconfig,
+            loaded_modules,
+            PerFileTypeContext::Build(internals),
+            profiler,
+        )?;
+        let internals = per_file_context.into_build()?;
 
         Ok(EvaluationResult::from(internals))
     }
@@ -10,6 +10,7 @@
 pub mod build_context;
 pub mod build_defs;
 pub mod calculation;
+pub mod config_schema;
 pub mod configuror;
 pub mod context;
 pub mod cycles;
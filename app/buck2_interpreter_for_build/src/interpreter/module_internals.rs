@@ -172,6 +172,8 @@ impl ModuleInternals {
                                 buildfile_path: self.buildfile_path.dupe(),
                                 oncall,
                                 default_visibility_to_public: self.default_visibility_to_public,
+                                visibility: self.super_package.visibility().clone(),
+                                within_view: self.super_package.within_view().clone(),
                             }),
                             recorder: TargetsRecorder::new(),
                         });
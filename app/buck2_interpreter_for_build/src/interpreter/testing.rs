@@ -253,7 +253,7 @@ impl Tester {
             .get(self.cell_alias_resolver.resolve_self())
             .unwrap();
         let root_buckconfig = self.configs.get(self.cell_resolver.root_cell()).unwrap();
-        let env = interpreter.eval_module(
+        let (env, declared_config_keys) = interpreter.eval_module(
             StarlarkModulePath::LoadFile(path),
             buckconfig,
             root_buckconfig,
@@ -265,6 +265,7 @@ impl Tester {
             OwnedStarlarkModulePath::LoadFile(path.clone()),
             loaded_modules,
             env,
+            declared_config_keys,
         ))
     }
 
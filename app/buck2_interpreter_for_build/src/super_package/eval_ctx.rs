@@ -7,25 +7,139 @@
  * of this source tree.
  */
 
+use std::cell::RefCell;
+
+use buck2_node::visibility::VisibilitySpecification;
+use buck2_node::visibility::WithinViewSpecification;
 use starlark::values::OwnedFrozenValue;
 use starlark_map::small_map::SmallMap;
 
 use crate::super_package::data::SuperPackage;
 
+/// Arguments of the `package()` call in a `PACKAGE` file.
+#[derive(Debug)]
+pub(crate) struct PackageFileVisibilityFields {
+    pub(crate) visibility: VisibilitySpecification,
+    /// `None` if `within_view` was not passed.
+    pub(crate) within_view: Option<WithinViewSpecification>,
+    /// Extend the parent `PACKAGE` file's visibility and `within_view` rather than replace them.
+    pub(crate) inherit: bool,
+}
+
 #[derive(Debug)]
 pub(crate) struct PackageFileEvalCtx {
     /// Parent file context.
     /// When evaluating root `PACKAGE` file, parent is still defined.
     pub(crate) parent: SuperPackage,
+    /// Set by `package()`, which may be called at most once.
+    pub(crate) visibility: RefCell<Option<PackageFileVisibilityFields>>,
 }
 
 impl PackageFileEvalCtx {
+    pub(crate) fn new(parent: SuperPackage) -> PackageFileEvalCtx {
+        PackageFileEvalCtx {
+            parent,
+            visibility: RefCell::new(None),
+        }
+    }
+
     pub(crate) fn build_super_package(
         self,
         package_values: SmallMap<String, OwnedFrozenValue>,
     ) -> SuperPackage {
         let mut merged_package_values = self.parent.package_values().clone();
         merged_package_values.extend(package_values);
-        SuperPackage::new(merged_package_values)
+
+        let (visibility, within_view) = match self.visibility.into_inner() {
+            None => (
+                self.parent.visibility().clone(),
+                self.parent.within_view().clone(),
+            ),
+            Some(PackageFileVisibilityFields {
+                visibility,
+                within_view,
+                inherit: true,
+            }) => (
+                self.parent.visibility().extend_with(&visibility),
+                match within_view {
+                    Some(within_view) => self.parent.within_view().extend_with(&within_view),
+                    None => self.parent.within_view().clone(),
+                },
+            ),
+            Some(PackageFileVisibilityFields {
+                visibility,
+                within_view,
+                inherit: false,
+            }) => (visibility, within_view.unwrap_or_default()),
+        };
+
+        SuperPackage::new(merged_package_values, visibility, within_view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::package::PackageLabel;
+    use buck2_core::pattern::ParsedPattern;
+    use buck2_node::visibility::VisibilityPattern;
+    use dupe::Dupe;
+
+    use super::*;
+
+    fn visible_to(package: &str) -> Box<Box<[VisibilityPattern]>> {
+        Box::new(
+            vec![VisibilityPattern(ParsedPattern::Package(
+                PackageLabel::testing_parse(package),
+            ))]
+            .into_boxed_slice(),
+        )
+    }
+
+    fn child(parent: &SuperPackage, fields: Option<PackageFileVisibilityFields>) -> SuperPackage {
+        let ctx = PackageFileEvalCtx::new(parent.dupe());
+        *ctx.visibility.borrow_mut() = fields;
+        ctx.build_super_package(SmallMap::new())
+    }
+
+    #[test]
+    fn test_build_super_package_visibility() {
+        let parent = child(
+            &SuperPackage::default(),
+            Some(PackageFileVisibilityFields {
+                visibility: VisibilitySpecification::VisibleTo(visible_to("root//a")),
+                within_view: Some(WithinViewSpecification::VisibleTo(visible_to("root//b"))),
+                inherit: false,
+            }),
+        );
+
+        // No `package()` call: everything is inherited.
+        assert!(child(&parent, None) == parent);
+
+        let inherited = child(
+            &parent,
+            Some(PackageFileVisibilityFields {
+                visibility: VisibilitySpecification::VisibleTo(visible_to("root//c")),
+                within_view: None,
+                inherit: true,
+            }),
+        );
+        assert_eq!(
+            inherited.visibility(),
+            &parent
+                .visibility()
+                .extend_with(&VisibilitySpecification::VisibleTo(visible_to("root//c")))
+        );
+        assert_eq!(inherited.within_view(), parent.within_view());
+
+        let replaced = child(
+            &parent,
+            Some(PackageFileVisibilityFields {
+                visibility: VisibilitySpecification::Public,
+                within_view: None,
+                inherit: false,
+            }),
+        );
+        assert_eq!(replaced.visibility(), &VisibilitySpecification::Public);
+        assert_eq!(replaced.within_view(), &WithinViewSpecification::Public);
     }
 }
@@ -7,13 +7,20 @@
  * of this source tree.
  */
 
+use buck2_core::cells::cell_path::CellPathRef;
+use buck2_core::cells::CellAliasResolver;
+use buck2_core::pattern::ParsedPattern;
 use buck2_interpreter::path::StarlarkPath;
+use buck2_node::visibility::VisibilityPattern;
+use buck2_node::visibility::VisibilitySpecification;
+use buck2_node::visibility::WithinViewSpecification;
 use starlark::environment::GlobalsBuilder;
 use starlark::eval::Evaluator;
 use starlark::starlark_module;
 use starlark::values::none::NoneType;
 
 use crate::interpreter::build_context::BuildContext;
+use crate::super_package::eval_ctx::PackageFileVisibilityFields;
 
 #[derive(Debug, thiserror::Error)]
 enum PackageFileError {
@@ -22,24 +29,87 @@ enum PackageFileError {
         or in `bzl` files included from `PACKAGE` files"
     )]
     NotPackage,
+    #[error("`package()` can only be called once per `PACKAGE` file")]
+    CalledTwice,
+}
+
+/// Patterns of a `visibility` or `within_view` list.
+enum ParsedVisibility {
+    /// The list was empty.
+    Unset,
+    Public,
+    VisibleTo(Box<Box<[VisibilityPattern]>>),
+}
+
+/// Parse patterns relative to the directory of the `PACKAGE` file.
+fn parse_visibility(
+    cell_alias_resolver: &CellAliasResolver,
+    dir: CellPathRef,
+    values: &[String],
+) -> anyhow::Result<ParsedVisibility> {
+    let mut patterns = Vec::with_capacity(values.len());
+    for value in values {
+        if value == "PUBLIC" {
+            return Ok(ParsedVisibility::Public);
+        }
+        patterns.push(VisibilityPattern(ParsedPattern::parsed_opt_absolute(
+            cell_alias_resolver,
+            Some(dir),
+            value,
+        )?));
+    }
+    if patterns.is_empty() {
+        Ok(ParsedVisibility::Unset)
+    } else {
+        Ok(ParsedVisibility::VisibleTo(Box::new(
+            patterns.into_boxed_slice(),
+        )))
+    }
 }
 
 /// Globals for `PACKAGE` files and `bzl` files included from `PACKAGE` files.
 #[starlark_module]
 pub(crate) fn register_package_function(globals: &mut GlobalsBuilder) {
+    /// Set the default `visibility` of the targets in this directory and below, and restrict
+    /// what they may depend on with `within_view`. With `inherit = True`, both extend those of
+    /// the parent `PACKAGE` file instead of replacing them.
     fn package(
         #[starlark(require=named, default=false)] inherit: bool,
         #[starlark(require=named, default=Vec::new())] visibility: Vec<String>,
         #[starlark(require=named, default=Vec::new())] within_view: Vec<String>,
         eval: &mut Evaluator,
     ) -> anyhow::Result<NoneType> {
-        let _ignore = (inherit, visibility, within_view);
         let build_context = BuildContext::from_context(eval)?;
-        match build_context.starlark_path {
-            StarlarkPath::PackageFile(_) => {}
+        let package_file_path = match build_context.starlark_path {
+            StarlarkPath::PackageFile(path) => path,
             _ => return Err(PackageFileError::NotPackage.into()),
         };
-        // TODO(nga): implement visibility.
+        let package_ctx = build_context.additional.require_package_file("package")?;
+
+        let cell_alias_resolver = build_context.cell_info().cell_alias_resolver();
+        let dir = package_file_path.dir();
+        let visibility = match parse_visibility(cell_alias_resolver, dir, &visibility)? {
+            ParsedVisibility::Unset => VisibilitySpecification::Default,
+            ParsedVisibility::Public => VisibilitySpecification::Public,
+            ParsedVisibility::VisibleTo(patterns) => VisibilitySpecification::VisibleTo(patterns),
+        };
+        let within_view = match parse_visibility(cell_alias_resolver, dir, &within_view)? {
+            ParsedVisibility::Unset => None,
+            ParsedVisibility::Public => Some(WithinViewSpecification::Public),
+            ParsedVisibility::VisibleTo(patterns) => {
+                Some(WithinViewSpecification::VisibleTo(patterns))
+            }
+        };
+
+        let mut fields = package_ctx.visibility.borrow_mut();
+        if fields.is_some() {
+            return Err(PackageFileError::CalledTwice.into());
+        }
+        *fields = Some(PackageFileVisibilityFields {
+            visibility,
+            within_view,
+            inherit,
+        });
         Ok(NoneType)
     }
 }
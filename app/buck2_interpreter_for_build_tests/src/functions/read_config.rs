@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use buck2_core::bzl::ImportPath;
 use buck2_interpreter_for_build::interpreter::functions::read_config::register_read_config;
 use buck2_interpreter_for_build::interpreter::testing::Tester;
 use indoc::indoc;
@@ -33,3 +34,89 @@ fn test_read_config() -> anyhow::Result<()> {
     ))?;
     Ok(())
 }
+
+#[test]
+fn test_read_typed_config() -> anyhow::Result<()> {
+    let mut tester = Tester::new().unwrap();
+    tester.additional_globals(register_read_config);
+    tester.run_starlark_test(indoc!(
+        r#"
+            def test():
+                assert_eq("value", read_typed_config("section", "key"))
+                assert_eq(["value"], read_typed_config("section", "key", type = "list"))
+                assert_eq(1, read_typed_config("section", "other", type = "int"))
+                assert_eq(1.0, read_typed_config("section", "other", type = "float"))
+
+                assert_eq(None, read_typed_config("section", "missing_key", type = "bool"))
+                assert_eq(
+                    True,
+                    read_typed_config("section", "missing_key", type = "bool", default = True),
+                )
+                assert_eq(
+                    2.0,
+                    read_typed_config("section", "missing_key", type = "float", default = 2),
+                )
+                assert_eq(
+                    ["a", "b"],
+                    read_typed_config("section", "missing_key", type = "list", default = ["a", "b"]),
+                )
+            "#
+    ))?;
+
+    let mut tester = Tester::new().unwrap();
+    tester.additional_globals(register_read_config);
+    tester.run_starlark_test_expecting_error(
+        indoc!(
+            r#"
+            def test():
+                read_typed_config("section", "key", type = "int")
+            "#
+        ),
+        "Invalid value for buckconfig `section.key`",
+    );
+
+    let mut tester = Tester::new().unwrap();
+    tester.additional_globals(register_read_config);
+    tester.run_starlark_test_expecting_error(
+        indoc!(
+            r#"
+            def test():
+                read_typed_config("section", "missing_key", type = "int", default = "1")
+            "#
+        ),
+        "Default of buckconfig `section.missing_key` must be a `int`",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_read_typed_config_declares_keys_of_bzl_files() -> anyhow::Result<()> {
+    let mut tester = Tester::new().unwrap();
+    tester.additional_globals(register_read_config);
+    let module = tester.add_import(
+        &ImportPath::testing_new("root//some/package:defs.bzl"),
+        indoc!(
+            r#"
+            _key = read_typed_config("section", "key", doc = "A key.")
+            _other = read_typed_config("section", "missing_key", type = "int", default = 3)
+            "#
+        ),
+    )?;
+    assert_eq!(
+        vec![
+            ("key", "string", None, "A key."),
+            ("missing_key", "int", Some("3"), ""),
+        ],
+        module
+            .declared_config_keys()
+            .iter()
+            .map(|k| (
+                k.key.as_str(),
+                k.ty.as_str(),
+                k.default.as_deref(),
+                k.doc.as_str()
+            ))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
@@ -105,6 +105,16 @@ impl CoercedSelector {
         Ok(())
     }
 
+    /// The keyed branches of the `select()`, in declaration order, excluding `DEFAULT`.
+    pub fn entries(&self) -> &[(TargetLabel, CoercedAttr)] {
+        &self.entries
+    }
+
+    /// The `DEFAULT` branch of the `select()`, if any.
+    pub fn default(&self) -> Option<&CoercedAttr> {
+        self.default.as_ref()
+    }
+
     fn all_entries(&self) -> impl Iterator<Item = (CoercedSelectorKeyRef, &CoercedAttr)> {
         self.entries
             .iter()
@@ -109,6 +109,7 @@ impl ExecutionPlatform {
 pub enum ExecutionPlatformIncompatibleReason {
     ConstraintNotSatisfied(TargetLabel),
     ExecutionDependencyIncompatible(Arc<IncompatiblePlatformReason>),
+    ToolchainDependencyIncompatible(Arc<IncompatiblePlatformReason>),
 }
 
 impl ExecutionPlatformIncompatibleReason {
@@ -121,12 +122,42 @@ impl ExecutionPlatformIncompatibleReason {
                 target,
                 cause: IncompatiblePlatformReasonCause::UnsatisfiedConfig(unsatisfied_config),
             },
-            Self::ExecutionDependencyIncompatible(previous) => IncompatiblePlatformReason {
+            Self::ExecutionDependencyIncompatible(previous)
+            | Self::ToolchainDependencyIncompatible(previous) => IncompatiblePlatformReason {
                 target,
                 cause: IncompatiblePlatformReasonCause::Dependency(previous),
             },
         }
     }
+
+    /// The attribute of the target which ruled out the platform.
+    pub fn attribute(&self) -> &'static str {
+        match self {
+            Self::ConstraintNotSatisfied(_) => "exec_compatible_with",
+            Self::ExecutionDependencyIncompatible(_) => "exec_deps",
+            Self::ToolchainDependencyIncompatible(_) => "toolchain_deps",
+        }
+    }
+
+    /// Structured version of this reason, for the execution platform `platform`.
+    pub fn rejection(&self, platform: &str) -> ExecutionPlatformRejection {
+        let (dependency, constraint, required_by) = match self {
+            Self::ConstraintNotSatisfied(constraint) => (None, constraint, None),
+            Self::ExecutionDependencyIncompatible(reason)
+            | Self::ToolchainDependencyIncompatible(reason) => {
+                let (required_by, constraint) = reason.root_cause();
+                (Some(&reason.target), constraint, Some(required_by))
+            }
+        };
+        ExecutionPlatformRejection {
+            platform: platform.to_owned(),
+            attribute: self.attribute(),
+            dependency: dependency.map(|d| d.unconfigured().to_string()),
+            constraint: constraint.to_string(),
+            constraint_required_by: required_by.map(|t| t.to_string()),
+            message: format!("{:#}", self),
+        }
+    }
 }
 
 impl std::fmt::Display for ExecutionPlatformIncompatibleReason {
@@ -137,15 +168,47 @@ impl std::fmt::Display for ExecutionPlatformIncompatibleReason {
                 "exec_compatible_with requires `{}` but it was not satisfied",
                 v
             ),
-            ExecutionPlatformIncompatibleReason::ExecutionDependencyIncompatible(v) => v.fmt(f),
+            ExecutionPlatformIncompatibleReason::ExecutionDependencyIncompatible(v) => {
+                write!(
+                    f,
+                    "exec_dep `{}` is incompatible: ",
+                    v.target.unconfigured()
+                )?;
+                v.fmt(f)
+            }
+            ExecutionPlatformIncompatibleReason::ToolchainDependencyIncompatible(v) => {
+                write!(
+                    f,
+                    "toolchain_dep `{}` is incompatible: ",
+                    v.target.unconfigured()
+                )?;
+                v.fmt(f)
+            }
         }
     }
 }
 
+/// Why an execution platform was ruled out for a target, pointing to the attribute and the
+/// constraint responsible.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ExecutionPlatformRejection {
+    pub platform: String,
+    /// `exec_compatible_with`, `exec_deps` or `toolchain_deps`.
+    pub attribute: &'static str,
+    /// The exec dep or toolchain dep that is incompatible with the platform.
+    pub dependency: Option<String>,
+    /// The constraint the platform doesn't satisfy.
+    pub constraint: String,
+    /// The (configured) target requiring the constraint, if not the target being resolved.
+    pub constraint_required_by: Option<String>,
+    /// Human readable explanation.
+    pub message: String,
+}
+
 #[derive(Debug, Error)]
 pub enum ExecutionPlatformError {
     // .indented() losing the alternate flag that we want to use to format the reason so we need to explicitly do that.
-    #[error("No compatible execution platform.\n{}", .0.iter().map(|(id, reason)| format!("  `{}` skipped because:\n{}", id, format!("{:#}", reason).indented("    "))).join("\n"))]
+    #[error("No compatible execution platform.\n{}\nRun `buck2 audit execution-platform-resolution` on the target for details.", .0.iter().map(|(id, reason)| format!("  `{}` skipped because:\n{}", id, format!("{:#}", reason).indented("    "))).join("\n"))]
     NoCompatiblePlatform(Arc<Vec<(String, ExecutionPlatformIncompatibleReason)>>),
 }
 
@@ -205,3 +268,57 @@ impl ExecutionPlatformResolution {
         Ok(self.platform()?.executor_config())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::target::label::TargetLabel;
+    use buck2_query::query::compatibility::IncompatiblePlatformReason;
+    use buck2_query::query::compatibility::IncompatiblePlatformReasonCause;
+
+    use crate::configuration::execution::ExecutionPlatformIncompatibleReason;
+
+    #[test]
+    fn test_rejection() {
+        let constraint = TargetLabel::testing_parse("cell//constraints:linux");
+        let rejection =
+            ExecutionPlatformIncompatibleReason::ConstraintNotSatisfied(constraint.clone())
+                .rejection("cell//platforms:mac");
+        assert_eq!("exec_compatible_with", rejection.attribute);
+        assert_eq!(None, rejection.dependency);
+        assert_eq!("cell//constraints:linux", rejection.constraint);
+        assert_eq!(None, rejection.constraint_required_by);
+
+        let tool = TargetLabel::testing_parse("cell//tools:compiler")
+            .configure(ConfigurationData::testing_new());
+        let toolchain = TargetLabel::testing_parse("cell//toolchains:cxx")
+            .configure(ConfigurationData::testing_new());
+        let reason = ExecutionPlatformIncompatibleReason::ToolchainDependencyIncompatible(
+            Arc::new(IncompatiblePlatformReason {
+                target: toolchain,
+                cause: IncompatiblePlatformReasonCause::Dependency(Arc::new(
+                    IncompatiblePlatformReason {
+                        target: tool.clone(),
+                        cause: IncompatiblePlatformReasonCause::UnsatisfiedConfig(constraint),
+                    },
+                )),
+            }),
+        );
+        let rejection = reason.rejection("cell//platforms:mac");
+        assert_eq!("cell//platforms:mac", rejection.platform);
+        assert_eq!("toolchain_deps", rejection.attribute);
+        assert_eq!(
+            Some("cell//toolchains:cxx".to_owned()),
+            rejection.dependency
+        );
+        assert_eq!("cell//constraints:linux", rejection.constraint);
+        assert_eq!(Some(tool.to_string()), rejection.constraint_required_by);
+        assert!(
+            rejection
+                .message
+                .starts_with("toolchain_dep `cell//toolchains:cxx` is incompatible: ")
+        );
+    }
+}
@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::collections::BTreeSet;
+use std::fmt::Write;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::sync::Arc;
@@ -20,7 +22,7 @@ use buck2_core::target::label::TargetLabel;
 use dupe::Dupe;
 use starlark_map::Equivalent;
 
-#[derive(Debug, Eq, Allocative)]
+#[derive(Debug, Eq, PartialOrd, Ord, Allocative)]
 pub struct ConfigurationSettingKey(pub TargetLabel);
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -91,6 +93,44 @@ impl ResolvedConfiguration {
     pub fn matches(&self, label: &TargetLabel) -> Option<&ConfigSettingData> {
         self.setting_matches(ConfigurationSettingKeyRef(label))
     }
+
+    /// The `section.key` and value of each buckconfig required by the config settings matching
+    /// this configuration, along with the setting which requires it.
+    pub fn matched_buckconfigs(&self) -> BTreeSet<(&str, &str, &TargetLabel)> {
+        self.0
+            .settings
+            .entries_sorted()
+            .into_iter()
+            .filter(|(_, node)| node.matches())
+            .flat_map(|(_, node)| {
+                node.configuration_data()
+                    .buckconfigs
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str(), node.label()))
+            })
+            .collect()
+    }
+}
+
+/// If the config settings matching two configurations of a target require different buckconfig
+/// values, return the difference, formatted like `cfg_diff`.
+///
+/// Buckconfigs are the same in every configuration, so this only finds differences when the
+/// constraints differ too, but it shows which buckconfig-dependent `select()` keys changed.
+pub fn buckconfig_diff(a: &ResolvedConfiguration, b: &ResolvedConfiguration) -> Result<(), String> {
+    let a = a.matched_buckconfigs();
+    let b = b.matched_buckconfigs();
+    if a == b {
+        return Ok(());
+    }
+
+    let mut diff = String::new();
+    for (sign, x, y) in [('-', &a, &b), ('+', &b, &a)] {
+        for (key, value, setting) in x.difference(y) {
+            writeln!(diff, "{} buckconfig: {}={} ({})", sign, key, value, setting).unwrap();
+        }
+    }
+    Err(diff)
 }
 
 /// A ConfigurationNode contains the information about a config_setting() or similar target in a certain configuration.
@@ -142,3 +182,64 @@ impl ConfigurationNode {
         &self.0.config_setting
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use buck2_core::collections::unordered_map::UnorderedMap;
+    use buck2_core::configuration::config_setting::ConfigSettingData;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::configuration::pair::ConfigurationNoExec;
+    use buck2_core::target::label::TargetLabel;
+    use dupe::Dupe;
+
+    use crate::configuration::resolved::buckconfig_diff;
+    use crate::configuration::resolved::ConfigurationNode;
+    use crate::configuration::resolved::ConfigurationSettingKey;
+    use crate::configuration::resolved::ResolvedConfiguration;
+
+    fn resolved(settings: &[(&str, &str, bool)]) -> ResolvedConfiguration {
+        ResolvedConfiguration::new(
+            ConfigurationNoExec::new(ConfigurationData::testing_new()),
+            settings
+                .iter()
+                .map(|(label, buckconfig, matches)| {
+                    let label = TargetLabel::testing_parse(label);
+                    let (key, value) = buckconfig.split_once('=').unwrap();
+                    (
+                        ConfigurationSettingKey(label.dupe()),
+                        ConfigurationNode::new(
+                            ConfigurationData::testing_new(),
+                            label,
+                            ConfigSettingData {
+                                constraints: BTreeMap::new(),
+                                buckconfigs: BTreeMap::from([(key.to_owned(), value.to_owned())]),
+                            },
+                            *matches,
+                        ),
+                    )
+                })
+                .collect::<UnorderedMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn test_buckconfig_diff() {
+        let a = resolved(&[
+            ("root//c:opt", "build.mode=opt", true),
+            ("root//c:asan", "build.sanitizer=address", false),
+        ]);
+        let b = resolved(&[
+            ("root//c:opt", "build.mode=opt", false),
+            ("root//c:asan", "build.sanitizer=address", true),
+        ]);
+        assert_eq!(Ok(()), buckconfig_diff(&a, &a));
+        assert_eq!(
+            Err("- buckconfig: build.mode=opt (root//c:opt)\n\
+                 + buckconfig: build.sanitizer=address (root//c:asan)\n"
+                .to_owned()),
+            buckconfig_diff(&a, &b)
+        );
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Where the configuration of a configured target comes from: a dependency path from a root to
+//! the target, and the edge on that path which introduced the target's configuration.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use buck2_core::configuration::transition::applied::TransitionApplied;
+use buck2_core::configuration::transition::id::TransitionId;
+use buck2_core::target::label::ConfiguredTargetLabel;
+use dupe::Dupe;
+
+use crate::nodes::configured::ConfiguredTargetNode;
+
+/// Why a dependency is configured differently from the node depending on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigurationChange {
+    /// The dependent is the forward node of a rule with an incoming transition (`cfg` of the rule).
+    RuleTransition(Option<Arc<TransitionId>>),
+    /// A transition on the attribute holding the dependency, with the key if it is a split
+    /// transition.
+    AttrTransition {
+        transition: Arc<TransitionId>,
+        split: Option<String>,
+    },
+    /// An exec dep, configured for the execution platform.
+    ExecDep,
+    /// A toolchain dep.
+    ToolchainDep,
+    /// None of the above, e.g. a `configured_dep` attribute.
+    Other,
+}
+
+impl ConfigurationChange {
+    /// Why `dep`, a dependency of `node`, has its configuration.
+    pub fn between(node: &ConfiguredTargetNode, dep: &ConfiguredTargetNode) -> Self {
+        if node.forward_target().map(|t| t.label()) == Some(dep.label()) {
+            return ConfigurationChange::RuleTransition(dep.rule_transition().cloned());
+        }
+        if node.exec_deps().any(|d| d.label() == dep.label()) {
+            return ConfigurationChange::ExecDep;
+        }
+        if node.toolchain_deps().any(|d| d.label() == dep.label()) {
+            return ConfigurationChange::ToolchainDep;
+        }
+        for (transition, applied) in node.resolved_transition_configurations() {
+            let split = match &**applied {
+                TransitionApplied::Single(cfg) => (cfg == dep.label().cfg()).then_some(None),
+                TransitionApplied::Split(cfgs) => cfgs
+                    .iter()
+                    .find(|(_, cfg)| *cfg == dep.label().cfg())
+                    .map(|(key, _)| Some(key.clone())),
+            };
+            if let Some(split) = split {
+                return ConfigurationChange::AttrTransition {
+                    transition: transition.dupe(),
+                    split,
+                };
+            }
+        }
+        ConfigurationChange::Other
+    }
+}
+
+impl Display for ConfigurationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigurationChange::RuleTransition(Some(transition)) => {
+                write!(f, "incoming transition `{}` of the rule", transition)
+            }
+            ConfigurationChange::RuleTransition(None) => {
+                write!(f, "incoming transition of the rule")
+            }
+            ConfigurationChange::AttrTransition {
+                transition,
+                split: None,
+            } => write!(f, "attribute transition `{}`", transition),
+            ConfigurationChange::AttrTransition {
+                transition,
+                split: Some(split),
+            } => write!(
+                f,
+                "split `{}` of attribute transition `{}`",
+                split, transition
+            ),
+            ConfigurationChange::ExecDep => {
+                write!(f, "exec dep, configured for the execution platform")
+            }
+            ConfigurationChange::ToolchainDep => write!(f, "toolchain dep"),
+            ConfigurationChange::Other => write!(f, "dependency with an explicit configuration"),
+        }
+    }
+}
+
+/// A dependency edge which changed the configuration.
+#[derive(Debug)]
+pub struct ConfigurationEdge {
+    pub node: ConfiguredTargetNode,
+    pub dep: ConfiguredTargetNode,
+    pub change: ConfigurationChange,
+}
+
+#[derive(Debug)]
+pub struct ConfigurationOrigin {
+    /// A shortest dependency path from a root to the target, both included.
+    pub path: Vec<ConfiguredTargetNode>,
+    /// The last edge on the path changing the configuration. `None` if the target has the
+    /// configuration of the root.
+    pub edge: Option<ConfigurationEdge>,
+}
+
+/// Shortest dependency paths from a set of roots to all the nodes reachable from them.
+pub struct ConfigurationOrigins {
+    /// Node on a shortest path from the roots to each node, `None` for the roots.
+    parents: HashMap<ConfiguredTargetLabel, Option<ConfiguredTargetNode>>,
+}
+
+impl ConfigurationOrigins {
+    pub fn new(roots: impl IntoIterator<Item = ConfiguredTargetNode>) -> Self {
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in roots {
+            if let Entry::Vacant(e) = parents.entry(root.label().dupe()) {
+                e.insert(None);
+                queue.push_back(root);
+            }
+        }
+        while let Some(node) = queue.pop_front() {
+            for dep in node.deps() {
+                if let Entry::Vacant(e) = parents.entry(dep.label().dupe()) {
+                    e.insert(Some(node.dupe()));
+                    queue.push_back(dep.dupe());
+                }
+            }
+        }
+        Self { parents }
+    }
+
+    /// Where the configuration of `node` comes from, or `None` if it is not reachable from the
+    /// roots.
+    pub fn origin(&self, node: &ConfiguredTargetNode) -> Option<ConfigurationOrigin> {
+        let mut path = vec![node.dupe()];
+        let mut edge = None;
+        while let Some(parent) = self.parents.get(path.last().unwrap().label())? {
+            let dep = path.last().unwrap();
+            if edge.is_none() && parent.label().cfg() != dep.label().cfg() {
+                edge = Some(ConfigurationEdge {
+                    node: parent.dupe(),
+                    dep: dep.dupe(),
+                    change: ConfigurationChange::between(parent, dep),
+                });
+            }
+            path.push(parent.dupe());
+        }
+        path.reverse();
+        Some(ConfigurationOrigin { path, edge })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use buck2_core::bzl::ImportPath;
+    use buck2_core::collections::ordered_map::OrderedMap;
+    use buck2_core::collections::unordered_map::UnorderedMap;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::configuration::data::ConfigurationDataData;
+    use buck2_core::configuration::pair::ConfigurationNoExec;
+    use buck2_core::target::label::TargetLabel;
+
+    use super::*;
+    use crate::configuration::execution::ExecutionPlatformResolution;
+    use crate::configuration::resolved::ResolvedConfiguration;
+    use crate::nodes::unconfigured::testing::TargetNodeExt;
+    use crate::nodes::unconfigured::TargetNode;
+    use crate::rule_type::RuleType;
+    use crate::rule_type::StarlarkRuleType;
+
+    fn cfg(name: &str) -> ConfigurationData {
+        ConfigurationData::from_platform(
+            name.to_owned(),
+            ConfigurationDataData::new(BTreeMap::new()),
+        )
+        .unwrap()
+    }
+
+    fn node(
+        label: &str,
+        cfg: &ConfigurationData,
+        transitions: OrderedMap<Arc<TransitionId>, Arc<TransitionApplied>>,
+        deps: Vec<ConfiguredTargetNode>,
+        exec_deps: Vec<ConfiguredTargetNode>,
+    ) -> ConfiguredTargetNode {
+        let label = TargetLabel::testing_parse(label);
+        let rule_type = RuleType::Starlark(Arc::new(StarlarkRuleType {
+            import_path: ImportPath::testing_new("cell//pkg:rules.bzl"),
+            name: "rule".to_owned(),
+        }));
+        ConfiguredTargetNode::new(
+            label.configure(cfg.dupe()),
+            TargetNode::testing_new(label, rule_type, Vec::new()),
+            ResolvedConfiguration::new(ConfigurationNoExec::new(cfg.dupe()), UnorderedMap::new()),
+            transitions,
+            ExecutionPlatformResolution::new(None, Vec::new()),
+            deps,
+            exec_deps,
+            OrderedMap::new(),
+        )
+    }
+
+    #[test]
+    fn test_origin() {
+        let target = cfg("target");
+        let transitioned = cfg("transitioned");
+        let exec = cfg("exec");
+        let transition = Arc::new(TransitionId {
+            path: ImportPath::testing_new("cell//pkg:transitions.bzl"),
+            name: "tr".to_owned(),
+        });
+
+        let leaf = node(
+            "cell//pkg:leaf",
+            &transitioned,
+            OrderedMap::new(),
+            vec![],
+            vec![],
+        );
+        let lib = node(
+            "cell//pkg:lib",
+            &transitioned,
+            OrderedMap::new(),
+            vec![leaf.dupe()],
+            vec![],
+        );
+        let tool = node("cell//pkg:tool", &exec, OrderedMap::new(), vec![], vec![]);
+        let root = node(
+            "cell//pkg:root",
+            &target,
+            OrderedMap::from_iter([(
+                transition.dupe(),
+                Arc::new(TransitionApplied::Single(transitioned.dupe())),
+            )]),
+            vec![lib.dupe()],
+            vec![tool.dupe()],
+        );
+
+        let origins = ConfigurationOrigins::new([root.dupe()]);
+
+        let origin = origins.origin(&leaf).unwrap();
+        assert_eq!(vec![root.dupe(), lib.dupe(), leaf.dupe()], origin.path);
+        let edge = origin.edge.unwrap();
+        assert_eq!(root.label(), edge.node.label());
+        assert_eq!(lib.label(), edge.dep.label());
+        assert_eq!(
+            ConfigurationChange::AttrTransition {
+                transition,
+                split: None
+            },
+            edge.change
+        );
+
+        let edge = origins.origin(&tool).unwrap().edge.unwrap();
+        assert_eq!(ConfigurationChange::ExecDep, edge.change);
+
+        let origin = origins.origin(&root).unwrap();
+        assert_eq!(vec![root.dupe()], origin.path);
+        assert!(origin.edge.is_none());
+
+        let unreachable = node(
+            "cell//pkg:other",
+            &target,
+            OrderedMap::new(),
+            vec![],
+            vec![],
+        );
+        assert!(origins.origin(&unreachable).is_none());
+    }
+}
@@ -564,6 +564,26 @@ impl ConfiguredTargetNode {
             TargetNodeOrForward::Forward(_, n) => Some(n),
         }
     }
+
+    /// The incoming transition declared by the rule (its `cfg`), if any.
+    pub fn rule_transition(&self) -> Option<&Arc<TransitionId>> {
+        match &self.0.target_node {
+            TargetNodeOrForward::TargetNode(node) => node.0.rule.cfg.as_ref(),
+            TargetNodeOrForward::Forward(_, n) => n.rule_transition(),
+        }
+    }
+
+    /// The configuration of this node, along with the config settings its `select()`s resolved.
+    pub fn resolved_configuration(&self) -> &ResolvedConfiguration {
+        &self.0.resolved_configuration
+    }
+
+    /// The configurations the transitions on attributes of this node resolved to.
+    pub fn resolved_transition_configurations(
+        &self,
+    ) -> impl Iterator<Item = (&Arc<TransitionId>, &Arc<TransitionApplied>)> {
+        self.0.resolved_transition_configurations.iter()
+    }
 }
 
 /// The representation of the deps for a ConfiguredTargetNode. Provides the operations we require
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+pub mod configuration_origin;
 pub mod configured;
 pub mod configured_node_visit_all_deps;
 pub mod configured_ref;
@@ -43,6 +43,7 @@ use crate::package::Package;
 use crate::rule::Rule;
 use crate::rule_type::RuleType;
 use crate::visibility::VisibilitySpecification;
+use crate::visibility::WithinViewSpecification;
 
 #[derive(Debug, thiserror::Error)]
 enum TargetNodeError {
@@ -239,6 +240,9 @@ impl TargetNode {
             }
             None => &VisibilitySpecification::Default,
         };
+        if visibility == &VisibilitySpecification::Default {
+            visibility = &self.0.package.visibility;
+        }
         if self.0.package.default_visibility_to_public
             && visibility == &VisibilitySpecification::Default
         {
@@ -254,6 +258,11 @@ impl TargetNode {
         Ok(self.visibility()?.is_visible_to(target))
     }
 
+    /// Whether this target may depend on `dep` according to the `within_view` of its package.
+    pub fn is_within_view(&self, dep: &TargetLabel) -> bool {
+        self.label().pkg() == dep.pkg() || self.0.package.within_view.matches(dep)
+    }
+
     pub fn attrs(&self, opts: AttrInspectOptions) -> impl Iterator<Item = CoercedAttrFull> {
         self.0.rule.attributes.attrs(&self.0.attributes, opts)
     }
@@ -511,6 +520,8 @@ pub mod testing {
                     buildfile_path,
                     oncall: None,
                     default_visibility_to_public: false,
+                    visibility: VisibilitySpecification::Default,
+                    within_view: WithinViewSpecification::Public,
                 }),
                 label,
                 attributes,
@@ -12,10 +12,10 @@ use std::sync::Arc;
 use allocative::Allocative;
 use buck2_core::build_file_path::BuildFilePath;
 
+use crate::visibility::VisibilitySpecification;
+use crate::visibility::WithinViewSpecification;
+
 /// Package-specific data for `TargetNode`.
-///
-/// (Note this has nothing to do with `PACKAGE` files which are not implemented
-/// at the moment of writing.)
 #[derive(Debug, Hash, Allocative, Eq, PartialEq)]
 pub struct Package {
     /// The build file which defined this target, e.g. `fbcode//foo/bar/TARGETS`
@@ -24,4 +24,8 @@ pub struct Package {
     pub oncall: Option<Arc<String>>,
     /// Visibility is public by default.
     pub default_visibility_to_public: bool,
+    /// Visibility of targets that don't set `visibility`, from `PACKAGE` files.
+    pub visibility: VisibilitySpecification,
+    /// What targets of this package may depend on, from `PACKAGE` files.
+    pub within_view: WithinViewSpecification,
 }
@@ -23,6 +23,10 @@ pub enum VisibilityError {
         "`{0}` is not visible to `{1}` (run `buck2 uquery --output-attribute visibility {0}` to check the visibility)"
     )]
     NotVisibleTo(TargetLabel, TargetLabel),
+    #[error(
+        "`{0}` is not within the view of `{1}` (it is not matched by `within_view` of the `PACKAGE` files containing `{1}`)"
+    )]
+    NotWithinView(TargetLabel, TargetLabel),
 }
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Allocative, derive_more::Display)]
@@ -39,6 +43,23 @@ pub enum VisibilitySpecification {
 }
 
 impl VisibilitySpecification {
+    /// Visibility that allows what either `self` or `other` allows.
+    pub fn extend_with(&self, other: &VisibilitySpecification) -> VisibilitySpecification {
+        match (self, other) {
+            (VisibilitySpecification::Public, _) | (_, VisibilitySpecification::Public) => {
+                VisibilitySpecification::Public
+            }
+            (VisibilitySpecification::Default, x) | (x, VisibilitySpecification::Default) => {
+                x.clone()
+            }
+            (VisibilitySpecification::VisibleTo(a), VisibilitySpecification::VisibleTo(b)) => {
+                VisibilitySpecification::VisibleTo(Box::new(
+                    a.iter().chain(b.iter()).cloned().collect(),
+                ))
+            }
+        }
+    }
+
     pub fn is_visible_to(&self, target: &TargetLabel) -> bool {
         match self {
             VisibilitySpecification::Public => true,
@@ -84,3 +105,108 @@ impl Display for VisibilitySpecification {
         }
     }
 }
+
+/// Which targets the targets of a package may depend on, as set by `within_view` in `PACKAGE`
+/// files. Targets in the same package can always depend on each other.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Allocative)]
+pub enum WithinViewSpecification {
+    /// No restriction, which is the default.
+    Public,
+    VisibleTo(Box<Box<[VisibilityPattern]>>),
+}
+
+impl Default for WithinViewSpecification {
+    fn default() -> Self {
+        WithinViewSpecification::Public
+    }
+}
+
+impl WithinViewSpecification {
+    /// Restriction that allows what either `self` or `other` allows.
+    pub fn extend_with(&self, other: &WithinViewSpecification) -> WithinViewSpecification {
+        match (self, other) {
+            (WithinViewSpecification::Public, _) | (_, WithinViewSpecification::Public) => {
+                WithinViewSpecification::Public
+            }
+            (WithinViewSpecification::VisibleTo(a), WithinViewSpecification::VisibleTo(b)) => {
+                WithinViewSpecification::VisibleTo(Box::new(
+                    a.iter().chain(b.iter()).cloned().collect(),
+                ))
+            }
+        }
+    }
+
+    pub fn matches(&self, target: &TargetLabel) -> bool {
+        match self {
+            WithinViewSpecification::Public => true,
+            WithinViewSpecification::VisibleTo(patterns) => {
+                patterns.iter().any(|pattern| pattern.0.matches(target))
+            }
+        }
+    }
+}
+
+impl Display for WithinViewSpecification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WithinViewSpecification::Public => write!(f, "[\"PUBLIC\"]"),
+            WithinViewSpecification::VisibleTo(patterns) => {
+                VisibilitySpecification::VisibleTo(patterns.clone()).fmt(f)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::package::PackageLabel;
+
+    use super::*;
+
+    fn package(package: &str) -> VisibilityPattern {
+        VisibilityPattern(ParsedPattern::Package(PackageLabel::testing_parse(package)))
+    }
+
+    fn recursive(path: &str) -> VisibilityPattern {
+        VisibilityPattern(ParsedPattern::Recursive(CellPath::testing_new(
+            "root", path,
+        )))
+    }
+
+    #[test]
+    fn test_visibility_extend_with() {
+        let foo = VisibilitySpecification::VisibleTo(Box::new(
+            vec![package("root//foo")].into_boxed_slice(),
+        ));
+        let bar =
+            VisibilitySpecification::VisibleTo(Box::new(vec![recursive("bar")].into_boxed_slice()));
+        assert_eq!(
+            VisibilitySpecification::Default.extend_with(&foo),
+            foo.clone()
+        );
+        assert_eq!(
+            foo.extend_with(&VisibilitySpecification::Public),
+            VisibilitySpecification::Public
+        );
+        let both = foo.extend_with(&bar);
+        assert!(both.is_visible_to(&TargetLabel::testing_parse("root//foo:x")));
+        assert!(both.is_visible_to(&TargetLabel::testing_parse("root//bar/baz:x")));
+        assert!(!both.is_visible_to(&TargetLabel::testing_parse("root//qux:x")));
+    }
+
+    #[test]
+    fn test_within_view() {
+        let view =
+            WithinViewSpecification::VisibleTo(Box::new(vec![recursive("foo")].into_boxed_slice()));
+        assert!(view.matches(&TargetLabel::testing_parse("root//foo/bar:x")));
+        assert!(!view.matches(&TargetLabel::testing_parse("root//bar:x")));
+        assert_eq!(
+            view.extend_with(&WithinViewSpecification::Public),
+            WithinViewSpecification::Public
+        );
+        assert!(
+            WithinViewSpecification::default().matches(&TargetLabel::testing_parse("root//bar:x"))
+        );
+    }
+}
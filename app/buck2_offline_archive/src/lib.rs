@@ -7,15 +7,430 @@
  * of this source tree.
  */
 
+//! Offline archives: a tarball of everything a build read from the repository, plus the
+//! artifacts it downloaded, so that the same build can run on a machine without network access.
+//!
+//! The archive is laid out like the repository it recreates, and only contains regular files and
+//! directories:
+//! - `<path>` for each path in the manifest.
+//! - `.buck2-offline/manifest.json`, the [`OfflineArchiveManifest`].
+//! - `.buck2-offline/blobs/<name>` for each blob in the manifest.
+//!
+//! Importing it extracts it at a destination and points `[buck2] offline_blobs` at the blobs, so
+//! that the materializer reads downloaded artifacts from there instead of the network.
+
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use buck2_common::cas_digest::DigestAlgorithm;
+use buck2_common::file_ops::FileDigest;
+
+/// Directory, relative to the root of an imported archive, holding the manifest and the blobs.
+pub const OFFLINE_DIR: &str = ".buck2-offline";
+
+const MANIFEST_NAME: &str = "manifest.json";
+const BLOBS_PREFIX: &str = "blobs";
+const CONFIG_NAME: &str = ".buckconfig.local";
+
+#[derive(Debug, thiserror::Error)]
+enum OfflineArchiveError {
+    #[error("Offline archive contains an invalid path: `{0}`")]
+    InvalidPath(String),
+
+    #[error("Offline archive entry `{0}` is not a regular file or a directory")]
+    UnsupportedEntry(String),
+
+    #[error("Offline archive has no `{}/{}`", OFFLINE_DIR, MANIFEST_NAME)]
+    MissingManifest,
+
+    #[error("Invalid blob name `{0}`")]
+    InvalidBlobName(String),
+
+    #[error("The contents of `{path}` don't match its blob name `{name}`")]
+    BlobMismatch { path: String, name: String },
+}
+
 /// Structured format for an "offline archive manifest", which contains information
 /// necessary to perform a fully offline build of a particular target.
 ///
 /// This manifest is generated by running:
-///   `buck2 debug io-trace export-manifest`
-#[derive(Debug, Clone, serde::Serialize)]
+///   `buck2 debug trace-io export-manifest`
+/// or as part of `buck2 debug offline-archive create`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct OfflineArchiveManifest {
     /// The repository revision this archive was generated from.
     pub repo_revision: Option<String>,
     /// List of project-relative paths that are required to perform a build.
     pub paths: Vec<String>,
+    /// Files of artifacts that were downloaded (from the CAS or over HTTP), and that an offline
+    /// build can't download.
+    #[serde(default)]
+    pub blobs: Vec<OfflineArchiveBlob>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OfflineArchiveBlob {
+    /// Project-relative path the file was downloaded to, where its contents are read from when
+    /// creating the archive.
+    pub path: String,
+    /// Name of the blob in the archive, which is how the materializer looks it up.
+    pub name: String,
+}
+
+impl OfflineArchiveManifest {
+    /// Traced paths that are neither in this manifest nor a directory containing one of its
+    /// paths. A build that only touched paths in the manifest returns nothing.
+    pub fn paths_outside<'a>(&self, traced: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut known: HashSet<&str> = HashSet::new();
+        for path in &self.paths {
+            let mut path = Path::new(path.as_str());
+            loop {
+                known.insert(path.to_str().unwrap_or_default());
+                match path.parent() {
+                    Some(parent) => path = parent,
+                    None => break,
+                }
+            }
+        }
+        let mut outside: Vec<String> = traced
+            .into_iter()
+            .filter(|p| !known.contains(p) && !Path::new(p).starts_with(OFFLINE_DIR))
+            .map(|p| p.to_owned())
+            .collect();
+        outside.sort();
+        outside
+    }
+}
+
+/// Write an offline archive for `manifest` to `out`, reading paths and blobs from `root`. Paths
+/// in the manifest that no longer exist are skipped, since the build only checked they were
+/// missing. Symlinks are archived as what they point to, since only regular files and directories
+/// can be imported.
+pub fn create_archive(
+    root: &Path,
+    manifest: &OfflineArchiveManifest,
+    out: &Path,
+) -> anyhow::Result<()> {
+    let file = File::create(out).with_context(|| format!("creating `{}`", out.display()))?;
+    let mut builder = tar::Builder::new(file);
+
+    let serialized = serde_json::to_vec_pretty(manifest).context("serializing manifest")?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(serialized.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(
+        &mut header,
+        Path::new(OFFLINE_DIR).join(MANIFEST_NAME),
+        serialized.as_slice(),
+    )?;
+
+    for path in &manifest.paths {
+        let name = relative_path(path)?;
+        if name.starts_with(OFFLINE_DIR) {
+            // Recreated from the manifest and the blobs.
+            continue;
+        }
+        let src = root.join(&name);
+        let metadata = match fs::metadata(&src) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("reading `{}`", src.display())),
+        };
+        if metadata.is_dir() {
+            // Directories are archived empty: whatever the build read inside is listed too.
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            header.set_cksum();
+            builder.append_data(&mut header, &name, std::io::empty())?;
+        } else {
+            builder
+                .append_path_with_name(&src, &name)
+                .with_context(|| format!("archiving `{}`", src.display()))?;
+        }
+    }
+
+    let mut archived_blobs = HashSet::new();
+    for blob in &manifest.blobs {
+        if !archived_blobs.insert(&blob.name) {
+            continue;
+        }
+        let src = root.join(relative_path(&blob.path)?);
+        // The materializer trusts blobs to have the contents their name says.
+        verify_blob(&src, &blob.name)?;
+        builder
+            .append_path_with_name(
+                &src,
+                Path::new(OFFLINE_DIR)
+                    .join(BLOBS_PREFIX)
+                    .join(relative_path(&blob.name)?),
+            )
+            .with_context(|| format!("archiving blob `{}`", src.display()))?;
+    }
+
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Check that the contents of `path` match the blob name `name`, which is
+/// `<prefix>/<hash>_<size>`, optionally followed by `_x` for executables.
+fn verify_blob(path: &Path, name: &str) -> anyhow::Result<()> {
+    let invalid_name = || OfflineArchiveError::InvalidBlobName(name.to_owned());
+
+    let file_name = name.rsplit('/').next().unwrap_or_default();
+    let file_name = file_name.strip_suffix("_x").unwrap_or(file_name);
+    let (hash, size) = file_name.split_once('_').ok_or_else(invalid_name)?;
+    let size: u64 = size.parse().map_err(|_| invalid_name())?;
+    // 256-bit hashes are either SHA256 or BLAKE3, depending on the daemon's digest config.
+    let algorithms: &[DigestAlgorithm] = match hash.len() {
+        40 => &[DigestAlgorithm::Sha1],
+        64 => &[DigestAlgorithm::Sha256, DigestAlgorithm::Blake3],
+        _ => return Err(invalid_name().into()),
+    };
+
+    for algorithm in algorithms {
+        let file = File::open(path).with_context(|| format!("opening `{}`", path.display()))?;
+        let digest = FileDigest::from_reader_for_algorithm(file, *algorithm)
+            .with_context(|| format!("hashing `{}`", path.display()))?;
+        if digest.size() == size && digest.raw_digest().to_string() == hash {
+            return Ok(());
+        }
+    }
+
+    Err(OfflineArchiveError::BlobMismatch {
+        path: path.display().to_string(),
+        name: name.to_owned(),
+    }
+    .into())
+}
+
+/// Extract the offline archive at `archive` into `dest`, and configure `dest` to read downloaded
+/// artifacts from the archive's blobs.
+pub fn import_archive(archive: &Path, dest: &Path) -> anyhow::Result<OfflineArchiveManifest> {
+    let blobs_dir = dest.join(OFFLINE_DIR).join(BLOBS_PREFIX);
+    fs::create_dir_all(&blobs_dir)
+        .with_context(|| format!("creating `{}`", blobs_dir.display()))?;
+
+    let file = File::open(archive).with_context(|| format!("opening `{}`", archive.display()))?;
+    let mut tar = tar::Archive::new(file);
+    tar.set_preserve_permissions(true);
+
+    let manifest_name = Path::new(OFFLINE_DIR).join(MANIFEST_NAME);
+    let mut has_manifest = false;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let invalid_path = || OfflineArchiveError::InvalidPath(name.display().to_string());
+
+        // Anything else could be used to write outside of `dest`, e.g. through a symlink
+        // extracted earlier.
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(OfflineArchiveError::UnsupportedEntry(name.display().to_string()).into());
+        }
+
+        relative_path(name.to_str().ok_or_else(invalid_path)?)?;
+        if name == manifest_name {
+            has_manifest = true;
+        } else if name.starts_with(OFFLINE_DIR)
+            && !name.starts_with(Path::new(OFFLINE_DIR).join(BLOBS_PREFIX))
+        {
+            return Err(invalid_path().into());
+        }
+
+        if !entry
+            .unpack_in(dest)
+            .with_context(|| format!("extracting `{}`", name.display()))?
+        {
+            return Err(invalid_path().into());
+        }
+    }
+
+    if !has_manifest {
+        return Err(OfflineArchiveError::MissingManifest.into());
+    }
+    let manifest_path = dest.join(&manifest_name);
+    let mut manifest: OfflineArchiveManifest =
+        serde_json::from_slice(&fs::read(&manifest_path)?).context("parsing manifest")?;
+
+    // The config we add is part of the offline tree, so it belongs in the manifest used for
+    // validation.
+    let mut config = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dest.join(CONFIG_NAME))?;
+    writeln!(
+        config,
+        "\n[buck2]\noffline_blobs = {}/{}",
+        OFFLINE_DIR, BLOBS_PREFIX
+    )?;
+    if !manifest.paths.iter().any(|p| p == CONFIG_NAME) {
+        manifest.paths.push(CONFIG_NAME.to_owned());
+        fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+    }
+
+    Ok(manifest)
+}
+
+/// The manifest of the archive imported at `root`.
+pub fn read_imported_manifest(root: &Path) -> anyhow::Result<OfflineArchiveManifest> {
+    let path = root.join(OFFLINE_DIR).join(MANIFEST_NAME);
+    let data = fs::read(&path).with_context(|| format!("reading `{}`", path.display()))?;
+    serde_json::from_slice(&data).with_context(|| format!("parsing `{}`", path.display()))
+}
+
+/// Validate that `path` stays within the directory it's relative to.
+fn relative_path(path: &str) -> anyhow::Result<PathBuf> {
+    let p = Path::new(path);
+    if p.components().next().is_none() || !p.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(OfflineArchiveError::InvalidPath(path.to_owned()).into());
+    }
+    Ok(p.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob_name(contents: &str, algorithm: DigestAlgorithm) -> String {
+        let digest = FileDigest::from_content_for_algorithm(contents.as_bytes(), algorithm);
+        let hash = digest.raw_digest().to_string();
+        format!("{}/{}_{}", &hash[..2], hash, digest.size())
+    }
+
+    #[test]
+    fn test_create_and_import() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let repo = tempdir.path().join("repo");
+        fs::create_dir_all(repo.join("foo/empty"))?;
+        fs::create_dir_all(repo.join("buck-out/v2/gen"))?;
+        fs::write(repo.join(".buckconfig"), "[cells]\nroot = .\n")?;
+        fs::write(repo.join("foo/BUCK"), "")?;
+        fs::write(repo.join("foo/unused.txt"), "")?;
+        fs::write(repo.join("buck-out/v2/gen/downloaded"), "blob")?;
+
+        let manifest = OfflineArchiveManifest {
+            repo_revision: None,
+            paths: vec![
+                ".buckconfig".to_owned(),
+                "foo/BUCK".to_owned(),
+                "foo/empty".to_owned(),
+                "foo/missing".to_owned(),
+            ],
+            blobs: vec![OfflineArchiveBlob {
+                path: "buck-out/v2/gen/downloaded".to_owned(),
+                name: blob_name("blob", DigestAlgorithm::Sha1),
+            }],
+        };
+        let archive = tempdir.path().join("archive.tar");
+        create_archive(&repo, &manifest, &archive)?;
+
+        let dest = tempdir.path().join("dest");
+        let imported = import_archive(&archive, &dest)?;
+
+        assert_eq!(
+            fs::read_to_string(dest.join(".buckconfig"))?,
+            "[cells]\nroot = .\n"
+        );
+        assert!(dest.join("foo/BUCK").exists());
+        assert!(dest.join("foo/empty").is_dir());
+        assert!(!dest.join("foo/unused.txt").exists());
+        assert!(!dest.join("buck-out").exists());
+        assert_eq!(
+            fs::read_to_string(
+                dest.join(".buck2-offline/blobs")
+                    .join(blob_name("blob", DigestAlgorithm::Sha1))
+            )?,
+            "blob"
+        );
+        assert!(
+            fs::read_to_string(dest.join(".buckconfig.local"))?
+                .contains("offline_blobs = .buck2-offline/blobs")
+        );
+
+        assert_eq!(imported.blobs, manifest.blobs);
+        assert!(imported.paths.contains(&".buckconfig.local".to_owned()));
+        assert_eq!(read_imported_manifest(&dest)?, imported);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_verifies_blobs() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        fs::write(tempdir.path().join("downloaded"), "blob")?;
+        let archive = tempdir.path().join("archive.tar");
+        let create = |name: String| {
+            let manifest = OfflineArchiveManifest {
+                repo_revision: None,
+                paths: Vec::new(),
+                blobs: vec![OfflineArchiveBlob {
+                    path: "downloaded".to_owned(),
+                    name,
+                }],
+            };
+            create_archive(tempdir.path(), &manifest, &archive)
+        };
+
+        assert!(create(blob_name("blob", DigestAlgorithm::Sha256)).is_ok());
+        assert!(create(format!("{}_x", blob_name("blob", DigestAlgorithm::Blake3))).is_ok());
+        assert!(create(blob_name("other", DigestAlgorithm::Sha1)).is_err());
+        assert!(create("ab/abcd_4".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_symlinks() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let archive = tempdir.path().join("archive.tar");
+        let mut builder = tar::Builder::new(File::create(&archive)?);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "escape", tempdir.path())?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        builder.append_data(&mut header, "escape/file", "evil".as_bytes())?;
+        builder.into_inner()?.flush()?;
+
+        assert!(import_archive(&archive, &tempdir.path().join("dest")).is_err());
+        assert!(!tempdir.path().join("file").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_outside() {
+        let manifest = OfflineArchiveManifest {
+            repo_revision: None,
+            paths: vec!["foo/bar/BUCK".to_owned(), ".buckconfig".to_owned()],
+            blobs: Vec::new(),
+        };
+        assert_eq!(
+            manifest.paths_outside([
+                "foo",
+                "foo/bar",
+                "foo/bar/BUCK",
+                ".buckconfig",
+                ".buck2-offline/manifest.json",
+                "foo/baz",
+                "other/BUCK",
+            ]),
+            vec!["foo/baz".to_owned(), "other/BUCK".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert!(relative_path("foo/bar").is_ok());
+        assert!(relative_path("").is_err());
+        assert!(relative_path("/etc").is_err());
+        assert!(relative_path("foo/../../etc").is_err());
+    }
 }
@@ -88,6 +88,17 @@ impl IncompatiblePlatformReason {
         CompatibilityErrors::TargetIncompatible(self.clone()).into()
     }
 
+    /// The target whose compatibility check failed, and the constraint it didn't satisfy, at the
+    /// end of the chain of incompatible dependencies.
+    pub fn root_cause(&self) -> (&ConfiguredTargetLabel, &TargetLabel) {
+        match &self.cause {
+            IncompatiblePlatformReasonCause::UnsatisfiedConfig(constraint) => {
+                (&self.target, constraint)
+            }
+            IncompatiblePlatformReasonCause::Dependency(previous) => previous.root_cause(),
+        }
+    }
+
     pub fn skipping_message(&self, target: &ConfiguredTargetLabel) -> String {
         format!("Skipping target incompatible node `{}`", target)
     }
@@ -145,10 +156,31 @@ impl Display for IncompatiblePlatformReason {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use buck2_core::configuration::data::ConfigurationData;
     use buck2_core::target::label::TargetLabel;
 
     use crate::query::compatibility::IncompatiblePlatformReason;
+    use crate::query::compatibility::IncompatiblePlatformReasonCause;
+
+    #[test]
+    fn test_root_cause() {
+        let lib =
+            TargetLabel::testing_parse("cell//foo:lib").configure(ConfigurationData::testing_new());
+        let constraint = TargetLabel::testing_parse("cell//constraints:linux");
+        let reason = IncompatiblePlatformReason {
+            target: TargetLabel::testing_parse("cell//foo:bin")
+                .configure(ConfigurationData::testing_new()),
+            cause: IncompatiblePlatformReasonCause::Dependency(Arc::new(
+                IncompatiblePlatformReason {
+                    target: lib.clone(),
+                    cause: IncompatiblePlatformReasonCause::UnsatisfiedConfig(constraint.clone()),
+                },
+            )),
+        };
+        assert_eq!((&lib, &constraint), reason.root_cause());
+    }
 
     #[test]
     fn test_skipping_message_for_multiple() {
@@ -26,6 +26,8 @@ enum QueryOutputFormatArg {
     Dot,
     Json,
     DotCompact,
+    JsonLines,
+    Protobuf,
 }
 
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
@@ -142,9 +144,11 @@ pub struct CommonQueryArgs {
         long_help = "Output format (default: list). \n
            dot -  dot graph format. \n
            dot_compact - compact alternative to dot format. \n
-           json - JSON format.
+           json - JSON format. \n
+           json_lines - one JSON object per target or file, written as they are computed. \n
+           protobuf - length-delimited `buck.query.QueryResult` messages, see `query.proto`.
          ",
-        value_name = "dot|dot_compact|json",
+        value_name = "dot|dot_compact|json|json_lines|protobuf",
         arg_enum
     )]
     output_format: Option<QueryOutputFormatArg>,
@@ -176,6 +180,8 @@ impl CommonQueryArgs {
             Some(QueryOutputFormatArg::Json) => QueryOutputFormat::Json,
             Some(QueryOutputFormatArg::Dot) => QueryOutputFormat::Dot,
             Some(QueryOutputFormatArg::DotCompact) => QueryOutputFormat::DotCompact,
+            Some(QueryOutputFormatArg::JsonLines) => QueryOutputFormat::JsonLines,
+            Some(QueryOutputFormatArg::Protobuf) => QueryOutputFormat::Protobuf,
             None => {
                 if self.json {
                     QueryOutputFormat::Json
@@ -0,0 +1,13 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Schema of the `--output-format=protobuf` output of `buck2 uquery`, `buck2 cquery` and
+//! `buck2 aquery`.
+
+tonic::include_proto!("buck.query");
@@ -12,13 +12,22 @@ use std::collections::HashSet;
 use anyhow::Context;
 use buck2_cli_proto::config_override::ConfigType;
 use buck2_cli_proto::ConfigOverride;
+use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::legacy_configs::cells::BuckConfigBasedCells;
+use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::diff::buckconfig_change_name;
+use buck2_common::legacy_configs::diff::diff_cells;
+use buck2_common::legacy_configs::diff::diff_configs;
+use buck2_common::legacy_configs::schema::ConfigSchema;
 use buck2_common::legacy_configs::LegacyBuckConfigs;
 use buck2_common::legacy_configs::LegacyConfigCmdArg;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_events::dispatch::EventDispatcher;
+use dice::DiceComputations;
+use itertools::Itertools;
 
 fn config_type_from_i32(value: i32) -> anyhow::Result<ConfigType> {
     ConfigType::from_i32(value).with_context(|| {
@@ -62,3 +71,76 @@ pub fn parse_legacy_cells<'a, Iter: IntoIterator<Item = &'a ConfigOverride>>(
     let res = BuckConfigBasedCells::parse_with_config_args(fs, &config_values, cwd)?;
     Ok((res.cell_resolver, res.configs_by_name, res.config_paths))
 }
+
+/// Warn about buckconfig keys that don't match the schema of the keys buck2 itself declares.
+///
+/// This runs when configs are loaded, before any Starlark is evaluated, so keys declared from
+/// Starlark are not checked here; `buck2 audit config --schema` lists those.
+pub fn check_config_schema(cell_resolver: &CellResolver, legacy_configs: &LegacyBuckConfigs) {
+    // Components read their configuration from the root cell, so that's the one we check.
+    if let Ok(root_config) = legacy_configs.get(cell_resolver.root_cell()) {
+        for diagnostic in ConfigSchema::builtin().validate(root_config) {
+            tracing::warn!("{}", diagnostic);
+        }
+    }
+}
+
+/// Compare the cells and configs of this command against those already in the graph, and emit
+/// an event describing what changed and what that invalidates.
+pub async fn report_configuration_changes(
+    previous: &DiceComputations,
+    cell_resolver: &CellResolver,
+    legacy_configs: &LegacyBuckConfigs,
+    events: &EventDispatcher,
+) -> anyhow::Result<()> {
+    // Nothing to compare against on the first command of a daemon.
+    if !previous.is_cell_resolver_key_set().await? || !previous.is_legacy_configs_key_set().await? {
+        return Ok(());
+    }
+
+    let cell_changes = diff_cells(&previous.get_cell_resolver().await?, cell_resolver);
+    let config_changes = diff_configs(
+        &previous.get_legacy_configs().await?,
+        legacy_configs,
+        ConfigSchema::builtin(),
+    );
+    if cell_changes.is_empty() && config_changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut invalidated = Vec::new();
+    if !cell_changes.is_empty() {
+        invalidated.push(format!(
+            "cell resolver reset (cells {} changed): everything that resolves cells is recomputed",
+            cell_changes.iter().map(|change| &change.cell).join(", ")
+        ));
+    }
+    let root_cell = cell_resolver.root_cell();
+    for change in &config_changes {
+        let name = buckconfig_change_name(change);
+        // Buckconfigs are not daemon constraints, so the daemon keeps running with the value it
+        // read when it started.
+        let daemon_startup = change.cell == root_cell.as_str()
+            && ConfigSchema::builtin()
+                .get(&change.section, &change.key)
+                .map_or(false, |key| key.daemon_startup);
+        invalidated.push(if daemon_startup {
+            format!(
+                "daemon restart required: {} is only read when the daemon starts, and changing it does not restart the daemon",
+                name
+            )
+        } else {
+            format!(
+                "DICE key {} changed: computations that read it, or all of the buckconfig of cell {}, are recomputed",
+                name, change.cell
+            )
+        });
+    }
+
+    events.instant_event(buck2_data::ConfigurationChanged {
+        config_changes,
+        cell_changes,
+        invalidated,
+    });
+    Ok(())
+}
@@ -44,6 +44,8 @@ use buck2_common::executor_config::CommandExecutorConfig;
 use buck2_common::io::IoProvider;
 use buck2_common::io::TracingIoProvider;
 use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::legacy_configs::LegacyBuckConfigs;
 use buck2_common::result::SharedError;
@@ -102,11 +104,14 @@ use dupe::Dupe;
 use gazebo::prelude::SliceExt;
 use host_sharing::HostSharingBroker;
 use host_sharing::HostSharingStrategy;
+use host_sharing::MemoryEstimates;
 use tokio::sync::Mutex;
 use tracing::warn;
 
 use crate::active_commands::ActiveCommandDropGuard;
+use crate::configs::check_config_schema;
 use crate::configs::parse_legacy_cells;
+use crate::configs::report_configuration_changes;
 use crate::daemon::common::get_default_executor_config;
 use crate::daemon::common::parse_concurrency;
 use crate::daemon::common::CommandExecutorFactory;
@@ -115,12 +120,43 @@ use crate::file_watcher::FileWatcher;
 use crate::heartbeat_guard::HeartbeatGuard;
 use crate::host_info;
 
+static MINIPERF: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "miniperf2",
+    "Whether to collect CPU counters for local actions with miniperf.",
+)
+.with_default("false");
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&MINIPERF),
+    }
+}
+
+static LOCAL_MEMORY_FRACTION: ConfigKey<f64> = ConfigKey::new(
+    "build",
+    "local_memory_fraction",
+    "Fraction of system memory, in (0, 1], that concurrently running local commands may use.",
+);
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&LOCAL_MEMORY_FRACTION),
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 enum DaemonCommunicationError {
     #[error("Got invalid working directory `{0}`")]
     InvalidWorkingDirectory(String),
 }
 
+#[derive(Debug, thiserror::Error)]
+enum ExecutorConfigError {
+    #[error("`build.local_memory_fraction` must be in (0, 1], got `{0}`")]
+    InvalidLocalMemoryFraction(f64),
+}
+
 /// BaseCommandContext provides access to the global daemon state and information specific to a command (like the
 /// EventDispatcher). Most commands use a ServerCommandContext which has more command/client-specific information.
 pub struct BaseServerCommandContext {
@@ -155,6 +191,8 @@ pub struct BaseServerCommandContext {
     pub daemon_start_time: Instant,
     /// Mutex for creating symlinks
     pub create_unhashed_outputs_lock: Arc<Mutex<()>>,
+    /// Peak memory observed for local actions across commands.
+    pub memory_estimates: Arc<MemoryEstimates>,
 }
 
 /// ServerCommandContext provides access to the global daemon state and information about the calling client for
@@ -361,6 +399,8 @@ impl ServerCommandContext {
 
         let create_unhashed_symlink_lock = self.base_context.create_unhashed_outputs_lock.dupe();
 
+        let memory_estimates = self.base_context.memory_estimates.dupe();
+
         DiceCommandDataProvider {
             cell_configs_loader: self.cell_configs_loader.dupe(),
             events: self.events().dupe(),
@@ -376,6 +416,7 @@ impl ServerCommandContext {
             upload_all_actions,
             no_remote_cache,
             create_unhashed_symlink_lock,
+            memory_estimates,
         }
     }
 
@@ -384,6 +425,7 @@ impl ServerCommandContext {
             host_info::get_host_info(self.host_platform_override, self.host_arch_override);
 
         Ok(DiceCommandUpdater {
+            events: self.events().dupe(),
             file_watcher: self.base_context.file_watcher.dupe(),
             cell_config_loader: self.cell_configs_loader.dupe(),
             buck_out_dir: self.buck_out_dir.clone(),
@@ -442,8 +484,11 @@ impl CellConfigLoader {
                         );
                     }
                 }
-                parse_legacy_cells(self.config_overrides.iter(), &self.working_dir, &self.project_root)
-                    .shared_error()
+                let res = parse_legacy_cells(self.config_overrides.iter(), &self.working_dir, &self.project_root);
+                if let Ok((cell_resolver, legacy_configs, _)) = &res {
+                    check_config_schema(cell_resolver, legacy_configs);
+                }
+                res.shared_error()
             })
             .await
             .clone()
@@ -465,6 +510,7 @@ struct DiceCommandDataProvider {
     run_action_knobs: RunActionKnobs,
     no_remote_cache: bool,
     create_unhashed_symlink_lock: Arc<Mutex<()>>,
+    memory_estimates: Arc<MemoryEstimates>,
 }
 
 #[async_trait]
@@ -492,16 +538,28 @@ impl DiceDataProvider for DiceCommandDataProvider {
                 .instant_event(buck2_data::ConsolePreferences { max_lines });
         }
 
-        let enable_miniperf = root_config
-            .parse::<RolloutPercentage>("buck2", "miniperf2")?
-            .unwrap_or_else(RolloutPercentage::never)
-            .roll();
+        let enable_miniperf = root_config.read_or_default(&MINIPERF)?.roll();
 
         let executor_global_knobs = ExecutorGlobalKnobs { enable_miniperf };
 
-        let host_sharing_broker =
+        let mut host_sharing_broker =
             HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, concurrency);
 
+        if let Some(fraction) = root_config.read(&LOCAL_MEMORY_FRACTION)? {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(ExecutorConfigError::InvalidLocalMemoryFraction(fraction).into());
+            }
+            let total_memory = sys_info::mem_info()
+                .context("Error reading system memory for `build.local_memory_fraction`")?
+                .total
+                * 1024;
+            host_sharing_broker = host_sharing_broker.with_memory_budget(
+                HostSharingStrategy::SmallerTasksFirst,
+                (total_memory as f64 * fraction) as u64,
+                self.memory_estimates.dupe(),
+            );
+        }
+
         // We use the job count for the low pass filter too. The low pass filter prevents sending
         // RE-eligile tasks to local if their concurrency is higher than our threshold. While it
         // doesn't *have* to be the same as the concurrency we give the actual executor, it's a
@@ -574,6 +632,7 @@ fn create_cycle_detector() -> Arc<dyn UserCycleDetector> {
 }
 
 struct DiceCommandUpdater {
+    events: EventDispatcher,
     file_watcher: Arc<dyn FileWatcher>,
     cell_config_loader: Arc<CellConfigLoader>,
     buck_out_dir: ProjectRelativePathBuf,
@@ -587,10 +646,18 @@ struct DiceCommandUpdater {
 #[async_trait]
 impl DiceUpdater for DiceCommandUpdater {
     async fn update(&self, ctx: DiceTransactionUpdater) -> anyhow::Result<DiceTransactionUpdater> {
+        let existing_state = ctx.existing_state().await;
         let (cell_resolver, legacy_configs, _): (CellResolver, LegacyBuckConfigs, _) = self
             .cell_config_loader
-            .cells_and_configs(&ctx.existing_state().await)
+            .cells_and_configs(&existing_state)
             .await?;
+        report_configuration_changes(
+            &existing_state,
+            &cell_resolver,
+            &legacy_configs,
+            &self.events,
+        )
+        .await?;
         // TODO(cjhopman): The CellResolver and the legacy configs shouldn't be leaves on the graph. This should
         // just be setting the config overrides and host platform override as leaves on the graph.
 
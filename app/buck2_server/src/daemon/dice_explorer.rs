@@ -0,0 +1,293 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A read-only JSON API over the live DICE graph, served on localhost when
+//! `buck2.dice_explorer_port` is set. Unlike `buck2 debug dice-dump`, this answers point queries
+//! (why was a key recomputed, what depends on it, what is running) without dumping the whole graph.
+//!
+//! There is no authentication, so requests are only answered when their `Host` is the loopback
+//! address we are bound to. This keeps web pages from reading the graph through DNS rebinding.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use dice::introspection::query::key_type_stats;
+use dice::introspection::query::GraphSnapshot;
+use dice::introspection::IntrospectionNotSupported;
+use dice::Dice;
+use dupe::Dupe;
+use hyper::header;
+use hyper::service::make_service_fn;
+use hyper::service::service_fn;
+use hyper::Body;
+use hyper::Method;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use serde::Serialize;
+
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>DICE explorer</title></head>
+<body>
+<h1>DICE explorer</h1>
+<p>All endpoints are read-only and return JSON. Keys are identified by their display string,
+optionally narrowed down with <code>type</code> (the short key type name).</p>
+<ul>
+<li><a href="/api/stats">/api/stats</a>: key count, running computations and memory per key type</li>
+<li><a href="/api/running">/api/running</a>: keys currently being computed</li>
+<li><code>/api/search?q=SUBSTRING[&amp;limit=N]</code>: find keys</li>
+<li><code>/api/key?key=KEY[&amp;type=TYPE]</code>: versions, history, dependencies and dependents of a key</li>
+<li><code>/api/why?key=KEY[&amp;type=TYPE]</code>: why a key was last recomputed</li>
+</ul>
+<form action="/api/search"><input name="q" placeholder="Search keys"> <input type="submit" value="Search"></form>
+<form action="/api/why"><input name="key" placeholder="Key"> <input name="type" placeholder="Type (optional)"> <input type="submit" value="Why recomputed?"></form>
+</body>
+</html>
+"#;
+
+#[derive(Debug, thiserror::Error)]
+enum DiceExplorerError {
+    #[error("Missing query parameter `{0}`")]
+    MissingParameter(&'static str),
+    #[error("Invalid value for query parameter `{0}`: `{1}`")]
+    InvalidParameter(&'static str, String),
+    #[error("No such endpoint `{0}`")]
+    NotFound(String),
+    #[error("Only GET requests are supported")]
+    MethodNotAllowed,
+    #[error("Requests must be addressed to `localhost:{0}` or `127.0.0.1:{0}`")]
+    ForbiddenHost(u16),
+}
+
+impl DiceExplorerError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::MissingParameter(_) | Self::InvalidParameter(..) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::ForbiddenHost(_) => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+/// Start serving on `127.0.0.1:port` (`0` picks a free port) and return the bound address.
+/// The server runs until the daemon exits.
+pub(crate) fn spawn(dice: Arc<Dice>, port: u16) -> anyhow::Result<SocketAddr> {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("Error binding DICE explorer to port {}", port))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let port = addr.port();
+
+    let make_service = make_service_fn(move |_conn| {
+        let dice = dice.dupe();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let dice = dice.dupe();
+                async move { Ok::<_, Infallible>(handle(dice, port, req).await) }
+            }))
+        }
+    });
+    let server = hyper::Server::from_tcp(listener)?.serve(make_service);
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            tracing::warn!("DICE explorer stopped: {:#}", e);
+        }
+    });
+
+    Ok(addr)
+}
+
+async fn handle(dice: Arc<Dice>, port: u16, req: Request<Body>) -> Response<Body> {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok());
+    if !is_allowed_host(host, port) {
+        return error_response(&DiceExplorerError::ForbiddenHost(port).into());
+    }
+    if req.method() != Method::GET {
+        return error_response(&DiceExplorerError::MethodNotAllowed.into());
+    }
+
+    let path = req.uri().path().to_owned();
+    let params = parse_query(req.uri().query().unwrap_or_default());
+
+    // Snapshotting the graph walks every key, so keep it off the async workers.
+    match tokio::task::spawn_blocking(move || route(&dice, &path, &params)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => error_response(&e),
+        Err(e) => error_response(&anyhow::Error::from(e).context("DICE explorer request panicked")),
+    }
+}
+
+fn route(
+    dice: &Dice,
+    path: &str,
+    params: &HashMap<String, String>,
+) -> anyhow::Result<Response<Body>> {
+    let type_name = params.get("type").map(String::as_str);
+
+    match path {
+        "/" => Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(INDEX))?),
+        "/api/stats" => json_response(&key_type_stats(&dice.to_introspectable()?)),
+        "/api/running" => {
+            json_response(&GraphSnapshot::new(&dice.to_introspectable()?).currently_running())
+        }
+        "/api/search" => {
+            let limit = match params.get("limit") {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| DiceExplorerError::InvalidParameter("limit", limit.to_owned()))?,
+                None => DEFAULT_SEARCH_LIMIT,
+            };
+            json_response(
+                &GraphSnapshot::new(&dice.to_introspectable()?).search(param(params, "q")?, limit),
+            )
+        }
+        "/api/key" => json_response(
+            &GraphSnapshot::new(&dice.to_introspectable()?)
+                .details(param(params, "key")?, type_name),
+        ),
+        "/api/why" => json_response(
+            &GraphSnapshot::new(&dice.to_introspectable()?)
+                .why_recomputed(param(params, "key")?, type_name),
+        ),
+        _ => Err(DiceExplorerError::NotFound(path.to_owned()).into()),
+    }
+}
+
+/// Whether `host`, the `Host` header of a request, names the loopback address on `port`.
+fn is_allowed_host(host: Option<&str>, port: u16) -> bool {
+    match host.and_then(|h| h.rsplit_once(':')) {
+        Some((name, p)) => {
+            (name.eq_ignore_ascii_case("localhost") || name == "127.0.0.1")
+                && p.parse::<u16>().ok() == Some(port)
+        }
+        None => false,
+    }
+}
+
+fn param<'a>(params: &'a HashMap<String, String>, name: &'static str) -> anyhow::Result<&'a str> {
+    Ok(params
+        .get(name)
+        .ok_or(DiceExplorerError::MissingParameter(name))?)
+}
+
+fn json_response<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<Response<Body>> {
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec_pretty(value)?))?)
+}
+
+fn error_response(e: &anyhow::Error) -> Response<Body> {
+    let status = if let Some(e) = e.downcast_ref::<DiceExplorerError>() {
+        e.status()
+    } else if e.is::<IntrospectionNotSupported>() {
+        StatusCode::NOT_IMPLEMENTED
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut response = Response::new(Body::from(format!("{:#}\n", e)));
+    *response.status_mut() = status;
+    response
+}
+
+/// Parse an `application/x-www-form-urlencoded` query string. Later values win for repeated keys.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    fn hex(b: u8) -> Option<u8> {
+        (b as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let params = parse_query("key=root%2F%2Ffoo%3Abar&type=Key+Type&empty&limit=5");
+        assert_eq!(params["key"], "root//foo:bar");
+        assert_eq!(params["type"], "Key Type");
+        assert_eq!(params["empty"], "");
+        assert_eq!(params["limit"], "5");
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn test_percent_decode_malformed() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%41"), "A");
+    }
+
+    #[test]
+    fn test_is_allowed_host() {
+        assert!(is_allowed_host(Some("localhost:8080"), 8080));
+        assert!(is_allowed_host(Some("127.0.0.1:8080"), 8080));
+        assert!(!is_allowed_host(Some("localhost:8081"), 8080));
+        assert!(!is_allowed_host(Some("localhost"), 8080));
+        assert!(!is_allowed_host(Some("evil.example.com:8080"), 8080));
+        assert!(!is_allowed_host(None, 8080));
+    }
+
+    #[test]
+    fn test_error_status() {
+        let e: anyhow::Error = DiceExplorerError::MissingParameter("key").into();
+        assert_eq!(error_response(&e).status(), StatusCode::BAD_REQUEST);
+        let e: anyhow::Error = IntrospectionNotSupported.into();
+        assert_eq!(error_response(&e).status(), StatusCode::NOT_IMPLEMENTED);
+        let e = anyhow::anyhow!("boom");
+        assert_eq!(
+            error_response(&e).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
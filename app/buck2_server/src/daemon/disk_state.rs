@@ -13,6 +13,8 @@ use std::sync::Arc;
 use allocative::Allocative;
 use anyhow::Context;
 use buck2_common::invocation_paths::InvocationPaths;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
@@ -28,6 +30,30 @@ use buck2_execute_impl::materializers::sqlite::MaterializerStateSqliteDb;
 use buck2_execute_impl::materializers::sqlite::DB_SCHEMA_VERSION;
 use chrono::Utc;
 
+static SQLITE_MATERIALIZER_STATE: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "sqlite_materializer_state",
+    "Whether to persist the deferred materializer state in sqlite across daemon restarts.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+static SQLITE_MATERIALIZER_STATE_VERSION: ConfigKey<String> = ConfigKey::new(
+    "buck2",
+    "sqlite_materializer_state_version",
+    "Changing this discards the materializer state persisted in sqlite.",
+)
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| {
+            schema.declare(&SQLITE_MATERIALIZER_STATE);
+            schema.declare(&SQLITE_MATERIALIZER_STATE_VERSION);
+        },
+    }
+}
+
 #[derive(Allocative)]
 pub struct DiskStateOptions {
     pub sqlite_materializer_state: bool,
@@ -44,8 +70,7 @@ impl DiskStateOptions {
             materialization_method,
             MaterializationMethod::Deferred | MaterializationMethod::DeferredSkipFinalArtifacts
         ) && root_config
-            .parse::<RolloutPercentage>("buck2", "sqlite_materializer_state")?
-            .unwrap_or_else(RolloutPercentage::never)
+            .read_or_default(&SQLITE_MATERIALIZER_STATE)?
             .roll();
         Ok(Self {
             sqlite_materializer_state,
@@ -87,9 +112,7 @@ pub(crate) async fn maybe_initialize_materializer_sqlite_db(
                 .to_string(),
         ),
     ]);
-    if let Some(buckconfig_version) =
-        root_config.parse("buck2", "sqlite_materializer_state_version")?
-    {
+    if let Some(buckconfig_version) = root_config.read(&SQLITE_MATERIALIZER_STATE_VERSION)? {
         versions.insert("buckconfig_version".to_owned(), buckconfig_version);
     }
     if let Some(hostname) = metadata.get("hostname") {
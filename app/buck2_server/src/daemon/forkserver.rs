@@ -7,31 +7,59 @@
  * of this source tree.
  */
 
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_forkserver::client::ForkserverClient;
 
+static FORKSERVER: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "forkserver",
+    "Whether to run local actions through the forkserver. Only used on Unix.",
+)
+.with_default("true")
+.read_at_daemon_startup();
+
+static FORKSERVER_CGROUPS: ConfigKey<bool> = ConfigKey::new(
+    "buck2",
+    "forkserver_cgroups",
+    "Whether the forkserver runs each action in its own cgroup.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| {
+            schema.declare(&FORKSERVER);
+            schema.declare(&FORKSERVER_CGROUPS);
+        },
+    }
+}
+
 #[cfg(unix)]
 pub async fn maybe_launch_forkserver(
     root_config: &LegacyBuckConfig,
     forkserver_state_dir: &AbsNormPath,
 ) -> anyhow::Result<Option<ForkserverClient>> {
     use anyhow::Context;
-    use buck2_core::rollout_percentage::RolloutPercentage;
 
-    let config = root_config
-        .parse::<RolloutPercentage>("buck2", "forkserver")?
-        .unwrap_or_else(RolloutPercentage::always);
+    let config = root_config.read_or_default(&FORKSERVER)?;
 
     if !config.roll() {
         return Ok(None);
     }
 
+    let mut args = vec!["forkserver"];
+    if root_config.read_or_default(&FORKSERVER_CGROUPS)? {
+        args.push("--enable-cgroups");
+    }
+
     let exe = std::env::current_exe().context("Cannot access current_exe")?;
-    Some(
-        buck2_forkserver::unix::launch_forkserver(exe, &["forkserver"], forkserver_state_dir).await,
-    )
-    .transpose()
+    Some(buck2_forkserver::unix::launch_forkserver(exe, &args, forkserver_state_dir).await)
+        .transpose()
 }
 
 #[cfg(not(unix))]
@@ -455,6 +455,7 @@ impl BuckdServer {
                     build_listener::scope(
                         base_context.events.dupe(),
                         data.critical_path_backend,
+                        data.critical_path_log_graph,
                         |build_sender| async {
                             let context = ServerCommandContext::new(
                                 base_context,
@@ -841,6 +842,11 @@ impl DaemonApi for BuckdServer {
                 uptime: Some(uptime.try_into()?),
                 snapshot,
                 daemon_constraints: Some(self.0.daemon_constraints.clone()),
+                dice_explorer_address: daemon_state
+                    .data()
+                    .ok()
+                    .and_then(|data| data.dice_explorer_address)
+                    .map(|addr| format!("http://{}", addr)),
                 ..Default::default()
             };
             Ok(base)
@@ -8,6 +8,7 @@
  */
 
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
@@ -22,11 +23,16 @@ use buck2_common::ignores::IgnoreSet;
 use buck2_common::invocation_paths::InvocationPaths;
 use buck2_common::io::IoProvider;
 use buck2_common::legacy_configs::cells::BuckConfigBasedCells;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
+use buck2_common::legacy_configs::CommaSeparated;
 use buck2_common::result::SharedResult;
 use buck2_common::result::ToSharedResultExt;
 use buck2_core::cells::name::CellName;
 use buck2_core::facebook_only;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_events::dispatch::EventDispatcher;
@@ -37,13 +43,18 @@ use buck2_events::EventSource;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::BuckBlockingExecutor;
+use buck2_execute::materialize::download_cache::DownloadCache;
+use buck2_execute::materialize::http::set_download_config;
+use buck2_execute::materialize::http::DownloadConfig;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute::materialize::materializer::Materializer;
+use buck2_execute::materialize::netrc::Netrc;
 use buck2_execute::re::manager::ReConnectionManager;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializer;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::deferred::TtlRefreshConfiguration;
 use buck2_execute_impl::materializers::immediate::ImmediateMaterializer;
+use buck2_execute_impl::materializers::local_store::LocalStoreMode;
 use buck2_execute_impl::materializers::sqlite::MaterializerState;
 use buck2_execute_impl::materializers::sqlite::MaterializerStateSqliteDb;
 use buck2_forkserver::client::ForkserverClient;
@@ -57,6 +68,7 @@ use buck2_wrapper_common::invocation_id::TraceId;
 use dupe::Dupe;
 use fbinit::FacebookInit;
 use gazebo::variants::VariantName;
+use host_sharing::MemoryEstimates;
 use tokio::sync::Mutex;
 
 use crate::active_commands::ActiveCommandDropGuard;
@@ -70,6 +82,186 @@ use crate::daemon::panic::DaemonStatePanicDiceDump;
 use crate::daemon::server::BuckdServerInitPreferences;
 use crate::file_watcher::FileWatcher;
 
+static DIGEST_ALGORITHMS: ConfigKey<CommaSeparated<DigestAlgorithm>> = ConfigKey::new(
+    "buck2",
+    "digest_algorithms",
+    "Digest algorithms to use, comma-separated. Defaults to SHA256 in open source builds.",
+)
+.read_at_daemon_startup();
+
+static DEFER_WRITE_ACTIONS: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "defer_write_actions",
+    "Whether the deferred materializer runs write actions only when their outputs are needed.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+static TTL_REFRESH_FREQUENCY_SECONDS: ConfigKey<u64> = ConfigKey::new(
+    "buck2",
+    "ttl_refresh_frequency_seconds",
+    "How often to look for remote artifacts whose TTL needs refreshing.",
+)
+.with_default("1800")
+.read_at_daemon_startup();
+
+static TTL_REFRESH_MIN_TTL_SECONDS: ConfigKey<i64> = ConfigKey::new(
+    "buck2",
+    "ttl_refresh_min_ttl_seconds",
+    "Refresh the TTL of remote artifacts that expire sooner than this.",
+)
+.with_default("3600")
+.read_at_daemon_startup();
+
+static TTL_REFRESH_ENABLED: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "ttl_refresh_enabled",
+    "Whether to periodically refresh the TTL of remote artifacts we depend on.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+static LOCAL_STORE: ConfigKey<LocalStoreMode> = ConfigKey::new(
+    "buck2",
+    "local_store",
+    "Materialize local copies from a content-addressed store, using `hardlink` or `reflink`.",
+)
+.read_at_daemon_startup();
+
+static OFFLINE_BLOBS: ConfigKey<String> = ConfigKey::new(
+    "buck2",
+    "offline_blobs",
+    "Project-relative directory of offline archive blobs to serve CAS and HTTP downloads from, instead of the network.",
+)
+.read_at_daemon_startup();
+
+static DOWNLOAD_NETRC: ConfigKey<String> = ConfigKey::new(
+    "download",
+    "netrc",
+    "Absolute path of a netrc-style file with credentials for HTTP downloads, by host.",
+)
+.read_at_daemon_startup();
+
+static DOWNLOAD_CACHE_DIR: ConfigKey<String> = ConfigKey::new(
+    "download",
+    "cache_dir",
+    "Absolute path of a content-addressed cache of HTTP downloads, keyed by sha256, which can be shared by every checkout on a machine.",
+)
+.read_at_daemon_startup();
+
+static HASH_ALL_COMMANDS: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "hash_all_commands",
+    "Whether to hash all commands, including those that only run locally.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+static NESTED_INVOCATION: ConfigKey<NestedInvocation> = ConfigKey::new(
+    "buck2",
+    "nested_invocation",
+    "What to do when buck2 is invoked by an action it is running: `run` or `error`.",
+)
+.with_default("run")
+.read_at_daemon_startup();
+
+static PARALLEL_INVOCATION: ConfigKey<ParallelInvocation> = ConfigKey::new(
+    "buck2",
+    "parallel_invocation",
+    "What to do when a command with different state starts while another runs: `run` or `block`.",
+)
+.with_default("run")
+.read_at_daemon_startup();
+
+static DICE_CLEANUP: ConfigKey<DiceCleanup> = ConfigKey::new(
+    "buck2",
+    "dice_cleanup",
+    "What to do when a command starts while DICE is cleaning up after another: `run` or `block`.",
+)
+.with_default("run")
+.read_at_daemon_startup();
+
+static EVENT_LOG_BUFFER_SIZE: ConfigKey<usize> = ConfigKey::new(
+    "buck2",
+    "event_log_buffer_size",
+    "How many events to buffer before sending them to Scribe.",
+)
+.with_default("10000")
+.read_at_daemon_startup();
+
+static EVENT_LOG_RETRY_BACKOFF_DURATION_MS: ConfigKey<u64> = ConfigKey::new(
+    "buck2",
+    "event_log_retry_backoff_duration_ms",
+    "How long to wait before retrying to send events to Scribe.",
+)
+.with_default("500")
+.read_at_daemon_startup();
+
+static EVENT_LOG_RETRY_ATTEMPTS: ConfigKey<usize> = ConfigKey::new(
+    "buck2",
+    "event_log_retry_attempts",
+    "How many times to try sending events to Scribe.",
+)
+.with_default("5")
+.read_at_daemon_startup();
+
+static EVENT_LOG_MESSAGE_BATCH_SIZE: ConfigKey<usize> = ConfigKey::new(
+    "buck2",
+    "event_log_message_batch_size",
+    "How many events to send to Scribe at once.",
+)
+.read_at_daemon_startup();
+
+static CRITICAL_PATH_BACKEND: ConfigKey<CriticalPathBackendName> = ConfigKey::new(
+    "buck2",
+    "critical_path_backend2",
+    "How to compute the critical path: `default` or `longest-path-graph`.",
+)
+.with_default("default")
+.read_at_daemon_startup();
+
+static CRITICAL_PATH_LOG_GRAPH: ConfigKey<bool> = ConfigKey::new(
+    "buck2",
+    "critical_path_log_graph",
+    "Whether to log the graph the critical path is computed from.",
+)
+.with_default("false")
+.read_at_daemon_startup();
+
+static DICE_EXPLORER_PORT: ConfigKey<u16> = ConfigKey::new(
+    "buck2",
+    "dice_explorer_port",
+    "Serve a read-only DICE graph explorer on this local port (0 picks one, see `buck2 status`).",
+)
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| {
+            schema.declare(&DIGEST_ALGORITHMS);
+            schema.declare(&DEFER_WRITE_ACTIONS);
+            schema.declare(&TTL_REFRESH_FREQUENCY_SECONDS);
+            schema.declare(&TTL_REFRESH_MIN_TTL_SECONDS);
+            schema.declare(&TTL_REFRESH_ENABLED);
+            schema.declare(&LOCAL_STORE);
+            schema.declare(&OFFLINE_BLOBS);
+            schema.declare(&DOWNLOAD_NETRC);
+            schema.declare(&DOWNLOAD_CACHE_DIR);
+            schema.declare(&HASH_ALL_COMMANDS);
+            schema.declare(&NESTED_INVOCATION);
+            schema.declare(&PARALLEL_INVOCATION);
+            schema.declare(&DICE_CLEANUP);
+            schema.declare(&EVENT_LOG_BUFFER_SIZE);
+            schema.declare(&EVENT_LOG_RETRY_BACKOFF_DURATION_MS);
+            schema.declare(&EVENT_LOG_RETRY_ATTEMPTS);
+            schema.declare(&EVENT_LOG_MESSAGE_BATCH_SIZE);
+            schema.declare(&CRITICAL_PATH_BACKEND);
+            schema.declare(&CRITICAL_PATH_LOG_GRAPH);
+            schema.declare(&DICE_EXPLORER_PORT);
+        },
+    }
+}
+
 /// For a buckd process there is a single DaemonState created at startup and never destroyed.
 #[derive(Allocative)]
 pub struct DaemonState {
@@ -130,6 +322,17 @@ pub struct DaemonStateData {
     pub create_unhashed_outputs_lock: Arc<Mutex<()>>,
 
     pub critical_path_backend: CriticalPathBackendName,
+
+    /// Whether to log the whole build graph along with the critical path.
+    pub critical_path_log_graph: bool,
+
+    /// Peak memory observed for local actions, used to admit them against the memory budget.
+    #[allocative(skip)]
+    pub memory_estimates: Arc<MemoryEstimates>,
+
+    /// Where the DICE explorer is listening, if it is enabled and could be started.
+    #[allocative(skip)]
+    pub dice_explorer_address: Option<SocketAddr>,
 }
 
 impl DaemonStateData {
@@ -191,7 +394,8 @@ impl DaemonState {
             .context("No config for root cell")?;
 
         let digest_algorithms = root_config
-            .parse_list("buck2", "digest_algorithms")?
+            .read(&DIGEST_ALGORITHMS)?
+            .map(|algorithms| algorithms.0)
             .unwrap_or_else(|| {
                 if buck2_core::is_open_source() {
                     vec![DigestAlgorithm::Sha256]
@@ -218,6 +422,21 @@ impl DaemonState {
             })
             .collect::<anyhow::Result<_>>()?;
 
+        let netrc = root_config
+            .read(&DOWNLOAD_NETRC)?
+            .map(|path| Netrc::from_file(&AbsNormPathBuf::from(path)?))
+            .transpose()
+            .context("Invalid `download.netrc`")?;
+        let download_cache = root_config
+            .read(&DOWNLOAD_CACHE_DIR)?
+            .map(|dir| anyhow::Ok(DownloadCache::new(AbsNormPathBuf::from(dir)?)))
+            .transpose()
+            .context("Invalid `download.cache_dir`")?;
+        set_download_config(DownloadConfig {
+            netrc,
+            cache: download_cache,
+        });
+
         let materialization_method =
             MaterializationMethod::try_new_from_config(legacy_configs.get(cells.root_cell()).ok())?;
         let disk_state_options = DiskStateOptions::new(root_config, materialization_method.dupe())?;
@@ -227,25 +446,24 @@ impl DaemonState {
         let fs_duped = fs.dupe();
 
         let deferred_materializer_configs = {
-            let defer_write_actions = root_config
-                .parse::<RolloutPercentage>("buck2", "defer_write_actions")?
-                .unwrap_or_else(RolloutPercentage::never)
-                .roll();
+            let defer_write_actions = root_config.read_or_default(&DEFER_WRITE_ACTIONS)?.roll();
 
             // RE will refresh any TTL < 1 hour, so we check twice an hour and refresh any TTL
             // < 1 hour.
-            let ttl_refresh_frequency = root_config
-                .parse("buck2", "ttl_refresh_frequency_seconds")?
-                .unwrap_or(1800);
+            let ttl_refresh_frequency =
+                root_config.read_or_default(&TTL_REFRESH_FREQUENCY_SECONDS)?;
+
+            let ttl_refresh_min_ttl = root_config.read_or_default(&TTL_REFRESH_MIN_TTL_SECONDS)?;
 
-            let ttl_refresh_min_ttl = root_config
-                .parse("buck2", "ttl_refresh_min_ttl_seconds")?
-                .unwrap_or(3600);
+            let ttl_refresh_enabled = root_config.read_or_default(&TTL_REFRESH_ENABLED)?.roll();
 
-            let ttl_refresh_enabled = root_config
-                .parse::<RolloutPercentage>("buck2", "ttl_refresh_enabled")?
-                .unwrap_or_else(RolloutPercentage::never)
-                .roll();
+            let local_store = root_config.read(&LOCAL_STORE)?;
+
+            let offline_blobs = root_config
+                .read(&OFFLINE_BLOBS)?
+                .map(|dir| -> anyhow::Result<_> { Ok(fs.resolve(ProjectRelativePath::new(&dir)?)) })
+                .transpose()
+                .context("Invalid `buck2.offline_blobs`")?;
 
             DeferredMaterializerConfigs {
                 materialize_final_artifacts: matches!(
@@ -258,6 +476,9 @@ impl DaemonState {
                     min_ttl: chrono::Duration::seconds(ttl_refresh_min_ttl),
                     enabled: ttl_refresh_enabled,
                 },
+                local_store,
+                offline_blobs,
+                trace_downloads: init_ctx.enable_trace_io,
             }
         };
 
@@ -323,43 +544,29 @@ impl DaemonState {
 
         let file_watcher = <dyn FileWatcher>::new(
             paths.project_root(),
+            &paths.file_watcher_state_path(),
             root_config,
             cells.dupe(),
             ignore_specs,
         )
         .context("Error creating a FileWatcher")?;
 
-        let hash_all_commands = root_config
-            .parse::<RolloutPercentage>("buck2", "hash_all_commands")?
-            .unwrap_or_else(RolloutPercentage::never)
-            .roll();
+        let hash_all_commands = root_config.read_or_default(&HASH_ALL_COMMANDS)?.roll();
 
-        let nested_invocation_config = root_config
-            .parse::<NestedInvocation>("buck2", "nested_invocation")?
-            .unwrap_or(NestedInvocation::Run);
+        let nested_invocation_config = root_config.read_or_default(&NESTED_INVOCATION)?;
 
-        let parallel_invocation_config = root_config
-            .parse::<ParallelInvocation>("buck2", "parallel_invocation")?
-            .unwrap_or(ParallelInvocation::Run);
+        let parallel_invocation_config = root_config.read_or_default(&PARALLEL_INVOCATION)?;
 
-        let cleanup_config = root_config
-            .parse::<DiceCleanup>("buck2", "dice_cleanup")?
-            .unwrap_or(DiceCleanup::Run);
+        let cleanup_config = root_config.read_or_default(&DICE_CLEANUP)?;
 
         let create_unhashed_outputs_lock = Arc::new(Mutex::new(()));
 
-        let buffer_size = root_config
-            .parse("buck2", "event_log_buffer_size")?
-            .unwrap_or(10000);
+        let buffer_size = root_config.read_or_default(&EVENT_LOG_BUFFER_SIZE)?;
         let retry_backoff = Duration::from_millis(
-            root_config
-                .parse("buck2", "event_log_retry_backoff_duration_ms")?
-                .unwrap_or(500),
+            root_config.read_or_default(&EVENT_LOG_RETRY_BACKOFF_DURATION_MS)?,
         );
-        let retry_attempts = root_config
-            .parse("buck2", "event_log_retry_attempts")?
-            .unwrap_or(5);
-        let message_batch_size = root_config.parse("buck2", "event_log_message_batch_size")?;
+        let retry_attempts = root_config.read_or_default(&EVENT_LOG_RETRY_ATTEMPTS)?;
+        let message_batch_size = root_config.read(&EVENT_LOG_MESSAGE_BATCH_SIZE)?;
         let scribe_sink = Self::init_scribe_sink(
             fb,
             buffer_size,
@@ -369,9 +576,24 @@ impl DaemonState {
         )
         .context("failed to init scribe sink")?;
 
-        let critical_path_backend = root_config
-            .parse("buck2", "critical_path_backend2")?
-            .unwrap_or(CriticalPathBackendName::Default);
+        let critical_path_backend = root_config.read_or_default(&CRITICAL_PATH_BACKEND)?;
+        let critical_path_log_graph = root_config.read_or_default(&CRITICAL_PATH_LOG_GRAPH)?;
+
+        // The explorer is a debugging aid, so failing to start it (e.g. because the port is
+        // busy) shouldn't keep the daemon from starting.
+        let dice_explorer_address = match root_config.read(&DICE_EXPLORER_PORT)? {
+            Some(port) => match crate::daemon::dice_explorer::spawn(dice.dupe(), port) {
+                Ok(addr) => {
+                    tracing::info!("DICE explorer listening on http://{}", addr);
+                    Some(addr)
+                }
+                Err(e) => {
+                    tracing::warn!("Not starting the DICE explorer: {:#}", e);
+                    None
+                }
+            },
+            None => None,
+        };
 
         // Kick off an initial sync eagerly. This gets Watchamn to start watching the path we care
         // about (potentially kicking off an initial crawl).
@@ -397,6 +619,9 @@ impl DaemonState {
             start_time: std::time::Instant::now(),
             create_unhashed_outputs_lock,
             critical_path_backend,
+            critical_path_log_graph,
+            memory_estimates: Arc::new(MemoryEstimates::new()),
+            dice_explorer_address,
         }))
     }
 
@@ -562,6 +787,7 @@ impl DaemonState {
             _drop_guard: drop_guard,
             daemon_start_time: data.start_time,
             create_unhashed_outputs_lock: data.create_unhashed_outputs_lock.dupe(),
+            memory_estimates: data.memory_estimates.dupe(),
         })
     }
 
@@ -0,0 +1,568 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use allocative::Allocative;
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_common::dice::file_ops::FileChangeTracker;
+use buck2_common::ignores::IgnoreSet;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::CellResolver;
+use buck2_core::collections::ordered_set::OrderedSet;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_events::dispatch::span_async;
+use dice::DiceTransactionUpdater;
+use dupe::Dupe;
+use tracing::info;
+use tracing::warn;
+
+use crate::file_watcher::inotify::journal::Journal;
+use crate::file_watcher::inotify::journal::JournalChange;
+use crate::file_watcher::inotify::journal::JournalRecord;
+use crate::file_watcher::inotify::sys::Inotify;
+use crate::file_watcher::inotify::sys::RawEvent;
+use crate::file_watcher::stats::FileWatcherStats;
+use crate::file_watcher::FileWatcher;
+
+/// Events can be reported a little after the change they describe happened, so when resuming
+/// from a journal we also look at anything that changed shortly before its last record.
+const RESUME_SLACK: Duration = Duration::from_secs(5);
+
+/// How often the event thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What to report when crawling a directory tree to watch it.
+#[derive(Clone, Copy)]
+enum CrawlReport {
+    /// Nothing: we only need the watches.
+    Nothing,
+    /// Entries whose inode changed after this time. Used when resuming from a journal.
+    ChangedSince(SystemTime),
+    /// Everything, as created. Used when a directory is created.
+    Everything,
+}
+
+/// State shared between the event thread and `sync`.
+struct WatcherState {
+    journal: Journal,
+    /// Changes since the last sync, relative to the project root.
+    changes: Vec<(JournalChange, String)>,
+    /// If set, we can't tell what changed, so the next sync has to start from scratch.
+    fresh_instance_reason: Option<String>,
+}
+
+impl WatcherState {
+    fn record(&mut self, changes: Vec<(JournalChange, String)>, overflowed: bool) {
+        let mut records = changes
+            .iter()
+            .map(|(change, path)| JournalRecord::Change(*change, path.clone()))
+            .collect::<Vec<_>>();
+        if overflowed {
+            records.push(JournalRecord::Overflow);
+            self.fresh_instance_reason = Some("inotify event queue overflowed".to_owned());
+        }
+        if let Err(e) = self.journal.append(&records) {
+            // A later daemon won't be able to trust the journal, so this one can't either.
+            warn!("Error writing file watcher journal: {:#}", e);
+            self.fresh_instance_reason = Some("Error writing file watcher journal".to_owned());
+        }
+        self.changes.extend(changes);
+    }
+}
+
+/// The inotify watches, keyed by watch descriptor.
+struct Watches {
+    inotify: Inotify,
+    root: ProjectRoot,
+    /// Path of each watched directory, relative to the project root.
+    dirs: HashMap<i32, String>,
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn is_buck_out(path: &str) -> bool {
+    // We ignore buck-out for the same reasons as the notify watcher: these are changes we make.
+    let prefix = InvocationPaths::buck_out_dir_prefix().as_str();
+    path.strip_prefix(prefix)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn changed_time(metadata: &std::fs::Metadata) -> SystemTime {
+    use std::os::unix::fs::MetadataExt;
+
+    SystemTime::UNIX_EPOCH
+        + Duration::new(
+            metadata.ctime().try_into().unwrap_or_default(),
+            metadata.ctime_nsec().try_into().unwrap_or_default(),
+        )
+}
+
+impl Watches {
+    /// Watch the directory at `dir` and everything below it.
+    fn watch_recursive(
+        &mut self,
+        dir: String,
+        report: CrawlReport,
+        changes: &mut Vec<(JournalChange, String)>,
+    ) -> anyhow::Result<()> {
+        let mut stack = vec![dir];
+        while let Some(dir) = stack.pop() {
+            if is_buck_out(&dir) {
+                continue;
+            }
+            let abs = self.root.resolve(ProjectRelativePath::unchecked_new(&dir));
+
+            // Watch before listing, so that nothing created in between is missed.
+            let wd = match self.inotify.add_dir_watch(abs.as_ref()) {
+                Ok(wd) => wd,
+                // The directory is gone already, or was replaced by a file. We'll get an event.
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ENOENT | libc::ENOTDIR)) => {
+                    continue;
+                }
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                    return Err(anyhow::Error::from(e).context(
+                        "Ran out of inotify watches, consider raising `fs.inotify.max_user_watches`",
+                    ));
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::from(e))
+                        .with_context(|| format!("Error watching `{}`", abs));
+                }
+            };
+            self.dirs.insert(wd, dir.clone());
+
+            if let CrawlReport::ChangedSince(since) = report {
+                if let Ok(metadata) = std::fs::symlink_metadata(&abs) {
+                    if changed_time(&metadata) > since {
+                        changes.push((JournalChange::DirModified, dir.clone()));
+                    }
+                }
+            }
+
+            let entries = match std::fs::read_dir(&abs) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(anyhow::Error::from(e))
+                        .with_context(|| format!("Error listing `{}`", abs));
+                }
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_) => {
+                        // We can't represent this path, but we can say its directory changed.
+                        changes.push((JournalChange::DirModified, dir.clone()));
+                        continue;
+                    }
+                };
+                let path = join(&dir, &name);
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                let is_dir = metadata.is_dir();
+
+                match report {
+                    CrawlReport::Nothing => {}
+                    CrawlReport::ChangedSince(since) => {
+                        // Directories report their own changes when we visit them.
+                        if !is_dir && changed_time(&metadata) > since {
+                            changes.push((JournalChange::FileModified, path.clone()));
+                        }
+                    }
+                    CrawlReport::Everything => changes.push((
+                        if is_dir {
+                            JournalChange::DirCreated
+                        } else {
+                            JournalChange::FileCreated
+                        },
+                        path.clone(),
+                    )),
+                }
+
+                if is_dir {
+                    stack.push(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop watching `dir` and everything below it.
+    fn unwatch_recursive(&mut self, dir: &str) {
+        let prefix = format!("{}/", dir);
+        let inotify = &self.inotify;
+        self.dirs.retain(|wd, path| {
+            let keep = path != dir && !path.starts_with(&prefix);
+            if !keep {
+                inotify.rm_watch(*wd);
+            }
+            keep
+        });
+    }
+
+    /// Turn raw events into changes. Returns whether the kernel dropped events.
+    fn process(
+        &mut self,
+        events: Vec<RawEvent>,
+        changes: &mut Vec<(JournalChange, String)>,
+    ) -> anyhow::Result<bool> {
+        let mut overflowed = false;
+        for event in events {
+            if event.is(libc::IN_Q_OVERFLOW) {
+                overflowed = true;
+                continue;
+            }
+            if event.is(libc::IN_IGNORED) {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            // Events about a watched directory itself are also reported on its parent.
+            if event.name.is_empty() {
+                continue;
+            }
+            let dir = match self.dirs.get(&event.wd) {
+                Some(dir) => dir.clone(),
+                None => continue,
+            };
+            let name = match event.name.into_string() {
+                Ok(name) => name,
+                Err(_) => {
+                    changes.push((JournalChange::DirModified, dir));
+                    continue;
+                }
+            };
+            let path = join(&dir, &name);
+            if is_buck_out(&path) {
+                continue;
+            }
+
+            let is_dir = event.mask & libc::IN_ISDIR != 0;
+            if event.is(libc::IN_CREATE | libc::IN_MOVED_TO) {
+                if is_dir {
+                    changes.push((JournalChange::DirCreated, path.clone()));
+                    // Anything created in there before the watch is added wouldn't be reported.
+                    self.watch_recursive(path, CrawlReport::Everything, changes)?;
+                } else {
+                    changes.push((JournalChange::FileCreated, path));
+                }
+            } else if event.is(libc::IN_DELETE | libc::IN_MOVED_FROM) {
+                if is_dir {
+                    self.unwatch_recursive(&path);
+                    changes.push((JournalChange::DirRemoved, path));
+                } else {
+                    changes.push((JournalChange::FileRemoved, path));
+                }
+            } else if !is_dir && event.is(libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE)
+            {
+                changes.push((JournalChange::FileModified, path));
+            }
+        }
+        Ok(overflowed)
+    }
+
+    fn run(mut self, state: Arc<Mutex<WatcherState>>, stop: Arc<AtomicBool>) {
+        while !stop.load(Ordering::Relaxed) {
+            let mut changes = Vec::new();
+            let res = self
+                .inotify
+                .read_events(POLL_INTERVAL)
+                .map_err(anyhow::Error::from)
+                .and_then(|events| self.process(events, &mut changes));
+
+            let mut state = state.lock().unwrap();
+            match res {
+                Ok(overflowed) => {
+                    if !changes.is_empty() || overflowed {
+                        state.record(changes, overflowed);
+                    }
+                }
+                Err(e) => {
+                    // We might be missing events from now on, so give up. Every sync will start
+                    // from scratch.
+                    warn!("inotify file watcher failed: {:#}", e);
+                    state.record(changes, true);
+                    state.fresh_instance_reason =
+                        Some(format!("inotify file watcher failed: {:#}", e));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// A file watcher using Linux inotify directly. Observed changes are persisted to a journal, so
+/// that a restarted daemon can pick up where the previous one left off instead of starting from
+/// scratch.
+#[derive(Allocative)]
+pub(crate) struct InotifyFileWatcher {
+    #[allocative(skip)]
+    state: Arc<Mutex<WatcherState>>,
+    #[allocative(skip)]
+    stop: Arc<AtomicBool>,
+    #[allocative(skip)]
+    cells: CellResolver,
+    #[allocative(skip)]
+    ignore_specs: HashMap<CellName, IgnoreSet>,
+}
+
+impl InotifyFileWatcher {
+    pub(crate) fn new(
+        root: &ProjectRoot,
+        journal_path: &AbsNormPath,
+        cells: CellResolver,
+        ignore_specs: HashMap<CellName, IgnoreSet>,
+    ) -> anyhow::Result<Self> {
+        let (mut journal, recovered) = Journal::open(journal_path.as_ref(), root.root().as_str())
+            .context("Error opening file watcher journal")?;
+
+        let mut watches = Watches {
+            inotify: Inotify::new().context("Error initializing inotify")?,
+            root: root.dupe(),
+            dirs: HashMap::new(),
+        };
+
+        let (mut changes, report, fresh_instance_reason) = match recovered {
+            Some(recovered) if !recovered.overflowed => {
+                let since = recovered
+                    .last_record_time
+                    .checked_sub(RESUME_SLACK)
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                (recovered.pending, CrawlReport::ChangedSince(since), None)
+            }
+            Some(_) => (
+                Vec::new(),
+                CrawlReport::Nothing,
+                Some("inotify event queue overflowed before the daemon restarted"),
+            ),
+            None => (
+                Vec::new(),
+                CrawlReport::Nothing,
+                Some("No usable file watcher journal"),
+            ),
+        };
+
+        let mut crawled = Vec::new();
+        watches.watch_recursive(String::new(), report, &mut crawled)?;
+        info!(
+            "inotify: watching {} directories, resumed = {}, {} changes found while stopped",
+            watches.dirs.len(),
+            fresh_instance_reason.is_none(),
+            crawled.len(),
+        );
+
+        // Journal what we found, so that it isn't lost if we don't get to sync it.
+        journal.append(
+            &crawled
+                .iter()
+                .map(|(change, path)| JournalRecord::Change(*change, path.clone()))
+                .collect::<Vec<_>>(),
+        )?;
+        changes.extend(crawled);
+
+        let state = Arc::new(Mutex::new(WatcherState {
+            journal,
+            changes,
+            fresh_instance_reason: fresh_instance_reason.map(ToOwned::to_owned),
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        std::thread::Builder::new()
+            .name("buck2-inotify".to_owned())
+            .spawn({
+                let state = state.dupe();
+                let stop = stop.dupe();
+                move || watches.run(state, stop)
+            })
+            .context("Error starting inotify thread")?;
+
+        Ok(Self {
+            state,
+            stop,
+            cells,
+            ignore_specs,
+        })
+    }
+
+    fn sync2(
+        &self,
+        mut dice: DiceTransactionUpdater,
+    ) -> anyhow::Result<(buck2_data::FileWatcherStats, DiceTransactionUpdater)> {
+        let mut state = self.state.lock().unwrap();
+        let changes = mem::take(&mut state.changes);
+
+        let mut stats = match state.fresh_instance_reason.take() {
+            Some(reason) => {
+                info!("inotify: starting from scratch: {}", reason);
+                dice = dice.unstable_take();
+                buck2_data::FileWatcherStats {
+                    fresh_instance: true,
+                    incomplete_events_reason: Some(reason),
+                    ..Default::default()
+                }
+            }
+            None => {
+                let (stats, tracker) = self.process_changes(changes)?;
+                tracker.write_to_dice(&mut dice)?;
+                stats
+            }
+        };
+
+        // If this fails, a later daemon will just apply these changes again.
+        if let Err(e) = state.journal.synced() {
+            warn!("Error writing file watcher journal: {:#}", e);
+        }
+        stats.journal_clock = Some(state.journal.clock());
+
+        Ok((stats, dice))
+    }
+
+    fn process_changes(
+        &self,
+        changes: Vec<(JournalChange, String)>,
+    ) -> anyhow::Result<(buck2_data::FileWatcherStats, FileChangeTracker)> {
+        let changes = changes.into_iter().collect::<OrderedSet<_>>();
+        let mut tracker = FileChangeTracker::new();
+        let mut stats = FileWatcherStats::new(changes.len(), None, None);
+
+        for (change, path) in changes {
+            // Paths we can't represent only invalidate their closest valid parent directory.
+            let (change, path) = match ProjectRelativePath::new(&path) {
+                Ok(path) => (change, path.to_buf()),
+                Err(_) => (
+                    JournalChange::DirModified,
+                    first_valid_parent(&path).to_buf(),
+                ),
+            };
+
+            let cell_path = self.cells.get_cell_path(&path)?;
+            let ignore = self
+                .ignore_specs
+                .get(&cell_path.cell())
+                .expect("unexpected cell name mismatch")
+                .is_match(cell_path.path());
+
+            info!("inotify: {:?} {} (ignore = {})", change, path, ignore);
+
+            if ignore {
+                stats.add_ignored(1);
+                continue;
+            }
+
+            let cell_path_str = cell_path.to_string();
+            let (event, kind) = match change {
+                JournalChange::FileCreated => {
+                    tracker.file_added(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Create,
+                        buck2_data::FileWatcherKind::File,
+                    )
+                }
+                JournalChange::FileModified => {
+                    tracker.file_changed(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Modify,
+                        buck2_data::FileWatcherKind::File,
+                    )
+                }
+                JournalChange::FileRemoved => {
+                    tracker.file_removed(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Delete,
+                        buck2_data::FileWatcherKind::File,
+                    )
+                }
+                JournalChange::DirCreated => {
+                    tracker.dir_added(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Create,
+                        buck2_data::FileWatcherKind::Directory,
+                    )
+                }
+                JournalChange::DirRemoved => {
+                    tracker.dir_removed(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Delete,
+                        buck2_data::FileWatcherKind::Directory,
+                    )
+                }
+                JournalChange::DirModified => {
+                    tracker.dir_changed(cell_path);
+                    (
+                        buck2_data::FileWatcherEventType::Modify,
+                        buck2_data::FileWatcherKind::Directory,
+                    )
+                }
+            };
+            stats.add(cell_path_str, event, kind);
+        }
+
+        Ok((stats.finish(), tracker))
+    }
+}
+
+fn first_valid_parent(mut path: &str) -> &ProjectRelativePath {
+    loop {
+        path = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        if let Ok(path) = ProjectRelativePath::new(path) {
+            return path;
+        }
+    }
+}
+
+impl Drop for InotifyFileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl FileWatcher for InotifyFileWatcher {
+    async fn sync(&self, dice: DiceTransactionUpdater) -> anyhow::Result<DiceTransactionUpdater> {
+        span_async(
+            buck2_data::FileWatcherStart {
+                provider: buck2_data::FileWatcherProvider::Inotify as i32,
+            },
+            async {
+                let (stats, res) = match self.sync2(dice) {
+                    Ok((stats, dice)) => ((Some(stats)), Ok(dice)),
+                    Err(e) => (None, Err(e)),
+                };
+                (res, buck2_data::FileWatcherEnd { stats })
+            },
+        )
+        .await
+    }
+}
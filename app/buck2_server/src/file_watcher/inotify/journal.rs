@@ -0,0 +1,492 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! On-disk journal of the changes observed by the inotify watcher.
+//!
+//! The journal is a text file with a header line followed by one line per record:
+//!
+//! ```text
+//! buck2-inotify-journal	1	<project root>
+//! <clock>	<unix time nanos>	<tag>	<path>
+//! ```
+//!
+//! Records are only ever appended, one `write` per batch of complete lines, so a crash can at
+//! worst leave a truncated last line, which we discard. Once the changes have been applied to
+//! DICE, the journal is atomically replaced with one that only contains a `synced` record.
+//!
+//! A journal that starts with a `synced` record describes everything that changed since the
+//! previous daemon had a consistent view of the repository, up to the time of its last record.
+//! Losing records (e.g. on power loss) only makes that time earlier, which is safe.
+
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::Context;
+
+const MAGIC: &str = "buck2-inotify-journal";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum JournalChange {
+    FileCreated,
+    FileModified,
+    FileRemoved,
+    DirCreated,
+    DirRemoved,
+    /// Entries may have been added to or removed from the directory.
+    DirModified,
+}
+
+impl JournalChange {
+    fn tag(self) -> &'static str {
+        match self {
+            Self::FileCreated => "fc",
+            Self::FileModified => "fm",
+            Self::FileRemoved => "fr",
+            Self::DirCreated => "dc",
+            Self::DirRemoved => "dr",
+            Self::DirModified => "dm",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        Some(match tag {
+            "fc" => Self::FileCreated,
+            "fm" => Self::FileModified,
+            "fr" => Self::FileRemoved,
+            "dc" => Self::DirCreated,
+            "dr" => Self::DirRemoved,
+            "dm" => Self::DirModified,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum JournalRecord {
+    /// A change to a path, relative to the project root.
+    Change(JournalChange, String),
+    /// The kernel dropped events, so we don't know what changed.
+    Overflow,
+    /// All records before this one were applied.
+    Synced,
+}
+
+/// What a previous daemon left in the journal, if it can be trusted.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RecoveredJournal {
+    /// Changes that were recorded but never applied.
+    pub(crate) pending: Vec<(JournalChange, String)>,
+    /// Whether events were dropped since the last sync.
+    pub(crate) overflowed: bool,
+    /// When the last record was written. Anything that changed after this was not observed.
+    pub(crate) last_record_time: SystemTime,
+}
+
+pub(crate) struct Journal {
+    path: PathBuf,
+    root: String,
+    file: File,
+    /// Monotonic across daemon restarts, as long as the journal survives.
+    clock: u64,
+}
+
+impl Journal {
+    /// Open the journal at `path`, for a watcher of the project at `root`. Returns what the
+    /// previous daemon recorded, if that can be used instead of starting from scratch.
+    pub(crate) fn open(
+        path: &Path,
+        root: &str,
+    ) -> anyhow::Result<(Self, Option<RecoveredJournal>)> {
+        let existing = match fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(anyhow::Error::from(e))
+                    .with_context(|| format!("Error reading `{}`", path.display()));
+            }
+        };
+
+        let parsed = existing.as_deref().and_then(|data| parse(data, root));
+
+        match parsed {
+            Some(parsed) => {
+                // Drop anything after the last complete record, so that appending works.
+                fs::OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|f| f.set_len(parsed.valid_len as u64))
+                    .with_context(|| format!("Error truncating `{}`", path.display()))?;
+                Ok((
+                    Self {
+                        path: path.to_owned(),
+                        root: root.to_owned(),
+                        file: open_append(path)?,
+                        clock: parsed.clock,
+                    },
+                    parsed.recovered,
+                ))
+            }
+            None => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)
+                        .with_context(|| format!("Error creating `{}`", dir.display()))?;
+                }
+                let mut clock = 0;
+                let file = write_new(path, root, &mut clock, &[])?;
+                Ok((
+                    Self {
+                        path: path.to_owned(),
+                        root: root.to_owned(),
+                        file,
+                        clock,
+                    },
+                    None,
+                ))
+            }
+        }
+    }
+
+    pub(crate) fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Append records, in a single write.
+    pub(crate) fn append(&mut self, records: &[JournalRecord]) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        let mut buf = String::new();
+        for record in records {
+            self.clock += 1;
+            format_record(&mut buf, self.clock, now, record);
+        }
+        self.file
+            .write_all(buf.as_bytes())
+            .with_context(|| format!("Error appending to `{}`", self.path.display()))
+    }
+
+    /// Record that everything so far was applied. This discards all previous records.
+    pub(crate) fn synced(&mut self) -> anyhow::Result<()> {
+        self.file = write_new(
+            &self.path,
+            &self.root,
+            &mut self.clock,
+            &[JournalRecord::Synced],
+        )?;
+        Ok(())
+    }
+}
+
+/// Atomically replace the journal at `path` with one containing only `records`, and open it for
+/// appending.
+fn write_new(
+    path: &Path,
+    root: &str,
+    clock: &mut u64,
+    records: &[JournalRecord],
+) -> anyhow::Result<File> {
+    let now = SystemTime::now();
+    let mut buf = format!("{}\t{}\t{}\n", MAGIC, VERSION, escape(root));
+    for record in records {
+        *clock += 1;
+        format_record(&mut buf, *clock, now, record);
+    }
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, buf).with_context(|| format!("Error writing `{}`", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Error renaming `{}`", tmp.display()))?;
+    open_append(path)
+}
+
+fn open_append(path: &Path) -> anyhow::Result<File> {
+    fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Error opening `{}`", path.display()))
+}
+
+fn format_record(buf: &mut String, clock: u64, time: SystemTime, record: &JournalRecord) {
+    let nanos = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let (tag, path) = match record {
+        JournalRecord::Change(change, path) => (change.tag(), path.as_str()),
+        JournalRecord::Overflow => ("overflow", ""),
+        JournalRecord::Synced => ("synced", ""),
+    };
+    buf.push_str(&format!(
+        "{}\t{}\t{}\t{}\n",
+        clock,
+        nanos,
+        tag,
+        escape(path)
+    ));
+}
+
+struct Parsed {
+    /// Length of the prefix made of complete, valid lines.
+    valid_len: usize,
+    clock: u64,
+    recovered: Option<RecoveredJournal>,
+}
+
+/// Parse a journal. Returns `None` if this isn't a journal we can append to.
+fn parse(data: &[u8], root: &str) -> Option<Parsed> {
+    let mut lines = CompleteLines { data, pos: 0 };
+
+    let header = lines.next()?;
+    let mut header = header.split('\t');
+    if header.next()? != MAGIC
+        || header.next()?.parse::<u32>().ok()? != VERSION
+        || unescape(header.next()?)? != root
+    {
+        return None;
+    }
+
+    let mut valid_len = lines.pos;
+    let mut clock = 0;
+    let mut first_is_synced = None;
+    let mut pending = Vec::new();
+    let mut overflowed = false;
+    let mut last_record_time = None;
+
+    while let Some(line) = lines.next() {
+        let Some((record_clock, time, record)) = parse_record(line) else {
+            break;
+        };
+        if record_clock <= clock {
+            break;
+        }
+        clock = record_clock;
+        valid_len = lines.pos;
+        last_record_time = Some(time);
+        first_is_synced.get_or_insert(record == JournalRecord::Synced);
+
+        match record {
+            JournalRecord::Change(change, path) => pending.push((change, path)),
+            JournalRecord::Overflow => overflowed = true,
+            JournalRecord::Synced => {
+                pending.clear();
+                overflowed = false;
+            }
+        }
+    }
+
+    let recovered = match (first_is_synced, last_record_time) {
+        (Some(true), Some(last_record_time)) => Some(RecoveredJournal {
+            pending,
+            overflowed,
+            last_record_time,
+        }),
+        _ => None,
+    };
+
+    Some(Parsed {
+        valid_len,
+        clock,
+        recovered,
+    })
+}
+
+fn parse_record(line: &str) -> Option<(u64, SystemTime, JournalRecord)> {
+    let mut parts = line.split('\t');
+    let clock = parts.next()?.parse().ok()?;
+    let nanos: u64 = parts.next()?.parse().ok()?;
+    let tag = parts.next()?;
+    let path = unescape(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let record = match tag {
+        "overflow" => JournalRecord::Overflow,
+        "synced" => JournalRecord::Synced,
+        tag => JournalRecord::Change(JournalChange::from_tag(tag)?, path),
+    };
+    Some((
+        clock,
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos),
+        record,
+    ))
+}
+
+/// Iterates over newline-terminated lines, ignoring a trailing partial line.
+struct CompleteLines<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for CompleteLines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = &self.data[self.pos..];
+        let end = rest.iter().position(|b| *b == b'\n')?;
+        let line = std::str::from_utf8(&rest[..end]).ok()?;
+        self.pos += end + 1;
+        Some(line)
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => out.push('\\'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                _ => return None,
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(change: JournalChange, path: &str) -> JournalRecord {
+        JournalRecord::Change(change, path.to_owned())
+    }
+
+    #[test]
+    fn test_escape() {
+        for s in ["", "a/b", "a\tb\nc\\d", "\\t"] {
+            assert_eq!(unescape(&escape(s)).as_deref(), Some(s));
+        }
+        assert_eq!(unescape("\\x"), None);
+    }
+
+    #[test]
+    fn test_new_journal_is_not_trusted() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let (mut journal, recovered) = Journal::open(&path, "/repo")?;
+        assert_eq!(recovered, None);
+        journal.append(&[change(JournalChange::FileModified, "a")])?;
+        drop(journal);
+
+        // No sync ever happened, so the recorded changes aren't everything that changed.
+        let (_journal, recovered) = Journal::open(&path, "/repo")?;
+        assert_eq!(recovered, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let (mut journal, _) = Journal::open(&path, "/repo")?;
+        journal.append(&[change(JournalChange::FileModified, "a")])?;
+        journal.synced()?;
+        journal.append(&[
+            change(JournalChange::FileCreated, "b\tc"),
+            change(JournalChange::DirRemoved, "d"),
+        ])?;
+        let clock = journal.clock();
+        drop(journal);
+
+        let (journal, recovered) = Journal::open(&path, "/repo")?;
+        let recovered = recovered.unwrap();
+        assert_eq!(
+            recovered.pending,
+            vec![
+                (JournalChange::FileCreated, "b\tc".to_owned()),
+                (JournalChange::DirRemoved, "d".to_owned()),
+            ]
+        );
+        assert!(!recovered.overflowed);
+        assert!(recovered.last_record_time <= SystemTime::now());
+        assert_eq!(journal.clock(), clock);
+
+        // A different project can't use it.
+        let (journal, recovered) = Journal::open(&path, "/other")?;
+        assert_eq!(recovered, None);
+        assert_eq!(journal.clock(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_overflow() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let (mut journal, _) = Journal::open(&path, "/repo")?;
+        journal.synced()?;
+        journal.append(&[JournalRecord::Overflow])?;
+        drop(journal);
+
+        let (_journal, recovered) = Journal::open(&path, "/repo")?;
+        assert!(recovered.unwrap().overflowed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_record_is_discarded() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("journal");
+
+        let (mut journal, _) = Journal::open(&path, "/repo")?;
+        journal.synced()?;
+        journal.append(&[change(JournalChange::FileModified, "a")])?;
+        drop(journal);
+
+        // Simulate a crash in the middle of a write.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"9\t123\tfm\tpartial")?;
+
+        let (mut journal, recovered) = Journal::open(&path, "/repo")?;
+        assert_eq!(
+            recovered.unwrap().pending,
+            vec![(JournalChange::FileModified, "a".to_owned())]
+        );
+
+        // Appending after recovery produces a valid journal.
+        journal.append(&[change(JournalChange::FileRemoved, "b")])?;
+        drop(journal);
+        let (_journal, recovered) = Journal::open(&path, "/repo")?;
+        assert_eq!(
+            recovered.unwrap().pending,
+            vec![
+                (JournalChange::FileModified, "a".to_owned()),
+                (JournalChange::FileRemoved, "b".to_owned()),
+            ]
+        );
+        Ok(())
+    }
+}
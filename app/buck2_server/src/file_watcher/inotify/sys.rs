@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Thin wrapper around the inotify syscalls.
+
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::OwnedFd;
+use std::path::Path;
+use std::time::Duration;
+
+/// Events we watch directories for.
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ONLYDIR
+    | libc::IN_DONT_FOLLOW
+    | libc::IN_EXCL_UNLINK;
+
+#[derive(Debug)]
+pub(crate) struct RawEvent {
+    pub(crate) wd: i32,
+    pub(crate) mask: u32,
+    /// Name of the entry in the watched directory. Empty for events about the directory itself.
+    pub(crate) name: OsString,
+}
+
+impl RawEvent {
+    pub(crate) fn is(&self, mask: u32) -> bool {
+        self.mask & mask != 0
+    }
+}
+
+pub(crate) struct Inotify {
+    fd: OwnedFd,
+    buf: Vec<u8>,
+}
+
+impl Inotify {
+    pub(crate) fn new() -> io::Result<Self> {
+        // SAFETY: no pointers involved.
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            // SAFETY: we just created this fd and nothing else owns it.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            buf: vec![0; 64 * 1024],
+        })
+    }
+
+    /// Watch a directory (not recursively). Watching the same directory twice returns the same
+    /// watch descriptor.
+    pub(crate) fn add_dir_watch(&self, path: &Path) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: `path` is a valid NUL-terminated string.
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    pub(crate) fn rm_watch(&self, wd: i32) {
+        // This fails if the watch is already gone, which is fine.
+        // SAFETY: no pointers involved.
+        unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+    }
+
+    /// Wait up to `timeout` for events, and return those that are available.
+    pub(crate) fn read_events(&mut self, timeout: Duration) -> io::Result<Vec<RawEvent>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+        // SAFETY: `pollfd` is valid for the duration of the call.
+        let res = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+        if res < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+        if res == 0 {
+            return Ok(Vec::new());
+        }
+
+        // SAFETY: `buf` is valid for writes of its length.
+        let len = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                self.buf.as_mut_ptr() as *mut libc::c_void,
+                self.buf.len(),
+            )
+        };
+        if len < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+
+        Ok(parse_events(&self.buf[..len as usize]))
+    }
+}
+
+fn parse_events(mut buf: &[u8]) -> Vec<RawEvent> {
+    const HEADER: usize = mem::size_of::<libc::inotify_event>();
+
+    let mut events = Vec::new();
+    while buf.len() >= HEADER {
+        // SAFETY: the kernel writes whole `inotify_event` structs, and we checked there is
+        // enough data. The buffer isn't necessarily aligned, hence `read_unaligned`.
+        let event = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const libc::inotify_event) };
+        let end = (HEADER + event.len as usize).min(buf.len());
+        let name = &buf[HEADER..end];
+        // The name is NUL-padded.
+        let name = match name.iter().position(|b| *b == 0) {
+            Some(nul) => &name[..nul],
+            None => name,
+        };
+        events.push(RawEvent {
+            wd: event.wd,
+            mask: event.mask,
+            name: OsString::from_vec(name.to_vec()),
+        });
+        buf = &buf[end..];
+    }
+    events
+}
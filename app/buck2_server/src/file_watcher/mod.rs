@@ -13,9 +13,13 @@ use std::sync::Arc;
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_common::ignores::IgnoreSet;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::is_open_source;
 use dice::DiceTransactionUpdater;
@@ -23,10 +27,25 @@ use dice::DiceTransactionUpdater;
 use crate::file_watcher::notify::NotifyFileWatcher;
 use crate::file_watcher::watchman::interface::WatchmanFileWatcher;
 
+#[cfg(target_os = "linux")]
+mod inotify;
 mod notify;
 mod stats;
 mod watchman;
 
+static FILE_WATCHER: ConfigKey<String> = ConfigKey::new(
+    "buck2",
+    "file_watcher",
+    "Which file watcher to use: `watchman`, `notify` or `inotify` (Linux only). Defaults to `notify` in open source builds.",
+)
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&FILE_WATCHER),
+    }
+}
+
 #[async_trait]
 pub trait FileWatcher: Allocative + Send + Sync + 'static {
     async fn sync(&self, dice: DiceTransactionUpdater) -> anyhow::Result<DiceTransactionUpdater>;
@@ -37,6 +56,7 @@ impl dyn FileWatcher {
     /// startup and shouldn't be doing any work that could warrant suspending.
     pub fn new(
         project_root: &ProjectRoot,
+        state_dir: &AbsNormPath,
         root_config: &LegacyBuckConfig,
         cells: CellResolver,
         ignore_specs: HashMap<CellName, IgnoreSet>,
@@ -47,7 +67,11 @@ impl dyn FileWatcher {
             "watchman"
         };
 
-        match root_config.get("buck2", "file_watcher").unwrap_or(default) {
+        match root_config
+            .read(&FILE_WATCHER)?
+            .as_deref()
+            .unwrap_or(default)
+        {
             "watchman" => Ok(Arc::new(WatchmanFileWatcher::new(
                 project_root.root(),
                 root_config,
@@ -59,6 +83,17 @@ impl dyn FileWatcher {
                 cells,
                 ignore_specs,
             )?)),
+            #[cfg(target_os = "linux")]
+            "inotify" => Ok(Arc::new(inotify::interface::InotifyFileWatcher::new(
+                project_root,
+                &state_dir.join(FileName::unchecked_new("inotify_journal")),
+                cells,
+                ignore_specs,
+            )?)),
+            #[cfg(not(target_os = "linux"))]
+            "inotify" => Err(anyhow::anyhow!(
+                "buck2.file_watcher = inotify is only supported on Linux"
+            )),
             other => Err(anyhow::anyhow!("Invalid buck2.file_watcher: {}", other)),
         }
     }
@@ -15,6 +15,8 @@ use anyhow::Context as _;
 use async_trait::async_trait;
 use buck2_common::dice::file_ops::FileChangeTracker;
 use buck2_common::ignores::IgnoreSet;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
@@ -37,6 +39,20 @@ use crate::file_watcher::watchman::core::WatchmanEventType;
 use crate::file_watcher::watchman::core::WatchmanKind;
 use crate::file_watcher::FileWatcher;
 
+static RETAIN_DEP_FILES_ON_WATCHMAN_FRESH_INSTANCE: ConfigKey<RolloutPercentage> = ConfigKey::new(
+    "buck2",
+    "retain_dep_files_on_watchman_fresh_instance",
+    "Whether to keep dep files when Watchman reports a fresh instance.",
+)
+.with_default("true")
+.read_at_daemon_startup();
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&RETAIN_DEP_FILES_ON_WATCHMAN_FRESH_INSTANCE),
+    }
+}
+
 struct WatchmanQueryProcessor {
     cells: CellResolver,
     ignore_specs: HashMap<CellName, IgnoreSet>,
@@ -270,8 +286,7 @@ impl WatchmanFileWatcher {
             .map(|s| s.to_owned());
 
         let retain_dep_files_on_watchman_fresh_instance = root_config
-            .parse::<RolloutPercentage>("buck2", "retain_dep_files_on_watchman_fresh_instance")?
-            .unwrap_or_else(RolloutPercentage::always)
+            .read_or_default(&RETAIN_DEP_FILES_ON_WATCHMAN_FRESH_INSTANCE)?
             .roll();
 
         let query = SyncableQuery::new(
@@ -39,11 +39,13 @@ fn trace_io(
     req: &buck2_cli_proto::TraceIoRequest,
 ) -> buck2_cli_proto::TraceIoResponse {
     if let Some(provider) = server_ctx.io.as_any().downcast_ref::<TracingIoProvider>() {
+        let with_trace = matches!(
+            req.read_state,
+            Some(trace_io_request::ReadIoTracingState { with_trace: true })
+        );
         buck2_cli_proto::TraceIoResponse {
             enabled: true,
-            trace: if let Some(trace_io_request::ReadIoTracingState { with_trace: true }) =
-                req.read_state
-            {
+            trace: if with_trace {
                 provider
                     .trace()
                     .iter()
@@ -52,11 +54,31 @@ fn trace_io(
             } else {
                 Vec::new()
             },
+            downloads: if with_trace {
+                traced_downloads(server_ctx)
+            } else {
+                Vec::new()
+            },
         }
     } else {
         buck2_cli_proto::TraceIoResponse {
             enabled: false,
             trace: Vec::new(),
+            downloads: Vec::new(),
         }
     }
 }
+
+fn traced_downloads(server_ctx: &BaseServerCommandContext) -> Vec<buck2_cli_proto::TracedDownload> {
+    server_ctx
+        .materializer
+        .as_deferred_materializer_extension()
+        .and_then(|materializer| materializer.traced_downloads())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(path, blob)| buck2_cli_proto::TracedDownload {
+            path: path.to_string(),
+            blob,
+        })
+        .collect()
+}
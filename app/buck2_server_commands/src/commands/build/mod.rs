@@ -31,6 +31,8 @@ use buck2_cli_proto::HasClientContext;
 use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::dice::file_ops::HasFileOps;
 use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::schema::ConfigKey;
+use buck2_common::legacy_configs::schema::ConfigSchemaRegistration;
 use buck2_common::pattern::resolve::resolve_target_patterns;
 use buck2_common::pattern::resolve::ResolvedPattern;
 use buck2_core::fs::fs_util;
@@ -73,6 +75,19 @@ use crate::commands::build::unhashed_outputs::create_unhashed_outputs;
 mod results;
 mod unhashed_outputs;
 
+static CREATE_UNHASHED_LINKS: ConfigKey<bool> = ConfigKey::new(
+    "buck2",
+    "create_unhashed_links",
+    "Whether `buck2 build` creates symlinks to outputs at their unhashed paths.",
+)
+.with_default("false");
+
+inventory::submit! {
+    ConfigSchemaRegistration {
+        register: |schema| schema.declare(&CREATE_UNHASHED_LINKS),
+    }
+}
+
 pub async fn build_command(
     ctx: Box<dyn ServerCommandContextTrait>,
     partial_result_dispatcher: PartialResultDispatcher<NoPartialResult>,
@@ -139,9 +154,15 @@ async fn build(
     let global_target_platform =
         target_platform_from_client_context(client_ctx, server_ctx, &ctx).await?;
 
-    let should_create_unhashed_links = ctx
-        .parse_legacy_config_property(cell_resolver.root_cell(), "buck2", "create_unhashed_links")
-        .await?;
+    let should_create_unhashed_links = CREATE_UNHASHED_LINKS.parse_or_default(
+        ctx.get_legacy_config_property(
+            cell_resolver.root_cell(),
+            CREATE_UNHASHED_LINKS.section,
+            CREATE_UNHASHED_LINKS.key,
+        )
+        .await?
+        .as_deref(),
+    )?;
 
     let parsed_patterns: Vec<ParsedPattern<ConfiguredProvidersPatternExtra>> =
         parse_patterns_from_cli_args(&ctx, &request.target_patterns, cwd).await?;
@@ -243,7 +264,7 @@ async fn build(
         provider_artifacts.extend(&mut outputs);
     }
 
-    if should_create_unhashed_links.unwrap_or(false) {
+    if should_create_unhashed_links {
         span_async(buck2_data::CreateOutputSymlinksStart {}, async {
             let lock = ctx
                 .per_transaction_data()
@@ -15,6 +15,8 @@ use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::net::TcpListener;
 use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::Context;
 use async_trait::async_trait;
@@ -54,15 +56,22 @@ use buck2_core::provider::label::ProvidersName;
 use buck2_core::target::name::TargetName;
 use buck2_data::InstallEventInfoEnd;
 use buck2_data::InstallEventInfoStart;
+use buck2_events::dispatch::current_span;
+use buck2_events::dispatch::get_dispatcher;
 use buck2_events::dispatch::span_async;
+use buck2_events::dispatch::EventDispatcher;
+use buck2_events::span::SpanId;
 use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
 use buck2_execute::artifact::fs::ExecutorFs;
 use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::materialize::materializer::HasMaterializer;
 use buck2_install_proto::installer_client::InstallerClient;
+use buck2_install_proto::CancelRequest;
 use buck2_install_proto::FileReadyRequest;
 use buck2_install_proto::InstallInfoRequest;
+use buck2_install_proto::ProgressRequest;
+use buck2_install_proto::ProgressResponse;
 use buck2_install_proto::ShutdownRequest;
 use buck2_interpreter_for_build::interpreter::calculation::InterpreterCalculation;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
@@ -81,6 +90,7 @@ use dice::DiceTransaction;
 use dupe::Dupe;
 use futures::future::try_join;
 use futures::future::try_join_all;
+use futures::future::Either;
 use futures::future::FutureExt;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
@@ -117,6 +127,11 @@ pub enum InstallError {
     NativeDateTime,
 }
 
+/// How many times a file is sent to the installer when it keeps failing transiently.
+const MAX_FILE_READY_ATTEMPTS: u32 = 3;
+/// Delay before the first resend of a file. It doubles with every further attempt.
+const FILE_READY_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
 async fn get_installer_log_directory(
     server_ctx: &dyn ServerCommandContextTrait,
     ctx: &DiceComputations,
@@ -353,13 +368,17 @@ async fn handle_install_request<'a>(
         build_launch_installer(ctx, installer_label, &installer_run_args, installer_debug).await?;
 
         let client: InstallerClient<Channel> = connect_to_installer(tcp_port).await?;
+        // From here on, if we bail out (or get dropped because the command was interrupted), the
+        // installer is told to stop.
+        let cancel_guard = CancelInstallerOnDrop::new(client.clone());
         let artifact_fs = ctx.get_artifact_fs().await?;
 
         for (install_id, install_files) in install_files_slice {
             send_install_info(client.clone(), install_id, install_files, &artifact_fs).await?;
         }
 
-        let send_files_result = tokio_stream::wrappers::UnboundedReceiverStream::new(files_rx)
+        let file_spans = FileSpans::default();
+        let send_files = tokio_stream::wrappers::UnboundedReceiverStream::new(files_rx)
             .map(anyhow::Ok)
             .try_for_each_concurrent(None, |file| {
                 send_file(
@@ -367,9 +386,23 @@ async fn handle_install_request<'a>(
                     &artifact_fs,
                     client.clone(),
                     installer_log_filename.to_owned(),
+                    &file_spans,
                 )
-            })
-            .await;
+            });
+        let forward_progress =
+            forward_installer_progress(client.clone(), &file_spans, get_dispatcher());
+        futures::pin_mut!(send_files, forward_progress);
+        let send_files_result = match futures::future::select(send_files, forward_progress).await {
+            Either::Left((res, _)) => res,
+            // The installer stopped reporting progress, but it's still processing files.
+            Either::Right(((), send_files)) => send_files.await,
+        };
+
+        cancel_guard.defuse();
+        if let Err(e) = &send_files_result {
+            // We are reporting the original error either way.
+            let _ignored = send_cancel_command(client.clone(), format!("{:#}", e)).await;
+        }
         send_shutdown_command(client.clone()).await?;
         send_files_result.context("Failed to send artifacts to installer")?;
         anyhow::Ok(())
@@ -412,7 +445,6 @@ async fn send_install_info(
     };
 
     if install_info_response.install_id != install_id {
-        send_shutdown_command(client.clone()).await?;
         return Err(anyhow::anyhow!(
             "Received install id: {} doesn't match with the sent one: {}",
             install_info_response.install_id,
@@ -423,6 +455,140 @@ async fn send_install_info(
     Ok(())
 }
 
+async fn send_cancel_command(
+    mut client: InstallerClient<Channel>,
+    reason: String,
+) -> anyhow::Result<()> {
+    match client
+        .cancel(tonic::Request::new(CancelRequest { reason }))
+        .await
+    {
+        Ok(_) => Ok(()),
+        // Installers aren't required to support cancellation, they'll be shut down regardless.
+        Err(status) if status.code() == tonic::Code::Unimplemented => Ok(()),
+        Err(status) => Err(InstallError::InstallerCommunicationFailure {
+            err: status.message().to_owned(),
+        }
+        .into()),
+    }
+}
+
+/// Cancels all installs and shuts the installer down if dropped before being defused.
+struct CancelInstallerOnDrop {
+    client: Option<InstallerClient<Channel>>,
+}
+
+impl CancelInstallerOnDrop {
+    fn new(client: InstallerClient<Channel>) -> Self {
+        Self {
+            client: Some(client),
+        }
+    }
+
+    fn defuse(mut self) {
+        self.client.take();
+    }
+}
+
+impl Drop for CancelInstallerOnDrop {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            // We can't wait here, and the installer doesn't need us to.
+            tokio::spawn(async move {
+                let _ignored =
+                    send_cancel_command(client.clone(), "Install was interrupted".to_owned()).await;
+                let _ignored = send_shutdown_command(client).await;
+            });
+        }
+    }
+}
+
+/// The install span of each file currently being sent to the installer, so that progress the
+/// installer reports about a file can be attached to it.
+#[derive(Default)]
+struct FileSpans {
+    spans: Mutex<HashMap<(String, String), SpanId>>,
+}
+
+impl FileSpans {
+    fn insert(&self, install_id: &str, name: &str, span: SpanId) {
+        self.spans
+            .lock()
+            .unwrap()
+            .insert((install_id.to_owned(), name.to_owned()), span);
+    }
+
+    fn remove(&self, install_id: &str, name: &str) {
+        self.spans
+            .lock()
+            .unwrap()
+            .remove(&(install_id.to_owned(), name.to_owned()));
+    }
+
+    fn get(&self, install_id: &str, name: &str) -> Option<SpanId> {
+        self.spans
+            .lock()
+            .unwrap()
+            .get(&(install_id.to_owned(), name.to_owned()))
+            .copied()
+    }
+}
+
+fn progress_event(progress: ProgressResponse) -> buck2_data::InstallProgress {
+    let percent = match (progress.bytes_done, progress.bytes_total) {
+        (Some(done), Some(total)) if total > 0 => Some(done as f64 * 100.0 / total as f64),
+        _ => None,
+    };
+    buck2_data::InstallProgress {
+        install_id: progress.install_id,
+        artifact_name: progress.name,
+        message: progress.message,
+        percent,
+    }
+}
+
+/// Forward the progress the installer reports as events, until it stops reporting. Progress is
+/// best-effort, so failures to obtain it are only logged.
+async fn forward_installer_progress(
+    mut client: InstallerClient<Channel>,
+    file_spans: &FileSpans,
+    dispatcher: EventDispatcher,
+) {
+    let mut stream = match client
+        .progress(tonic::Request::new(ProgressRequest {}))
+        .await
+    {
+        Ok(r) => r.into_inner(),
+        Err(status) => {
+            if status.code() != tonic::Code::Unimplemented {
+                tracing::debug!("Failed to obtain installer progress: {}", status);
+            }
+            return;
+        }
+    };
+
+    loop {
+        match stream.message().await {
+            Ok(Some(progress)) => {
+                let span = progress
+                    .name
+                    .as_ref()
+                    .and_then(|name| file_spans.get(&progress.install_id, name));
+                let event = progress_event(progress);
+                match span {
+                    Some(span) => dispatcher.instant_event_in_span(event, span),
+                    None => dispatcher.instant_event(event),
+                }
+            }
+            Ok(None) => return,
+            Err(status) => {
+                tracing::debug!("Installer progress stream failed: {}", status);
+                return;
+            }
+        }
+    }
+}
+
 async fn send_shutdown_command(mut client: InstallerClient<Channel>) -> anyhow::Result<()> {
     let response_result = client
         .shutdown_server(tonic::Request::new(ShutdownRequest {}))
@@ -587,11 +753,65 @@ async fn connect_to_installer(tcp_port: u16) -> anyhow::Result<InstallerClient<C
     .await
 }
 
+/// Why the installer failed to process a file.
+struct FileReadyFailure {
+    message: String,
+    /// Whether sending the file again may succeed.
+    retryable: bool,
+}
+
+fn is_transient_status(status: &tonic::Status) -> bool {
+    matches!(
+        status.code(),
+        tonic::Code::Unavailable
+            | tonic::Code::DeadlineExceeded
+            | tonic::Code::ResourceExhausted
+            | tonic::Code::Aborted
+    )
+}
+
+async fn send_file_once(
+    client: &mut InstallerClient<Channel>,
+    request: &FileReadyRequest,
+) -> Result<(), FileReadyFailure> {
+    let response = match client
+        .file_ready(tonic::Request::new(request.clone()))
+        .await
+    {
+        Ok(r) => r.into_inner(),
+        Err(status) => {
+            return Err(FileReadyFailure {
+                message: status.message().to_owned(),
+                retryable: is_transient_status(&status),
+            });
+        }
+    };
+
+    if response.install_id != request.install_id {
+        return Err(FileReadyFailure {
+            message: format!(
+                "Received install id: {} doesn't match with the sent one: {}",
+                response.install_id, request.install_id
+            ),
+            retryable: false,
+        });
+    }
+
+    match response.error_detail {
+        Some(error_detail) => Err(FileReadyFailure {
+            message: error_detail.message,
+            retryable: error_detail.retryable,
+        }),
+        None => Ok(()),
+    }
+}
+
 async fn send_file(
     file: FileResult,
     artifact_fs: &ArtifactFs,
     mut client: InstallerClient<Channel>,
     install_log: String,
+    file_spans: &FileSpans,
 ) -> anyhow::Result<()> {
     let install_id = file.install_id;
     let name = file.name;
@@ -627,65 +847,53 @@ async fn send_file(
     let path = &artifact_fs
         .fs()
         .resolve(&artifact.resolve_path(artifact_fs)?);
-    let request = tonic::Request::new(FileReadyRequest {
+    let request = FileReadyRequest {
         install_id: install_id.to_owned(),
         name: name.to_owned(),
         digest,
         digest_algorithm,
         size,
         path: path.to_string(),
-    });
+    };
 
     let start = InstallEventInfoStart {
         artifact_name: name.to_owned(),
         file_path: path.to_string(),
     };
-    let end = InstallEventInfoEnd {};
     span_async(start, async {
-        let mut outcome: anyhow::Result<()> = Ok(());
-        let response_result = client.file_ready(request).await;
-        let response = match response_result {
-            Ok(r) => r.into_inner(),
-            Err(status) => {
-                return (
-                    Err(InstallError::ProcessingFileReadyFailure {
+        if let Some(span) = current_span() {
+            file_spans.insert(&install_id, &name, span);
+        }
+
+        let mut attempts = 0;
+        let outcome = loop {
+            attempts += 1;
+            match send_file_once(&mut client, &request).await {
+                Ok(()) => break Ok(()),
+                Err(failure) if failure.retryable && attempts < MAX_FILE_READY_ATTEMPTS => {
+                    tracing::debug!(
+                        "Installer failed to process `{}` (attempt {}), retrying: {}",
+                        name,
+                        attempts,
+                        failure.message
+                    );
+                    tokio::time::sleep(FILE_READY_INITIAL_BACKOFF * 2u32.pow(attempts - 1)).await;
+                }
+                Err(failure) => {
+                    break Err(InstallError::ProcessingFileReadyFailure {
                         install_id: install_id.to_owned(),
-                        artifact: name,
+                        artifact: name.to_owned(),
                         path: path.to_owned(),
-                        err: status.message().to_owned(),
+                        err: failure.message,
                         installer_log: install_log.to_owned(),
                     }
-                    .into()),
-                    end,
-                );
+                    .into());
+                }
             }
         };
 
-        if response.install_id != install_id {
-            outcome = Err(InstallError::ProcessingFileReadyFailure {
-                install_id: install_id.to_owned(),
-                artifact: name.to_owned(),
-                path: path.to_owned(),
-                err: format!(
-                    "Received install id: {} doesn't match with the sent one: {}",
-                    response.install_id, &install_id
-                ),
-                installer_log: install_log.to_owned(),
-            }
-            .into());
-        }
-
-        if let Some(error_detail) = response.error_detail {
-            outcome = Err(InstallError::ProcessingFileReadyFailure {
-                install_id: install_id.to_owned(),
-                artifact: name.to_owned(),
-                path: path.to_owned(),
-                err: error_detail.message,
-                installer_log: install_log.to_owned(),
-            }
-            .into());
-        }
-        (outcome, end)
+        file_spans.remove(&install_id, &name);
+        (outcome, InstallEventInfoEnd { attempts })
     })
     .await?;
     Ok(())
@@ -33,6 +33,7 @@ use dice::DiceComputations;
 use dice::DiceTransaction;
 use dupe::Dupe;
 
+use crate::commands::query::explain_configurations::explain_configurations;
 use crate::commands::query::printer::ProviderLookUp;
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
@@ -105,6 +106,7 @@ async fn cquery(
         target_call_stacks,
         show_providers,
         correct_owner,
+        explain_configurations,
         ..
     } = request;
     // The request will always have a universe value, an empty one indicates the user didn't provide a universe.
@@ -144,6 +146,21 @@ async fn cquery(
         .eval_query(query, query_args, target_universe.as_ref().map(|v| &v[..]))
         .await?;
 
+    if *explain_configurations {
+        let targets = match query_result {
+            QueryEvaluationResult::Single(value) => value.try_into_targets()?,
+            QueryEvaluationResult::Multiple(results) => results.merged()?.try_into_targets()?,
+        };
+        let roots = match target_universe {
+            Some(target_universe) => evaluator.universe_roots(target_universe).await?,
+            None => targets.clone(),
+        };
+        explain_configurations(&mut stdout, &targets, &roots)?;
+        return Ok(CqueryResponse {
+            error_messages: Vec::new(),
+        });
+    }
+
     let should_print_providers = if *show_providers {
         ShouldPrintProviders::Yes(&*ctx as &dyn ProviderLookUp<ConfiguredTargetNode>)
     } else {
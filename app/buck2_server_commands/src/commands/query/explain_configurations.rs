@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! `buck2 cquery --explain-configurations`: for each target of the query result, print where each
+//! of its configurations comes from, and how the configurations differ: in constraints, and in
+//! the buckconfig values required by the `select()` keys which matched.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use buck2_core::configuration::cfg_diff::cfg_diff;
+use buck2_core::target::label::TargetLabel;
+use buck2_node::configuration::resolved::buckconfig_diff;
+use buck2_node::nodes::configuration_origin::ConfigurationOrigins;
+use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::set::TargetSet;
+use dupe::IterDupedExt;
+use itertools::Itertools;
+
+/// Print, for each target in `targets`, its configurations along with the dependency path from
+/// `roots` and the edge which introduced each one, followed by the differences between the
+/// configurations and the buckconfig values their matching `select()` keys require.
+pub(crate) fn explain_configurations(
+    mut stdout: impl Write,
+    targets: &TargetSet<ConfiguredTargetNode>,
+    roots: &TargetSet<ConfiguredTargetNode>,
+) -> anyhow::Result<()> {
+    let origins = ConfigurationOrigins::new(roots.iter().duped());
+
+    let mut by_target: BTreeMap<&TargetLabel, Vec<&ConfiguredTargetNode>> = BTreeMap::new();
+    for target in targets.iter() {
+        by_target
+            .entry(target.label().unconfigured())
+            .or_default()
+            .push(target);
+    }
+
+    for (label, nodes) in by_target {
+        writeln!(stdout, "{}", label)?;
+        for node in &nodes {
+            writeln!(stdout, "  {}", node.label())?;
+            let origin = match origins.origin(node) {
+                Some(origin) => origin,
+                None => {
+                    writeln!(stdout, "    not reachable from the universe")?;
+                    continue;
+                }
+            };
+            writeln!(
+                stdout,
+                "    path: {}",
+                origin.path.iter().map(|n| n.label()).join(" -> ")
+            )?;
+            match origin.edge {
+                Some(edge) => writeln!(
+                    stdout,
+                    "    introduced by: {} -> {}: {}",
+                    edge.node.label(),
+                    edge.dep.label(),
+                    edge.change
+                )?,
+                None => writeln!(
+                    stdout,
+                    "    introduced by: {} (root of the universe)",
+                    origin.path[0].label()
+                )?,
+            }
+        }
+        if let Some((first, rest)) = nodes.split_first() {
+            for node in rest {
+                writeln!(
+                    stdout,
+                    "  diff {} -> {}:",
+                    first.label().cfg(),
+                    node.label().cfg()
+                )?;
+                let diffs = [
+                    cfg_diff(first.label().cfg(), node.label().cfg()),
+                    buckconfig_diff(
+                        first.resolved_configuration(),
+                        node.resolved_configuration(),
+                    ),
+                ];
+                for diff in diffs.iter().filter_map(|diff| diff.as_ref().err()) {
+                    for line in diff.lines() {
+                        writeln!(stdout, "    {}", line)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -11,6 +11,7 @@ use thiserror::Error;
 
 pub mod aquery;
 pub mod cquery;
+mod explain_configurations;
 pub mod printer;
 pub mod uquery;
 
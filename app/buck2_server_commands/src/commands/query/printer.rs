@@ -23,12 +23,16 @@ use buck2_query::query::syntax::simple::eval::file_set::FileSet;
 use buck2_query::query::syntax::simple::eval::multi_query::MultiQueryResult;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
+use buck2_query_proto::query_result;
 use buck2_util::indent::indent;
 use dupe::Clone_;
 use dupe::Copy_;
+use dupe::Dupe;
 use dupe::Dupe_;
+use futures::StreamExt;
 use gazebo::variants::UnpackVariants;
 use indent_write::fmt::IndentWriter;
+use prost::Message;
 use regex::RegexSet;
 use serde::ser::SerializeMap;
 use serde::ser::SerializeSeq;
@@ -40,6 +44,12 @@ use crate::dot::targets::DotTargetGraph;
 use crate::dot::Dot;
 use crate::dot::DotCompact;
 
+/// Key of the multi-query argument in the entries of the `json_lines` output.
+const QUERY_ARG: &str = "buck.query_arg";
+
+/// How many targets may have their providers looked up concurrently when streaming results.
+const STREAMING_LOOKUP_CONCURRENCY: usize = 64;
+
 #[derive(Copy_, Dupe_, Clone_, UnpackVariants)]
 pub enum ShouldPrintProviders<'a, T> {
     No,
@@ -126,13 +136,8 @@ impl<'a, T: QueryTarget> Display for PrintableQueryTarget<'a, T> {
     }
 }
 
-impl<'a, T: QueryTarget> Serialize for PrintableQueryTarget<'a, T> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut map = serializer.serialize_map(None)?;
-
+impl<'a, T: QueryTarget> PrintableQueryTarget<'a, T> {
+    fn serialize_entries<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
         QueryTargets::for_all_attrs(self.value, |attr_name, attr_value| {
             if let Some(attr_regex) = self.attributes {
                 if attr_regex.is_match(attr_name) {
@@ -170,10 +175,197 @@ impl<'a, T: QueryTarget> Serialize for PrintableQueryTarget<'a, T> {
             map.serialize_entry("buck.providers", providers)?;
         }
 
+        Ok(())
+    }
+
+    fn to_proto(&self) -> anyhow::Result<buck2_query_proto::TargetNode> {
+        let mut attributes = Vec::new();
+        QueryTargets::for_all_attrs(self.value, |attr_name, attr_value| {
+            if let Some(attr_regex) = self.attributes {
+                if attr_regex.is_match(attr_name) {
+                    let value = self
+                        .value
+                        .attr_serialize(attr_value, serde_json::value::Serializer)?;
+                    attributes.push(buck2_query_proto::Attribute {
+                        name: attr_name.to_owned(),
+                        value: Some(json_to_attr_value(value)),
+                    });
+                }
+            }
+            anyhow::Ok(())
+        })?;
+
+        Ok(buck2_query_proto::TargetNode {
+            label: self.label(),
+            attributes,
+            call_stack: if self.target_call_stacks {
+                self.value.call_stack()
+            } else {
+                None
+            },
+            providers: match &self.providers {
+                Some(providers) => Some(json_to_attr_value(serde_json::to_value(providers)?)),
+                None => None,
+            },
+        })
+    }
+}
+
+impl<'a, T: QueryTarget> Serialize for PrintableQueryTarget<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.serialize_entries(&mut map)?;
+        map.end()
+    }
+}
+
+/// A target as a single line of `json_lines` output.
+struct JsonLinesTargetPrinter<'a, 'b, T: QueryTarget> {
+    query_arg: Option<&'a str>,
+    target: &'a PrintableQueryTarget<'b, T>,
+}
+
+impl<'a, 'b, T: QueryTarget> Serialize for JsonLinesTargetPrinter<'a, 'b, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(query_arg) = self.query_arg {
+            map.serialize_entry(QUERY_ARG, query_arg)?;
+        }
+        map.serialize_entry("buck.label", &self.target.label())?;
+        self.target.serialize_entries(&mut map)?;
         map.end()
     }
 }
 
+fn json_to_attr_value(value: serde_json::Value) -> buck2_query_proto::AttrValue {
+    use buck2_query_proto::attr_value::Value;
+
+    let value = match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(b) => Some(Value::BoolValue(b)),
+        serde_json::Value::Number(n) => Some(match n.as_i64() {
+            Some(i) => Value::IntValue(i),
+            None => Value::FloatValue(n.as_f64().unwrap_or(f64::NAN)),
+        }),
+        serde_json::Value::String(s) => Some(Value::StringValue(s)),
+        serde_json::Value::Array(items) => Some(Value::ListValue(buck2_query_proto::AttrList {
+            items: items.into_iter().map(json_to_attr_value).collect(),
+        })),
+        serde_json::Value::Object(entries) => Some(Value::DictValue(buck2_query_proto::AttrDict {
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| buck2_query_proto::AttrDictEntry {
+                    key,
+                    value: Some(json_to_attr_value(value)),
+                })
+                .collect(),
+        })),
+    };
+    buck2_query_proto::AttrValue { value }
+}
+
+/// Output formats that write each target or file as soon as it is available, instead of
+/// building a single document for the whole result.
+#[derive(Clone, Copy, Dupe)]
+enum StreamingFormat {
+    JsonLines,
+    Protobuf,
+}
+
+impl StreamingFormat {
+    fn write_target<T: QueryTarget>(
+        self,
+        output: &mut impl std::io::Write,
+        query_arg: Option<&str>,
+        target: &PrintableQueryTarget<'_, T>,
+    ) -> anyhow::Result<()> {
+        match self {
+            StreamingFormat::JsonLines => {
+                serde_json::to_writer(&mut *output, &JsonLinesTargetPrinter { query_arg, target })?;
+                writeln!(output)?;
+                Ok(())
+            }
+            StreamingFormat::Protobuf => Self::write_proto(
+                output,
+                query_arg,
+                query_result::Result::Target(target.to_proto()?),
+            ),
+        }
+    }
+
+    fn write_file(
+        self,
+        output: &mut impl std::io::Write,
+        query_arg: Option<&str>,
+        path: String,
+    ) -> anyhow::Result<()> {
+        self.write_string(
+            output,
+            query_arg,
+            "buck.file",
+            path,
+            query_result::Result::File,
+        )
+    }
+
+    fn write_error(
+        self,
+        output: &mut impl std::io::Write,
+        query_arg: Option<&str>,
+        error: String,
+    ) -> anyhow::Result<()> {
+        self.write_string(
+            output,
+            query_arg,
+            "$error",
+            error,
+            query_result::Result::Error,
+        )
+    }
+
+    fn write_string(
+        self,
+        output: &mut impl std::io::Write,
+        query_arg: Option<&str>,
+        json_key: &str,
+        value: String,
+        proto: impl FnOnce(String) -> query_result::Result,
+    ) -> anyhow::Result<()> {
+        match self {
+            StreamingFormat::JsonLines => {
+                let mut map = serde_json::Map::new();
+                if let Some(query_arg) = query_arg {
+                    map.insert(QUERY_ARG.to_owned(), query_arg.into());
+                }
+                map.insert(json_key.to_owned(), value.into());
+                serde_json::to_writer(&mut *output, &map)?;
+                writeln!(output)?;
+                Ok(())
+            }
+            StreamingFormat::Protobuf => Self::write_proto(output, query_arg, proto(value)),
+        }
+    }
+
+    fn write_proto(
+        output: &mut impl std::io::Write,
+        query_arg: Option<&str>,
+        result: query_result::Result,
+    ) -> anyhow::Result<()> {
+        let message = buck2_query_proto::QueryResult {
+            query_arg: query_arg.map(str::to_owned),
+            result: Some(result),
+        };
+        output.write_all(&message.encode_length_delimited_to_vec())?;
+        Ok(())
+    }
+}
+
 impl<'a, T: QueryTarget> Serialize for TargetSetJsonPrinter<'a, T> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -250,6 +442,54 @@ impl<'a> QueryResultPrinter<'a> {
         })
     }
 
+    fn streaming_format(&self) -> Option<StreamingFormat> {
+        match self.output_format {
+            QueryOutputFormat::JsonLines => Some(StreamingFormat::JsonLines),
+            QueryOutputFormat::Protobuf => Some(StreamingFormat::Protobuf),
+            QueryOutputFormat::Default
+            | QueryOutputFormat::Json
+            | QueryOutputFormat::Dot
+            | QueryOutputFormat::DotCompact => None,
+        }
+    }
+
+    async fn print_streaming_output<'b, T: QueryTarget, W: std::io::Write>(
+        &self,
+        format: StreamingFormat,
+        output: &mut W,
+        query_arg: Option<&str>,
+        result: QueryEvaluationValue<T>,
+        target_call_stacks: bool,
+        print_providers: ShouldPrintProviders<'b, T>,
+    ) -> anyhow::Result<()> {
+        match result {
+            QueryEvaluationValue::TargetSet(targets) => {
+                // Unlike the other formats, only hold a bounded number of targets at a time.
+                let mut printable = futures::stream::iter(targets.iter())
+                    .map(|t| {
+                        printable_target(t, print_providers, &self.attributes, target_call_stacks)
+                    })
+                    .buffered(STREAMING_LOOKUP_CONCURRENCY);
+                while let Some(target) = printable.next().await {
+                    format.write_target(output, query_arg, &target?)?;
+                }
+            }
+            QueryEvaluationValue::FileSet(files) => {
+                if self.attributes.is_some() {
+                    return Err(QueryCommandError::FileSetHasNoAttributes.into());
+                }
+                for file in files.iter() {
+                    format.write_file(
+                        output,
+                        query_arg,
+                        self.resolver.resolve_path(file.as_ref())?.to_string(),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub async fn print_multi_output<'b, T: QueryTarget, W: std::io::Write>(
         &self,
         mut output: W,
@@ -257,6 +497,31 @@ impl<'a> QueryResultPrinter<'a> {
         target_call_stacks: bool,
         print_providers: ShouldPrintProviders<'b, T>,
     ) -> anyhow::Result<()> {
+        if let Some(format) = self.streaming_format() {
+            // Streaming formats always keep the results of a multi-query separate.
+            let mut captured_error = Ok(());
+            for (arg, result) in multi_result.0 {
+                match result {
+                    Ok(v) => {
+                        self.print_streaming_output(
+                            format,
+                            &mut output,
+                            Some(&arg),
+                            v,
+                            target_call_stacks,
+                            print_providers,
+                        )
+                        .await?
+                    }
+                    Err(e) => {
+                        format.write_error(&mut output, Some(&arg), format!("{:#}", e))?;
+                        captured_error = Err(e);
+                    }
+                }
+            }
+            return captured_error;
+        }
+
         match (self.output_format, &self.attributes) {
             // A multi-query only has interesting output with --json output. For non-json output it gets merged together.
             // TODO(cjhopman): buck1 does this really odd thing that a multi-query that requests any attributes
@@ -323,6 +588,19 @@ impl<'a> QueryResultPrinter<'a> {
         call_stack: bool,
         print_providers: ShouldPrintProviders<'b, T>,
     ) -> anyhow::Result<()> {
+        if let Some(format) = self.streaming_format() {
+            return self
+                .print_streaming_output(
+                    format,
+                    &mut output,
+                    None,
+                    result,
+                    call_stack,
+                    print_providers,
+                )
+                .await;
+        }
+
         match result {
             QueryEvaluationValue::TargetSet(targets) => match self.output_format {
                 QueryOutputFormat::Default => {
@@ -365,6 +643,9 @@ impl<'a> QueryResultPrinter<'a> {
                         &mut output,
                     )?;
                 }
+                QueryOutputFormat::JsonLines | QueryOutputFormat::Protobuf => {
+                    unreachable!("streaming formats are handled above")
+                }
             },
             QueryEvaluationValue::FileSet(files) => {
                 if self.attributes.is_some() {
@@ -397,6 +678,9 @@ impl<'a> QueryResultPrinter<'a> {
                     QueryOutputFormat::DotCompact => {
                         unimplemented!("dot_compact output for files not implemented yet")
                     }
+                    QueryOutputFormat::JsonLines | QueryOutputFormat::Protobuf => {
+                        unreachable!("streaming formats are handled above")
+                    }
                 }
             }
         }
@@ -411,23 +695,106 @@ async fn printable_targets<'a, T: QueryTarget>(
     attributes: &'a Option<RegexSet>,
     target_call_stacks: bool,
 ) -> anyhow::Result<Vec<PrintableQueryTarget<'a, T>>> {
-    futures::future::join_all(targets.iter().map(|t| {
-        let print_providers = &print_providers;
-        async move {
-            Ok(PrintableQueryTarget {
-                value: t,
-                attributes,
-                target_call_stacks,
-                providers: match print_providers {
-                    ShouldPrintProviders::No => None,
-                    ShouldPrintProviders::Yes(lookup) => {
-                        Some(lookup.lookup(t).await?.require_compatible()?)
-                    }
-                },
-            })
-        }
-    }))
+    futures::future::join_all(
+        targets
+            .iter()
+            .map(|t| printable_target(t, print_providers, attributes, target_call_stacks)),
+    )
     .await
     .into_iter()
     .collect::<anyhow::Result<_>>()
 }
+
+async fn printable_target<'a, T: QueryTarget>(
+    target: &'a T,
+    print_providers: ShouldPrintProviders<'a, T>,
+    attributes: &'a Option<RegexSet>,
+    target_call_stacks: bool,
+) -> anyhow::Result<PrintableQueryTarget<'a, T>> {
+    Ok(PrintableQueryTarget {
+        value: target,
+        attributes,
+        target_call_stacks,
+        providers: match print_providers {
+            ShouldPrintProviders::No => None,
+            ShouldPrintProviders::Yes(lookup) => {
+                Some(lookup.lookup(target).await?.require_compatible()?)
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_json_lines() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        StreamingFormat::JsonLines.write_file(&mut output, None, "foo/bar.txt".to_owned())?;
+        StreamingFormat::JsonLines.write_error(&mut output, Some("//a:b"), "oops".to_owned())?;
+        assert_eq!(
+            "{\"buck.file\":\"foo/bar.txt\"}\n{\"$error\":\"oops\",\"buck.query_arg\":\"//a:b\"}\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_protobuf() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        StreamingFormat::Protobuf.write_file(&mut output, None, "foo/bar.txt".to_owned())?;
+        StreamingFormat::Protobuf.write_error(&mut output, Some("//a:b"), "oops".to_owned())?;
+
+        let mut buf = output.as_slice();
+        assert_eq!(
+            buck2_query_proto::QueryResult {
+                query_arg: None,
+                result: Some(query_result::Result::File("foo/bar.txt".to_owned())),
+            },
+            buck2_query_proto::QueryResult::decode_length_delimited(&mut buf)?
+        );
+        assert_eq!(
+            buck2_query_proto::QueryResult {
+                query_arg: Some("//a:b".to_owned()),
+                result: Some(query_result::Result::Error("oops".to_owned())),
+            },
+            buck2_query_proto::QueryResult::decode_length_delimited(&mut buf)?
+        );
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_to_attr_value() {
+        use buck2_query_proto::attr_value::Value;
+
+        let value = json_to_attr_value(serde_json::json!({"a": [1, true, null, "s"]}));
+        assert_eq!(
+            buck2_query_proto::AttrValue {
+                value: Some(Value::DictValue(buck2_query_proto::AttrDict {
+                    entries: vec![buck2_query_proto::AttrDictEntry {
+                        key: "a".to_owned(),
+                        value: Some(buck2_query_proto::AttrValue {
+                            value: Some(Value::ListValue(buck2_query_proto::AttrList {
+                                items: vec![
+                                    buck2_query_proto::AttrValue {
+                                        value: Some(Value::IntValue(1)),
+                                    },
+                                    buck2_query_proto::AttrValue {
+                                        value: Some(Value::BoolValue(true)),
+                                    },
+                                    buck2_query_proto::AttrValue { value: None },
+                                    buck2_query_proto::AttrValue {
+                                        value: Some(Value::StringValue("s".to_owned())),
+                                    },
+                                ],
+                            })),
+                        }),
+                    }],
+                })),
+            },
+            value
+        );
+    }
+}
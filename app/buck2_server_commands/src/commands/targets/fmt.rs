@@ -19,6 +19,8 @@ use buck2_cli_proto::TargetsRequest;
 use buck2_core::bzl::ImportPath;
 use buck2_core::cells::cell_path::CellPath;
 use buck2_core::package::PackageLabel;
+use buck2_node::attrs::attr_type::attr_literal::AttrLiteral;
+use buck2_node::attrs::coerced_attr::CoercedAttr;
 use buck2_node::attrs::inspect_options::AttrInspectOptions;
 use buck2_node::nodes::attributes::DEPS;
 use buck2_node::nodes::attributes::INPUTS;
@@ -27,6 +29,9 @@ use buck2_node::nodes::attributes::TARGET_CALL_STACK;
 use buck2_node::nodes::attributes::TARGET_HASH;
 use buck2_node::nodes::attributes::TYPE;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_node::rule_type::RuleType;
+use buck2_node::rule_type::StarlarkRuleType;
+use buck2_node::visibility::VisibilitySpecification;
 use buck2_util::indent::indent;
 use gazebo::prelude::SliceExt;
 use itertools::Itertools;
@@ -321,6 +326,216 @@ impl TargetFormatter for TargetNameFormat {
     }
 }
 
+/// Prints each target as the rule call that would define it in a BUCK file, with all macros
+/// expanded, similar to Bazel's `query --output=build`.
+struct StarlarkFormat {
+    attr_inspect_opts: AttrInspectOptions,
+    target_call_stacks: bool,
+}
+
+impl TargetFormatter for StarlarkFormat {
+    fn separator(&self, buffer: &mut String) {
+        buffer.push('\n');
+    }
+
+    fn target(&self, target_info: TargetInfo<'_>, buffer: &mut String) {
+        if self.target_call_stacks {
+            if let Some(call_stack) = target_info.node.call_stack() {
+                write!(buffer, "{}", indent("# ", &call_stack)).unwrap();
+            }
+        }
+        if let RuleType::Starlark(rule_type) = target_info.node.rule_type() {
+            write_starlark_load(buffer, rule_type);
+        }
+        writeln!(buffer, "{}(", target_info.node.rule_type().name()).unwrap();
+        for a in target_info.node.attrs(self.attr_inspect_opts) {
+            // Private attributes can only have their default value, so can't be written out.
+            if a.name.starts_with('_') {
+                continue;
+            }
+            write!(buffer, "    {} = ", a.name).unwrap();
+            write_starlark_attr(buffer, a.value, 1);
+            buffer.push_str(",\n");
+        }
+        buffer.push_str(")\n");
+    }
+
+    fn imports(
+        &self,
+        source: &CellPath,
+        imports: &[ImportPath],
+        package: Option<PackageLabel>,
+        buffer: &mut String,
+    ) {
+        if let Some(package) = package {
+            writeln!(buffer, "# {}: {}", PACKAGE, package).unwrap();
+        }
+        writeln!(buffer, "# buck.file: {}", source).unwrap();
+        for import in imports {
+            writeln!(buffer, "# buck.imports: {}", import.path()).unwrap();
+        }
+    }
+
+    fn package_error(&self, package: PackageLabel, error: &anyhow::Error, buffer: &mut String) {
+        writeln!(buffer, "# {}: {}", PACKAGE, package).unwrap();
+        write!(
+            buffer,
+            "{}",
+            indent("# ", &format!("buck.error: {:?}", error))
+        )
+        .unwrap();
+    }
+}
+
+/// Write the `load()` that brings the rule into scope, so the output can be evaluated as a BUCK
+/// file.
+fn write_starlark_load(buffer: &mut String, rule_type: &StarlarkRuleType) {
+    // Not the `Display` of `ImportPath`, which has no `:` and may have an `@cell` suffix.
+    let file_name = rule_type
+        .import_path
+        .path()
+        .path()
+        .file_name()
+        .expect("import path has a parent, so it has a file name");
+    buffer.push_str("load(");
+    write_starlark_string(
+        buffer,
+        &format!("{}:{}", rule_type.import_path.path_parent(), file_name),
+    );
+    buffer.push_str(", ");
+    write_starlark_string(buffer, &rule_type.name);
+    buffer.push_str(")\n");
+}
+
+/// Write an attribute as the Starlark expression that would coerce to it. Sources are written
+/// relative to the package, as they would be in the BUCK file.
+fn write_starlark_attr(buffer: &mut String, attr: &CoercedAttr, depth: usize) {
+    match attr {
+        CoercedAttr::Literal(literal) => write_starlark_literal(buffer, literal, depth),
+        CoercedAttr::Selector(selector) => {
+            buffer.push_str("select(");
+            write_starlark_items(
+                buffer,
+                "{",
+                "}",
+                selector
+                    .entries()
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .chain(selector.default().map(|v| ("DEFAULT".to_owned(), v)))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+                depth,
+                |buffer, (key, value)| {
+                    write_starlark_string(buffer, &key);
+                    buffer.push_str(": ");
+                    write_starlark_attr(buffer, value, depth + 1);
+                },
+            );
+            buffer.push(')');
+        }
+        CoercedAttr::Concat(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    buffer.push_str(" + ");
+                }
+                write_starlark_attr(buffer, item, depth);
+            }
+        }
+    }
+}
+
+fn write_starlark_literal(buffer: &mut String, literal: &AttrLiteral<CoercedAttr>, depth: usize) {
+    let write_attr =
+        |buffer: &mut String, value: &CoercedAttr| write_starlark_attr(buffer, value, depth + 1);
+    let write_string = |buffer: &mut String, value: String| write_starlark_string(buffer, &value);
+    match literal {
+        AttrLiteral::Bool(true) => buffer.push_str("True"),
+        AttrLiteral::Bool(false) => buffer.push_str("False"),
+        AttrLiteral::Int(i) => write!(buffer, "{}", i).unwrap(),
+        AttrLiteral::String(s) | AttrLiteral::EnumVariant(s) => write_starlark_string(buffer, s),
+        AttrLiteral::List(items) => {
+            write_starlark_items(buffer, "[", "]", items.iter(), depth, write_attr)
+        }
+        AttrLiteral::Tuple(items) => {
+            write_starlark_items(buffer, "(", ")", items.iter(), depth, write_attr)
+        }
+        AttrLiteral::Dict(items) => {
+            write_starlark_items(buffer, "{", "}", items.iter(), depth, |buffer, (k, v)| {
+                write_starlark_attr(buffer, k, depth + 1);
+                buffer.push_str(": ");
+                write_starlark_attr(buffer, v, depth + 1);
+            })
+        }
+        AttrLiteral::None => buffer.push_str("None"),
+        AttrLiteral::Dep(d) => write_string(buffer, d.to_string()),
+        AttrLiteral::ConfiguredDep(d) => write_string(buffer, d.to_string()),
+        AttrLiteral::ExplicitConfiguredDep(d) => write_starlark_items(
+            buffer,
+            "(",
+            ")",
+            [d.label.to_string(), d.platform.to_string()].into_iter(),
+            depth,
+            write_string,
+        ),
+        AttrLiteral::ConfigurationDep(d) => write_string(buffer, d.to_string()),
+        AttrLiteral::SplitTransitionDep(d) => write_string(buffer, d.label.to_string()),
+        AttrLiteral::Query(q) => write_starlark_string(buffer, q.query()),
+        AttrLiteral::SourceLabel(s) => write_string(buffer, s.to_string()),
+        AttrLiteral::SourceFile(s) => write_string(buffer, s.path().to_string()),
+        AttrLiteral::Arg(a) => write_string(buffer, a.to_string()),
+        AttrLiteral::Label(l) => write_string(buffer, l.to_string()),
+        AttrLiteral::OneOf(l, _) => write_starlark_literal(buffer, l, depth),
+        AttrLiteral::Visibility(v) => {
+            let patterns = match v {
+                VisibilitySpecification::Public => vec!["PUBLIC".to_owned()],
+                VisibilitySpecification::Default => Vec::new(),
+                VisibilitySpecification::VisibleTo(patterns) => patterns.map(|p| p.to_string()),
+            };
+            write_starlark_items(buffer, "[", "]", patterns.into_iter(), depth, write_string)
+        }
+    }
+}
+
+/// Write a list, tuple or dict, one item per line, each followed by a comma (so one-element
+/// tuples stay tuples).
+fn write_starlark_items<T>(
+    buffer: &mut String,
+    open: &str,
+    close: &str,
+    items: impl ExactSizeIterator<Item = T>,
+    depth: usize,
+    mut write_item: impl FnMut(&mut String, T),
+) {
+    buffer.push_str(open);
+    if items.len() != 0 {
+        buffer.push('\n');
+        for item in items {
+            buffer.push_str(&"    ".repeat(depth + 1));
+            write_item(buffer, item);
+            buffer.push_str(",\n");
+        }
+        buffer.push_str(&"    ".repeat(depth));
+    }
+    buffer.push_str(close);
+}
+
+fn write_starlark_string(buffer: &mut String, s: &str) {
+    buffer.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buffer.push_str("\\\""),
+            '\\' => buffer.push_str("\\\\"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            '\t' => buffer.push_str("\\t"),
+            c if c.is_control() => write!(buffer, "\\u{:04x}", c as u32).unwrap(),
+            c => buffer.push(c),
+        }
+    }
+    buffer.push('"');
+}
+
 pub(crate) fn print_target_call_stack_after_target(out: &mut String, call_stack: Option<&str>) {
     if let Some(call_stack) = call_stack {
         write!(out, "{}", indent("  ", call_stack)).unwrap();
@@ -368,5 +583,207 @@ pub(crate) fn create_formatter(
                 json_lines: output_format == OutputFormat::JsonLines,
             },
         })),
+        OutputFormat::Starlark => Ok(Arc::new(StarlarkFormat {
+            attr_inspect_opts: if other.include_default_attributes {
+                AttrInspectOptions::All
+            } else {
+                AttrInspectOptions::DefinedOnly
+            },
+            target_call_stacks: other.target_call_stacks,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use buck2_core::package::package_relative_path::PackageRelativePath;
+    use buck2_core::provider::label::ProvidersLabel;
+    use buck2_core::target::label::TargetLabel;
+    use buck2_node::attrs::attr::testing::AttributeExt;
+    use buck2_node::attrs::attr::Attribute;
+    use buck2_node::attrs::attr_type::dep::DepAttr;
+    use buck2_node::attrs::attr_type::dep::DepAttrTransition;
+    use buck2_node::attrs::attr_type::dep::DepAttrType;
+    use buck2_node::attrs::attr_type::AttrType;
+    use buck2_node::attrs::coerced_attr::CoercedSelector;
+    use buck2_node::attrs::coerced_path::CoercedPath;
+    use buck2_node::nodes::unconfigured::testing::TargetNodeExt;
+    use buck2_node::provider_id_set::ProviderIdSet;
+    use buck2_util::arc_str::ArcSlice;
+    use starlark::environment::Globals;
+    use starlark::environment::Module;
+    use starlark::eval::Evaluator;
+    use starlark::eval::ReturnFileLoader;
+    use starlark::syntax::AstModule;
+    use starlark::syntax::Dialect;
+
+    use super::*;
+
+    fn string(s: &str) -> CoercedAttr {
+        CoercedAttr::Literal(AttrLiteral::String(s.into()))
+    }
+
+    fn source(path: &str) -> CoercedAttr {
+        CoercedAttr::Literal(AttrLiteral::SourceFile(CoercedPath::File(
+            PackageRelativePath::unchecked_new(path).to_arc(),
+        )))
+    }
+
+    fn to_starlark(attr: &CoercedAttr) -> String {
+        let mut buffer = String::new();
+        write_starlark_attr(&mut buffer, attr, 0);
+        buffer
+    }
+
+    #[test]
+    fn test_write_starlark_attr() {
+        assert_eq!(
+            "None",
+            to_starlark(&CoercedAttr::Literal(AttrLiteral::None))
+        );
+        assert_eq!(
+            r#""a\"b\\c\nd\u0001""#,
+            to_starlark(&string("a\"b\\c\nd\u{1}"))
+        );
+        assert_eq!(
+            "[]",
+            to_starlark(&CoercedAttr::Literal(AttrLiteral::List(ArcSlice::new([]))))
+        );
+        assert_eq!(
+            "(\n    \"a\",\n)",
+            to_starlark(&CoercedAttr::Literal(AttrLiteral::Tuple(ArcSlice::new([
+                string("a")
+            ]))))
+        );
+        assert_eq!(
+            "{\n    \"k\": \"sub/v.c\",\n}",
+            to_starlark(&CoercedAttr::Literal(AttrLiteral::Dict(ArcSlice::new([(
+                string("k"),
+                source("sub/v.c"),
+            )]))))
+        );
+        assert_eq!(
+            "[\n    \"a\",\n] + select({\n    \"root//c:x\": [],\n    \"DEFAULT\": None,\n})",
+            to_starlark(&CoercedAttr::Concat(Box::new([
+                CoercedAttr::Literal(AttrLiteral::List(ArcSlice::new([string("a")]))),
+                CoercedAttr::Selector(Box::new(
+                    CoercedSelector::new(
+                        ArcSlice::new([(
+                            TargetLabel::testing_parse("root//c:x"),
+                            CoercedAttr::Literal(AttrLiteral::List(ArcSlice::new([]))),
+                        )]),
+                        Some(CoercedAttr::Literal(AttrLiteral::None)),
+                    )
+                    .unwrap()
+                )),
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_starlark_round_trip() -> anyhow::Result<()> {
+        let rule_type = RuleType::Starlark(Arc::new(StarlarkRuleType {
+            import_path: ImportPath::testing_new("root//rules:defs.bzl"),
+            name: "some_rule".to_owned(),
+        }));
+        let node = TargetNode::testing_new(
+            TargetLabel::testing_parse("root//foo/bar:t"),
+            rule_type,
+            vec![
+                (
+                    "srcs",
+                    Attribute::testing_new(None, AttrType::list(AttrType::source(false))),
+                    CoercedAttr::Literal(AttrLiteral::List(ArcSlice::new([
+                        source("foo.c"),
+                        source("sub/bar.c"),
+                    ]))),
+                ),
+                (
+                    "pair",
+                    Attribute::testing_new(
+                        None,
+                        AttrType::tuple(vec![AttrType::string(), AttrType::string()]),
+                    ),
+                    CoercedAttr::Literal(AttrLiteral::Tuple(ArcSlice::new([
+                        string("a"),
+                        string("b"),
+                    ]))),
+                ),
+                (
+                    "deps",
+                    Attribute::testing_new(
+                        None,
+                        AttrType::list(AttrType::dep(ProviderIdSet::EMPTY)),
+                    ),
+                    CoercedAttr::Literal(AttrLiteral::List(ArcSlice::new([CoercedAttr::Literal(
+                        AttrLiteral::Dep(Box::new(DepAttr {
+                            attr_type: DepAttrType::new(
+                                ProviderIdSet::EMPTY,
+                                DepAttrTransition::Identity,
+                            ),
+                            label: ProvidersLabel::default_for(TargetLabel::testing_parse(
+                                "root//baz:dep",
+                            )),
+                        })),
+                    )]))),
+                ),
+            ],
+        );
+
+        let mut buffer = String::new();
+        StarlarkFormat {
+            attr_inspect_opts: AttrInspectOptions::DefinedOnly,
+            target_call_stacks: false,
+        }
+        .target(
+            TargetInfo {
+                node: &node,
+                target_hash: None,
+            },
+            &mut buffer,
+        );
+        assert!(
+            buffer.starts_with("load(\"root//rules:defs.bzl\", \"some_rule\")\n"),
+            "{}",
+            buffer
+        );
+
+        // Evaluate the output as a BUCK file against a rule that reports what it was called with.
+        let globals = Globals::standard();
+        let defs = Module::new();
+        Evaluator::new(&defs).eval_module(
+            AstModule::parse(
+                "defs.bzl",
+                "def some_rule(**kwargs):\n    return {k: [type(v), v] for k, v in kwargs.items()}\n"
+                    .to_owned(),
+                &Dialect::Extended,
+            )?,
+            &globals,
+        )?;
+        let defs = defs.freeze()?;
+        let modules = HashMap::from([("root//rules:defs.bzl", &defs)]);
+        let loader = ReturnFileLoader { modules: &modules };
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        eval.set_loader(&loader);
+        let kwargs = eval
+            .eval_module(
+                AstModule::parse("BUCK", buffer, &Dialect::Extended)?,
+                &globals,
+            )?
+            .to_json()?;
+
+        assert_eq!(
+            serde_json::json!({
+                "name": ["string", "t"],
+                "srcs": ["list", ["foo.c", "sub/bar.c"]],
+                "pair": ["tuple", ["a", "b"]],
+                "deps": ["list", ["root//baz:dep"]],
+            }),
+            serde_json::from_str::<serde_json::Value>(&kwargs)?
+        );
+        Ok(())
     }
 }
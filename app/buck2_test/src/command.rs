@@ -49,6 +49,7 @@ use buck2_core::quiet_soft_error;
 use buck2_core::target::label::TargetLabel;
 use buck2_core::target::name::TargetName;
 use buck2_execute::materialize::materializer::HasMaterializer;
+use buck2_execute_impl::downward_api::BuckDownwardApi;
 use buck2_interpreter_for_build::interpreter::calculation::InterpreterCalculation;
 use buck2_node::nodes::eval_result::EvaluationResult;
 use buck2_query::query::compatibility::MaybeCompatible;
@@ -79,7 +80,6 @@ use indexmap::IndexSet;
 use more_futures::cancellable_future::critical_section;
 use serde::Serialize;
 
-use crate::downward_api::BuckTestDownwardApi;
 use crate::executor_launcher::ExecutorLaunch;
 use crate::executor_launcher::ExecutorLauncher;
 use crate::executor_launcher::OutOfProcessTestExecutor;
@@ -427,7 +427,13 @@ async fn test_targets(
                     .await
                     .context("Failed to create a BuckTestOrchestrator")?;
 
-                    let server_handle = make_server(orchestrator, BuckTestDownwardApi);
+                    let downward_api = BuckDownwardApi::with_span(
+                        ctx.per_transaction_data().get_dispatcher().dupe(),
+                        buck2_data::DownwardApiSpanStart {
+                            name: "test executor".to_owned(),
+                        },
+                    );
+                    let server_handle = make_server(orchestrator, downward_api);
 
                     let mut driver = TestDriver::new(TestDriverState {
                         ctx: &ctx,
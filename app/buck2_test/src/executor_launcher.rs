@@ -13,6 +13,7 @@ use std::pin::Pin;
 use anyhow::Context as _;
 use async_trait::async_trait;
 use buck2_events::dispatch::EventDispatcher;
+use buck2_execute_impl::downward_api::BuckDownwardApi;
 use buck2_grpc::DuplexChannel;
 use buck2_grpc::ServerHandle;
 use buck2_test_api::grpc::spawn_orchestrator_server;
@@ -26,14 +27,12 @@ use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 use tokio::process::Child;
 
-use crate::downward_api::BuckTestDownwardApi;
 use crate::orchestrator::BuckTestOrchestrator;
 
 pub struct ExecutorLaunch {
     pub handle: Pin<Box<dyn Future<Output = anyhow::Result<ExecutorOutput>> + Send>>,
     pub client: TestExecutorClient,
-    pub make_server:
-        Box<dyn FnOnce(BuckTestOrchestrator, BuckTestDownwardApi) -> ServerHandle + Send>,
+    pub make_server: Box<dyn FnOnce(BuckTestOrchestrator, BuckDownwardApi) -> ServerHandle + Send>,
 }
 
 #[derive(Debug, Display)]
@@ -12,7 +12,6 @@
 #![feature(async_closure)]
 
 pub mod command;
-pub mod downward_api;
 pub mod executor_launcher;
 pub mod orchestrator;
 pub mod session;
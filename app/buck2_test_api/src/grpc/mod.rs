@@ -12,6 +12,7 @@ mod orchestrator;
 
 pub use executor::spawn_executor_server;
 pub use executor::TestExecutorClient;
+pub use orchestrator::downward_api_service;
 pub use orchestrator::spawn_orchestrator_server;
 pub use orchestrator::TestOrchestratorClient;
 
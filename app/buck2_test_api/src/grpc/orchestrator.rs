@@ -14,11 +14,18 @@ use std::time::Duration;
 
 use anyhow::Context as _;
 use buck2_downward_api::DownwardApi;
+use buck2_downward_api::DownwardSpanId;
+use buck2_downward_api::Metric;
 use buck2_downward_api_proto::downward_api_client;
 use buck2_downward_api_proto::downward_api_server;
 use buck2_downward_api_proto::ConsoleRequest;
 use buck2_downward_api_proto::ExternalEventRequest;
 use buck2_downward_api_proto::LogRequest;
+use buck2_downward_api_proto::MetricsRequest;
+use buck2_downward_api_proto::ProgressRequest;
+use buck2_downward_api_proto::SpanEndRequest;
+use buck2_downward_api_proto::SpanStartRequest;
+use buck2_downward_api_proto::SpanStartResponse;
 use buck2_events::dispatch::with_dispatcher_async;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_grpc::make_channel;
@@ -119,6 +126,77 @@ impl DownwardApi for TestOrchestratorClient {
 
         Ok(())
     }
+
+    async fn progress(
+        &self,
+        step: String,
+        percent: Option<f64>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()> {
+        self.downward_api_client
+            .clone()
+            .progress(ProgressRequest {
+                step,
+                percent,
+                span_id: span.map(|s| s.0),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn metrics(
+        &self,
+        metrics: Vec<Metric>,
+        span: Option<DownwardSpanId>,
+    ) -> anyhow::Result<()> {
+        let metrics = metrics
+            .into_iter()
+            .map(
+                |Metric { key, value, unit }| buck2_downward_api_proto::Metric { key, value, unit },
+            )
+            .collect();
+
+        self.downward_api_client
+            .clone()
+            .metrics(MetricsRequest {
+                metrics,
+                span_id: span.map(|s| s.0),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn span_start(
+        &self,
+        name: String,
+        parent: Option<DownwardSpanId>,
+    ) -> anyhow::Result<DownwardSpanId> {
+        let SpanStartResponse { span_id } = self
+            .downward_api_client
+            .clone()
+            .span_start(SpanStartRequest {
+                name,
+                parent_span_id: parent.map(|s| s.0),
+            })
+            .await?
+            .into_inner();
+
+        Ok(DownwardSpanId(span_id))
+    }
+
+    async fn span_end(&self, span: DownwardSpanId, success: bool) -> anyhow::Result<()> {
+        self.downward_api_client
+            .clone()
+            .span_end(SpanEndRequest {
+                span_id: span.0,
+                success,
+            })
+            .await?;
+
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -501,6 +579,93 @@ where
         })
         .await
     }
+
+    async fn progress(
+        &self,
+        request: tonic::Request<ProgressRequest>,
+    ) -> Result<tonic::Response<buck2_downward_api_proto::Empty>, tonic::Status> {
+        to_tonic(async move {
+            let ProgressRequest {
+                step,
+                percent,
+                span_id,
+            } = request.into_inner();
+
+            self.inner
+                .progress(step, percent, span_id.map(DownwardSpanId))
+                .await
+                .context("Failed to report progress")?;
+
+            Ok(buck2_downward_api_proto::Empty {})
+        })
+        .await
+    }
+
+    async fn metrics(
+        &self,
+        request: tonic::Request<MetricsRequest>,
+    ) -> Result<tonic::Response<buck2_downward_api_proto::Empty>, tonic::Status> {
+        to_tonic(async move {
+            let MetricsRequest { metrics, span_id } = request.into_inner();
+
+            let metrics = metrics
+                .into_iter()
+                .map(
+                    |buck2_downward_api_proto::Metric { key, value, unit }| Metric {
+                        key,
+                        value,
+                        unit,
+                    },
+                )
+                .collect();
+
+            self.inner
+                .metrics(metrics, span_id.map(DownwardSpanId))
+                .await
+                .context("Failed to report metrics")?;
+
+            Ok(buck2_downward_api_proto::Empty {})
+        })
+        .await
+    }
+
+    async fn span_start(
+        &self,
+        request: tonic::Request<SpanStartRequest>,
+    ) -> Result<tonic::Response<SpanStartResponse>, tonic::Status> {
+        to_tonic(async move {
+            let SpanStartRequest {
+                name,
+                parent_span_id,
+            } = request.into_inner();
+
+            let span = self
+                .inner
+                .span_start(name, parent_span_id.map(DownwardSpanId))
+                .await
+                .context("Failed to start span")?;
+
+            Ok(SpanStartResponse { span_id: span.0 })
+        })
+        .await
+    }
+
+    async fn span_end(
+        &self,
+        request: tonic::Request<SpanEndRequest>,
+    ) -> Result<tonic::Response<buck2_downward_api_proto::Empty>, tonic::Status> {
+        to_tonic(async move {
+            let SpanEndRequest { span_id, success } = request.into_inner();
+
+            self.inner
+                .span_end(DownwardSpanId(span_id), success)
+                .await
+                .context("Failed to end span")?;
+
+            Ok(buck2_downward_api_proto::Empty {})
+        })
+        .await
+    }
 }
 
 pub fn spawn_orchestrator_server<I, O, D>(
@@ -521,13 +686,23 @@ where
                 inner: orchestrator,
             },
         ))
-        .add_service(downward_api_server::DownwardApiServer::new(Service {
-            inner: downward_api,
-        }));
+        .add_service(downward_api_service(downward_api));
 
     spawn_oneshot(io, router)
 }
 
+/// The gRPC service serving `downward_api`.
+pub fn downward_api_service<D>(
+    downward_api: D,
+) -> downward_api_server::DownwardApiServer<Service<D>>
+where
+    D: DownwardApi + Send + Sync + 'static,
+{
+    downward_api_server::DownwardApiServer::new(Service {
+        inner: downward_api,
+    })
+}
+
 /// Used to wrap the Tonic server so that the spawned server has its EventDispatcher set.
 #[derive(Clone, Dupe)]
 pub struct EventDispatcherLayer {
@@ -205,6 +205,10 @@ pub(crate) trait EngineForIntrospection {
     ) -> Box<dyn Iterator<Item = SerializedGraphNodesForKey> + 'a>;
     fn len_for_introspection(&self) -> usize;
     fn currently_running_key_count(&self) -> usize;
+    /// The short type name of the keys stored in this engine.
+    fn key_type_name(&self) -> &'static str;
+    /// Memory retained by this engine, including all of its keys and values.
+    fn memory_for_introspection(&self) -> usize;
 }
 
 pub(crate) trait KeyForIntrospection: Display + 'static {
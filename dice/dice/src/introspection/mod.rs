@@ -18,18 +18,21 @@ use crate::DiceImplementation;
 
 pub mod graph;
 pub(crate) mod introspect;
+pub mod query;
 
 pub use crate::introspection::introspect::serialize_dense_graph;
 pub use crate::introspection::introspect::serialize_graph;
 use crate::legacy::DiceLegacy;
 
+#[derive(Debug, thiserror::Error)]
+#[error("Graph introspection is not supported for modern DICE")]
+pub struct IntrospectionNotSupported;
+
 impl Dice {
-    pub fn to_introspectable(&self) -> GraphIntrospectable {
+    pub fn to_introspectable(&self) -> anyhow::Result<GraphIntrospectable> {
         match &self.implementation {
-            DiceImplementation::Legacy(dice) => dice.to_introspectable(),
-            DiceImplementation::Modern(_) => {
-                unimplemented!("todo")
-            }
+            DiceImplementation::Legacy(dice) => Ok(dice.to_introspectable()),
+            DiceImplementation::Modern(_) => Err(IntrospectionNotSupported.into()),
         }
     }
 }
@@ -55,6 +58,7 @@ mod tests {
     use crate::api::key::Key;
     use crate::introspection::graph::SerializedGraphNodesForKey;
     use crate::introspection::serialize_graph;
+    use crate::Dice;
     use crate::DiceLegacy;
     use crate::HashMap;
 
@@ -161,4 +165,10 @@ mod tests {
         let _out: Vec<SerializedGraphNodesForKey> = bincode::deserialize(&node)?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_modern_is_not_introspectable() {
+        let dice = Dice::modern().build(DetectCycles::Disabled);
+        assert!(dice.to_introspectable().is_err());
+    }
 }
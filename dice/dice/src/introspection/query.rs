@@ -0,0 +1,578 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//!
+//! Point queries over a snapshot of the DICE graph: looking up keys, their dependencies and
+//! dependents, and explaining why a key was recomputed. This is what backs live graph
+//! explorers, as opposed to the full dumps in `introspect`, which are meant for offline analysis.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use serde::Serialize;
+
+use crate::introspection::graph::AnyKey;
+use crate::introspection::graph::GraphIntrospectable;
+use crate::introspection::graph::GraphNodeKind;
+use crate::introspection::graph::HistoryState;
+use crate::introspection::graph::KeyID;
+use crate::introspection::graph::SerializedGraphNode;
+use crate::introspection::graph::VersionNumber;
+use crate::HashMap;
+use crate::HashSet;
+
+/// How many keys to visit at most when looking for the root cause of an invalidation.
+const MAX_ROOT_CAUSE_SEARCH: usize = 10000;
+
+struct SnapshotKey {
+    key: String,
+    type_name: String,
+    /// Nodes by the version they were computed at.
+    nodes: BTreeMap<VersionNumber, SerializedGraphNode>,
+}
+
+/// A point-in-time copy of the DICE graph, indexed for lookups.
+///
+/// `KeyID`s are only meaningful within a single snapshot, so anything handed out to users
+/// identifies keys by type and display string instead (see [`KeyRef`]).
+pub struct GraphSnapshot {
+    keys: HashMap<KeyID, SnapshotKey>,
+    by_key: HashMap<String, Vec<KeyID>>,
+    rdeps: HashMap<KeyID, BTreeSet<usize>>,
+    running: Vec<(KeyID, VersionNumber, String)>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KeyRef {
+    pub type_name: String,
+    pub key: String,
+}
+
+#[derive(Serialize)]
+pub struct NodeSummary<'a> {
+    pub version: VersionNumber,
+    pub kind: &'a GraphNodeKind,
+    pub history: &'a BTreeMap<VersionNumber, HistoryState>,
+}
+
+#[derive(Serialize)]
+pub struct KeyDetails<'a> {
+    #[serde(flatten)]
+    pub key: KeyRef,
+    pub nodes: Vec<NodeSummary<'a>>,
+    /// Dependencies of the latest node.
+    pub deps: Vec<KeyRef>,
+    /// Keys that depend on any node of this key.
+    pub rdeps: Vec<KeyRef>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum RecomputeReason {
+    /// There is no computed node for this key.
+    NotComputed,
+    /// The key was only computed once, so there is nothing to explain.
+    FirstComputation,
+    /// The key itself was reported as changed to DICE, e.g. because a file it reads changed.
+    Invalidated { at: VersionNumber },
+    /// One or more dependencies changed, see `changed_deps`.
+    DepsChanged,
+    /// The key was recomputed but the history retained by DICE doesn't say why.
+    Unknown,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ChangedDep {
+    #[serde(flatten)]
+    pub key: KeyRef,
+    /// The earliest version in the window at which this dependency was dirtied or recomputed.
+    pub changed_at: VersionNumber,
+    /// Whether the dependency itself was invalidated, as opposed to one of its own dependencies.
+    pub invalidated: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Recomputation {
+    #[serde(flatten)]
+    pub key: KeyRef,
+    pub computed_at: Option<VersionNumber>,
+    pub previously_computed_at: Option<VersionNumber>,
+    pub reason: RecomputeReason,
+    /// Direct dependencies that changed between the two computations.
+    pub changed_deps: Vec<ChangedDep>,
+    /// The changes that ultimately caused this recomputation, found by following `changed_deps`
+    /// transitively.
+    pub root_causes: Vec<ChangedDep>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunningKey {
+    #[serde(flatten)]
+    pub key: KeyRef,
+    pub version: VersionNumber,
+    pub state: String,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct KeyTypeStats {
+    pub type_name: String,
+    pub keys: usize,
+    pub currently_running: usize,
+    pub memory_bytes: usize,
+}
+
+impl GraphSnapshot {
+    pub fn new(graph: &GraphIntrospectable) -> Self {
+        let mut ids = HashMap::<AnyKey, KeyID>::default();
+        let mut keys = HashMap::default();
+        let mut running = Vec::new();
+
+        for engine in graph.introspectables() {
+            for entry in engine.nodes(&mut ids) {
+                keys.insert(
+                    entry.id,
+                    SnapshotKey {
+                        key: entry.key,
+                        type_name: entry.type_name,
+                        nodes: entry
+                            .nodes
+                            .into_iter()
+                            .filter_map(|(v, node)| Some((v, node?)))
+                            .collect(),
+                    },
+                );
+            }
+        }
+
+        for engine in graph.introspectables() {
+            for (key, version, state) in engine.keys_currently_running() {
+                let num_ids = ids.len();
+                let key_str = key.to_string();
+                let type_name = key.short_type_name().to_owned();
+                let id = *ids.entry(key).or_insert(KeyID(num_ids));
+                keys.entry(id).or_insert_with(|| SnapshotKey {
+                    key: key_str,
+                    type_name,
+                    nodes: BTreeMap::new(),
+                });
+                running.push((id, version, format!("{:?}", state)));
+            }
+        }
+
+        let mut by_key = HashMap::<String, Vec<KeyID>>::default();
+        let mut rdeps = HashMap::<KeyID, BTreeSet<usize>>::default();
+        for (id, key) in &keys {
+            by_key.entry(key.key.clone()).or_default().push(*id);
+            for node in key.nodes.values() {
+                for dep in node.deps.iter().flatten() {
+                    rdeps.entry(*dep).or_default().insert(id.0);
+                }
+            }
+        }
+
+        Self {
+            keys,
+            by_key,
+            rdeps,
+            running,
+        }
+    }
+
+    fn key_ref(&self, id: KeyID) -> Option<KeyRef> {
+        self.keys.get(&id).map(|k| KeyRef {
+            type_name: k.type_name.clone(),
+            key: k.key.clone(),
+        })
+    }
+
+    fn key_refs(&self, ids: impl IntoIterator<Item = KeyID>) -> Vec<KeyRef> {
+        let mut refs: Vec<KeyRef> = ids.into_iter().filter_map(|id| self.key_ref(id)).collect();
+        refs.sort();
+        refs
+    }
+
+    /// Keys whose display string is exactly `key`, optionally restricted to a (short) type name.
+    fn lookup<'a>(
+        &'a self,
+        key: &str,
+        type_name: Option<&'a str>,
+    ) -> impl Iterator<Item = KeyID> + 'a {
+        self.by_key
+            .get(key)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |id| match type_name {
+                Some(t) => self.keys[id].type_name == t,
+                None => true,
+            })
+    }
+
+    /// Keys whose display string contains `query`, sorted, and at most `limit` of them.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<KeyRef> {
+        let mut found = self.key_refs(
+            self.keys
+                .iter()
+                .filter(|(_, k)| k.key.contains(query))
+                .map(|(id, _)| *id),
+        );
+        found.truncate(limit);
+        found
+    }
+
+    pub fn details(&self, key: &str, type_name: Option<&str>) -> Vec<KeyDetails<'_>> {
+        self.lookup(key, type_name)
+            .filter_map(|id| {
+                let entry = self.keys.get(&id)?;
+                Some(KeyDetails {
+                    key: self.key_ref(id)?,
+                    nodes: entry
+                        .nodes
+                        .iter()
+                        .map(|(v, node)| NodeSummary {
+                            version: *v,
+                            kind: &node.kind,
+                            history: &node.history.history,
+                        })
+                        .collect(),
+                    deps: self.key_refs(
+                        entry
+                            .nodes
+                            .values()
+                            .last()
+                            .and_then(|n| n.deps.as_ref())
+                            .into_iter()
+                            .flatten()
+                            .copied(),
+                    ),
+                    rdeps: self.key_refs(
+                        self.rdeps
+                            .get(&id)
+                            .into_iter()
+                            .flatten()
+                            .map(|id| KeyID(*id)),
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// If `id` was dirtied or recomputed within `window`, the earliest such version, and whether
+    /// it was invalidated directly.
+    fn changed_within(
+        &self,
+        id: KeyID,
+        window: &RangeInclusive<VersionNumber>,
+    ) -> Option<(VersionNumber, bool)> {
+        let entry = self.keys.get(&id)?;
+        let mut changed_at = entry.nodes.range(window.clone()).map(|(v, _)| *v).next();
+        let mut invalidated = false;
+        for node in entry.nodes.values() {
+            for (v, state) in node.history.history.range(window.clone()) {
+                match state {
+                    HistoryState::Verified => continue,
+                    HistoryState::ForceDirty => invalidated = true,
+                    HistoryState::Dirty => {}
+                }
+                changed_at = Some(changed_at.map_or(*v, |c| c.min(*v)));
+            }
+        }
+        changed_at.map(|v| (v, invalidated))
+    }
+
+    fn changed_deps(
+        &self,
+        deps: impl IntoIterator<Item = KeyID>,
+        window: &RangeInclusive<VersionNumber>,
+    ) -> Vec<(KeyID, ChangedDep)> {
+        let mut changed: Vec<_> = deps
+            .into_iter()
+            .filter_map(|dep| {
+                let (changed_at, invalidated) = self.changed_within(dep, window)?;
+                Some((
+                    dep,
+                    ChangedDep {
+                        key: self.key_ref(dep)?,
+                        changed_at,
+                        invalidated,
+                    },
+                ))
+            })
+            .collect();
+        changed.sort_by(|(_, a), (_, b)| (a.changed_at, &a.key).cmp(&(b.changed_at, &b.key)));
+        changed
+    }
+
+    /// Explain the latest recomputation of a key: which dependencies changed between the last two
+    /// times it was computed, and at which versions.
+    pub fn why_recomputed(&self, key: &str, type_name: Option<&str>) -> Vec<Recomputation> {
+        self.lookup(key, type_name)
+            .filter_map(|id| self.why_recomputed_id(id))
+            .collect()
+    }
+
+    fn why_recomputed_id(&self, id: KeyID) -> Option<Recomputation> {
+        let entry = self.keys.get(&id)?;
+        let mut recomputation = Recomputation {
+            key: self.key_ref(id)?,
+            computed_at: None,
+            previously_computed_at: None,
+            reason: RecomputeReason::NotComputed,
+            changed_deps: Vec::new(),
+            root_causes: Vec::new(),
+        };
+
+        let mut computed = entry.nodes.iter().rev();
+        let (current_version, current) = match computed.next() {
+            Some(c) => c,
+            None => return Some(recomputation),
+        };
+        recomputation.computed_at = Some(*current_version);
+        let (previous_version, previous) = match computed.next() {
+            Some(p) => p,
+            None => {
+                recomputation.reason = RecomputeReason::FirstComputation;
+                return Some(recomputation);
+            }
+        };
+        recomputation.previously_computed_at = Some(*previous_version);
+
+        // Anything that happened after the previous computation, up to the current one.
+        let window = VersionNumber(previous_version.0 + 1)..=*current_version;
+
+        let invalidated_at = [previous, current]
+            .iter()
+            .flat_map(|n| n.history.history.range(window.clone()))
+            .filter(|(_, state)| matches!(state, HistoryState::ForceDirty))
+            .map(|(v, _)| *v)
+            .min();
+
+        // The deps that mattered are the ones the previous value was computed from.
+        let deps = previous.deps.as_ref().or(current.deps.as_ref());
+        let changed = self.changed_deps(deps.into_iter().flatten().copied(), &window);
+
+        recomputation.reason = match invalidated_at {
+            Some(at) => RecomputeReason::Invalidated { at },
+            None if !changed.is_empty() => RecomputeReason::DepsChanged,
+            None => RecomputeReason::Unknown,
+        };
+        recomputation.root_causes = self.root_causes(&changed, &window);
+        recomputation.changed_deps = changed.into_iter().map(|(_, c)| c).collect();
+        Some(recomputation)
+    }
+
+    /// Follow changed dependencies transitively to the changes that started it all: keys that were
+    /// invalidated directly, or that changed without any of their own dependencies changing
+    /// (e.g. injected values).
+    fn root_causes(
+        &self,
+        changed: &[(KeyID, ChangedDep)],
+        window: &RangeInclusive<VersionNumber>,
+    ) -> Vec<ChangedDep> {
+        let mut roots = Vec::new();
+        let mut visited = HashSet::default();
+        let mut queue: VecDeque<(KeyID, VersionNumber, bool)> = changed
+            .iter()
+            .map(|(id, c)| (*id, c.changed_at, c.invalidated))
+            .collect();
+
+        while let Some((id, changed_at, invalidated)) = queue.pop_front() {
+            if visited.len() >= MAX_ROOT_CAUSE_SEARCH {
+                break;
+            }
+            if !visited.insert(id) {
+                continue;
+            }
+            let deps = self.keys[&id]
+                .nodes
+                .values()
+                .flat_map(|n| n.deps.iter().flatten().copied());
+            let changed_deps = self.changed_deps(deps, window);
+            if invalidated || changed_deps.is_empty() {
+                if let Some(key) = self.key_ref(id) {
+                    roots.push(ChangedDep {
+                        key,
+                        changed_at,
+                        invalidated,
+                    });
+                }
+            }
+            queue.extend(
+                changed_deps
+                    .into_iter()
+                    .map(|(dep, c)| (dep, c.changed_at, c.invalidated)),
+            );
+        }
+
+        roots.sort_by(|a, b| (a.changed_at, &a.key).cmp(&(b.changed_at, &b.key)));
+        roots
+    }
+
+    pub fn currently_running(&self) -> Vec<RunningKey> {
+        let mut running: Vec<RunningKey> = self
+            .running
+            .iter()
+            .filter_map(|(id, version, state)| {
+                Some(RunningKey {
+                    key: self.key_ref(*id)?,
+                    version: *version,
+                    state: state.clone(),
+                })
+            })
+            .collect();
+        running.sort_by(|a, b| (a.version, &a.key).cmp(&(b.version, &b.key)));
+        running
+    }
+}
+
+/// Number of keys, running computations and retained memory for each key type, largest first.
+/// Measuring memory walks every key and value, so this is as expensive as a full dump.
+pub fn key_type_stats(graph: &GraphIntrospectable) -> Vec<KeyTypeStats> {
+    let mut stats: Vec<KeyTypeStats> = graph
+        .introspectables()
+        .map(|engine| KeyTypeStats {
+            type_name: engine.key_type_name().to_owned(),
+            keys: engine.len_for_introspection(),
+            currently_running: engine.currently_running_key_count(),
+            memory_bytes: engine.memory_for_introspection(),
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.memory_bytes
+            .cmp(&a.memory_bytes)
+            .then_with(|| a.type_name.cmp(&b.type_name))
+    });
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use allocative::Allocative;
+    use async_trait::async_trait;
+    use derive_more::Display;
+    use dupe::Dupe;
+
+    use super::*;
+    use crate::api::computations::DiceComputations;
+    use crate::api::cycles::DetectCycles;
+    use crate::api::key::Key;
+    use crate::DiceLegacy;
+
+    #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
+    #[display(fmt = "{:?}", self)]
+    struct Leaf(usize);
+
+    #[async_trait]
+    impl Key for Leaf {
+        type Value = usize;
+
+        async fn compute(&self, _: &DiceComputations) -> Self::Value {
+            unreachable!("injected")
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
+    #[display(fmt = "{:?}", self)]
+    struct Sum;
+
+    #[async_trait]
+    impl Key for Sum {
+        type Value = usize;
+
+        async fn compute(&self, ctx: &DiceComputations) -> Self::Value {
+            ctx.compute(&Leaf(0)).await.unwrap() + ctx.compute(&Leaf(1)).await.unwrap()
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    fn key_ref(type_name: &str, key: &str) -> KeyRef {
+        KeyRef {
+            type_name: type_name.to_owned(),
+            key: key.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queries() -> anyhow::Result<()> {
+        let dice = DiceLegacy::builder().build(DetectCycles::Disabled);
+
+        let mut updater = dice.updater();
+        updater.changed_to(vec![(Leaf(0), 1), (Leaf(1), 2)])?;
+        let ctx = updater.commit().await;
+        assert_eq!(3, ctx.compute(&Sum).await?);
+
+        let snapshot = GraphSnapshot::new(&dice.to_introspectable());
+        let sum = snapshot.details("Sum", None);
+        assert_eq!(1, sum.len());
+        assert_eq!(
+            vec![key_ref("Leaf", "Leaf(0)"), key_ref("Leaf", "Leaf(1)")],
+            sum[0].deps
+        );
+        let leaf = snapshot.details("Leaf(1)", Some("Leaf"));
+        assert_eq!(vec![key_ref("Sum", "Sum")], leaf[0].rdeps);
+        assert!(snapshot.details("Leaf(1)", Some("Sum")).is_empty());
+        assert_eq!(
+            vec![key_ref("Leaf", "Leaf(0)"), key_ref("Leaf", "Leaf(1)")],
+            snapshot.search("Leaf", 10)
+        );
+        assert_eq!(1, snapshot.search("Leaf", 1).len());
+
+        let why = snapshot.why_recomputed("Sum", None);
+        assert_eq!(RecomputeReason::FirstComputation, why[0].reason);
+
+        // Change one leaf and recompute.
+        let mut updater = dice.updater();
+        updater.changed_to(vec![(Leaf(1), 5)])?;
+        let ctx = updater.commit().await;
+        assert_eq!(6, ctx.compute(&Sum).await?);
+
+        let snapshot = GraphSnapshot::new(&dice.to_introspectable());
+        let why = snapshot.why_recomputed("Sum", None);
+        assert_eq!(1, why.len());
+        assert_eq!(RecomputeReason::DepsChanged, why[0].reason);
+        assert_eq!(
+            vec![key_ref("Leaf", "Leaf(1)")],
+            why[0]
+                .changed_deps
+                .iter()
+                .map(|c| c.key.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(why[0].computed_at, Some(why[0].changed_deps[0].changed_at));
+        assert_eq!(
+            vec![key_ref("Leaf", "Leaf(1)")],
+            why[0]
+                .root_causes
+                .iter()
+                .map(|c| c.key.clone())
+                .collect::<Vec<_>>()
+        );
+
+        assert!(snapshot.currently_running().is_empty());
+
+        let stats = key_type_stats(&dice.to_introspectable());
+        let mut types: Vec<_> = stats
+            .iter()
+            .map(|s| (s.type_name.as_str(), s.keys))
+            .collect();
+        types.sort();
+        assert_eq!(vec![("Leaf", 2), ("Sum", 1)], types);
+
+        Ok(())
+    }
+}
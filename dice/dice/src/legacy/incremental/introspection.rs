@@ -13,6 +13,7 @@ use std::sync::Arc;
 use dupe::Dupe;
 use sorted_vector_map::SortedVectorMap;
 
+use crate::introspection::graph::short_type_name;
 use crate::introspection::graph::AnyKey;
 use crate::introspection::graph::EngineForIntrospection;
 use crate::introspection::graph::GraphNodeKind;
@@ -184,4 +185,12 @@ where
             .map(|(_, e)| e.len())
             .sum()
     }
+
+    fn key_type_name(&self) -> &'static str {
+        short_type_name(std::any::type_name::<K::Key>())
+    }
+
+    fn memory_for_introspection(&self) -> usize {
+        allocative::size_of_unique_allocated_data(self)
+    }
 }
@@ -496,7 +496,7 @@ impl DiceExecutionOrder {
             fs::create_dir_all(dump_path.parent().unwrap())?;
             let mut dump_loc = File::create(&dump_path)?;
             serialize_dense_graph(
-                &dice.to_introspectable(),
+                &dice.to_introspectable()?,
                 &mut serde_json::Serializer::pretty(&mut dump_loc),
             )?;
         }
@@ -8,12 +8,15 @@
  */
 
 use std::fmt;
+use std::sync::Arc;
 
 use allocative::Allocative;
 use anyhow::Context;
 use futures_intrusive::sync::SharedSemaphore;
 use futures_intrusive::sync::SharedSemaphoreReleaser;
 
+use crate::memory::bytes_to_permits;
+use crate::MemoryEstimates;
 use crate::NamedSemaphores;
 
 const SINGLE_RUN: usize = 1;
@@ -101,6 +104,7 @@ impl Default for HostSharingRequirements {
 pub struct HostSharingGuard {
     _run_guard: SharedSemaphoreReleaser,
     _name_guard: Option<SharedSemaphoreReleaser>,
+    _memory_guard: Option<SharedSemaphoreReleaser>,
 }
 
 /// Used to ensure that host resources are properly reserved before executing a command spec.
@@ -108,6 +112,15 @@ pub struct HostSharingBroker {
     permits: SharedSemaphore,
     num_machine_permits: usize,
     named_semaphores: NamedSemaphores,
+    memory: Option<MemoryBudget>,
+}
+
+/// Admits commands based on their estimated memory footprint, so that the commands running
+/// concurrently fit in a budget. Permits are MiB.
+struct MemoryBudget {
+    permits: SharedSemaphore,
+    num_permits: usize,
+    estimates: Arc<MemoryEstimates>,
 }
 
 impl HostSharingBroker {
@@ -137,31 +150,92 @@ impl HostSharingBroker {
             permits,
             num_machine_permits,
             named_semaphores: NamedSemaphores::new(),
+            memory: None,
         }
     }
 
+    /// Also admit commands based on their memory footprint, up to `budget_bytes` in total. The
+    /// footprint is either declared by the command or estimated from `estimates`.
+    pub fn with_memory_budget(
+        mut self,
+        host_sharing_strategy: HostSharingStrategy,
+        budget_bytes: u64,
+        estimates: Arc<MemoryEstimates>,
+    ) -> Self {
+        let num_permits = bytes_to_permits(budget_bytes);
+        let permits = match host_sharing_strategy {
+            HostSharingStrategy::Fifo => SharedSemaphore::new(true, num_permits),
+            HostSharingStrategy::SmallerTasksFirst => SharedSemaphore::new(false, num_permits),
+        };
+        self.memory = Some(MemoryBudget {
+            permits,
+            num_permits,
+            estimates,
+        });
+        self
+    }
+
     pub fn num_machine_permits(&self) -> usize {
         self.num_machine_permits
     }
 
+    /// The memory permits (MiB) a command will reserve, capped to the budget so that it can
+    /// always run eventually. Commands with no declared or previously observed footprint reserve
+    /// nothing.
+    pub fn requested_memory_permits(
+        &self,
+        command_key: &str,
+        declared_bytes: Option<u64>,
+    ) -> usize {
+        match &self.memory {
+            Some(memory) => declared_bytes
+                .or_else(|| memory.estimates.get(command_key))
+                .map_or(0, |bytes| bytes_to_permits(bytes).min(memory.num_permits)),
+            None => 0,
+        }
+    }
+
+    /// Remember how much memory a command used, to admit its next run accordingly.
+    pub fn record_peak_memory(&self, command_key: &str, peak_bytes: u64) {
+        if let Some(memory) = &self.memory {
+            memory.estimates.record(command_key, peak_bytes);
+        }
+    }
+
     pub async fn acquire(
         &self,
         host_sharing_requirements: &HostSharingRequirements,
+    ) -> HostSharingGuard {
+        self.acquire_with_memory(host_sharing_requirements, 0).await
+    }
+
+    /// Like `acquire`, but also reserve `memory_permits` from the memory budget, if there is one.
+    /// Memory is reserved before the run permits, so that no run permits are held while waiting
+    /// for memory to free up.
+    pub async fn acquire_with_memory(
+        &self,
+        host_sharing_requirements: &HostSharingRequirements,
+        memory_permits: usize,
     ) -> HostSharingGuard {
         match host_sharing_requirements {
             HostSharingRequirements::Shared(weight_class) => {
+                let _memory_guard = self.acquire_memory(memory_permits).await;
                 let permits = self.requested_permits(weight_class);
                 let _run_guard = self.permits.acquire(permits).await;
                 HostSharingGuard {
                     _run_guard,
                     _name_guard: None,
+                    _memory_guard,
                 }
             }
             HostSharingRequirements::ExclusiveAccess => {
+                let all_memory = self.memory.as_ref().map_or(0, |m| m.num_permits);
+                let _memory_guard = self.acquire_memory(all_memory).await;
                 let _run_guard = self.permits.acquire(self.num_machine_permits).await;
                 HostSharingGuard {
                     _run_guard,
                     _name_guard: None,
+                    _memory_guard,
                 }
             }
             HostSharingRequirements::OnePerToken(identifier, weight_class) => {
@@ -171,15 +245,29 @@ impl HostSharingBroker {
                 // for the previous run on this identifier to finish.
                 let run_semaphore = self.named_semaphores.get(identifier);
                 let _name_guard = Some(run_semaphore.acquire(SINGLE_RUN).await);
+                let _memory_guard = self.acquire_memory(memory_permits).await;
                 let permits = self.requested_permits(weight_class);
                 let _run_guard = self.permits.acquire(permits).await;
                 HostSharingGuard {
                     _run_guard,
                     _name_guard,
+                    _memory_guard,
                 }
             }
         }
     }
+
+    async fn acquire_memory(&self, memory_permits: usize) -> Option<SharedSemaphoreReleaser> {
+        match &self.memory {
+            Some(memory) if memory_permits > 0 => Some(
+                memory
+                    .permits
+                    .acquire(memory_permits.min(memory.num_permits))
+                    .await,
+            ),
+            _ => None,
+        }
+    }
 }
 
 /// Determines whether a fair or unfair semaphore is used to manage host sharing
@@ -232,4 +320,32 @@ mod tests {
             10,
         );
     }
+
+    #[test]
+    fn test_requested_memory_permits() {
+        const MIB: u64 = 1024 * 1024;
+
+        let broker = HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, 10);
+        assert_eq!(broker.requested_memory_permits("a", Some(100 * MIB)), 0);
+
+        let estimates = Arc::new(MemoryEstimates::new());
+        let broker = HostSharingBroker::new(HostSharingStrategy::SmallerTasksFirst, 10)
+            .with_memory_budget(
+                HostSharingStrategy::SmallerTasksFirst,
+                1024 * MIB,
+                estimates.clone(),
+            );
+
+        // Nothing known about this command.
+        assert_eq!(broker.requested_memory_permits("a", None), 0);
+
+        // Learned from a previous run.
+        broker.record_peak_memory("a", 200 * MIB);
+        assert_eq!(estimates.get("a"), Some(200 * MIB));
+        assert_eq!(broker.requested_memory_permits("a", None), 200);
+
+        // Declared footprints win, and are capped to the budget.
+        assert_eq!(broker.requested_memory_permits("a", Some(50 * MIB)), 50);
+        assert_eq!(broker.requested_memory_permits("a", Some(4096 * MIB)), 1024);
+    }
 }
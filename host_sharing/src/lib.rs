@@ -9,7 +9,9 @@
 
 #![feature(int_roundings)]
 #![deny(unused_crate_dependencies)]
+mod memory;
 mod named_semaphores;
+pub use memory::MemoryEstimates;
 pub use named_semaphores::NamedSemaphores;
 
 pub mod host_sharing;
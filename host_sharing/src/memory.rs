@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use dashmap::DashMap;
+
+/// Peak memory usage observed for past runs of commands, keyed by a stable identifier for the
+/// command (not its digest, since that changes whenever its inputs do). This outlives individual
+/// buck2 commands so that we learn across builds.
+#[derive(Default)]
+pub struct MemoryEstimates {
+    estimates: DashMap<String, u64>,
+}
+
+impl MemoryEstimates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.estimates.get(key).map(|e| *e)
+    }
+
+    /// Record the peak memory of a run. An estimate only decays by a quarter per run, so that a
+    /// command whose footprint varies keeps being admitted with some headroom.
+    pub fn record(&self, key: &str, peak_bytes: u64) {
+        match self.estimates.get_mut(key) {
+            Some(mut estimate) => {
+                *estimate = peak_bytes.max(*estimate - *estimate / 4);
+            }
+            None => {
+                self.estimates.insert(key.to_owned(), peak_bytes);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.estimates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.estimates.is_empty()
+    }
+}
+
+/// Convert a memory amount to the unit we use for semaphore permits, rounding up.
+pub(crate) fn bytes_to_permits(bytes: u64) -> usize {
+    const MIB: u64 = 1024 * 1024;
+    bytes.div_ceil(MIB).try_into().unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_decays_slowly() {
+        let estimates = MemoryEstimates::new();
+        assert_eq!(estimates.get("a"), None);
+
+        estimates.record("a", 1000);
+        assert_eq!(estimates.get("a"), Some(1000));
+
+        // Growth is taken as-is.
+        estimates.record("a", 2000);
+        assert_eq!(estimates.get("a"), Some(2000));
+
+        // Shrinking is gradual.
+        estimates.record("a", 100);
+        assert_eq!(estimates.get("a"), Some(1500));
+        estimates.record("a", 1400);
+        assert_eq!(estimates.get("a"), Some(1400));
+
+        assert_eq!(estimates.get("b"), None);
+        assert_eq!(estimates.len(), 1);
+    }
+
+    #[test]
+    fn test_bytes_to_permits() {
+        assert_eq!(bytes_to_permits(0), 0);
+        assert_eq!(bytes_to_permits(1), 1);
+        assert_eq!(bytes_to_permits(1024 * 1024), 1);
+        assert_eq!(bytes_to_permits(1024 * 1024 + 1), 2);
+    }
+}
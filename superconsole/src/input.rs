@@ -0,0 +1,474 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Building blocks for interactive consoles.
+//!
+//! Superconsole does not read from the terminal itself: the embedding tool owns stdin and feeds the
+//! raw characters it reads into a [`KeyDecoder`](KeyDecoder), which turns them into [`Key`](Key)s.
+//! Keys are then routed to whatever currently has focus (see [`Focus`](Focus)), and the
+//! resulting state ([`ScrollState`](ScrollState), [`TextInput`](TextInput)) is handed to
+//! [`Component`](crate::Component)s through [`State`](crate::State) at render time, like any other state.
+
+use std::ops::Range;
+
+/// A single decoded key press.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Tab,
+    BackTab,
+}
+
+/// Whether a key was consumed by the handler it was offered to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InputResult {
+    /// The key was consumed and should not be offered to anything else.
+    Handled,
+    /// The key was not meaningful to this handler.
+    Ignored,
+}
+
+impl InputResult {
+    pub fn is_handled(self) -> bool {
+        self == Self::Handled
+    }
+}
+
+/// Something that reacts to key presses, e.g. a scrollable list or a text field.
+pub trait InputHandler {
+    fn handle_key(&mut self, key: Key) -> InputResult;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DecoderState {
+    Ground,
+    /// Saw `ESC`.
+    Escape,
+    /// Saw `ESC [` (CSI) or `ESC O` (SS3), followed by `params`.
+    Sequence,
+}
+
+/// Incrementally decodes characters read from a terminal in non-canonical mode into [`Key`]s.
+///
+/// Only the small subset of VT100/xterm sequences that terminals commonly send for navigation is
+/// recognized. Unrecognized sequences are dropped rather than being reported as individual
+/// characters.
+#[derive(Debug)]
+pub struct KeyDecoder {
+    state: DecoderState,
+    params: String,
+}
+
+impl Default for KeyDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: DecoderState::Ground,
+            params: String::new(),
+        }
+    }
+
+    /// Feed a single character. Returns the keys that are complete as a result; this is usually
+    /// zero or one key, but can be two when a lone `ESC` turns out to be followed by a regular key.
+    pub fn push(&mut self, c: char) -> Vec<Key> {
+        match self.state {
+            DecoderState::Ground => self.ground(c).into_iter().collect(),
+            DecoderState::Escape => match c {
+                '[' | 'O' => {
+                    self.state = DecoderState::Sequence;
+                    self.params.clear();
+                    Vec::new()
+                }
+                '\x1b' => vec![Key::Escape],
+                c => {
+                    self.state = DecoderState::Ground;
+                    let mut keys = vec![Key::Escape];
+                    keys.extend(self.ground(c));
+                    keys
+                }
+            },
+            DecoderState::Sequence => {
+                if c.is_ascii_digit() || c == ';' {
+                    self.params.push(c);
+                    return Vec::new();
+                }
+                self.state = DecoderState::Ground;
+                let key = match (c, self.params.as_str()) {
+                    ('A', _) => Some(Key::Up),
+                    ('B', _) => Some(Key::Down),
+                    ('C', _) => Some(Key::Right),
+                    ('D', _) => Some(Key::Left),
+                    ('H', _) => Some(Key::Home),
+                    ('F', _) => Some(Key::End),
+                    ('Z', _) => Some(Key::BackTab),
+                    ('~', "1" | "7") => Some(Key::Home),
+                    ('~', "4" | "8") => Some(Key::End),
+                    ('~', "3") => Some(Key::Delete),
+                    ('~', "5") => Some(Key::PageUp),
+                    ('~', "6") => Some(Key::PageDown),
+                    _ => None,
+                };
+                key.into_iter().collect()
+            }
+        }
+    }
+
+    /// A lone `ESC` is indistinguishable from the start of a sequence until the next character
+    /// arrives. Call this when input has been idle for a while to report it as [`Key::Escape`].
+    pub fn flush(&mut self) -> Option<Key> {
+        match self.state {
+            DecoderState::Escape => {
+                self.state = DecoderState::Ground;
+                Some(Key::Escape)
+            }
+            DecoderState::Ground | DecoderState::Sequence => None,
+        }
+    }
+
+    fn ground(&mut self, c: char) -> Option<Key> {
+        match c {
+            '\x1b' => {
+                self.state = DecoderState::Escape;
+                None
+            }
+            '\r' | '\n' => Some(Key::Enter),
+            '\t' => Some(Key::Tab),
+            '\x7f' | '\x08' => Some(Key::Backspace),
+            c if c.is_control() => None,
+            c => Some(Key::Char(c)),
+        }
+    }
+}
+
+/// Tracks which of a fixed set of targets currently receives input.
+/// `T` is typically a small `Copy` enum naming the focusable parts of a UI.
+#[derive(Debug, Clone)]
+pub struct Focus<T> {
+    targets: Vec<T>,
+    current: usize,
+}
+
+impl<T: Copy + Eq> Focus<T> {
+    /// Create a focus ring over `targets`, focusing the first one.
+    ///
+    /// Panics if `targets` is empty.
+    pub fn new(targets: Vec<T>) -> Self {
+        assert!(!targets.is_empty(), "Focus requires at least one target");
+        Self {
+            targets,
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> T {
+        self.targets[self.current]
+    }
+
+    pub fn is_focused(&self, target: T) -> bool {
+        self.current() == target
+    }
+
+    /// Focus the given target. Targets that are not part of this ring are ignored.
+    pub fn focus(&mut self, target: T) {
+        if let Some(idx) = self.targets.iter().position(|t| *t == target) {
+            self.current = idx;
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.targets.len();
+    }
+
+    pub fn prev(&mut self) {
+        self.current = (self.current + self.targets.len() - 1) % self.targets.len();
+    }
+
+    /// Handle the keys that move focus around (`Tab` and `Shift-Tab`).
+    pub fn handle_key(&mut self, key: Key) -> InputResult {
+        match key {
+            Key::Tab => self.next(),
+            Key::BackTab => self.prev(),
+            _ => return InputResult::Ignored,
+        }
+        InputResult::Handled
+    }
+}
+
+/// Selection and scroll position within a list of `len` items, of which `height` are visible.
+///
+/// The list contents are owned by the caller and can change between renders, so the length and
+/// height are supplied on every operation and the selection is clamped accordingly.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScrollState {
+    selected: usize,
+    offset: usize,
+}
+
+impl ScrollState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The index of the selected item, if the list isn't empty.
+    pub fn selected(&self, len: usize) -> Option<usize> {
+        if len == 0 {
+            None
+        } else {
+            Some(self.selected.min(len - 1))
+        }
+    }
+
+    pub fn select(&mut self, idx: usize, len: usize, height: usize) {
+        self.selected = idx.min(len.saturating_sub(1));
+        self.offset = self.visible_range(len, height).start;
+    }
+
+    pub fn move_by(&mut self, delta: isize, len: usize, height: usize) {
+        let current = self.selected(len).unwrap_or(0);
+        let target = if delta < 0 {
+            current.saturating_sub(delta.unsigned_abs())
+        } else {
+            current.saturating_add(delta as usize)
+        };
+        self.select(target, len, height);
+    }
+
+    /// Handle the usual list navigation keys: arrows, `j`/`k`, page up/down, home/end.
+    pub fn handle_key(&mut self, key: Key, len: usize, height: usize) -> InputResult {
+        let page = height.max(1) as isize;
+        match key {
+            Key::Up | Key::Char('k') => self.move_by(-1, len, height),
+            Key::Down | Key::Char('j') => self.move_by(1, len, height),
+            Key::PageUp => self.move_by(-page, len, height),
+            Key::PageDown => self.move_by(page, len, height),
+            Key::Home | Key::Char('g') => self.select(0, len, height),
+            Key::End | Key::Char('G') => self.select(len.saturating_sub(1), len, height),
+            _ => return InputResult::Ignored,
+        }
+        InputResult::Handled
+    }
+
+    /// The range of items to draw. The viewport only moves when the selection would leave it, so
+    /// this is stable across renders as long as the selection stays in view.
+    pub fn visible_range(&self, len: usize, height: usize) -> Range<usize> {
+        if len == 0 || height == 0 {
+            return 0..0;
+        }
+        let selected = self.selected(len).unwrap_or(0);
+        let mut offset = self.offset;
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + height {
+            offset = selected + 1 - height;
+        }
+        // Don't leave blank space at the bottom if the list shrank.
+        offset = offset.min(len.saturating_sub(height));
+        offset..(offset + height).min(len)
+    }
+}
+
+/// A single-line text field, e.g. for filters or search boxes.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TextInput {
+    text: String,
+    /// Cursor position, in chars.
+    cursor: usize,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    fn byte_offset(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+}
+
+impl InputHandler for TextInput {
+    fn handle_key(&mut self, key: Key) -> InputResult {
+        match key {
+            Key::Char(c) => {
+                let at = self.byte_offset(self.cursor);
+                self.text.insert(at, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte_offset(self.cursor);
+                self.text.remove(at);
+            }
+            Key::Delete if self.cursor < self.text.chars().count() => {
+                let at = self.byte_offset(self.cursor);
+                self.text.remove(at);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.text.chars().count(),
+            Key::Backspace | Key::Delete => {}
+            _ => return InputResult::Ignored,
+        }
+        InputResult::Handled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(input: &str) -> Vec<Key> {
+        let mut decoder = KeyDecoder::new();
+        let mut keys: Vec<Key> = input.chars().flat_map(|c| decoder.push(c)).collect();
+        keys.extend(decoder.flush());
+        keys
+    }
+
+    #[test]
+    fn test_decode_plain() {
+        assert_eq!(
+            decode("a\r\t\x7f"),
+            vec![Key::Char('a'), Key::Enter, Key::Tab, Key::Backspace]
+        );
+    }
+
+    #[test]
+    fn test_decode_sequences() {
+        assert_eq!(
+            decode("\x1b[A\x1b[B\x1bOC\x1b[5~\x1b[6~\x1b[H\x1b[4~\x1b[Z"),
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Home,
+                Key::End,
+                Key::BackTab,
+            ]
+        );
+        // Modified arrows still decode as arrows, unknown sequences are dropped.
+        assert_eq!(decode("\x1b[1;5A\x1b[99~x"), vec![Key::Up, Key::Char('x')]);
+    }
+
+    #[test]
+    fn test_decode_escape() {
+        assert_eq!(decode("\x1b"), vec![Key::Escape]);
+        assert_eq!(decode("\x1bq"), vec![Key::Escape, Key::Char('q')]);
+        assert_eq!(decode("\x1b\x1b[A"), vec![Key::Escape, Key::Up]);
+    }
+
+    #[test]
+    fn test_focus() {
+        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        enum Target {
+            A,
+            B,
+            C,
+        }
+
+        let mut focus = Focus::new(vec![Target::A, Target::B, Target::C]);
+        assert!(focus.is_focused(Target::A));
+        assert!(focus.handle_key(Key::BackTab).is_handled());
+        assert_eq!(focus.current(), Target::C);
+        assert!(focus.handle_key(Key::Tab).is_handled());
+        assert_eq!(focus.current(), Target::A);
+        focus.focus(Target::B);
+        assert_eq!(focus.current(), Target::B);
+        assert_eq!(focus.handle_key(Key::Enter), InputResult::Ignored);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut scroll = ScrollState::new();
+        assert_eq!(scroll.selected(0), None);
+        assert_eq!(scroll.visible_range(0, 3), 0..0);
+
+        assert_eq!(scroll.visible_range(10, 3), 0..3);
+        scroll.handle_key(Key::Down, 10, 3);
+        scroll.handle_key(Key::Down, 10, 3);
+        assert_eq!(scroll.visible_range(10, 3), 0..3);
+        scroll.handle_key(Key::Down, 10, 3);
+        assert_eq!(scroll.selected(10), Some(3));
+        assert_eq!(scroll.visible_range(10, 3), 1..4);
+
+        scroll.handle_key(Key::End, 10, 3);
+        assert_eq!(scroll.visible_range(10, 3), 7..10);
+        scroll.handle_key(Key::PageUp, 10, 3);
+        assert_eq!(scroll.selected(10), Some(6));
+        assert_eq!(scroll.visible_range(10, 3), 6..9);
+
+        // The list shrinking clamps both selection and viewport.
+        assert_eq!(scroll.selected(4), Some(3));
+        assert_eq!(scroll.visible_range(4, 3), 1..4);
+
+        assert_eq!(
+            scroll.handle_key(Key::Char('x'), 4, 3),
+            InputResult::Ignored
+        );
+    }
+
+    #[test]
+    fn test_text_input() {
+        let mut input = TextInput::new();
+        for c in "fo".chars() {
+            input.handle_key(Key::Char(c));
+        }
+        input.handle_key(Key::Left);
+        input.handle_key(Key::Char('é'));
+        assert_eq!(input.text(), "féo");
+        assert_eq!(input.cursor(), 2);
+        input.handle_key(Key::Backspace);
+        input.handle_key(Key::Home);
+        input.handle_key(Key::Delete);
+        assert_eq!(input.text(), "o");
+        input.handle_key(Key::Backspace);
+        assert_eq!(input.text(), "o");
+        assert_eq!(input.handle_key(Key::Up), InputResult::Ignored);
+        input.clear();
+        assert!(input.is_empty());
+    }
+}
@@ -22,6 +22,8 @@
 //! [`State`](State) and [`Component`s](Component) are decoupled.  `Component`s are stateless, and `State` is supplied at render time.
 //!
 //! A set of pre-baked composition and testing oriented components are provided in the [`components`](components) module.
+//!
+//! Interactive consoles can use the key decoding, focus and scrolling primitives in the [`input`](input) module.
 
 pub use components::Component;
 pub use components::DrawMode;
@@ -41,6 +43,7 @@ pub mod components;
 pub mod content;
 mod dimensions;
 mod error;
+pub mod input;
 pub mod output;
 mod state;
 pub mod style;